@@ -1,5 +1,9 @@
-use crate::storage::{List, Storage};
-use crate::types::Message;
+use crate::storage::backend::TweetKind;
+use crate::storage::{
+    Card, Data, GraphCount, List, MediaMeta, MediaReport, Place, Poll, PollOption, RawArchive,
+    Snapshot, SpaceCard, Storage,
+};
+use crate::types::{ApiStatus, CrawlError, Message};
 use egg_mode::{
     cursor,
     list::{self, ListID},
@@ -7,14 +11,23 @@ use egg_mode::{
     user::{self, TwitterUser},
     RateLimit,
 };
-use reqwest::Client;
+use futures::StreamExt;
+use reqwest::{
+    header::{CONTENT_RANGE, RANGE, RETRY_AFTER},
+    Client, StatusCode,
+};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::Hasher;
 use std::io::Write;
 use std::time::SystemTime;
-use std::{collections::HashSet, path::PathBuf, str::FromStr, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
 use tokio::sync::{
-    mpsc::{channel, Sender},
+    mpsc::{channel, error::SendError, Sender},
     Mutex,
 };
 use tokio::task::JoinHandle;
@@ -22,50 +35,208 @@ use tracing::{info, trace, warn};
 
 use eyre::{bail, Result};
 
-use crate::config::Config;
+type CrawlResult<T> = std::result::Result<T, CrawlError>;
+
+use crate::config::{Config, MediaDomains, MediaNaming, RetryPolicy};
 
 /// Internal messaging between the different threads
 #[derive(Debug)]
-pub enum DownloadInstruction {
+pub(crate) enum DownloadInstruction {
     /// Download an image
     Image(String),
-    /// Download a Movie
-    Movie(mime::Mime, String),
+    /// Download a Movie. The metadata is stored once it was downloaded
+    Movie(mime::Mime, String, MediaMeta),
     /// Download the media of a profile
     ProfileMedia(String),
+    /// Download an image or movie of the tweet with the id. The index is the
+    /// position of the media in the tweet
+    TweetMedia(u64, usize, Box<DownloadInstruction>),
     /// Tells the thread to close as all the crawling finished
     Done,
 }
 
-pub async fn crawl_new_storage(
+impl DownloadInstruction {
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            DownloadInstruction::Image(url)
+            | DownloadInstruction::Movie(_, url, _)
+            | DownloadInstruction::ProfileMedia(url) => Some(url),
+            DownloadInstruction::TweetMedia(_, _, media) => media.url(),
+            DownloadInstruction::Done => None,
+        }
+    }
+}
+
+/// Which kinds of media are downloaded
+#[derive(Clone, Copy, Debug)]
+pub struct MediaFilter {
+    pub images: bool,
+    pub videos: bool,
+    pub profile_media: bool,
+}
+
+impl MediaFilter {
+    pub fn all() -> Self {
+        Self {
+            images: true,
+            videos: true,
+            profile_media: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            images: false,
+            videos: false,
+            profile_media: false,
+        }
+    }
+
+    fn allows(&self, instruction: &DownloadInstruction) -> bool {
+        match instruction {
+            DownloadInstruction::Image(_) => self.images,
+            DownloadInstruction::Movie(..) => self.videos,
+            DownloadInstruction::ProfileMedia(_) => self.profile_media,
+            DownloadInstruction::TweetMedia(_, _, media) => self.allows(media),
+            DownloadInstruction::Done => true,
+        }
+    }
+}
+
+/// Sends instructions to the download task. Media types that are filtered
+/// out are never queued. Profile media is only queued once, as the same
+/// profiles are encountered over and over.
+/// It also goes into a separate channel, so that the media of tweets
+/// is downloaded first.
+#[derive(Clone)]
+pub(crate) struct InstructionSender {
+    sender: Sender<DownloadInstruction>,
+    low_priority_sender: Sender<DownloadInstruction>,
+    filter: MediaFilter,
+    queued_profile_media: Arc<std::sync::Mutex<HashSet<String>>>,
+    pending_media: PendingMedia,
+}
+
+impl InstructionSender {
+    fn new(
+        sender: Sender<DownloadInstruction>,
+        low_priority_sender: Sender<DownloadInstruction>,
+        filter: MediaFilter,
+    ) -> Self {
+        Self {
+            sender,
+            low_priority_sender,
+            filter,
+            queued_profile_media: Default::default(),
+            pending_media: Default::default(),
+        }
+    }
+
+    /// The downloads that aren't in the media index yet
+    pub(crate) fn pending_media(&self) -> &PendingMedia {
+        &self.pending_media
+    }
+
+    pub async fn send(
+        &self,
+        instruction: DownloadInstruction,
+    ) -> Result<(), SendError<DownloadInstruction>> {
+        if !self.filter.allows(&instruction) {
+            return Ok(());
+        }
+        if let DownloadInstruction::ProfileMedia(url) = &instruction {
+            // If the lock is poisoned, queue anyway. The download deduplicates, too
+            if let Ok(mut queued) = self.queued_profile_media.lock() {
+                if !queued.insert(url.clone()) {
+                    return Ok(());
+                }
+            }
+            return self.low_priority_sender.send(instruction).await;
+        }
+        self.sender.send(instruction).await
+    }
+}
+
+pub(crate) async fn crawl_new_storage(
     config: Config,
     message_sender: Sender<Message>,
     user_id: u64,
 ) -> Result<()> {
     let storage_path = config.actual_storage_path();
+    // The login is verified once the crawl starts, see `fetch`
     let Ok(user_container) = egg_mode::user::lookup([user_id], &config.token).await else { bail!("Could not find user") };
     let Some(user) = user_container.response.first() else { bail!("Empty User Response") };
-    let mut storage = Storage::new(user.clone(), storage_path)?;
+    // An interrupted crawl continues with the data it already saved
+    let mut storage = match Storage::open(&storage_path) {
+        Ok(storage) if !config.finished_stages(user_id).is_empty() => storage,
+        _ => Storage::new(user.clone(), storage_path)?,
+    };
     storage.with_data(|d| {
-        d.profiles.insert(user.id, user.clone());
+        d.insert_profile(user.clone());
     });
     crawl_into_storage(user_id, config, storage, message_sender).await
 }
 
-pub async fn crawl_into_storage(
+/// Checks the login of the config for the "Test Login" button. App-only
+/// tokens have no user to verify, for them it is checked that Twitter
+/// accepts them
+pub(crate) async fn test_login(config: &Config) -> std::result::Result<String, String> {
+    match verify_token(config).await {
+        Ok(Some(user)) => Ok(format!("Logged in as @{}", user.screen_name)),
+        Ok(None) => match api_status(config).await {
+            ApiStatus::Ok => Ok("Twitter accepts the app-only login".to_string()),
+            status => Err(status.to_string()),
+        },
+        Err(e) => Err(format!("The login is invalid: {e}")),
+    }
+}
+
+/// Makes one cheap call with the token of the config to find out whether
+/// Twitter is reachable and accepts it. Works for app-only tokens, too
+pub async fn api_status(config: &Config) -> ApiStatus {
+    match egg_mode::service::rate_limit_status(&config.token).await {
+        Ok(_) => ApiStatus::Ok,
+        Err(e) => ApiStatus::from_error(&e),
+    }
+}
+
+/// Checks that the token of the config is still valid and returns the
+/// profile of the logged in user. A rejected token is `CrawlError::Auth`.
+/// App-only tokens have no user to verify and return `None`
+async fn verify_token(config: &Config) -> CrawlResult<Option<TwitterUser>> {
+    verify_login(config, || egg_mode::auth::verify_tokens(&config.token)).await
+}
+
+/// `verify_token` with the request that verifies the user
+async fn verify_login<F, Fut>(config: &Config, verify: F) -> CrawlResult<Option<TwitterUser>>
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = egg_mode::error::Result<egg_mode::Response<TwitterUser>>>,
+{
+    if config.is_app_only() {
+        return Ok(None);
+    }
+    Ok(Some(verify().await?.response))
+}
+
+pub(crate) async fn crawl_into_storage(
     user_id: u64,
     config: Config,
     storage: Storage,
     sender: Sender<Message>,
 ) -> Result<()> {
     let crawl_task = tokio::spawn(async move {
+        let started = std::time::Instant::now();
         match fetch(user_id, &config, storage, sender.clone()).await {
             Ok(_) => {
-                println!("crawl_task done");
+                trace!("crawl_task done");
             }
             Err(e) => {
-                if let Err(e) = sender.send(Message::Error(e)).await {
-                    println!("Could not close channel for error  {e:?}");
+                if !matches!(e, CrawlError::Cancelled) {
+                    crate::notify::crawl_failed(&config, &e, started.elapsed()).await;
+                }
+                if let Err(e) = sender.send(Message::Error(e.into())).await {
+                    warn!("Could not close channel for error {e:?}");
                 }
             }
         }
@@ -76,308 +247,1285 @@ pub async fn crawl_into_storage(
     Ok(())
 }
 
-pub fn create_instruction_handler(
-    should_download_media: bool,
+/// Media above `max_media_bytes` is skipped and recorded as `skipped_media`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn create_instruction_handler(
+    filter: MediaFilter,
+    retry_policy: RetryPolicy,
+    bandwidth: BandwidthLimiter,
+    naming: MediaNaming,
+    domains: MediaDomains,
+    max_media_bytes: Option<u64>,
+    client: Client,
     shared_storage: Arc<Mutex<Storage>>,
-) -> (JoinHandle<()>, Sender<DownloadInstruction>) {
+) -> (JoinHandle<()>, InstructionSender) {
     let (instruction_sender, mut instruction_receiver) = channel(4096);
+    let (low_priority_sender, mut low_priority_receiver) = channel(4096);
+    let instruction_sender =
+        InstructionSender::new(instruction_sender, low_priority_sender, filter);
+    // Downloads that are not in the media index of the storage yet
+    let pending = instruction_sender.pending_media().clone();
     let instruction_task = tokio::spawn(async move {
-        let client = Client::new();
-        while let Some(instruction) = instruction_receiver.recv().await {
+        let mut done = false;
+        loop {
+            let instruction = if done {
+                // Profile media that was queued before `Done` is still downloaded
+                match low_priority_receiver.try_recv() {
+                    Ok(instruction) => instruction,
+                    Err(_) => break,
+                }
+            } else {
+                tokio::select! {
+                    biased;
+                    Some(instruction) = instruction_receiver.recv() => instruction,
+                    Some(instruction) = low_priority_receiver.recv() => instruction,
+                    else => break,
+                }
+            };
             if matches!(instruction, DownloadInstruction::Done) {
-                break;
-            }
-            if !should_download_media {
+                done = true;
+                pending.flush(&shared_storage).await;
                 continue;
             }
-            if let Err(e) = handle_instruction(&client, instruction, shared_storage.clone()).await {
-                warn!("Download Error {e:?}");
+            let url = instruction.url().unwrap_or_default().to_string();
+            match handle_instruction(
+                &client,
+                &retry_policy,
+                &bandwidth,
+                naming,
+                &domains,
+                max_media_bytes,
+                instruction,
+                &pending,
+                shared_storage.clone(),
+            )
+            .await
+            {
+                Ok(Some(DownloadOutcome::Downloaded(downloaded))) => pending.push(downloaded),
+                Ok(Some(DownloadOutcome::TooLarge(url, bytes))) => {
+                    info!(url, bytes, "Skipping media that is larger than max_media_bytes");
+                    pending.skip(url, bytes);
+                }
+                Ok(None) => {}
+                Err(e) => warn!(url, error = %e, "Download failed"),
+            }
+            if pending.len() >= MEDIA_INDEX_BATCH {
+                pending.flush(&shared_storage).await;
             }
         }
+        pending.flush(&shared_storage).await;
     });
     (instruction_task, instruction_sender)
 }
 
-async fn msg(msg: impl AsRef<str>, sender: &Sender<Message>) {
-    if let Err(e) = sender
-        .send(Message::Loading(msg.as_ref().to_string()))
-        .await
+/// Queue the missing and corrupt media of a report for download again.
+/// The downloads are retried like the ones of a crawl with the config
+pub async fn re_download_missing(
+    storage: Storage,
+    report: &MediaReport,
+    config: &Config,
+) -> Result<Storage> {
+    let client = config.http_client()?;
+    let shared_storage = Arc::new(Mutex::new(storage));
     {
-        warn!("Could not send message: {}: {e:?}", msg.as_ref());
+        // Otherwise the downloader considers them done
+        let mut storage = shared_storage.lock().await;
+        for url in report.broken() {
+            storage.data_mut().media.remove(url);
+        }
+    }
+
+    let (instruction_task, instruction_sender) = create_instruction_handler(
+        MediaFilter::all(),
+        config.retry_policy().clone(),
+        config.bandwidth_limiter(),
+        config.config_data.media_naming,
+        config.media_domains(),
+        config.config_data.max_media_bytes,
+        client,
+        shared_storage.clone(),
+    );
+    let instructions = {
+        let storage = shared_storage.lock().await;
+        instructions_for_urls(storage.data(), report.broken())
+    };
+    for instruction in instructions {
+        instruction_sender.send(instruction).await?;
     }
+    instruction_sender.send(DownloadInstruction::Done).await?;
+    instruction_task.await?;
+
+    let storage = shared_storage.lock_owned().await.clone();
+    Ok(storage)
 }
 
-async fn fetch(
-    user_id: u64,
+/// The download instructions for media urls, with the tweet that contains
+/// them so that videos are downloaded as movies again. Urls that no tweet
+/// contains are profile images and banners
+fn instructions_for_urls<'a>(
+    data: &Data,
+    urls: impl Iterator<Item = &'a String>,
+) -> Vec<DownloadInstruction> {
+    let urls: Vec<&String> = urls.collect();
+    let wanted: HashSet<&str> = urls.iter().map(|url| url.as_str()).collect();
+    let mut found: HashMap<String, DownloadInstruction> = HashMap::new();
+    let tweets = data
+        .tweets
+        .iter()
+        .chain(data.mentions.iter())
+        .chain(data.likes.iter())
+        .chain(data.responses.values().flatten());
+    for tweet in tweets {
+        let nested = [
+            tweet.quoted_status.as_deref(),
+            tweet.retweeted_status.as_deref(),
+        ];
+        for t in std::iter::once(tweet).chain(nested.into_iter().flatten()) {
+            let media = crate::helpers::media_in_tweet(t).unwrap_or_default();
+            for (index, entry) in media.into_iter().enumerate() {
+                let Some(url) = entry.url().filter(|url| wanted.contains(url)) else {
+                    continue;
+                };
+                if !found.contains_key(url) {
+                    found.insert(
+                        url.to_string(),
+                        DownloadInstruction::TweetMedia(t.id, index, Box::new(entry)),
+                    );
+                }
+            }
+        }
+    }
+    urls.into_iter()
+        .map(|url| {
+            found
+                .remove(url)
+                .unwrap_or_else(|| DownloadInstruction::ProfileMedia(url.clone()))
+        })
+        .collect()
+}
+
+/// Download the members of a single list again and replace the stored members.
+/// Everything else in the archive stays untouched. Fails if the list was
+/// deleted on Twitter, in which case the stored copy is kept
+pub async fn refresh_list(
+    list_id: u64,
     config: &Config,
     storage: Storage,
     sender: Sender<Message>,
-) -> Result<()> {
-    let shared_storage = Arc::new(Mutex::new(storage));
-
-    async fn save_data(storage: &Arc<Mutex<Storage>>) {
-        if let Err(e) = storage.lock().await.save() {
-            warn!("Could not write out data {e:?}");
+) -> Result<Storage> {
+    let client = config.http_client()?;
+    config.rate_limiter().ready().await;
+    let list = match list::show(ListID::from_id(list_id), &config.token).await {
+        Ok(list) => list.response,
+        Err(egg_mode::error::Error::TwitterError(_, ref errors))
+            if errors.errors.iter().any(|e| e.code == 34) =>
+        {
+            bail!("The list {list_id} no longer exists on Twitter")
         }
-    }
+        Err(e) => return Err(CrawlError::from(e).into()),
+    };
 
-    let (instruction_task, instruction_sender) =
-        create_instruction_handler(config.crawl_options().media, shared_storage.clone());
+    let shared_storage = Arc::new(Mutex::new(storage));
+    let (instruction_task, instruction_sender) = create_instruction_handler(
+        config.crawl_options().media_filter(),
+        config.retry_policy().clone(),
+        config.bandwidth_limiter(),
+        config.config_data.media_naming,
+        config.media_domains(),
+        config.config_data.max_media_bytes,
+        client,
+        shared_storage.clone(),
+    );
 
-    fetch_single_profile(
-        user_id,
+    // A sync would skip the list because it already exists
+    let mut config = config.clone();
+    config.is_sync = false;
+    msg(format!("Refreshing list {}", list.name), &sender).await;
+    fetch_list_members(
+        list,
         shared_storage.clone(),
-        config,
+        &config,
         instruction_sender.clone(),
+        sender,
     )
     .await?;
 
-    if config.crawl_options().tweets {
-        fetch_user_tweets(
-            user_id,
-            shared_storage.clone(),
-            config,
-            instruction_sender.clone(),
-            sender.clone(),
-        )
-        .await?;
-        save_data(&shared_storage).await;
+    instruction_sender.send(DownloadInstruction::Done).await?;
+    instruction_task.await?;
+
+    let storage = shared_storage.lock_owned().await.clone();
+    Ok(storage)
+}
+
+/// Download the profile of the owner again, e.g. after a new bio or avatar.
+/// Only the profile media whose url changed is downloaded
+pub(crate) async fn refresh_profile(
+    config: &Config,
+    storage: Storage,
+    sender: Sender<Message>,
+) -> Result<Storage> {
+    let client = config.http_client()?;
+    let user_id = storage.data().profile.id;
+    msg("Refreshing the profile", &sender).await;
+    config.rate_limiter().ready().await;
+    let profile = user::show(user_id, &config.token)
+        .await
+        .map_err(CrawlError::from)?
+        .response;
+    let changed = changed_profile_media(&storage.data().profile, &profile);
+
+    let shared_storage = Arc::new(Mutex::new(storage));
+    shared_storage.lock().await.with_data(|data| {
+        data.profile = profile.clone();
+        data.insert_profile(profile.clone());
+    });
+    if changed.is_empty() {
+        let storage = shared_storage.lock_owned().await.clone();
+        return Ok(storage);
     }
 
-    // If we're not crawling for the authenticated user
-    // we can't crawl mentions
-    if config.crawl_options().mentions {
-        if config.user_id() != user_id {
-            info!("Can't crawl mentions for custom-user");
-        } else {
-            fetch_user_mentions(
-                shared_storage.clone(),
-                config,
-                instruction_sender.clone(),
-                sender.clone(),
-            )
+    let (instruction_task, instruction_sender) = create_instruction_handler(
+        config.crawl_options().media_filter(),
+        config.retry_policy().clone(),
+        config.bandwidth_limiter(),
+        config.config_data.media_naming,
+        config.media_domains(),
+        config.config_data.max_media_bytes,
+        client,
+        shared_storage.clone(),
+    );
+    for url in changed {
+        instruction_sender
+            .send(DownloadInstruction::ProfileMedia(url))
             .await?;
-            save_data(&shared_storage).await;
-        }
     }
+    instruction_sender.send(DownloadInstruction::Done).await?;
+    instruction_task.await?;
 
-    if config.crawl_options().likes {
-        fetch_user_likes(
-            user_id,
-            shared_storage.clone(),
-            config,
-            instruction_sender.clone(),
-            sender.clone(),
-        )
-        .await?;
-        save_data(&shared_storage).await;
-    }
+    let storage = shared_storage.lock_owned().await.clone();
+    Ok(storage)
+}
 
-    if config.crawl_options().followers {
-        fetch_user_followers(
-            user_id,
-            shared_storage.clone(),
-            config,
-            instruction_sender.clone(),
-            sender.clone(),
-        )
-        .await?;
-        save_data(&shared_storage).await;
-    }
+/// The avatar, banner and background urls of `new` that `old` doesn't have
+fn changed_profile_media(old: &TwitterUser, new: &TwitterUser) -> Vec<String> {
+    let urls = |profile: &TwitterUser| {
+        let mut urls = vec![profile.profile_image_url_https.clone()];
+        urls.extend(profile.profile_banner_url.clone());
+        urls.extend(profile.profile_background_image_url_https.clone());
+        urls
+    };
+    let known = urls(old);
+    urls(new)
+        .into_iter()
+        .filter(|url| !known.contains(url))
+        .collect()
+}
 
-    if config.crawl_options().follows {
-        fetch_user_follows(
-            user_id,
-            shared_storage.clone(),
-            config,
-            instruction_sender.clone(),
-            sender.clone(),
-        )
-        .await?;
-        save_data(&shared_storage).await;
+/// Download the stored profiles again that were last downloaded longer than
+/// `older_than` ago, so that bios and avatars stay current. Only the profile
+/// media whose url changed is downloaded. Profiles that are not returned
+/// anymore are kept and marked as unavailable, and aren't refreshed again
+pub async fn refresh_stale_profiles(
+    config: &Config,
+    storage: Storage,
+    older_than: chrono::Duration,
+    sender: Sender<Message>,
+) -> Result<Storage> {
+    let client = config.http_client()?;
+    let ids: Vec<u64> = storage
+        .data()
+        .stale_profile_ids(older_than, chrono::Utc::now())
+        .into_iter()
+        .filter(|id| !config.is_skipped(*id, None))
+        .collect();
+    let shared_storage = Arc::new(Mutex::new(storage));
+    let (instruction_task, instruction_sender) = create_instruction_handler(
+        config.crawl_options().media_filter(),
+        config.retry_policy().clone(),
+        config.bandwidth_limiter(),
+        config.config_data.media_naming,
+        config.media_domains(),
+        config.config_data.max_media_bytes,
+        client,
+        shared_storage.clone(),
+    );
+
+    let label = "Stale Profiles";
+    start_stage(label, &sender).await;
+    let mut processed = 0;
+    for chunk in ids.chunks(PROFILES_PER_LOOKUP) {
+        let profiles = loop {
+            config.rate_limiter().ready().await;
+            match user::lookup(chunk.to_vec(), &config.token).await {
+                Ok(profiles) => break profiles.response,
+                Err(egg_mode::error::Error::RateLimit(limit)) => {
+                    wait_for_rate_limit(config.rate_limiter(), label, reset_time(limit), &sender)
+                        .await;
+                }
+                // If none of the users is available, the lookup fails
+                Err(e) if unavailable_reason(&e).is_some() => break Vec::new(),
+                Err(e) => return Err(CrawlError::from(e).into()),
+            }
+        };
+        let mut changed = Vec::new();
+        {
+            let mut storage = shared_storage.lock().await;
+            let data = storage.data_mut();
+            for profile in profiles.iter() {
+                if let Some(old) = data.profiles.get(&profile.id) {
+                    changed.extend(changed_profile_media(old, profile));
+                }
+                if data.profile.id == profile.id {
+                    data.profile = profile.clone();
+                }
+                data.unavailable_profiles.remove(&profile.id);
+                data.insert_profile(profile.clone());
+            }
+            for id in chunk.iter().filter(|id| !profiles.iter().any(|p| p.id == **id)) {
+                data.unavailable_profiles.insert(
+                    *id,
+                    "Not returned by the lookup (suspended, deleted or protected)".to_string(),
+                );
+            }
+        }
+        for url in changed {
+            instruction_sender
+                .send(DownloadInstruction::ProfileMedia(url))
+                .await?;
+        }
+        processed += chunk.len();
+        msg(format!("{label}: {processed} / {}", ids.len()), &sender).await;
     }
 
-    if config.crawl_options().lists {
-        fetch_lists(
-            user_id,
+    instruction_sender.send(DownloadInstruction::Done).await?;
+    instruction_task.await?;
+
+    let storage = shared_storage.lock_owned().await.clone();
+    Ok(storage)
+}
+
+/// Download the profiles of authors and mentioned users of archived tweets
+/// that are missing in the archive, e.g. after a partial crawl. Profiles
+/// that are known to be unavailable are not looked up again
+pub async fn backfill_profiles(
+    config: &Config,
+    storage: Storage,
+    sender: Sender<Message>,
+) -> Result<Storage> {
+    let client = config.http_client()?;
+    let ids = storage.data().missing_profile_ids();
+    let shared_storage = Arc::new(Mutex::new(storage));
+    let (instruction_task, instruction_sender) = create_instruction_handler(
+        config.crawl_options().media_filter(),
+        config.retry_policy().clone(),
+        config.bandwidth_limiter(),
+        config.config_data.media_naming,
+        config.media_domains(),
+        config.config_data.max_media_bytes,
+        client,
+        shared_storage.clone(),
+    );
+
+    let label = "Missing Profiles";
+    start_stage(label, &sender).await;
+    let mut processed = 0;
+    for chunk in ids.chunks(profiles_batch_size(config)) {
+        fetch_multiple_profiles_data(
+            chunk,
             shared_storage.clone(),
             config,
             instruction_sender.clone(),
-            sender.clone(),
+            &sender,
         )
         .await?;
-        save_data(&shared_storage).await;
+        processed += chunk.len();
+        msg(format!("{label}: {processed} / {}", ids.len()), &sender).await;
     }
 
-    sender
-        .send(Message::Loading("Downloading Media".to_string()))
-        .await
-        .unwrap();
-
     instruction_sender.send(DownloadInstruction::Done).await?;
     instruction_task.await?;
 
     let storage = shared_storage.lock_owned().await.clone();
-    sender.send(Message::Finished(storage)).await?;
+    Ok(storage)
+}
 
-    Ok(())
+/// Followers and follows of a protected account are only visible to the
+/// account itself and its followers
+async fn graph_hidden(user_id: u64, storage: &Arc<Mutex<Storage>>, config: &Config) -> bool {
+    let is_protected = storage
+        .lock()
+        .await
+        .data()
+        .profiles
+        .get(&user_id)
+        .map(|profile| profile.protected)
+        .unwrap_or(false);
+    user_id != config.user_id() && is_protected && !config.follows(user_id).await
 }
 
-async fn fetch_user_tweets(
-    id: u64,
-    shared_storage: Arc<Mutex<Storage>>,
-    config: &Config,
-    sender: Sender<DownloadInstruction>,
-    message_sender: Sender<Message>,
-) -> Result<()> {
-    let label = "User Tweets";
-    msg(label, &message_sender).await;
-    let mut timeline = tweet::user_timeline(id, true, true, &config.token).with_page_size(200);
+/// Announce the start of a crawl stage in the UI and the log
+async fn start_stage(label: &'static str, sender: &Sender<Message>) {
+    info!(stage = label, "Stage started");
+    msg(label, sender).await;
+}
 
-    let mut first_page = config.paging_position("user_tweets");
+async fn msg(msg: impl AsRef<str>, sender: &Sender<Message>) {
+    if let Err(e) = sender
+        .send(Message::Loading(msg.as_ref().to_string()))
+        .await
+    {
+        warn!("Could not send message: {}: {e:?}", msg.as_ref());
+    }
+}
 
-    let first_id = shared_storage.lock().await.data().tweets.first().cloned();
-    let is_sync = config.is_sync;
+/// Serializing a large archive takes a while, so it is only saved if
+/// `save_interval_secs` passed or `save_every_items` entries were added
+/// since the last save. Stages are only marked as finished once their data
+/// is on disk, so that a resumed crawl doesn't skip unsaved data
+struct SaveThrottle {
+    /// The crawled user, whose finished stages are recorded
+    user_id: u64,
+    interval: std::time::Duration,
+    items: usize,
+    /// Written into the media index before every save
+    pending_media: PendingMedia,
+    state: std::sync::Mutex<SaveState>,
+}
 
-    let mut collected = Vec::new();
+struct SaveState {
+    last_save: std::time::Instant,
+    last_count: usize,
+    pending_stages: Vec<&'static str>,
+}
 
-    'outer: loop {
-        tracing::info!("Downloading Tweets before {:?}", timeline.min_id);
-        let (next_timeline, mut feed) = timeline.older(first_page).await?;
-        first_page = None;
-        if feed.response.is_empty() {
-            break;
-        }
-        for tweet in feed.response.iter() {
-            // In this case, we know the tweet and we stop loading further
-            if is_sync && Some(tweet.id) == first_id.as_ref().map(|e| e.id) {
-                break 'outer;
-            }
-            inspect_tweet(
-                tweet,
-                shared_storage.clone(),
-                config,
-                &sender,
-                &message_sender,
-            )
-            .await?;
+impl SaveThrottle {
+    fn new(
+        user_id: u64,
+        options: &crate::config::CrawlOptions,
+        pending_media: PendingMedia,
+    ) -> Self {
+        Self {
+            user_id,
+            interval: std::time::Duration::from_secs(options.save_interval_secs()),
+            items: options.save_every_items(),
+            pending_media,
+            state: std::sync::Mutex::new(SaveState {
+                last_save: std::time::Instant::now(),
+                last_count: 0,
+                pending_stages: Vec::new(),
+            }),
         }
-        collected.append(&mut feed.response);
-
-        handle_rate_limit(&feed.rate_limit_status, "User Feed", message_sender.clone()).await;
-        timeline = next_timeline;
-        config.set_paging_position("user_tweets", timeline.min_id);
-
-        msg(format!("{label}: {}", collected.len()), &message_sender).await;
     }
 
-    let mut s = shared_storage.lock().await;
-    if is_sync {
-        s.data_mut().tweets.splice(0..0, collected);
-    } else {
-        s.data_mut().tweets.append(&mut collected);
+    fn should_save(&self, state: &SaveState, count: usize, now: std::time::Instant) -> bool {
+        now.duration_since(state.last_save) >= self.interval
+            || count.saturating_sub(state.last_count) >= self.items
     }
 
-    config.set_paging_position("user_tweets", None);
+    async fn finish_stage(&self, stage: &'static str, storage: &Arc<Mutex<Storage>>, config: &Config) {
+        if let Ok(mut state) = self.state.lock() {
+            state.pending_stages.push(stage);
+        }
+        self.save(storage, config, false).await;
+    }
 
-    Ok(())
+    /// Save if the throttle allows it or if `force` is set
+    async fn save(&self, storage: &Arc<Mutex<Storage>>, config: &Config, force: bool) {
+        let mut storage = storage.lock().await;
+        self.pending_media.flush_into(storage.data_mut());
+        let data = storage.data();
+        let count = data.tweets.len()
+            + data.mentions.len()
+            + data.likes.len()
+            + data.profiles.len()
+            + data.media.len()
+            + data.followers.len()
+            + data.follows.len();
+        let Ok(mut state) = self.state.lock() else { return };
+        info!(
+            tweets = data.tweets.len(),
+            mentions = data.mentions.len(),
+            likes = data.likes.len(),
+            profiles = data.profiles.len(),
+            media = data.media.len(),
+            "Stage finished"
+        );
+        let now = std::time::Instant::now();
+        if !force && !self.should_save(&state, count, now) {
+            return;
+        }
+        if let Err(e) = storage.save() {
+            warn!("Could not write out data {e:?}");
+            return;
+        }
+        state.last_save = now;
+        state.last_count = count;
+        for stage in state.pending_stages.drain(..) {
+            config.set_stage_finished(self.user_id, stage);
+        }
+    }
 }
 
-async fn fetch_user_mentions(
-    shared_storage: Arc<Mutex<Storage>>,
+/// The stages that only work with a user login. Everything else works with
+/// an app-only token. Bookmarks and direct messages aren't crawled yet, but
+/// are skipped the same way once they are
+const LOGIN_STAGES: [&str; 3] = ["mentions", "bookmarks", "direct_messages"];
+
+/// Stages that finished during an interrupted previous crawl are skipped,
+/// as are all stages after the crawl was cancelled and the ones that need a
+/// login with an app-only token
+async fn should_run(stage: &str, user_id: u64, config: &Config, sender: &Sender<Message>) -> bool {
+    if config.is_cancelled() {
+        return false;
+    }
+    if config.is_app_only() && LOGIN_STAGES.contains(&stage) {
+        info!(stage, "Stage needs a login");
+        msg(format!("{stage} need a login, skipping them"), sender).await;
+        return false;
+    }
+    let Some(finished) = config.stage_finished_at(user_id, stage) else {
+        return true
+    };
+    info!(stage, "Stage skipped");
+    msg(
+        format!(
+            "Resuming: {stage} already finished at {}",
+            finished.with_timezone(&chrono::Local).format("%H:%M")
+        ),
+        sender,
+    )
+    .await;
+    false
+}
+
+/// Warns if the media of the crawl probably doesn't fit on the disk of the
+/// archive, so that the crawl doesn't fail halfway through with a full disk.
+/// `available_space` is `fs2::available_space` outside of tests
+async fn check_disk_space(
+    user_id: u64,
+    storage: &Arc<Mutex<Storage>>,
     config: &Config,
-    sender: Sender<DownloadInstruction>,
-    message_sender: Sender<Message>,
-) -> Result<()> {
-    let label = "User Mentions";
-    msg(label, &message_sender).await;
-    let mut timeline = tweet::mentions_timeline(&config.token).with_page_size(200);
+    sender: &Sender<Message>,
+    available_space: impl FnOnce(&Path) -> std::io::Result<u64>,
+) {
+    let (required, root_folder) = {
+        let storage = storage.lock().await;
+        let required = required_media_bytes(storage.data(), user_id, config.crawl_options());
+        (required, storage.root_folder.clone())
+    };
+    let available = match available_space(&root_folder) {
+        Ok(available) => available,
+        Err(e) => {
+            warn!("Could not determine the free disk space: {e:?}");
+            return;
+        }
+    };
+    if let Some(warning) = disk_space_warning(required, available, config.crawl_options()) {
+        warn!(required, available, "Low disk space");
+        if let Err(e) = sender.send(Message::Warning(warning)).await {
+            warn!("Could not send warning {e:?}");
+        }
+    }
+}
 
-    let mut first_page = config.paging_position("user_mentions");
+/// Without archived tweets to go by, about one in five tweets has media
+const DEFAULT_MEDIA_TWEET_SHARE: f64 = 0.2;
 
-    let first_id = shared_storage.lock().await.data().mentions.first().cloned();
-    let is_sync = config.is_sync;
+/// The expected size of the media of the tweets and likes that aren't
+/// archived yet. The share of them with media is taken from the archived
+/// ones, each needs about `media_bytes_per_media_tweet`
+fn required_media_bytes(data: &Data, user_id: u64, options: &crate::config::CrawlOptions) -> u64 {
+    let Some(profile) = data.profiles.get(&user_id) else { return 0 };
+    let media_tweets = |total: i32, archived: Vec<&Tweet>| {
+        let missing = (total.max(0) as u64)
+            .min(crate::estimate::MAX_TIMELINE_TWEETS)
+            .saturating_sub(archived.len() as u64);
+        let with_media = archived
+            .iter()
+            .filter(|tweet| !crate::helpers::media_urls_in_tweet(tweet).is_empty())
+            .count();
+        let share = match archived.len() {
+            0 => DEFAULT_MEDIA_TWEET_SHARE,
+            archived => with_media as f64 / archived as f64,
+        };
+        (missing as f64 * share).ceil() as u64
+    };
+    let mut tweets = 0;
+    if options.tweets {
+        let timeline = data
+            .tweets
+            .iter()
+            .filter(|tweet| !data.inner_tweets.contains(&tweet.id))
+            .collect();
+        tweets += media_tweets(profile.statuses_count, timeline);
+    }
+    if options.likes {
+        tweets += media_tweets(profile.favourites_count, data.likes.iter().collect());
+    }
+    tweets * options.media_bytes_per_media_tweet()
+}
 
-    let mut collected = Vec::new();
+/// The warning for the user if less than `min_free_space_mb` would be left
+fn disk_space_warning(
+    required: u64,
+    available: u64,
+    options: &crate::config::CrawlOptions,
+) -> Option<String> {
+    const MB: u64 = 1024 * 1024;
+    let needed = required.saturating_add(options.min_free_space_mb() * MB);
+    (available < needed).then(|| {
+        format!(
+            "The media of this crawl may need about {} MB, but only {} MB are free in the archive folder",
+            required / MB,
+            available / MB
+        )
+    })
+}
 
-    'outer: loop {
-        tracing::info!("Downloading Mentions before {:?}", timeline.min_id);
-        let (next_timeline, mut feed) = timeline.older(first_page).await?;
-        first_page = None;
-        if feed.response.is_empty() {
-            break;
+/// Marks a successful stage as finished. A failed stage is reported and the
+/// crawl continues with the next one, unless the error is fatal
+async fn end_stage(
+    stage: &'static str,
+    result: CrawlResult<()>,
+    saver: &SaveThrottle,
+    storage: &Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: &Sender<Message>,
+) -> CrawlResult<()> {
+    match result {
+        Ok(()) => {
+            saver.finish_stage(stage, storage, config).await;
+            Ok(())
         }
-        for tweet in feed.response.iter() {
-            // In this case, we know the tweet and we stop loading further
-            if is_sync && Some(tweet.id) == first_id.as_ref().map(|e| e.id) {
-                break 'outer;
-            }
-            inspect_tweet(
-                tweet,
+        Err(e) => report_stage_failure(stage, e, config, sender).await,
+    }
+}
+
+/// A rejected login, a failing disk or a cancel end the crawl, every later
+/// stage would fail in the same way. Other errors only end the stage
+async fn report_stage_failure(
+    stage: &'static str,
+    error: CrawlError,
+    config: &Config,
+    sender: &Sender<Message>,
+) -> CrawlResult<()> {
+    if matches!(
+        error,
+        CrawlError::Auth(_) | CrawlError::Io(_) | CrawlError::Cancelled
+    ) {
+        return Err(error);
+    }
+    warn!(stage, error = %error, "Stage failed");
+    config.record_stage_failure(stage, &error.to_string());
+    let failed = Message::StageFailed {
+        stage: stage.to_string(),
+        error: error.to_string(),
+    };
+    if let Err(e) = sender.send(failed).await {
+        warn!("Could not send stage failure {e:?}");
+    }
+    Ok(())
+}
+
+/// Saves the archive and ends the crawl with `CrawlError::Cancelled` after
+/// `Config::cancel`. The paging positions are kept, so that the next crawl
+/// resumes where this one stopped
+async fn stop_if_cancelled(
+    config: &Config,
+    storage: &Arc<Mutex<Storage>>,
+    pending_media: &PendingMedia,
+) -> CrawlResult<()> {
+    if !config.is_cancelled() {
+        return Ok(());
+    }
+    info!("Crawl cancelled, saving the archive");
+    let mut storage = storage.lock().await;
+    // Otherwise the downloaded files are not in the saved media index
+    pending_media.flush_into(storage.data_mut());
+    storage.save()?;
+    Err(CrawlError::Cancelled)
+}
+
+/// Wait before the next attempt, or give up with the error once the
+/// attempts of the retry policy are used up
+async fn retry(policy: &RetryPolicy, failures: &mut u32, error: CrawlError) -> CrawlResult<()> {
+    retry_after(policy, failures, error, None).await
+}
+
+/// Like `retry`, but waits for `delay` instead of the backoff of the
+/// policy if the server said how long to wait. The delay is capped by
+/// the `max_delay` of the policy, so that a server can't stall the crawl
+async fn retry_after(
+    policy: &RetryPolicy,
+    failures: &mut u32,
+    error: CrawlError,
+    delay: Option<std::time::Duration>,
+) -> CrawlResult<()> {
+    *failures += 1;
+    if *failures >= policy.max_attempts {
+        return Err(error);
+    }
+    let delay = match delay {
+        Some(delay) => policy.capped(delay),
+        None => policy.delay(*failures),
+    };
+    trace!(seconds = delay.as_secs(), "Retrying");
+    tokio::time::sleep(delay).await;
+    Ok(())
+}
+
+/// A `Retry-After` header is either a number of seconds or an HTTP date.
+/// Dates in the past mean no delay
+fn parse_retry_after(
+    value: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<std::time::Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(std::time::Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let seconds = (date.with_timezone(&chrono::Utc) - now)
+        .num_seconds()
+        .max(0);
+    Some(std::time::Duration::from_secs(seconds as u64))
+}
+
+async fn fetch(
+    user_id: u64,
+    config: &Config,
+    storage: Storage,
+    sender: Sender<Message>,
+) -> CrawlResult<()> {
+    let started = std::time::Instant::now();
+    config.clear_failed_stages();
+    // Fail before crawling if the proxy is invalid or the token was revoked
+    let client = config.http_client()?;
+    let verified = verify_token(config).await?;
+    let shared_storage = Arc::new(Mutex::new(storage));
+    if let Some(user) = verified {
+        msg(format!("Logged in as @{}", user.screen_name), &sender).await;
+        // The profile of the login is part of the verification
+        if user.id == user_id {
+            let mut storage = shared_storage.lock().await;
+            storage.data_mut().profile = user.clone();
+            storage.data_mut().insert_profile(user);
+        }
+    }
+
+    // Otherwise every single download fails, e.g. on a full or read-only drive
+    let mut media_filter = config.crawl_options().media_filter();
+    if config.crawl_options().media {
+        if let Err(e) = shared_storage.lock().await.probe_media_folder() {
+            warn!(error = %e, "Media folder is not writable");
+            msg(
+                format!("The media folder is not writable, no media will be downloaded: {e}"),
+                &sender,
+            )
+            .await;
+            media_filter = MediaFilter::none();
+        }
+    }
+
+    let (instruction_task, instruction_sender) = create_instruction_handler(
+        media_filter,
+        config.retry_policy().clone(),
+        config.bandwidth_limiter(),
+        config.config_data.media_naming,
+        config.media_domains(),
+        config.config_data.max_media_bytes,
+        client,
+        shared_storage.clone(),
+    );
+    let saver = SaveThrottle::new(
+        user_id,
+        config.crawl_options(),
+        instruction_sender.pending_media().clone(),
+    );
+
+    fetch_single_profile(
+        user_id,
+        shared_storage.clone(),
+        config,
+        instruction_sender.clone(),
+    )
+    .await?;
+
+    // Profile media is small, the tweets' images and videos fill the disk
+    if media_filter.images || media_filter.videos {
+        check_disk_space(user_id, &shared_storage, config, &sender, |path| {
+            fs2::available_space(path)
+        })
+        .await;
+    }
+
+    // Other accounts are archived with the login of the config. Only their
+    // public data can be crawled
+    let is_self = user_id == config.user_id();
+    let graph_hidden = graph_hidden(user_id, &shared_storage, config).await;
+
+    if config.crawl_options().tweets && should_run("tweets", user_id, config, &sender).await {
+        let result = fetch_user_tweets(
+            user_id,
+            shared_storage.clone(),
+            config,
+            instruction_sender.clone(),
+            sender.clone(),
+        )
+        .await;
+        end_stage("tweets", result, &saver, &shared_storage, config, &sender).await?;
+    }
+
+    // If we're not crawling for the authenticated user
+    // we can't crawl mentions
+    if config.crawl_options().mentions && should_run("mentions", user_id, config, &sender).await {
+        if !is_self {
+            msg("Mentions are only crawled for your own account, skipping them", &sender).await;
+        } else {
+            let result = fetch_user_mentions(
                 shared_storage.clone(),
                 config,
-                &sender,
-                &message_sender,
+                instruction_sender.clone(),
+                sender.clone(),
             )
-            .await?;
+            .await;
+            end_stage("mentions", result, &saver, &shared_storage, config, &sender).await?;
         }
-        collected.append(&mut feed.response);
+    }
 
-        handle_rate_limit(
-            &feed.rate_limit_status,
-            "User Mentions",
-            message_sender.clone(),
+    if config.crawl_options().likes && !is_self {
+        msg("Likes are only crawled for your own account, skipping them", &sender).await;
+    } else if config.crawl_options().likes && should_run("likes", user_id, config, &sender).await {
+        let result = fetch_user_likes(
+            user_id,
+            shared_storage.clone(),
+            config,
+            instruction_sender.clone(),
+            sender.clone(),
         )
         .await;
-        timeline = next_timeline;
-        config.set_paging_position("user_mentions", timeline.min_id);
+        end_stage("likes", result, &saver, &shared_storage, config, &sender).await?;
+    }
 
-        msg(format!("{label}: {}", collected.len()), &message_sender).await;
+    if config.crawl_options().followers && graph_hidden {
+        msg(
+            "The account is protected and you don't follow it, skipping its followers",
+            &sender,
+        )
+        .await;
+    } else if config.crawl_options().followers && should_run("followers", user_id, config, &sender).await {
+        let result = fetch_user_followers(
+            user_id,
+            shared_storage.clone(),
+            config,
+            instruction_sender.clone(),
+            sender.clone(),
+        )
+        .await;
+        end_stage("followers", result, &saver, &shared_storage, config, &sender).await?;
     }
 
-    let mut s = shared_storage.lock().await;
-    if is_sync {
-        s.data_mut().mentions.splice(0..0, collected);
-    } else {
-        s.data_mut().mentions.append(&mut collected);
+    if config.crawl_options().follows && graph_hidden {
+        msg(
+            "The account is protected and you don't follow it, skipping its follows",
+            &sender,
+        )
+        .await;
+    } else if config.crawl_options().follows && should_run("follows", user_id, config, &sender).await {
+        let result = fetch_user_follows(
+            user_id,
+            shared_storage.clone(),
+            config,
+            instruction_sender.clone(),
+            sender.clone(),
+        )
+        .await;
+        end_stage("follows", result, &saver, &shared_storage, config, &sender).await?;
     }
 
-    config.set_paging_position("user_mentions", None);
+    if !graph_hidden {
+        report_graph_counts(&shared_storage, config, &sender).await;
+    }
+
+    if config.crawl_options().lists && should_run("lists", user_id, config, &sender).await {
+        let result = fetch_lists(
+            user_id,
+            shared_storage.clone(),
+            config,
+            instruction_sender.clone(),
+            sender.clone(),
+        )
+        .await;
+        end_stage("lists", result, &saver, &shared_storage, config, &sender).await?;
+    }
+
+    if config.crawl_options().tweet_profiles
+        && should_run("mentioned_profiles", user_id, config, &sender).await
+    {
+        let result = fetch_mentioned_profiles(
+            shared_storage.clone(),
+            config,
+            instruction_sender.clone(),
+            sender.clone(),
+        )
+        .await;
+        end_stage("mentioned_profiles", result, &saver, &shared_storage, config, &sender).await?;
+    }
+
+    if config.crawl_options().metrics && should_run("metrics", user_id, config, &sender).await {
+        let result = fetch_tweet_metrics(shared_storage.clone(), config, sender.clone()).await;
+        end_stage("metrics", result, &saver, &shared_storage, config, &sender).await?;
+    }
+
+    let options = config.crawl_options();
+    if options.v2_lookup() && should_run("polls", user_id, config, &sender).await {
+        let result = fetch_tweet_polls(
+            shared_storage.clone(),
+            config,
+            instruction_sender.clone(),
+            sender.clone(),
+        )
+        .await;
+        end_stage("polls", result, &saver, &shared_storage, config, &sender).await?;
+    }
+
+    // Most archives have no Spaces, so this is usually a no-op
+    if should_run("spaces", user_id, config, &sender).await {
+        let result = fetch_space_metadata(shared_storage.clone(), config, sender.clone()).await;
+        end_stage("spaces", result, &saver, &shared_storage, config, &sender).await?;
+    }
+
+    if config.crawl_options().raw_json && should_run("raw_json", user_id, config, &sender).await {
+        let result = fetch_raw_json(shared_storage.clone(), config, sender.clone()).await;
+        end_stage("raw_json", result, &saver, &shared_storage, config, &sender).await?;
+    }
+
+    stop_if_cancelled(config, &shared_storage, instruction_sender.pending_media()).await?;
+
+    sender
+        .send(Message::Loading("Downloading Media".to_string()))
+        .await
+        .unwrap();
+
+    instruction_sender.send(DownloadInstruction::Done).await?;
+    instruction_task.await?;
+    if let Err(e) = shared_storage
+        .lock()
+        .await
+        .record_backup(config.crawl_options())
+    {
+        warn!("Could not record the followers of this crawl: {e:?}");
+    }
+    saver.save(&shared_storage, config, true).await;
+
+    let storage = shared_storage.lock_owned().await.clone();
+    let failed: Vec<String> = config
+        .failed_stages()
+        .into_iter()
+        .map(|(stage, _)| stage)
+        .collect();
+    if failed.is_empty() {
+        config.clear_finished_stages(user_id);
+    } else {
+        // The next crawl only repeats the stages that failed
+        msg(
+            format!("Finished, but these stages failed: {}", failed.join(", ")),
+            &sender,
+        )
+        .await;
+    }
+    crate::notify::crawl_finished(config, &storage, started.elapsed()).await;
+    sender.send(Message::Finished(storage)).await?;
 
     Ok(())
 }
 
-async fn fetch_user_likes(
+async fn fetch_user_tweets(
     id: u64,
     shared_storage: Arc<Mutex<Storage>>,
     config: &Config,
-    sender: Sender<DownloadInstruction>,
+    sender: InstructionSender,
     message_sender: Sender<Message>,
-) -> Result<()> {
-    let label = "User Likes";
-    msg(label, &message_sender).await;
-    let mut timeline = tweet::liked_by(id, &config.token).with_page_size(200);
+) -> CrawlResult<()> {
+    let label = "User Tweets";
+    start_stage(label, &message_sender).await;
+    let timeline = RawTimeline::user_tweets(id, config);
+    let mut raw = RawCapture::new(RAW_TWEETS, &shared_storage, config).await?;
 
-    let mut first_page = config.paging_position("user_likes");
+    let first_page = config.paging_position("user_tweets");
 
-    let first_id = shared_storage.lock().await.data().likes.first().cloned();
     let is_sync = config.is_sync;
 
+    // Walk the whole timeline in order to find deleted tweets. This is only
+    // meaningful if we didn't resume from a previous, partial crawl
+    let full_pass = is_sync && config.crawl_options().deleted_tweets;
+    let is_complete_pass = first_page.is_none();
+    let timeline_ids: HashSet<u64> = if is_sync {
+        // Quoted and retweeted tweets of the owner are read again, so that
+        // they count as timeline tweets
+        let s = shared_storage.lock().await;
+        let inner = &s.data().inner_tweets;
+        s.data()
+            .tweets
+            .iter()
+            .map(|t| t.id)
+            .filter(|id| !inner.contains(id))
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    // Otherwise a sync stops loading at the first archived tweet. A sync
+    // that resumes a cancelled one doesn't reach the newest tweets that one
+    // merged, but stops at the newest tweet of the sync before
+    let (known_ids, archived) = match full_pass {
+        true => (timeline_ids, HashSet::new()),
+        false => (HashSet::new(), timeline_ids),
+    };
+    let mut seen_ids = HashSet::new();
+
     let mut collected = Vec::new();
 
-    'outer: loop {
-        tracing::info!("Downloading Likes before {:?}", timeline.min_id);
-        let (next_timeline, mut feed) = timeline.older(first_page).await?;
-        first_page = None;
+    tracing::info!("Downloading Tweets before {:?}", timeline.min_id);
+    config.rate_limiter().ready().await;
+    let mut page = timeline.older(first_page).await?;
+
+    loop {
+        let (timeline, feed, raw_page) = page;
         if feed.response.is_empty() {
             break;
         }
-        for tweet in feed.response.iter() {
-            // In this case, we know the tweet and we stop loading further
-            if is_sync && Some(tweet.id) == first_id.as_ref().map(|e| e.id) {
-                break 'outer;
+        raw.capture(raw_page)?;
+        let (mut fresh, reached_archive) = new_tweets(&feed.response, &archived, &known_ids);
+        seen_ids.extend(feed.response.iter().map(|tweet| tweet.id));
+
+        handle_rate_limit(
+            config.rate_limiter(),
+            &feed.rate_limit_status,
+            "User Feed",
+            message_sender.clone(),
+        )
+        .await;
+
+        // The next page is downloaded while this one is inspected. The paging
+        // position only moves on once a page was inspected, so an interrupted
+        // crawl resumes with the first page that wasn't
+        let min_id = timeline.min_id;
+        let capped = config
+            .crawl_options()
+            .reached_max_items(collected.len() + fresh.len());
+        let prefetch = async {
+            if reached_archive || capped {
+                return None;
             }
-            inspect_tweet(
+            tracing::info!("Downloading Tweets before {:?}", min_id);
+            config.rate_limiter().ready().await;
+            Some(timeline.older(None).await)
+        };
+        let inspect = async {
+            for tweet in fresh.iter() {
+                inspect_tweet(
+                    tweet,
+                    shared_storage.clone(),
+                    config,
+                    &sender,
+                    &message_sender,
+                )
+                .await?;
+            }
+            CrawlResult::Ok(())
+        };
+        let (next_page, inspected) = tokio::join!(prefetch, inspect);
+        inspected?;
+        collected.append(&mut fresh);
+        if reached_archive {
+            break;
+        }
+
+        config.set_paging_position("user_tweets", min_id);
+
+        msg(format!("{label}: {}", collected.len()), &message_sender).await;
+
+        if capped {
+            info!("Stopping after {} tweets", collected.len());
+            break;
+        }
+        if config.is_cancelled() {
+            break;
+        }
+
+        match next_page {
+            Some(next_page) => page = next_page?,
+            None => break,
+        }
+    }
+
+    {
+        let mut s = shared_storage.lock().await;
+        s.data_mut().add_timeline_tweets(collected);
+        if full_pass && is_complete_pass {
+            record_deleted_tweets(s.data_mut(), &seen_ids);
+        }
+    }
+
+    stop_if_cancelled(config, &shared_storage, sender.pending_media()).await?;
+    config.set_paging_position("user_tweets", None);
+
+    Ok(())
+}
+
+/// The tweets of a timeline page that are new, in the order of the page.
+/// A sync stops at the first tweet in `archived`, which is the second
+/// value. Tweets in `known_ids` are archived already
+fn new_tweets(
+    page: &[Tweet],
+    archived: &HashSet<u64>,
+    known_ids: &HashSet<u64>,
+) -> (Vec<Tweet>, bool) {
+    let end = page.iter().position(|tweet| archived.contains(&tweet.id));
+    let fresh = page[..end.unwrap_or(page.len())]
+        .iter()
+        .filter(|tweet| !known_ids.contains(&tweet.id))
+        .cloned()
+        .collect();
+    (fresh, end.is_some())
+}
+
+/// Stored tweets that didn't show up in a complete pass over the timeline
+/// were deleted. The API only returns the most recent tweets, so only
+/// tweets newer than the oldest returned tweet are considered.
+fn record_deleted_tweets(data: &mut Data, seen_ids: &HashSet<u64>) {
+    let Some(oldest_seen) = seen_ids.iter().min().copied() else {
+        return;
+    };
+    let already_deleted: HashSet<u64> = data.deleted_tweets.iter().map(|(id, _)| *id).collect();
+    let now = chrono::Utc::now();
+    let deleted: Vec<_> = data
+        .tweets
+        .iter()
+        .map(|t| t.id)
+        .filter(|id| *id >= oldest_seen && !seen_ids.contains(id) && !already_deleted.contains(id))
+        .filter(|id| !data.inner_tweets.contains(id))
+        .map(|id| (id, now))
+        .collect();
+    if !deleted.is_empty() {
+        info!("Found {} deleted tweets", deleted.len());
+    }
+    data.deleted_tweets.extend(deleted);
+}
+
+async fn fetch_user_mentions(
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    let label = "User Mentions";
+    start_stage(label, &message_sender).await;
+    let mut timeline = RawTimeline::mentions(config);
+    let mut raw = RawCapture::new(RAW_TWEETS, &shared_storage, config).await?;
+
+    let mut first_page = config.paging_position("user_mentions");
+
+    let first_id = shared_storage.lock().await.data().mentions.first().cloned();
+    let is_sync = config.is_sync;
+
+    let mut collected = Vec::new();
+
+    'outer: loop {
+        tracing::info!("Downloading Mentions before {:?}", timeline.min_id);
+        config.rate_limiter().ready().await;
+        let (next_timeline, mut feed, raw_page) = timeline.older(first_page).await?;
+        first_page = None;
+        if feed.response.is_empty() {
+            break;
+        }
+        raw.capture(raw_page)?;
+        for tweet in feed.response.iter() {
+            // In this case, we know the tweet and we stop loading further
+            if is_sync && Some(tweet.id) == first_id.as_ref().map(|e| e.id) {
+                break 'outer;
+            }
+            inspect_tweet(
+                tweet,
+                shared_storage.clone(),
+                config,
+                &sender,
+                &message_sender,
+            )
+            .await?;
+        }
+        collected.append(&mut feed.response);
+
+        handle_rate_limit(
+            config.rate_limiter(),
+            &feed.rate_limit_status,
+            "User Mentions",
+            message_sender.clone(),
+        )
+        .await;
+        timeline = next_timeline;
+        config.set_paging_position("user_mentions", timeline.min_id);
+
+        msg(format!("{label}: {}", collected.len()), &message_sender).await;
+
+        if config.crawl_options().reached_max_items(collected.len()) {
+            info!("Stopping after {} mentions", collected.len());
+            break;
+        }
+        if config.is_cancelled() {
+            break;
+        }
+    }
+
+    {
+        let mut s = shared_storage.lock().await;
+        if is_sync {
+            s.data_mut().mentions.splice(0..0, collected);
+        } else {
+            s.data_mut().mentions.append(&mut collected);
+        }
+    }
+
+    stop_if_cancelled(config, &shared_storage, sender.pending_media()).await?;
+    config.set_paging_position("user_mentions", None);
+
+    Ok(())
+}
+
+async fn fetch_user_likes(
+    id: u64,
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    let label = "User Likes";
+    start_stage(label, &message_sender).await;
+    let mut timeline = RawTimeline::likes(id, config);
+    let mut raw = RawCapture::new(RAW_TWEETS, &shared_storage, config).await?;
+
+    let mut first_page = config.paging_position("user_likes");
+
+    let first_id = shared_storage.lock().await.data().likes.first().cloned();
+    let is_sync = config.is_sync;
+
+    let mut collected = Vec::new();
+
+    'outer: loop {
+        tracing::info!("Downloading Likes before {:?}", timeline.min_id);
+        config.rate_limiter().ready().await;
+        let (next_timeline, mut feed, raw_page) = timeline.older(first_page).await?;
+        first_page = None;
+        if feed.response.is_empty() {
+            break;
+        }
+        raw.capture(raw_page)?;
+        for tweet in feed.response.iter() {
+            // In this case, we know the tweet and we stop loading further
+            if is_sync && Some(tweet.id) == first_id.as_ref().map(|e| e.id) {
+                break 'outer;
+            }
+            inspect_tweet(
                 tweet,
                 shared_storage.clone(),
                 config,
@@ -389,6 +1537,7 @@ async fn fetch_user_likes(
         collected.append(&mut feed.response);
 
         handle_rate_limit(
+            config.rate_limiter(),
             &feed.rate_limit_status,
             "User Likes",
             message_sender.clone(),
@@ -397,596 +1546,3892 @@ async fn fetch_user_likes(
         timeline = next_timeline;
         config.set_paging_position("user_likes", timeline.min_id);
 
-        msg(format!("{label}: {}", collected.len()), &message_sender).await;
+        msg(format!("{label}: {}", collected.len()), &message_sender).await;
+
+        if config.crawl_options().reached_max_items(collected.len()) {
+            info!("Stopping after {} likes", collected.len());
+            break;
+        }
+        if config.is_cancelled() {
+            break;
+        }
+    }
+
+    {
+        let mut s = shared_storage.lock().await;
+        if is_sync {
+            s.data_mut().likes.splice(0..0, collected);
+        } else {
+            s.data_mut().likes.append(&mut collected);
+        }
+    }
+
+    stop_if_cancelled(config, &shared_storage, sender.pending_media()).await?;
+    config.set_paging_position("user_likes", None);
+
+    Ok(())
+}
+
+async fn fetch_user_followers(
+    id: u64,
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    let followers = { shared_storage.lock().await.data().followers.clone() };
+    let ids = fetch_profiles_ids(
+        "Followers",
+        user::followers_ids(id, &config.token)
+            .with_page_size(config.crawl_options().profiles_page_size()),
+        shared_storage.clone(),
+        config,
+        sender.clone(),
+        followers,
+        message_sender.clone(),
+    )
+    .await?;
+    shared_storage.lock().await.data_mut().followers = ids;
+    stop_if_cancelled(config, &shared_storage, sender.pending_media()).await
+}
+
+async fn fetch_user_follows(
+    id: u64,
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    let follows = { shared_storage.lock().await.data().follows.clone() };
+    let ids = fetch_profiles_ids(
+        "Follows",
+        user::friends_ids(id, &config.token)
+            .with_page_size(config.crawl_options().profiles_page_size()),
+        shared_storage.clone(),
+        config,
+        sender.clone(),
+        follows,
+        message_sender.clone(),
+    )
+    .await?;
+    shared_storage.lock().await.data_mut().follows = ids;
+    stop_if_cancelled(config, &shared_storage, sender.pending_media()).await
+}
+
+/// Re-fetch the already archived tweets in order to record
+/// a snapshot of their current engagement numbers
+async fn fetch_tweet_metrics(
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    let label = "Tweet Metrics";
+    start_stage(label, &message_sender).await;
+    let ids: Vec<u64> = {
+        let storage = shared_storage.lock().await;
+        let data = storage.data();
+        data.tweets
+            .iter()
+            .map(|t| t.id)
+            .filter(|id| !data.inner_tweets.contains(id))
+            .collect()
+    };
+
+    let mut processed = 0;
+    for chunk in ids.chunks(100) {
+        let resp = loop {
+            config.rate_limiter().ready().await;
+            match tweet::lookup(chunk.to_vec(), &config.token).await {
+                Ok(n) => break n,
+                Err(egg_mode::error::Error::RateLimit(limit)) => {
+                    wait_for_rate_limit(
+                        config.rate_limiter(),
+                        "Tweet Metrics",
+                        reset_time(limit),
+                        &message_sender,
+                    )
+                    .await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        let timestamp = chrono::Utc::now().timestamp();
+        shared_storage.lock().await.with_data(|data| {
+            for tweet in resp.response.iter() {
+                let reply_count = data.responses.get(&tweet.id).map(|e| e.len());
+                data.metrics.entry(tweet.id).or_default().push(Snapshot {
+                    timestamp,
+                    favorite_count: tweet.favorite_count,
+                    retweet_count: tweet.retweet_count,
+                    reply_count,
+                });
+            }
+        });
+
+        processed += chunk.len();
+        msg(format!("{label}: {processed} / {}", ids.len()), &message_sender).await;
+        handle_rate_limit(
+            config.rate_limiter(),
+            &resp.rate_limit_status,
+            label,
+            message_sender.clone(),
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// The v1.1 API doesn't return polls, conversation ids or link previews, so
+/// the archived tweets are looked up again via the v2 API. Every looked up
+/// tweet gets a conversation id, so only tweets with an open poll are
+/// looked up again. Tweets with links are looked up once for their link
+/// preview
+async fn fetch_tweet_polls(
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    let label = "Tweet Polls";
+    start_stage(label, &message_sender).await;
+    let options = config.crawl_options();
+    let mut card_ids = HashSet::new();
+    let ids: Vec<u64> = {
+        let storage = shared_storage.lock().await;
+        let data = storage.data();
+        let mut ids: Vec<u64> = data
+            .tweets
+            .iter()
+            .chain(data.mentions.iter())
+            .chain(data.likes.iter())
+            .filter(|tweet| {
+                let id = &tweet.id;
+                let looked_up = data.conversation_ids.contains_key(id);
+                let poll_open = data.polls.get(id).map(|p| !p.is_closed()).unwrap_or(false);
+                let needs_poll = options.polls && (!looked_up || poll_open);
+                let needs_conversation = options.threads && !looked_up;
+                let needs_card = options.link_previews && data.needs_card(tweet);
+                if needs_card {
+                    card_ids.insert(*id);
+                }
+                // Tweets can only be edited shortly after posting, so one
+                // lookup is enough
+                let needs_edits = options.tweet_edits && data.needs_edit_history(*id);
+                needs_poll || needs_conversation || needs_card || needs_edits
+            })
+            .map(|t| t.id)
+            .collect();
+        // The quoted and retweeted tweets found by `inspect_inner_tweet`
+        if options.link_previews {
+            let pending: Vec<u64> = data
+                .card_lookups
+                .iter()
+                .filter(|id| card_ids.insert(**id))
+                .copied()
+                .collect();
+            ids.extend(pending);
+        }
+        ids
+    };
+
+    let mut processed = 0;
+    for chunk in ids.chunks(100) {
+        let joined: Vec<String> = chunk.iter().map(|id| id.to_string()).collect();
+        let params = egg_mode::raw::ParamList::new()
+            .add_param("ids", joined.join(","))
+            .add_param("expansions", "attachments.poll_ids")
+            .add_param("tweet.fields", "conversation_id,entities,edit_history_tweet_ids")
+            .add_param(
+                "poll.fields",
+                "duration_minutes,end_datetime,options,voting_status",
+            );
+        let resp = loop {
+            config.rate_limiter().ready().await;
+            let request = egg_mode::raw::request_get(
+                "https://api.twitter.com/2/tweets",
+                &config.token,
+                Some(&params),
+            );
+            match egg_mode::raw::response_json::<PollLookup>(request).await {
+                Ok(n) => break n,
+                Err(egg_mode::error::Error::RateLimit(limit)) => {
+                    wait_for_rate_limit(
+                        config.rate_limiter(),
+                        "Tweet Polls",
+                        reset_time(limit),
+                        &message_sender,
+                    )
+                    .await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        let polls = resp.response.polls();
+        let conversations = resp.response.conversations();
+        let cards = resp.response.cards();
+        let edit_histories = resp.response.edit_histories();
+        shared_storage.lock().await.with_data(|data| {
+            data.store_edit_histories(chunk, &edit_histories);
+            for (id, poll) in polls.iter() {
+                data.polls.insert(*id, poll.clone());
+            }
+            for (id, conversation_id) in conversations.iter() {
+                data.conversation_ids.insert(*id, *conversation_id);
+            }
+            let looked_up: Vec<u64> = chunk
+                .iter()
+                .copied()
+                .filter(|id| card_ids.contains(id))
+                .collect();
+            data.store_cards(&looked_up, &cards);
+        });
+        for image_url in cards.into_iter().filter_map(|(_, card)| card.image_url) {
+            if let Err(e) = sender.send(DownloadInstruction::Image(image_url)).await {
+                warn!("Send Error {e:?}");
+            }
+        }
+
+        processed += chunk.len();
+        msg(format!("{label}: {processed} / {}", ids.len()), &message_sender).await;
+        handle_rate_limit(
+            config.rate_limiter(),
+            &resp.rate_limit_status,
+            label,
+            message_sender.clone(),
+        )
+        .await;
+    }
+
+    if options.tweet_edits {
+        fetch_tweet_edits(&shared_storage, config, &sender, &message_sender).await?;
+    }
+
+    Ok(())
+}
+
+/// Looks up the earlier versions of edited tweets one by one. Versions that
+/// can't be found anymore are skipped
+async fn fetch_tweet_edits(
+    shared_storage: &Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: &InstructionSender,
+    message_sender: &Sender<Message>,
+) -> CrawlResult<()> {
+    let missing: Vec<(u64, Vec<u64>)> = {
+        let storage = shared_storage.lock().await;
+        let data = storage.data();
+        data.edit_history
+            .iter()
+            .filter_map(|(id, history)| {
+                let known = data.tweet_edits.get(id);
+                let missing: Vec<u64> = history
+                    .iter()
+                    .copied()
+                    .filter(|version| version != id)
+                    .filter(|version| !known.into_iter().flatten().any(|t| t.id == *version))
+                    .collect();
+                (!missing.is_empty()).then_some((*id, missing))
+            })
+            .collect()
+    };
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let label = "Tweet Edits";
+    let fetch_profile = config.crawl_options().tweet_profiles;
+    let total = missing.len();
+    for (processed, (id, versions)) in missing.into_iter().enumerate() {
+        stop_if_cancelled(config, shared_storage, sender.pending_media()).await?;
+        msg(format!("{label}: {processed} / {total}"), message_sender).await;
+        for version in versions {
+            let edit = loop {
+                config.rate_limiter().ready().await;
+                match tweet::show(version, &config.token).await {
+                    Ok(edit) => break Some(edit),
+                    Err(egg_mode::error::Error::RateLimit(limit)) => {
+                        wait_for_rate_limit(
+                            config.rate_limiter(),
+                            label,
+                            reset_time(limit),
+                            message_sender,
+                        )
+                        .await;
+                    }
+                    Err(e) => {
+                        warn!("Could not fetch the version {version} of {id}: {e:?}");
+                        break None;
+                    }
+                }
+            };
+            let Some(edit) = edit else { continue };
+            let edit = edit.response;
+            inspect_inner_tweet(&edit, fetch_profile, config, shared_storage, sender.clone())
+                .await?;
+            shared_storage.lock().await.with_data(|data| {
+                let versions = data.tweet_edits.entry(id).or_default();
+                versions.push(edit.clone());
+                versions.sort_by_key(|t| t.id);
+            });
+        }
+    }
+    msg(format!("{label}: {total} / {total}"), message_sender).await;
+    Ok(())
+}
+
+/// The v1.1 API only returns the link of a shared Space, so the title,
+/// hosts and state are looked up via the v2 API. Ended Spaces are not
+/// looked up again. As not all tokens may use this endpoint, failures
+/// only skip the metadata
+async fn fetch_space_metadata(
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    let ids: Vec<String> = {
+        let storage = shared_storage.lock().await;
+        let ids: HashSet<String> = storage
+            .data()
+            .spaces
+            .values()
+            .filter(|space| space.state.as_deref() != Some("ended"))
+            .map(|space| space.id.clone())
+            .collect();
+        ids.into_iter().collect()
+    };
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let label = "Spaces";
+    start_stage(label, &message_sender).await;
+
+    for chunk in ids.chunks(100) {
+        let params = egg_mode::raw::ParamList::new()
+            .add_param("ids", chunk.join(","))
+            .add_param("space.fields", "title,host_ids,state");
+        let resp = loop {
+            config.rate_limiter().ready().await;
+            let request = egg_mode::raw::request_get(
+                "https://api.twitter.com/2/spaces",
+                &config.token,
+                Some(&params),
+            );
+            match egg_mode::raw::response_json::<SpaceLookup>(request).await {
+                Ok(n) => break Some(n),
+                Err(egg_mode::error::Error::RateLimit(limit)) => {
+                    wait_for_rate_limit(
+                        config.rate_limiter(),
+                        "Spaces",
+                        reset_time(limit),
+                        &message_sender,
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    warn!("Could not look up Spaces {e:?}");
+                    break None;
+                }
+            }
+        };
+        let Some(resp) = resp else {
+            return Ok(());
+        };
+
+        shared_storage.lock().await.with_data(|data| {
+            for found in &resp.response.data {
+                for space in data.spaces.values_mut().filter(|s| s.id == found.id) {
+                    space.title = found.title.clone();
+                    space.host_ids = found
+                        .host_ids
+                        .iter()
+                        .filter_map(|id| id.parse().ok())
+                        .collect();
+                    space.state = Some(found.state.clone());
+                }
+            }
+        });
+
+        handle_rate_limit(
+            config.rate_limiter(),
+            &resp.rate_limit_status,
+            label,
+            message_sender.clone(),
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// The parts of a v2 Spaces lookup that are archived
+#[derive(serde::Deserialize)]
+struct SpaceLookup {
+    #[serde(default)]
+    data: Vec<SpaceLookupSpace>,
+}
+
+#[derive(serde::Deserialize)]
+struct SpaceLookupSpace {
+    id: String,
+    state: String,
+    title: Option<String>,
+    #[serde(default)]
+    host_ids: Vec<String>,
+}
+
+/// A tweet timeline like egg_mode's `Timeline`, which also returns the JSON
+/// of the tweets as the API sent it. egg_mode drops the fields it doesn't
+/// model, so this is what ends up in the raw JSON files
+struct RawTimeline {
+    url: &'static str,
+    params: egg_mode::raw::ParamList,
+    token: egg_mode::Token,
+    count: i32,
+    /// The oldest tweet of the last page
+    min_id: Option<u64>,
+}
+
+impl RawTimeline {
+    fn user_tweets(id: u64, config: &Config) -> Self {
+        let params = egg_mode::raw::ParamList::new()
+            .add_param("user_id", id.to_string())
+            .add_param("exclude_replies", "false")
+            .add_param("include_rts", "true");
+        Self::new(
+            "https://api.twitter.com/1.1/statuses/user_timeline.json",
+            params,
+            config,
+        )
+    }
+
+    fn mentions(config: &Config) -> Self {
+        Self::new(
+            "https://api.twitter.com/1.1/statuses/mentions_timeline.json",
+            egg_mode::raw::ParamList::new(),
+            config,
+        )
+    }
+
+    fn likes(id: u64, config: &Config) -> Self {
+        let params = egg_mode::raw::ParamList::new().add_param("user_id", id.to_string());
+        Self::new(
+            "https://api.twitter.com/1.1/favorites/list.json",
+            params,
+            config,
+        )
+    }
+
+    fn new(url: &'static str, params: egg_mode::raw::ParamList, config: &Config) -> Self {
+        Self {
+            url,
+            params,
+            token: config.token.clone(),
+            count: config.crawl_options().tweets_page_size(),
+            min_id: None,
+        }
+    }
+
+    /// The page of tweets older than the last page, and newer than
+    /// `since_id`, with their JSON
+    async fn older(
+        mut self,
+        since_id: Option<u64>,
+    ) -> egg_mode::error::Result<(Self, egg_mode::Response<Vec<Tweet>>, Vec<serde_json::Value>)>
+    {
+        let params = self
+            .params
+            .clone()
+            .add_param("count", self.count.to_string())
+            .add_param("tweet_mode", "extended")
+            .add_param("include_ext_alt_text", "true")
+            .add_opt_param("since_id", since_id.map(|id| id.to_string()))
+            .add_opt_param("max_id", self.min_id.map(|id| (id - 1).to_string()));
+        let request = egg_mode::raw::request_get(self.url, &self.token, Some(&params));
+        let page = egg_mode::raw::response_json::<Vec<serde_json::Value>>(request).await?;
+        let tweets = page
+            .response
+            .iter()
+            .map(|value| serde_json::from_value::<Tweet>(value.clone()))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        self.min_id = tweets.last().map(|t| t.id);
+        let feed = egg_mode::Response {
+            rate_limit_status: page.rate_limit_status,
+            response: tweets,
+        };
+        Ok((self, feed, page.response))
+    }
+}
+
+/// Appends the JSON that a stage received to the raw JSON files, if they
+/// are enabled. Objects that are already in them are skipped
+struct RawCapture {
+    archive: Option<RawArchive>,
+    kind: &'static str,
+    known: HashSet<u64>,
+}
+
+impl RawCapture {
+    async fn new(
+        kind: &'static str,
+        shared_storage: &Arc<Mutex<Storage>>,
+        config: &Config,
+    ) -> CrawlResult<Self> {
+        if !config.crawl_options().raw_json {
+            return Ok(Self {
+                archive: None,
+                kind,
+                known: HashSet::new(),
+            });
+        }
+        let archive = shared_storage.lock().await.raw_archive();
+        let known = archive.ids(kind)?;
+        Ok(Self {
+            archive: Some(archive),
+            kind,
+            known,
+        })
+    }
+
+    fn capture(&mut self, values: Vec<serde_json::Value>) -> CrawlResult<()> {
+        let Some(archive) = &self.archive else {
+            return Ok(());
+        };
+        let new: Vec<_> = values
+            .into_iter()
+            .filter(|value| match raw_id(value) {
+                Some(id) => self.known.insert(id),
+                None => true,
+            })
+            .collect();
+        if !new.is_empty() {
+            archive.append(self.kind, &new)?;
+        }
+        Ok(())
+    }
+}
+
+fn raw_id(value: &serde_json::Value) -> Option<u64> {
+    value.get("id").and_then(|id| id.as_u64())
+}
+
+/// Looks up profiles like `user::lookup` does, with their JSON
+async fn lookup_raw_profiles(
+    ids: &[u64],
+    config: &Config,
+) -> egg_mode::error::Result<(Vec<TwitterUser>, Vec<serde_json::Value>, RateLimit)> {
+    let joined: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+    let params = egg_mode::raw::ParamList::new()
+        .add_param("user_id", joined.join(","))
+        .add_param("tweet_mode", "extended");
+    let request = egg_mode::raw::request_post(
+        "https://api.twitter.com/1.1/users/lookup.json",
+        &config.token,
+        Some(&params),
+    );
+    let response = egg_mode::raw::response_json::<Vec<serde_json::Value>>(request).await?;
+    let raw = response.response;
+    let profiles = raw
+        .iter()
+        .map(|value| serde_json::from_value::<TwitterUser>(value.clone()))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok((profiles, raw, response.rate_limit_status))
+}
+
+/// The tweets and profiles are stored with their JSON while they are
+/// crawled. This looks up the ones that weren't, e.g. replies and the
+/// archives from before the raw JSON was enabled. Objects that the API
+/// doesn't return are recorded, so that they aren't requested again
+async fn fetch_raw_json(
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    start_stage("Raw JSON", &message_sender).await;
+    let (archive, mut tweet_ids, mut profile_ids) = {
+        let storage = shared_storage.lock().await;
+        let data = storage.data();
+        let tweet_ids: HashSet<u64> = data
+            .tweets
+            .iter()
+            .chain(data.mentions.iter())
+            .chain(data.likes.iter())
+            .chain(data.responses.values().flatten())
+            .map(|t| t.id)
+            .collect();
+        let profile_ids: HashSet<u64> = std::iter::once(data.profile.id)
+            .chain(data.profiles.keys().copied())
+            .collect();
+        (storage.raw_archive(), tweet_ids, profile_ids)
+    };
+    for (kind, ids) in [
+        (RAW_TWEETS, &mut tweet_ids),
+        (RAW_PROFILES, &mut profile_ids),
+    ] {
+        let known = archive.ids(kind)?;
+        let unavailable = archive.unavailable_ids(kind)?;
+        ids.retain(|id| !known.contains(id) && !unavailable.contains(id));
+    }
+
+    fetch_raw_objects(
+        RAW_TWEETS,
+        "https://api.twitter.com/1.1/statuses/lookup.json",
+        "id",
+        &tweet_ids.into_iter().collect::<Vec<_>>(),
+        &archive,
+        config,
+        &message_sender,
+    )
+    .await?;
+    fetch_raw_objects(
+        RAW_PROFILES,
+        "https://api.twitter.com/1.1/users/lookup.json",
+        "user_id",
+        &profile_ids.into_iter().collect::<Vec<_>>(),
+        &archive,
+        config,
+        &message_sender,
+    )
+    .await
+}
+
+const RAW_TWEETS: &str = "tweets";
+const RAW_PROFILES: &str = "profiles";
+
+async fn fetch_raw_objects(
+    kind: &'static str,
+    url: &'static str,
+    id_param: &'static str,
+    ids: &[u64],
+    archive: &RawArchive,
+    config: &Config,
+    message_sender: &Sender<Message>,
+) -> CrawlResult<()> {
+    let mut processed = 0;
+    for chunk in ids.chunks(100) {
+        let joined: Vec<String> = chunk.iter().map(|id| id.to_string()).collect();
+        let params = egg_mode::raw::ParamList::new()
+            .add_param(id_param, joined.join(","))
+            .add_param("tweet_mode", "extended");
+        let (values, rate_limit) = loop {
+            config.rate_limiter().ready().await;
+            let request = egg_mode::raw::request_get(url, &config.token, Some(&params));
+            match egg_mode::raw::response_json::<Vec<serde_json::Value>>(request).await {
+                Ok(n) => break (n.response, Some(n.rate_limit_status)),
+                // The profile lookup fails if none of the users is available
+                Err(e) if unavailable_reason(&e).is_some() => break (Vec::new(), None),
+                Err(egg_mode::error::Error::RateLimit(limit)) => {
+                    wait_for_rate_limit(
+                        config.rate_limiter(),
+                        &format!("Raw JSON {kind}"),
+                        reset_time(limit),
+                        message_sender,
+                    )
+                    .await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        archive.append(kind, &values)?;
+        let missing = missing_ids(chunk, &values);
+        if !missing.is_empty() {
+            info!("{} raw {kind} are unavailable", missing.len());
+            archive.append_unavailable(kind, &missing)?;
+        }
+
+        processed += chunk.len();
+        msg(
+            format!("Raw JSON {kind}: {processed} / {}", ids.len()),
+            message_sender,
+        )
+        .await;
+        if let Some(rate_limit) = rate_limit {
+            handle_rate_limit(
+                config.rate_limiter(),
+                &rate_limit,
+                "Raw JSON",
+                message_sender.clone(),
+            )
+            .await;
+        }
+    }
+    Ok(())
+}
+
+/// The requested ids that a lookup didn't return. Deleted, suspended and
+/// protected objects are silently left out of it
+fn missing_ids(requested: &[u64], values: &[serde_json::Value]) -> Vec<u64> {
+    let returned: HashSet<u64> = values.iter().filter_map(raw_id).collect();
+    requested
+        .iter()
+        .copied()
+        .filter(|id| !returned.contains(id))
+        .collect()
+}
+
+/// The parts of a v2 tweet lookup that are needed for polls, conversations
+/// and link previews
+#[derive(serde::Deserialize)]
+struct PollLookup {
+    #[serde(default)]
+    data: Vec<PollLookupTweet>,
+    #[serde(default)]
+    includes: Option<PollLookupIncludes>,
+}
+
+#[derive(serde::Deserialize)]
+struct PollLookupTweet {
+    id: String,
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    attachments: Option<PollLookupAttachments>,
+    #[serde(default)]
+    entities: Option<PollLookupEntities>,
+    /// The ids of all versions of the tweet, oldest first
+    #[serde(default)]
+    edit_history_tweet_ids: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct PollLookupEntities {
+    #[serde(default)]
+    urls: Vec<PollLookupUrl>,
+}
+
+/// A link of a tweet. Links with a preview have a title
+#[derive(serde::Deserialize)]
+struct PollLookupUrl {
+    #[serde(default)]
+    expanded_url: Option<String>,
+    /// The final url after all redirects
+    #[serde(default)]
+    unwound_url: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    /// The preview image in different sizes, the largest first
+    #[serde(default)]
+    images: Vec<PollLookupImage>,
+}
+
+#[derive(serde::Deserialize)]
+struct PollLookupImage {
+    url: String,
+}
+
+#[derive(serde::Deserialize)]
+struct PollLookupAttachments {
+    #[serde(default)]
+    poll_ids: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct PollLookupIncludes {
+    #[serde(default)]
+    polls: Vec<PollLookupPoll>,
+}
+
+#[derive(serde::Deserialize)]
+struct PollLookupPoll {
+    id: String,
+    options: Vec<PollOption>,
+    #[serde(default)]
+    voting_status: Option<String>,
+    end_datetime: Option<chrono::DateTime<chrono::Utc>>,
+    duration_minutes: Option<u32>,
+}
+
+impl PollLookup {
+    /// The ids of all versions by tweet id, only for edited tweets
+    fn edit_histories(&self) -> Vec<(u64, Vec<u64>)> {
+        self.data
+            .iter()
+            .filter(|tweet| tweet.edit_history_tweet_ids.len() > 1)
+            .filter_map(|tweet| {
+                let ids = tweet
+                    .edit_history_tweet_ids
+                    .iter()
+                    .map(|id| id.parse().ok())
+                    .collect::<Option<Vec<u64>>>()?;
+                Some((tweet.id.parse().ok()?, ids))
+            })
+            .collect()
+    }
+
+    /// The conversation id by tweet id
+    fn conversations(&self) -> Vec<(u64, u64)> {
+        self.data
+            .iter()
+            .filter_map(|tweet| {
+                let conversation_id = tweet.conversation_id.as_ref()?.parse().ok()?;
+                Some((tweet.id.parse().ok()?, conversation_id))
+            })
+            .collect()
+    }
+
+    /// The link previews by the id of their tweet. Twitter only shows the
+    /// preview of one link, the first one that has a title is used
+    fn cards(&self) -> Vec<(u64, Card)> {
+        self.data
+            .iter()
+            .filter_map(|tweet| {
+                let tweet_id = tweet.id.parse().ok()?;
+                let urls = &tweet.entities.as_ref()?.urls;
+                let card = urls.iter().find_map(|link| {
+                    let title = link.title.clone()?;
+                    let target = link.unwound_url.as_ref().or(link.expanded_url.as_ref())?;
+                    let host = url::Url::parse(target).ok()?.host_str()?.to_string();
+                    Some(Card {
+                        url: target.clone(),
+                        title,
+                        description: link.description.clone().filter(|d| !d.is_empty()),
+                        image_url: link.images.first().map(|image| image.url.clone()),
+                        vendor: host.trim_start_matches("www.").to_string(),
+                    })
+                })?;
+                Some((tweet_id, card))
+            })
+            .collect()
+    }
+
+    /// The polls by the id of their tweet. Tweets without a poll are skipped
+    fn polls(&self) -> Vec<(u64, Poll)> {
+        let Some(includes) = &self.includes else {
+            return Vec::new();
+        };
+        self.data
+            .iter()
+            .filter_map(|tweet| {
+                let tweet_id = tweet.id.parse().ok()?;
+                let poll_id = tweet.attachments.as_ref()?.poll_ids.first()?;
+                let poll = includes.polls.iter().find(|p| &p.id == poll_id)?;
+                let mut options = poll.options.clone();
+                options.sort_by_key(|o| o.position);
+                Some((
+                    tweet_id,
+                    Poll {
+                        options,
+                        voting_status: poll.voting_status.clone().unwrap_or_default(),
+                        end_datetime: poll.end_datetime,
+                        duration_minutes: poll.duration_minutes,
+                    },
+                ))
+            })
+            .collect()
+    }
+}
+
+// Helpers
+
+async fn fetch_profiles_ids(
+    kind: &'static str,
+    mut cursor: cursor::CursorIter<cursor::IDCursor>,
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+    mut ids: Vec<u64>,
+    message_sender: Sender<Message>,
+) -> CrawlResult<Vec<u64>> {
+    start_stage(kind, &message_sender).await;
+    cursor.next_cursor = config.paging_position(kind).map(|e| e as i64).unwrap_or(-1);
+
+    let is_sync = config.is_sync;
+
+    let mut failures = 0;
+    let mut collected = 0;
+    loop {
+        if cursor.next_cursor == 0 {
+            break;
+        }
+        info!("Downloading {kind} before {}", cursor.next_cursor);
+        config.rate_limiter().ready().await;
+        let called = cursor.call();
+        let resp = match called.await {
+            Ok(n) => {
+                failures = 0;
+                n
+            }
+            Err(e) => match e {
+                egg_mode::error::Error::RateLimit(limit) => {
+                    wait_for_rate_limit(
+                        config.rate_limiter(),
+                        kind,
+                        reset_time(limit),
+                        &message_sender,
+                    )
+                    .await;
+                    continue;
+                }
+                _ => {
+                    let error = CrawlError::from(e);
+                    // Retrying with an expired token only fails again
+                    if matches!(error, CrawlError::Auth(_)) {
+                        return Err(error);
+                    }
+                    warn!("Profile Ids Error {error:?}");
+                    retry(config.retry_policy(), &mut failures, error).await?;
+                    continue;
+                }
+            },
+        };
+
+        let new_ids = resp.response.ids.clone();
+
+        if new_ids.is_empty() {
+            break;
+        }
+
+        let mut unknown_new: Vec<_> = new_ids
+            .iter()
+            .filter(|s| !ids.contains(s))
+            .copied()
+            .collect();
+        let unknown_new_len = unknown_new.len();
+
+        fetch_multiple_profiles_data(
+            &unknown_new,
+            shared_storage.clone(),
+            config,
+            sender.clone(),
+            &message_sender,
+        )
+        .await?;
+
+        if is_sync {
+            ids.splice(0..0, unknown_new);
+        } else {
+            ids.append(&mut unknown_new);
+        }
+
+        msg(format!("{kind}: {}", ids.len()), &message_sender).await;
+
+        // if we have less unknown then new, we ran into known data
+        if is_sync && unknown_new_len < new_ids.len() {
+            break;
+        }
+
+        collected += unknown_new_len;
+        if config.crawl_options().reached_max_items(collected) {
+            info!("Stopping after {collected} {kind}");
+            break;
+        }
+
+        handle_rate_limit(
+            config.rate_limiter(),
+            &resp.rate_limit_status,
+            kind,
+            message_sender.clone(),
+        )
+        .await;
+        cursor.next_cursor = resp.response.next_cursor;
+        config.set_paging_position(kind, u64::try_from(cursor.next_cursor).ok());
+        if config.is_cancelled() {
+            break;
+        }
+    }
+
+    // The caller saves the ids, the next crawl continues after them
+    if !config.is_cancelled() {
+        config.set_paging_position(kind, None);
+    }
+
+    Ok(ids)
+}
+
+/// Download the profiles of users that archived tweets mention
+async fn fetch_mentioned_profiles(
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    let label = "Mentioned Profiles";
+    start_stage(label, &message_sender).await;
+    let ids: Vec<u64> = {
+        let storage = shared_storage.lock().await;
+        let data = storage.data();
+        data.mentions_index
+            .keys()
+            .filter(|id| {
+                !data.profiles.contains_key(id) && !data.unavailable_profiles.contains_key(id)
+            })
+            .copied()
+            .collect()
+    };
+    let mut processed = 0;
+    for chunk in ids.chunks(profiles_batch_size(config)) {
+        fetch_multiple_profiles_data(
+            chunk,
+            shared_storage.clone(),
+            config,
+            sender.clone(),
+            &message_sender,
+        )
+        .await?;
+        processed += chunk.len();
+        msg(format!("{label}: {processed} / {}", ids.len()), &message_sender).await;
+    }
+    Ok(())
+}
+
+/// The maximum number of profiles of one lookup call
+const PROFILES_PER_LOOKUP: usize = 100;
+
+/// Enough ids for all the concurrent lookups of `fetch_multiple_profiles_data`
+fn profiles_batch_size(config: &Config) -> usize {
+    PROFILES_PER_LOOKUP * config.crawl_options().profile_lookups()
+}
+
+/// Downloads the profiles that aren't known yet. The lookups of up to 100
+/// profiles each run concurrently, up to `profile_lookups` of the options
+async fn fetch_multiple_profiles_data(
+    ids: &[u64],
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+    message_sender: &Sender<Message>,
+) -> CrawlResult<()> {
+    let filtered = {
+        let storage = shared_storage.lock().await;
+        profiles_to_look_up(ids, storage.data(), config)
+    };
+    if filtered.is_empty() {
+        return Ok(());
+    }
+    info!("Downloading {} profiles", filtered.len());
+    lookup_in_chunks(
+        &filtered,
+        config.crawl_options().profile_lookups(),
+        |chunk| {
+            lookup_profiles(
+                chunk,
+                shared_storage.clone(),
+                config,
+                sender.clone(),
+                message_sender.clone(),
+            )
+        },
+    )
+    .await
+}
+
+/// The ids of the profiles that aren't known yet, each once
+fn profiles_to_look_up(ids: &[u64], data: &Data, config: &Config) -> Vec<u64> {
+    let mut known_ids: HashSet<u64> = data
+        .profiles
+        .keys()
+        .chain(data.unavailable_profiles.keys())
+        .chain(
+            data.skipped_profiles
+                .keys()
+                .filter(|id| skipped_before(**id, data, config)),
+        )
+        .copied()
+        .collect();
+    // `insert` also removes duplicates, so that no id is in two lookups
+    ids.iter()
+        .filter(|id| !config.is_skipped(**id, None) && known_ids.insert(**id))
+        .copied()
+        .collect()
+}
+
+/// Calls `lookup` with the chunks of `ids`, up to `concurrency` at a time
+async fn lookup_in_chunks<F, Fut>(ids: &[u64], concurrency: usize, lookup: F) -> CrawlResult<()>
+where
+    F: FnMut(Vec<u64>) -> Fut,
+    Fut: std::future::Future<Output = CrawlResult<()>>,
+{
+    let mut lookups = futures::stream::iter(lookup_chunks(ids))
+        .map(lookup)
+        .buffer_unordered(concurrency);
+    while let Some(result) = lookups.next().await {
+        result?;
+    }
+    Ok(())
+}
+
+/// The ids of a page of follower or follow ids, which can be up to 5000,
+/// split into the ids of the single lookups. Owned chunks, a closure over
+/// borrowed ones makes the crawl future `!Send`
+fn lookup_chunks(ids: &[u64]) -> Vec<Vec<u64>> {
+    ids.chunks(PROFILES_PER_LOOKUP)
+        .map(|chunk| chunk.to_vec())
+        .collect()
+}
+
+/// One lookup of at most `PROFILES_PER_LOOKUP` profiles. The concurrent
+/// lookups share the rate limit
+async fn lookup_profiles(
+    filtered: Vec<u64>,
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    let (mut profiles, rate_limit) = loop {
+        config.rate_limiter().ready().await;
+        match lookup_raw_profiles(&filtered, config).await {
+            Ok((profiles, raw_profiles, rate_limit)) => {
+                RawCapture::new(RAW_PROFILES, &shared_storage, config)
+                    .await?
+                    .capture(raw_profiles)?;
+                break (profiles, Some(rate_limit));
+            }
+            Err(egg_mode::error::Error::RateLimit(limit)) => {
+                wait_for_rate_limit(
+                    config.rate_limiter(),
+                    "Profiles",
+                    reset_time(limit),
+                    &message_sender,
+                )
+                .await;
+            }
+            // If none of the users is available, the lookup fails
+            Err(e) if unavailable_reason(&e).is_some() => break (Vec::new(), None),
+            Err(e) => return Err(e.into()),
+        }
+    };
+    // Skipped by screen name, they don't count as unavailable below
+    let skipped: HashMap<u64, String> = profiles
+        .iter()
+        .filter(|p| config.is_skipped(p.id, Some(&p.screen_name)))
+        .map(|p| (p.id, p.screen_name.clone()))
+        .collect();
+    profiles.retain(|p| !skipped.contains_key(&p.id));
+    for profile in profiles.iter() {
+        inspect_profile(profile, sender.clone()).await?;
+    }
+    let missing = {
+        let mut storage = shared_storage.lock().await;
+        store_lookup(storage.data_mut(), &filtered, &profiles, &skipped)
+    };
+    if missing > 0 {
+        info!("{missing} profiles are unavailable");
+    }
+    if let Some(rate_limit) = rate_limit {
+        handle_rate_limit(
+            config.rate_limiter(),
+            &rate_limit,
+            "Profiles",
+            message_sender,
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// Stores the profiles of a lookup. Suspended, deleted and protected users
+/// are silently left out of it, so the requested ids that are neither
+/// returned nor skipped are recorded as unavailable. Returns their number
+fn store_lookup(
+    data: &mut Data,
+    requested: &[u64],
+    profiles: &[TwitterUser],
+    skipped: &HashMap<u64, String>,
+) -> usize {
+    let returned: HashSet<u64> = profiles.iter().map(|p| p.id).collect();
+    for profile in profiles {
+        data.insert_profile(profile.clone());
+    }
+    data.skipped_profiles.extend(skipped.clone());
+    let mut missing = 0;
+    for id in requested {
+        if returned.contains(id) || skipped.contains_key(id) {
+            continue;
+        }
+        data.unavailable_profiles.insert(
+            *id,
+            "Not returned by the lookup (suspended, deleted or protected)".to_string(),
+        );
+        missing += 1;
+    }
+    missing
+}
+
+/// Whether the profile is skipped by id or was skipped by its screen name
+/// before. Removing the screen name from the config looks it up again
+fn skipped_before(id: u64, data: &Data, config: &Config) -> bool {
+    let screen_name = data.skipped_profiles.get(&id).map(|name| name.as_str());
+    config.is_skipped(id, screen_name)
+}
+
+/// The reason if an error means that a user is suspended or deleted
+fn unavailable_reason(error: &egg_mode::error::Error) -> Option<&'static str> {
+    let egg_mode::error::Error::TwitterError(_, errors) = error else {
+        return None;
+    };
+    errors.errors.iter().find_map(|e| match e.code {
+        17 => Some("No user matches"),
+        50 => Some("User not found"),
+        63 => Some("User has been suspended"),
+        _ => None,
+    })
+}
+
+async fn fetch_lists(
+    id: u64,
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    let label = "Lists";
+    start_stage(label, &message_sender).await;
+    let mut cursor = list::ownerships(id, &config.token).with_page_size(100);
+    cursor.next_cursor = config
+        .paging_position("lists")
+        .map(|e| e as i64)
+        .unwrap_or(-1);
+    let mut failures = 0;
+    loop {
+        config.rate_limiter().ready().await;
+        let called = cursor.call();
+
+        let resp = match called.await {
+            Ok(n) => {
+                failures = 0;
+                n
+            }
+            Err(e) => match e {
+                egg_mode::error::Error::RateLimit(limit) => {
+                    wait_for_rate_limit(
+                        config.rate_limiter(),
+                        "Lists",
+                        reset_time(limit),
+                        &message_sender,
+                    )
+                    .await;
+                    continue;
+                }
+                _ => {
+                    let error = CrawlError::from(e);
+                    // Retrying with an expired token only fails again
+                    if matches!(error, CrawlError::Auth(_)) {
+                        return Err(error);
+                    }
+                    warn!("Lists Error {error:?}");
+                    retry(config.retry_policy(), &mut failures, error).await?;
+                    continue;
+                }
+            },
+        };
+
+        let lists = resp.response.lists;
+
+        if lists.is_empty() {
+            break;
+        }
+
+        for list in lists {
+            info!("Fetching members for list {}", list.full_name);
+            msg(
+                format!("Processing List: {}", list.full_name),
+                &message_sender,
+            )
+            .await;
+            fetch_list_members(
+                list,
+                shared_storage.clone(),
+                config,
+                sender.clone(),
+                message_sender.clone(),
+            )
+            .await?;
+        }
+
+        handle_rate_limit(
+            config.rate_limiter(),
+            &resp.rate_limit_status,
+            "Lists",
+            message_sender.clone(),
+        )
+        .await;
+        cursor.next_cursor = resp.response.next_cursor;
+        config.set_paging_position("lists", u64::try_from(cursor.next_cursor).ok());
+    }
+
+    config.set_paging_position("lists", None);
+    Ok(())
+}
+
+async fn fetch_list_members(
+    list: list::List,
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+    message_sender: Sender<Message>,
+) -> CrawlResult<()> {
+    // Lists are not really synced, they're just not downloaded if they already exists
+    if config.is_sync {
+        let s = shared_storage.lock().await;
+        if s.data().lists.iter().any(|e| e.list.id == list.id) {
+            info!(
+                "Ignoring list {} because it was already downloaded",
+                &list.name
+            );
+            return Ok(());
+        }
+    }
+
+    let list_id = ListID::from_id(list.id);
+    let mut cursor = list::members(list_id, &config.token)
+        .with_page_size(config.crawl_options().list_members_page_size());
+    let paging_key = format!("list-{}", list.id);
+    cursor.next_cursor = config
+        .paging_position(&paging_key)
+        .map(|e| e as i64)
+        .unwrap_or(-1);
+    let mut member_ids = Vec::new();
+    let mut failures = 0;
+    loop {
+        config.rate_limiter().ready().await;
+        let called = cursor.call();
+        let resp = match called.await {
+            Ok(n) => {
+                failures = 0;
+                n
+            }
+            Err(e) => match e {
+                egg_mode::error::Error::RateLimit(limit) => {
+                    wait_for_rate_limit(
+                        config.rate_limiter(),
+                        "Lists Members",
+                        reset_time(limit),
+                        &message_sender,
+                    )
+                    .await;
+                    continue;
+                }
+                _ => {
+                    let error = CrawlError::from(e);
+                    // Retrying with an expired token only fails again
+                    if matches!(error, CrawlError::Auth(_)) {
+                        return Err(error);
+                    }
+                    warn!("Lists Members Error {error:?}");
+                    retry(config.retry_policy(), &mut failures, error).await?;
+                    continue;
+                }
+            },
+        };
+
+        if resp.users.is_empty() {
+            break;
+        }
+
+        let mut storage = shared_storage.lock().await;
+
+        info!("Processing {} members", resp.users.len());
+        for member in &resp.users {
+            member_ids.push(member.id);
+            if config.is_skipped(member.id, Some(&member.screen_name)) {
+                continue;
+            }
+            if let Err(e) = inspect_profile(member, sender.clone()).await {
+                warn!("Could not inspect profile {e:?}");
+            }
+            storage.data_mut().insert_profile(member.clone());
+        }
+
+        handle_rate_limit(
+            config.rate_limiter(),
+            &resp.rate_limit_status,
+            "List Members",
+            message_sender.clone(),
+        )
+        .await;
+        cursor.next_cursor = resp.response.next_cursor;
+        config.set_paging_position(&paging_key, u64::try_from(cursor.next_cursor).ok());
+    }
+
+    config.set_paging_position(&paging_key, None);
+
+    let private = is_private_list(list.id, config).await;
+    let list = List {
+        name: list.name.clone(),
+        list,
+        members: member_ids,
+        private,
+    };
+    let mut storage = shared_storage.lock().await;
+    let lists = &mut storage.data_mut().lists;
+    match lists.iter_mut().find(|e| e.list.id == list.list.id) {
+        Some(existing) => *existing = list,
+        None => lists.push(list),
+    }
+
+    Ok(())
+}
+
+/// `egg_mode` doesn't parse the `mode` of a list, so it is read from the
+/// raw response. Lists count as public if the lookup fails
+async fn is_private_list(list_id: u64, config: &Config) -> bool {
+    #[derive(serde::Deserialize)]
+    struct ListMode {
+        mode: String,
+    }
+    let params = egg_mode::raw::ParamList::new().add_param("list_id", list_id.to_string());
+    config.rate_limiter().ready().await;
+    let request = egg_mode::raw::request_get(
+        "https://api.twitter.com/1.1/lists/show.json",
+        &config.token,
+        Some(&params),
+    );
+    match egg_mode::raw::response_json::<ListMode>(request).await {
+        Ok(response) => response.response.mode == "private",
+        Err(e) => {
+            warn!("Could not look up the mode of list {list_id}: {e:?}");
+            false
+        }
+    }
+}
+
+async fn fetch_single_profile(
+    id: u64,
+    shared_storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: InstructionSender,
+) -> CrawlResult<()> {
+    {
+        let storage = shared_storage.lock().await;
+        let data = storage.data();
+        if data.profiles.contains_key(&id)
+            || data.unavailable_profiles.contains_key(&id)
+            || skipped_before(id, data, config)
+        {
+            return Ok(());
+        }
+    }
+
+    config.rate_limiter().ready().await;
+    let user = match user::show(id, &config.token).await {
+        Ok(user) => user,
+        Err(e) => {
+            let Some(reason) = unavailable_reason(&e) else {
+                return Err(e.into());
+            };
+            info!("Profile {id} is unavailable: {reason}");
+            shared_storage
+                .lock()
+                .await
+                .data_mut()
+                .unavailable_profiles
+                .insert(id, reason.to_string());
+            return Ok(());
+        }
+    };
+    if config.is_skipped(id, Some(&user.screen_name)) {
+        shared_storage
+            .lock()
+            .await
+            .data_mut()
+            .skipped_profiles
+            .insert(id, user.response.screen_name);
+        return Ok(());
+    }
+    if let Err(e) = inspect_profile(&user, sender).await {
+        warn!("Inspect profile error {e:?}");
+    }
+
+    shared_storage
+        .lock()
+        .await
+        .data_mut()
+        .insert_profile(user.response);
+    Ok(())
+}
+
+pub(crate) async fn inspect_tweet(
+    tweet: &Tweet,
+    storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: &InstructionSender,
+    message_sender: &Sender<Message>,
+) -> CrawlResult<()> {
+    let options = config.crawl_options();
+    if let Err(e) = inspect_inner_tweet(
+        tweet,
+        options.tweet_profiles,
+        config,
+        &storage,
+        sender.clone(),
+    )
+    .await
+    {
+        warn!("Inspect Tweet Error {e:?}");
+    }
+
+    if tweet.quoted_status.is_some() || tweet.retweeted_status.is_some() {
+        storage.lock().await.data_mut().archive_relations(tweet);
+    }
+
+    let inner_profiles = options.inner_profiles();
+    if let Some(quoted_tweet) = &tweet.quoted_status {
+        if let Err(e) = inspect_inner_tweet(
+            quoted_tweet,
+            inner_profiles,
+            config,
+            &storage,
+            sender.clone(),
+        )
+        .await
+        {
+            warn!("Inspect Quoted Tweet Error {e:?}");
+        }
+        let chain = fetch_quote_chain(
+            tweet,
+            quoted_tweet,
+            &storage,
+            config,
+            sender,
+            message_sender,
+        );
+        if let Err(e) = chain.await {
+            warn!(
+                "Could not fetch the quoted tweets of {}: {e:?}",
+                quoted_tweet.id
+            );
+        }
+    }
+
+    if let Some(retweet) = &tweet.retweeted_status {
+        if let Err(e) =
+            inspect_inner_tweet(retweet, inner_profiles, config, &storage, sender.clone()).await
+        {
+            warn!("Inspect Retweet Error {e:?}");
+        }
+    }
+
+    if searches_responses(tweet, config) {
+        if let Err(e) =
+            fetch_tweet_replies(tweet, storage.clone(), config, sender, message_sender).await
+        {
+            warn!("Could not fetch replies for tweet {}: {e:?}", tweet.id);
+        }
+    }
+
+    Ok(())
+}
+
+/// For our own tweets, we search for responses. Tweets without an author
+/// are not known to be ours
+fn searches_responses(tweet: &Tweet, config: &Config) -> bool {
+    config.crawl_options().tweet_responses
+        && tweet
+            .user
+            .as_ref()
+            .map(|e| config.is_mine(e.id))
+            .unwrap_or(false)
+}
+
+/// The API only embeds the first level of quoted tweets. Up to the
+/// `quote_depth` of the options, the tweets quoted by `quoted_tweet` are
+/// looked up one by one. The chain ends at deleted or protected tweets
+async fn fetch_quote_chain(
+    tweet: &Tweet,
+    quoted_tweet: &Tweet,
+    storage: &Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: &InstructionSender,
+    message_sender: &Sender<Message>,
+) -> CrawlResult<()> {
+    let show = |id: u64| async move {
+        loop {
+            config.rate_limiter().ready().await;
+            match tweet::show(id, &config.token).await {
+                Ok(response) => break Some(response.response),
+                Err(egg_mode::error::Error::RateLimit(limit)) => {
+                    wait_for_rate_limit(
+                        config.rate_limiter(),
+                        "Quoted Tweets",
+                        reset_time(limit),
+                        message_sender,
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    info!("Quoted tweet {id} is not available: {e:?}");
+                    break None;
+                }
+            }
+        }
+    };
+    follow_quote_chain(tweet, quoted_tweet, storage, config, sender, show).await
+}
+
+/// `fetch_quote_chain` with the request that looks up a tweet, `None` if it
+/// is not available
+async fn follow_quote_chain<F, Fut>(
+    tweet: &Tweet,
+    quoted_tweet: &Tweet,
+    storage: &Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: &InstructionSender,
+    mut show: F,
+) -> CrawlResult<()>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: std::future::Future<Output = Option<Tweet>>,
+{
+    let options = config.crawl_options();
+    let inner_profiles = options.inner_profiles();
+    // A tweet can't quote a newer one, but edits and the API are not to be trusted
+    let mut seen = HashSet::from([tweet.id, quoted_tweet.id]);
+    let mut parent = quoted_tweet.clone();
+    for _ in 1..options.quote_depth() {
+        let Some(id) = parent.quoted_status_id else { break };
+        if !seen.insert(id) {
+            warn!("Tweet {} quotes a tweet of its own chain", parent.id);
+            break;
+        }
+        storage.lock().await.data_mut().index_relations(&parent);
+
+        let known = storage.lock().await.data().quoted_tweets.get(&id).cloned();
+        let next = match known {
+            Some(known) => known,
+            None => {
+                let Some(next) = show(id).await else { break };
+                inspect_inner_tweet(&next, inner_profiles, config, storage, sender.clone()).await?;
+                storage
+                    .lock()
+                    .await
+                    .data_mut()
+                    .quoted_tweets
+                    .insert(next.id, next.clone());
+                next
+            }
+        };
+        parent = next;
+    }
+    Ok(())
+}
+
+/// Compares the archived followers and follows with the counts of the
+/// profile and records them, so that an incomplete backup of the graph is
+/// noticed
+async fn report_graph_counts(
+    shared_storage: &Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: &Sender<Message>,
+) {
+    let counts = shared_storage
+        .lock()
+        .await
+        .data_mut()
+        .record_graph_counts(config.crawl_options());
+    for (label, count) in counts {
+        let delta = count.delta();
+        if delta == 0 {
+            continue;
+        }
+        let GraphCount { reported, archived } = count;
+        warn!(
+            label,
+            reported, archived, delta, "The archived count differs from the profile"
+        );
+        msg(
+            format!("Archived {archived} {label}, the profile reports {reported}"),
+            sender,
+        )
+        .await;
+    }
+}
+
+/// Indexes the tweet, downloads its media and queues the lookup of its link
+/// preview. With `fetch_profile`, the profile of the author is downloaded, too
+async fn inspect_inner_tweet(
+    tweet: &Tweet,
+    fetch_profile: bool,
+    config: &Config,
+    storage: &Arc<Mutex<Storage>>,
+    sender: InstructionSender,
+) -> CrawlResult<()> {
+    if fetch_profile {
+        if let Some(user) = &tweet.user {
+            if user.id != config.user_id() {
+                if let Err(e) =
+                    fetch_single_profile(user.id, storage.clone(), config, sender.clone()).await
+                {
+                    warn!("Could not download profile {e:?}");
+                }
+            }
+        }
+    }
+
+    let expanded_text = crate::helpers::expanded_text(tweet);
+    let place = Place::from_tweet(tweet);
+    let space = SpaceCard::from_tweet(tweet);
+    let has_entities =
+        !tweet.entities.user_mentions.is_empty() || !tweet.entities.hashtags.is_empty();
+    if expanded_text.is_some() || place.is_some() || space.is_some() || has_entities {
+        let mut storage = storage.lock().await;
+        let data = storage.data_mut();
+        data.index_entities(tweet);
+        if let Some(text) = expanded_text {
+            data.expanded_texts.insert(tweet.id, text);
+        }
+        if let Some(place) = place {
+            data.places.insert(tweet.id, place);
+        }
+        if let Some(space) = space {
+            // Keep the metadata of a previous lookup
+            data.spaces.entry(tweet.id).or_insert(space);
+        }
+    }
+
+    // The v1.1 API returns no link previews, they are looked up with the
+    // archived tweets in `fetch_tweet_polls`
+    if config.crawl_options().link_previews {
+        let mut storage = storage.lock().await;
+        let data = storage.data_mut();
+        if data.needs_card(tweet) {
+            data.card_lookups.insert(tweet.id);
+        }
+    }
+
+    let videos = crate::helpers::video_meta_in_tweet(tweet);
+    if !videos.is_empty() {
+        let mut storage = storage.lock().await;
+        let data = storage.data_mut();
+        for (url, meta) in videos {
+            // New videos get their metadata once they are downloaded. Keep
+            // the size of an earlier download
+            if !data.media.contains_key(&url) {
+                continue;
+            }
+            let stored = data.media_meta.entry(url).or_default();
+            stored.width = meta.width;
+            stored.height = meta.height;
+            stored.duration_millis = meta.duration_millis;
+        }
+    }
+
+    let Some(media) = crate::helpers::media_in_tweet(tweet) else {
+        return Ok(())
+    };
+
+    for (index, entry) in media.into_iter().enumerate() {
+        let entry = DownloadInstruction::TweetMedia(tweet.id, index, Box::new(entry));
+        if let Err(e) = sender.send(entry).await {
+            warn!("Send Error {e:?}");
+        }
+    }
+
+    Ok(())
+}
+
+/// The maximum number of results of one search call
+const REPLY_SEARCH_PAGE_SIZE: u32 = 100;
+
+/// How far back the standard search goes
+const SEARCH_WINDOW_DAYS: i64 = 7;
+
+/// Whether the replies to the tweet can still be found by the standard search
+fn within_search_window(tweet: &Tweet, now: chrono::DateTime<chrono::Utc>) -> bool {
+    now - tweet.created_at < chrono::Duration::days(SEARCH_WINDOW_DAYS)
+}
+
+/// The ids of a v2 search
+#[derive(serde::Deserialize)]
+struct SearchIds {
+    #[serde(default)]
+    data: Vec<SearchId>,
+    #[serde(default)]
+    meta: Option<SearchMeta>,
+}
+
+#[derive(serde::Deserialize)]
+struct SearchMeta {
+    #[serde(default)]
+    next_token: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct SearchId {
+    id: String,
+}
+
+async fn fetch_tweet_replies(
+    tweet: &Tweet,
+    storage: Arc<Mutex<Storage>>,
+    config: &Config,
+    sender: &InstructionSender,
+    message_sender: &Sender<Message>,
+) -> CrawlResult<()> {
+    let found = if within_search_window(tweet, chrono::Utc::now()) {
+        search_recent_replies(tweet, config, message_sender).await?
+    } else if config.crawl_options().full_archive_search {
+        let found = search_all_replies(tweet, config, message_sender).await?;
+        storage
+            .lock()
+            .await
+            .data_mut()
+            .unsearched_replies
+            .remove(&tweet.id);
+        found
+    } else {
+        // The search would find nothing, so don't spend a call on it
+        info!(
+            "Not searching the replies to {}, it is older than {SEARCH_WINDOW_DAYS} days",
+            tweet.id
+        );
+        storage
+            .lock()
+            .await
+            .data_mut()
+            .unsearched_replies
+            .insert(tweet.id);
+        return Ok(());
+    };
+
+    msg(format!("Processing {} responses", found.len()), message_sender).await;
+
+    let mut replies = Vec::new();
+
+    for related_tweet in found.into_iter() {
+        if related_tweet.in_reply_to_status_id == Some(tweet.id) {
+            let fetch_profile = config.crawl_options().tweet_profiles;
+            if let Err(e) = inspect_inner_tweet(
+                &related_tweet,
+                fetch_profile,
+                config,
+                &storage,
+                sender.clone(),
+            )
+            .await
+            {
+                warn!("Could not inspect tweet {}: {e:?}", related_tweet.id);
+            }
+            replies.push(related_tweet);
+        }
+    }
+
+    if replies.is_empty() {
+        return Ok(());
+    }
+
+    // Replies found by an earlier crawl are kept, even if they're outside
+    // of the search window now
+    let mut shared_storage = storage.lock().await;
+    for reply in replies {
+        shared_storage.upsert_tweet(TweetKind::Response(tweet.id), reply)?;
+    }
+    if let Some(stored) = shared_storage.data_mut().responses.get_mut(&tweet.id) {
+        stored.sort_by(|a, b| b.id.cmp(&a.id));
+    }
+
+    Ok(())
+}
+
+/// The replies to the tweet from the standard search, which only covers
+/// the last ~7 days
+async fn search_recent_replies(
+    tweet: &Tweet,
+    config: &Config,
+    message_sender: &Sender<Message>,
+) -> CrawlResult<Vec<Tweet>> {
+    // Tweets of alt accounts get replies to that account
+    let screen_name = tweet
+        .user
+        .as_ref()
+        .map(|user| user.screen_name.as_str())
+        .filter(|screen_name| !screen_name.is_empty())
+        .unwrap_or_else(|| config.screen_name());
+    let pages = config.crawl_options().reply_search_pages();
+    search_pages(pages, |max_id| async move {
+        config.rate_limiter().ready().await;
+        let mut search = egg_mode::search::search(format!("to:{screen_name}"))
+            .since_tweet(tweet.id)
+            .count(REPLY_SEARCH_PAGE_SIZE);
+        if let Some(max_id) = max_id {
+            search = search.max_tweet(max_id);
+        }
+        let search_results = search.call(&config.token).await?;
+        handle_rate_limit(
+            config.rate_limiter(),
+            &search_results.rate_limit_status,
+            "Tweet Replies",
+            message_sender.clone(),
+        )
+        .await;
+        Ok(search_results.response.statuses)
+    })
+    .await
+}
+
+/// Calls `search` with the `max_id` for each next, older page until a page
+/// isn't full or `pages` pages were loaded
+async fn search_pages<F, Fut>(pages: u32, mut search: F) -> CrawlResult<Vec<Tweet>>
+where
+    F: FnMut(Option<u64>) -> Fut,
+    Fut: std::future::Future<Output = CrawlResult<Vec<Tweet>>>,
+{
+    let mut found = Vec::new();
+    let mut max_id: Option<u64> = None;
+    for _ in 0..pages {
+        let statuses = search(max_id).await?;
+        let page_size = statuses.len();
+        max_id = statuses
+            .iter()
+            .map(|status| status.id)
+            .min()
+            .map(|id| id - 1);
+        found.extend(statuses);
+        if page_size < REPLY_SEARCH_PAGE_SIZE as usize || max_id.is_none() {
+            break;
+        }
+    }
+    Ok(found)
+}
+
+/// The replies to the tweet from the v2 full-archive search. It only
+/// returns ids, the tweets are then looked up like the timeline ones
+async fn search_all_replies(
+    tweet: &Tweet,
+    config: &Config,
+    message_sender: &Sender<Message>,
+) -> CrawlResult<Vec<Tweet>> {
+    let mut found = Vec::new();
+    let mut next_token: Option<String> = None;
+    for _ in 0..config.crawl_options().reply_search_pages() {
+        let mut params = egg_mode::raw::ParamList::new()
+            .add_param("query", format!("in_reply_to_tweet_id:{}", tweet.id))
+            .add_param("max_results", REPLY_SEARCH_PAGE_SIZE.to_string());
+        if let Some(next_token) = next_token.take() {
+            params = params.add_param("next_token", next_token);
+        }
+        let resp = loop {
+            config.rate_limiter().ready().await;
+            let request = egg_mode::raw::request_get(
+                "https://api.twitter.com/2/tweets/search/all",
+                &config.token,
+                Some(&params),
+            );
+            match egg_mode::raw::response_json::<SearchIds>(request).await {
+                Ok(n) => break n,
+                Err(egg_mode::error::Error::RateLimit(limit)) => {
+                    wait_for_rate_limit(
+                        config.rate_limiter(),
+                        "Tweet Replies",
+                        reset_time(limit),
+                        message_sender,
+                    )
+                    .await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+        let ids: Vec<u64> = resp
+            .response
+            .data
+            .iter()
+            .filter_map(|found| found.id.parse().ok())
+            .collect();
+        if ids.is_empty() {
+            break;
+        }
+        config.rate_limiter().ready().await;
+        let tweets = tweet::lookup(ids, &config.token).await?;
+        handle_rate_limit(
+            config.rate_limiter(),
+            &tweets.rate_limit_status,
+            "Tweet Replies",
+            message_sender.clone(),
+        )
+        .await;
+        found.extend(tweets.response);
+        next_token = resp.response.meta.and_then(|meta| meta.next_token);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(found)
+}
+
+async fn inspect_profile(profile: &TwitterUser, sender: InstructionSender) -> CrawlResult<()> {
+    if let Some(background_image) = profile.profile_background_image_url_https.as_ref() {
+        sender
+            .send(DownloadInstruction::ProfileMedia(background_image.clone()))
+            .await?;
+    }
+    if let Some(profile_banner_url) = profile.profile_banner_url.as_ref() {
+        sender
+            .send(DownloadInstruction::ProfileMedia(
+                profile_banner_url.clone(),
+            ))
+            .await?;
+    }
+    sender
+        .send(DownloadInstruction::ProfileMedia(
+            profile.profile_image_url_https.clone(),
+        ))
+        .await?;
+    Ok(())
+}
+
+/// How many downloads are collected before they are written into the media
+/// index of the storage, so that the storage isn't locked for every file
+const MEDIA_INDEX_BATCH: usize = 32;
+
+/// A downloaded media file that is not in the media index yet
+#[derive(Debug)]
+struct DownloadedMedia {
+    url: String,
+    relative_path: String,
+    meta: MediaMeta,
+}
+
+#[derive(Debug)]
+enum DownloadOutcome {
+    Downloaded(DownloadedMedia),
+    /// The url and the size of media above `max_media_bytes`. For a download
+    /// without `Content-Length`, the size is where it was stopped
+    TooLarge(String, u64),
+}
+
+/// The downloads of the instruction handler that aren't in the media index
+/// yet. The crawl writes them into the index before it saves the archive,
+/// via `SaveThrottle` and `stop_if_cancelled`
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PendingMedia {
+    inner: Arc<std::sync::Mutex<PendingEntries>>,
+}
+
+#[derive(Debug, Default)]
+struct PendingEntries {
+    entries: Vec<DownloadedMedia>,
+    /// Media above `max_media_bytes` with its size
+    skipped: Vec<(String, u64)>,
+    /// The urls of `entries` and `skipped`, so that they are not downloaded
+    /// twice
+    urls: HashSet<String>,
+}
+
+impl PendingMedia {
+    fn push(&self, downloaded: DownloadedMedia) {
+        let Ok(mut pending) = self.inner.lock() else {
+            return;
+        };
+        pending.urls.insert(downloaded.url.clone());
+        pending.entries.push(downloaded);
+    }
+
+    fn skip(&self, url: String, bytes: u64) {
+        let Ok(mut pending) = self.inner.lock() else {
+            return;
+        };
+        pending.urls.insert(url.clone());
+        pending.skipped.push((url, bytes));
+    }
+
+    fn contains(&self, url: &str) -> bool {
+        self.inner
+            .lock()
+            .map(|pending| pending.urls.contains(url))
+            .unwrap_or(false)
+    }
+
+    fn len(&self) -> usize {
+        self.inner
+            .lock()
+            .map(|pending| pending.entries.len() + pending.skipped.len())
+            .unwrap_or(0)
+    }
+
+    /// Writes all pending downloads into the media index
+    fn flush_into(&self, data: &mut Data) {
+        let Ok(mut pending) = self.inner.lock() else {
+            return;
+        };
+        for (url, bytes) in std::mem::take(&mut pending.skipped) {
+            data.skipped_media.retain(|(skipped, _)| *skipped != url);
+            data.skipped_media.push((url, bytes));
+        }
+        for downloaded in std::mem::take(&mut pending.entries) {
+            // e.g. after `max_media_bytes` was raised
+            data.skipped_media.retain(|(skipped, _)| *skipped != downloaded.url);
+            data.media_meta.insert(downloaded.url.clone(), downloaded.meta);
+            data.media.insert(downloaded.url, downloaded.relative_path);
+        }
+        pending.urls.clear();
+    }
+
+    /// Writes all pending downloads into the media index with one lock
+    async fn flush(&self, shared_storage: &Arc<Mutex<Storage>>) {
+        if self.len() == 0 {
+            return;
+        }
+        self.flush_into(shared_storage.lock().await.data_mut());
+    }
+}
+
+/// The file name of media that isn't downloaded yet. Tweet media is named
+/// after the tweet and its position with `MediaNaming::TweetIndexed`,
+/// everything else after the hash of the url
+fn media_file_name(
+    naming: MediaNaming,
+    origin: Option<(u64, usize)>,
+    url: &str,
+    extension: &str,
+    storage: &Storage,
+) -> String {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(url.as_bytes());
+    let hash = hasher.finish();
+    match (naming, origin) {
+        (MediaNaming::TweetIndexed, Some((tweet_id, index))) => {
+            let file_name = format!("{tweet_id}_{index}.{extension}");
+            // The url isn't downloaded yet, so an existing file belongs to
+            // other media, e.g. of an earlier version of the tweet
+            if storage.media_path(&file_name).exists() {
+                format!("{tweet_id}_{index}_{hash}.{extension}")
+            } else {
+                file_name
+            }
+        }
+        _ => format!("{hash}.{extension}"),
+    }
+}
+
+/// Downloads the media of the instruction. The result still has to be added
+/// to the media index, see `PendingMedia`. `None` if there was nothing to
+/// download
+#[allow(clippy::too_many_arguments)]
+async fn handle_instruction(
+    client: &Client,
+    retry_policy: &RetryPolicy,
+    bandwidth: &BandwidthLimiter,
+    naming: MediaNaming,
+    domains: &MediaDomains,
+    max_media_bytes: Option<u64>,
+    instruction: DownloadInstruction,
+    pending: &PendingMedia,
+    shared_storage: Arc<Mutex<Storage>>,
+) -> CrawlResult<Option<DownloadOutcome>> {
+    let (origin, instruction) = match instruction {
+        DownloadInstruction::TweetMedia(tweet_id, index, media) => {
+            (Some((tweet_id, index)), *media)
+        }
+        instruction => (None, instruction),
+    };
+    let is_image = !matches!(instruction, DownloadInstruction::Movie(..));
+    let (extension, url, mut meta) = match instruction {
+        DownloadInstruction::Image(url) => (extension_for_url(&url), url, MediaMeta::default()),
+        DownloadInstruction::Movie(mime, url, meta) => (
+            match mime.subtype().as_str().to_lowercase().as_str() {
+                "mp4" => "mp4".to_string(),
+                "avi" => "avi".to_string(),
+                "3gp" => "3gp".to_string(),
+                "mov" => "mov".to_string(),
+                _ => extension_for_url(&url),
+            },
+            url,
+            meta,
+        ),
+        DownloadInstruction::ProfileMedia(url) => {
+            (extension_for_url(&url), url, MediaMeta::default())
+        }
+        _ => return Ok(None),
+    };
+    if !domains.allows(&url) {
+        info!(url, "Skipping media from a domain that is not allowed");
+        return Ok(None);
+    }
+    let (absolute_path, relative_path) = {
+        let storage = shared_storage.lock().await;
+        if storage.data().media.contains_key(&url) || pending.contains(&url) {
+            return Ok(None);
+        }
+        // Skipped before and still too large
+        let still_too_large = storage.data().skipped_media.iter().any(|(skipped, bytes)| {
+            *skipped == url && matches!(max_media_bytes, Some(max) if *bytes > max)
+        });
+        if still_too_large {
+            return Ok(None);
+        }
+        let file_name = media_file_name(naming, origin, &url, &extension, &storage);
+        (storage.media_path(&file_name), file_name)
+    };
+
+    let download = download_file(
+        client,
+        retry_policy,
+        bandwidth,
+        max_media_bytes,
+        &url,
+        &absolute_path,
+    );
+    if let Some(bytes) = download.await? {
+        return Ok(Some(DownloadOutcome::TooLarge(url, bytes)));
+    }
+
+    meta.bytes = std::fs::metadata(&absolute_path)?.len();
+    // Only the header is read
+    if let Some((width, height)) = is_image
+        .then(|| image::image_dimensions(&absolute_path).ok())
+        .flatten()
+    {
+        meta.width = Some(width);
+        meta.height = Some(height);
+    }
+
+    Ok(Some(DownloadOutcome::Downloaded(DownloadedMedia {
+        url,
+        relative_path,
+        meta,
+    })))
+}
+
+/// Downloads the url into the file. Interrupted downloads are kept in a
+/// `.part` file and continued with a range request on the next attempt or
+/// crawl. Returns the size of media that is larger than `max_media_bytes`,
+/// which isn't kept
+async fn download_file(
+    client: &Client,
+    retry_policy: &RetryPolicy,
+    bandwidth: &BandwidthLimiter,
+    max_media_bytes: Option<u64>,
+    url: &str,
+    absolute_path: &std::path::Path,
+) -> CrawlResult<Option<u64>> {
+    let part_path = part_path(absolute_path);
+    let mut failures = 0;
+    loop {
+        let offset = resumable_length(&part_path);
+        let mut request = client.get(url);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={offset}-"));
+        }
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                retry(retry_policy, &mut failures, e.into()).await?;
+                continue;
+            }
+        };
+        let status = response.status();
+        if status == StatusCode::RANGE_NOT_SATISFIABLE {
+            // The partial download doesn't match the file on the server
+            std::fs::remove_file(&part_path)?;
+            let error = CrawlError::Network(format!("Could not resume the download of {url}"));
+            retry(retry_policy, &mut failures, error).await?;
+            continue;
+        }
+        if let Err(e) = response.error_for_status_ref() {
+            let throttled = status == StatusCode::TOO_MANY_REQUESTS
+                || status == StatusCode::SERVICE_UNAVAILABLE;
+            if !throttled && status.is_client_error() {
+                // Retrying a missing file doesn't help
+                return Err(e.into());
+            }
+            let delay = throttled
+                .then(|| response.headers().get(RETRY_AFTER))
+                .flatten()
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| parse_retry_after(value, chrono::Utc::now()));
+            retry_after(retry_policy, &mut failures, e.into(), delay).await?;
+            continue;
+        }
+        // Servers that ignore the range send the whole file again
+        let append = offset > 0 && status == StatusCode::PARTIAL_CONTENT;
+        if append && content_range_start(response.headers()) != Some(offset) {
+            // The body doesn't continue the partial download
+            remove_part(&part_path);
+            let error = CrawlError::Network(format!("Unexpected Content-Range for {url}"));
+            retry(retry_policy, &mut failures, error).await?;
+            continue;
+        }
+        let existing = if append { offset } else { 0 };
+        if let (Some(max), Some(length)) = (max_media_bytes, response.content_length()) {
+            if existing + length > max {
+                remove_part(&part_path);
+                return Ok(Some(existing + length));
+            }
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&part_path)?;
+        // Without a `Content-Length`, the size is only known while downloading
+        let limit = max_media_bytes.map(|max| max.saturating_sub(existing));
+        match write_body(response, bandwidth, limit, &mut file).await {
+            Ok(BodyEnd::Complete) => break,
+            Ok(BodyEnd::TooLarge(written)) => {
+                drop(file);
+                remove_part(&part_path);
+                return Ok(Some(existing + written));
+            }
+            // e.g. a full disk, retrying doesn't help
+            Err(CrawlError::Io(e)) => return Err(CrawlError::Io(e)),
+            Err(e) => retry(retry_policy, &mut failures, e).await?,
+        }
+    }
+    std::fs::rename(&part_path, absolute_path)?;
+    Ok(None)
+}
+
+/// The first byte of a `Content-Range: bytes 100-199/200` header
+fn content_range_start(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = value.trim().strip_prefix("bytes ")?;
+    range.split('-').next()?.trim().parse().ok()
+}
+
+enum BodyEnd {
+    Complete,
+    /// The body was longer than the limit, contains the bytes that arrived
+    TooLarge(u64),
+}
+
+/// Writes the body of the response into the file as it arrives, paced by the
+/// bandwidth limiter. If the connection drops, the file keeps what arrived.
+/// Stops once more than `limit` bytes arrived
+async fn write_body(
+    mut response: reqwest::Response,
+    bandwidth: &BandwidthLimiter,
+    limit: Option<u64>,
+    file: &mut std::fs::File,
+) -> CrawlResult<BodyEnd> {
+    let mut written = 0u64;
+    while let Some(chunk) = response.chunk().await? {
+        written += chunk.len() as u64;
+        if matches!(limit, Some(limit) if written > limit) {
+            return Ok(BodyEnd::TooLarge(written));
+        }
+        bandwidth.consume(chunk.len()).await;
+        file.write_all(&chunk)?;
+    }
+    Ok(BodyEnd::Complete)
+}
+
+/// Removes a partial download that won't be continued
+fn remove_part(part_path: &std::path::Path) {
+    if part_path.exists() {
+        if let Err(e) = std::fs::remove_file(part_path) {
+            warn!("Could not remove {}: {e:?}", part_path.display());
+        }
+    }
+}
+
+/// Partial downloads older than this are started over, as the file on the
+/// server may have changed in between
+const PART_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Where the media file is downloaded to until it is complete
+fn part_path(path: &std::path::Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// The length of a partial download that can be continued, or 0. Stale
+/// partial downloads are removed
+fn resumable_length(part_path: &std::path::Path) -> u64 {
+    let Ok(metadata) = std::fs::metadata(part_path) else {
+        return 0
+    };
+    let stale = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map(|age| age > PART_MAX_AGE)
+        .unwrap_or(true);
+    if stale {
+        if let Err(e) = std::fs::remove_file(part_path) {
+            warn!("Could not remove {}: {e:?}", part_path.display());
+        }
+        return 0;
+    }
+    metadata.len()
+}
+
+fn extension_for_url(url: &str) -> String {
+    let default = "png".to_string();
+    let Ok(parsed) = url::Url::parse(url) else {
+        return default;
+    };
+    let Some(Some(last_part)) = parsed.path_segments().and_then(|e| e.last().map(|p| PathBuf::from_str(p).ok())) else {
+        return default;
+    };
+    let Some(extension) = last_part.extension().and_then(|e| e.to_str().map(|s| s.to_string())) else {
+        return default
+    };
+    extension
+}
+
+/// Shared by all crawl stages. Twitter counts some limits per app and not
+/// per endpoint, so while one stage sleeps off a rate limit, all other
+/// stages wait, too. Sleeps are serialized instead of running side by side.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimiter {
+    gate: Arc<Mutex<()>>,
+}
+
+impl RateLimiter {
+    /// Wait while another stage is sleeping off a rate limit
+    pub async fn ready(&self) {
+        let _gate = self.gate.lock().await;
+    }
+
+    /// Pause all stages until `resume_at`. The wait is computed once the
+    /// gate is acquired, so stages that waited for another one to sleep
+    /// off the same limit don't sleep again
+    pub async fn sleep_until(&self, resume_at: chrono::DateTime<chrono::Utc>) {
+        let _gate = self.gate.lock().await;
+        // The limit may already be over
+        let duration = (resume_at - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default();
+        tokio::time::sleep(duration).await;
+    }
+}
+
+/// Caps the download speed of media with a token bucket. Clones share the
+/// bucket, so the limit holds for all downloads together. Without a limit,
+/// nothing is paced
+#[derive(Clone, Debug, Default)]
+pub struct BandwidthLimiter {
+    bytes_per_sec: Option<u64>,
+    bucket: Arc<Mutex<TokenBucket>>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    /// Bytes that may be read right away. Negative while a read that
+    /// exceeded the bucket is paid off
+    tokens: f64,
+    refilled: std::time::Instant,
+}
+
+impl Default for TokenBucket {
+    fn default() -> Self {
+        Self {
+            tokens: 0.0,
+            refilled: std::time::Instant::now(),
+        }
+    }
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.filter(|rate| *rate > 0),
+            bucket: Default::default(),
+        }
+    }
+
+    /// Wait until `bytes` may be read. The bucket holds at most one
+    /// second of bytes, so pauses don't lead to bursts afterwards
+    pub async fn consume(&self, bytes: usize) {
+        let Some(rate) = self.bytes_per_sec else { return };
+        let rate = rate as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock().await;
+            let now = std::time::Instant::now();
+            let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+            bucket.refilled = now;
+            bucket.tokens -= bytes as f64;
+            (bucket.tokens < 0.0).then(|| -bucket.tokens / rate)
+        };
+        if let Some(seconds) = wait {
+            tokio::time::sleep(std::time::Duration::from_secs_f64(seconds)).await;
+        }
+    }
+}
+
+/// If the rate limit for a call is used up, delay all calls
+async fn handle_rate_limit(
+    limiter: &RateLimiter,
+    limit: &RateLimit,
+    call_info: &'static str,
+    sender: Sender<Message>,
+) {
+    if limit.remaining <= 1 {
+        let seconds = {
+            use std::time::UNIX_EPOCH;
+            match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(n) => (((limit.reset as i64) - n.as_secs() as i64) + 10) as u64,
+                Err(_) => 1000,
+            }
+        };
+        info!(
+            endpoint = call_info,
+            seconds, "Rate limit for {call_info} reached. Waiting {seconds} seconds"
+        );
+        let resume_at = chrono::Utc::now() + chrono::Duration::seconds(seconds as i64);
+        wait_for_rate_limit(limiter, call_info, resume_at, &sender).await;
+    } else {
+        trace!(
+            "Rate limit for {call_info}: {} / {}",
+            limit.remaining,
+            limit.limit
+        );
+    }
+}
+
+/// Pause all stages until `resume_at`. The UI is told when the wait starts
+/// and when it ends, so it can show a countdown
+async fn wait_for_rate_limit(
+    limiter: &RateLimiter,
+    endpoint: &str,
+    resume_at: chrono::DateTime<chrono::Utc>,
+    sender: &Sender<Message>,
+) {
+    let rate_limited = Message::RateLimited {
+        endpoint: endpoint.to_string(),
+        resume_at,
+    };
+    if let Err(e) = sender.send(rate_limited).await {
+        warn!("Could not send message: {e:?}");
+    }
+    limiter.sleep_until(resume_at).await;
+    msg(format!("Rate limit for {endpoint} is over, resuming"), sender).await;
+}
+
+/// The unix timestamp of a rate limit reset as a date
+fn reset_time(reset: i32) -> chrono::DateTime<chrono::Utc> {
+    use chrono::TimeZone;
+    chrono::Utc
+        .timestamp_opt(reset.max(0) as i64, 0)
+        .single()
+        .unwrap_or_else(chrono::Utc::now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CrawlOptions;
+    use crate::test_fixtures::{
+        storage, temp_folder, tweet, user, with_photo, with_url, with_video, OWNER_ID,
+    };
+
+    #[test]
+    fn a_refreshed_profile_downloads_only_the_changed_media() {
+        let mut old = user(OWNER_ID, "owner");
+        old.profile_image_url_https = "https://pbs.twimg.com/profile_images/1.jpg".to_string();
+        old.profile_banner_url = Some("https://pbs.twimg.com/profile_banners/1".to_string());
+        let mut new = old.clone();
+        new.description = Some("A new bio".to_string());
+        assert!(changed_profile_media(&old, &new).is_empty());
+
+        new.profile_banner_url = Some("https://pbs.twimg.com/profile_banners/2".to_string());
+        assert_eq!(
+            changed_profile_media(&old, &new),
+            vec!["https://pbs.twimg.com/profile_banners/2".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn low_disk_space_is_warned_about_from_the_share_of_media_tweets() {
+        let mut storage = storage("disk-space");
+        let mut owner = user(OWNER_ID, "owner");
+        owner.statuses_count = 1000;
+        owner.favourites_count = 0;
+        let data = storage.data_mut();
+        data.profiles.insert(OWNER_ID, owner);
+        data.tweets = (1..=10)
+            .map(|id| match id % 2 {
+                0 => with_photo(
+                    tweet(id, "photo"),
+                    &format!("https://pbs.twimg.com/{id}.jpg"),
+                ),
+                _ => tweet(id, "text"),
+            })
+            .collect();
+        let config = Config::offline(temp_folder("disk-space-config"), &user(OWNER_ID, "owner"));
+
+        // Half of the 990 missing tweets have media
+        const MB: u64 = 1024 * 1024;
+        let required = required_media_bytes(storage.data(), OWNER_ID, config.crawl_options());
+        assert_eq!(required, 495 * MB);
+
+        let storage = Arc::new(Mutex::new(storage));
+        let (sender, mut receiver) = channel(16);
+        check_disk_space(OWNER_ID, &storage, &config, &sender, |_| Ok(MB)).await;
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(Message::Warning(warning)) if warning.contains("about 495 MB")
+        ));
+
+        check_disk_space(OWNER_ID, &storage, &config, &sender, |_| Ok(10_000 * MB)).await;
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn pending_downloads_are_in_the_media_index_of_every_save() {
+        let storage = storage("pending-media");
+        let root = storage.root_folder.clone();
+        let shared_storage = Arc::new(Mutex::new(storage));
+        let config = Config::offline(
+            temp_folder("pending-media-config"),
+            &user(OWNER_ID, "owner"),
+        );
+        let pending = PendingMedia::default();
+        let download = |index: usize| DownloadedMedia {
+            url: format!("https://pbs.twimg.com/media/{index}.jpg"),
+            relative_path: format!("{index}.jpg"),
+            meta: MediaMeta {
+                bytes: index as u64,
+                ..Default::default()
+            },
+        };
+        let saved_media = || Storage::open(&root).unwrap().data().media.len();
+
+        // Fewer than a batch, so the handler hasn't flushed them yet
+        for index in 0..MEDIA_INDEX_BATCH - 1 {
+            pending.push(download(index));
+        }
+        assert!(pending.contains("https://pbs.twimg.com/media/0.jpg"));
+        let saver = SaveThrottle::new(OWNER_ID, config.crawl_options(), pending.clone());
+        saver.save(&shared_storage, &config, true).await;
+        assert_eq!(pending.len(), 0);
+        assert_eq!(saved_media(), MEDIA_INDEX_BATCH - 1);
+
+        pending.push(download(100));
+        config.cancel();
+        let cancelled = stop_if_cancelled(&config, &shared_storage, &pending).await;
+        assert!(matches!(cancelled, Err(CrawlError::Cancelled)));
+        assert_eq!(saved_media(), MEDIA_INDEX_BATCH);
+
+        pending.push(download(101));
+        pending.flush(&shared_storage).await;
+        let storage = shared_storage.lock().await;
+        assert_eq!(storage.data().media.len(), MEDIA_INDEX_BATCH + 1);
+        assert_eq!(storage.data().media_meta[&download(101).url].bytes, 101);
+        assert!(!pending.contains(&download(101).url));
+    }
+
+    #[test]
+    fn broken_videos_are_downloaded_as_movies() {
+        let mut storage = storage("redownload");
+        let video_url = "https://video.twimg.com/1.mp4".to_string();
+        let photo_url = "https://pbs.twimg.com/media/2.jpg".to_string();
+        let profile_url = "https://pbs.twimg.com/profile_images/3.jpg".to_string();
+        storage.data_mut().tweets = vec![
+            with_video(tweet(1, "video"), "https://pbs.twimg.com/1.jpg", &video_url),
+            with_photo(tweet(2, "photo"), &photo_url),
+        ];
+
+        let broken = [video_url.clone(), photo_url.clone(), profile_url.clone()];
+        let instructions = instructions_for_urls(storage.data(), broken.iter());
+
+        assert!(matches!(
+            &instructions[0],
+            DownloadInstruction::TweetMedia(1, 0, media)
+                if matches!(**media, DownloadInstruction::Movie(ref mime, ref url, _)
+                    if mime.subtype() == mime::MP4 && *url == video_url)
+        ));
+        assert!(matches!(
+            &instructions[1],
+            DownloadInstruction::TweetMedia(2, 0, media)
+                if matches!(**media, DownloadInstruction::Image(ref url) if *url == photo_url)
+        ));
+        assert!(matches!(
+            &instructions[2],
+            DownloadInstruction::ProfileMedia(url) if *url == profile_url
+        ));
+    }
+
+    #[tokio::test]
+    async fn repeated_profiles_queue_their_media_once() {
+        let (high, mut high_receiver) = channel(16);
+        let (low, mut low_receiver) = channel(16);
+        let sender = InstructionSender::new(high, low, MediaFilter::all());
+        let mut profile = user(2, "friend");
+        profile.profile_banner_url = Some("https://pbs.twimg.com/banner/2.jpg".to_string());
+
+        inspect_profile(&profile, sender.clone()).await.unwrap();
+        inspect_profile(&profile, sender.clone()).await.unwrap();
+        drop(sender);
+
+        let mut queued = Vec::new();
+        while let Some(instruction) = low_receiver.recv().await {
+            queued.push(instruction.url().unwrap().to_string());
+        }
+        assert_eq!(
+            queued,
+            vec![
+                "https://pbs.twimg.com/banner/2.jpg".to_string(),
+                profile.profile_image_url_https.clone(),
+            ]
+        );
+        assert!(high_receiver.recv().await.is_none());
     }
 
-    let mut s = shared_storage.lock().await;
-    if is_sync {
-        s.data_mut().likes.splice(0..0, collected);
-    } else {
-        s.data_mut().likes.append(&mut collected);
+    #[tokio::test]
+    async fn rate_limiter_throttles_other_tasks() {
+        let limiter = RateLimiter::default();
+        let started = std::time::Instant::now();
+        let resume_at = chrono::Utc::now() + chrono::Duration::milliseconds(200);
+
+        let sleeping = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.sleep_until(resume_at).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        limiter.ready().await;
+        assert!(started.elapsed() >= std::time::Duration::from_millis(190));
+
+        // Both hit the same limit, the second one doesn't sleep again
+        limiter.sleep_until(resume_at).await;
+        sleeping.await.unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_millis(390));
     }
 
-    config.set_paging_position("user_mentions", None);
+    #[tokio::test]
+    async fn videos_are_not_queued_without_download_videos() {
+        let options = crate::config::CrawlOptions {
+            download_videos: false,
+            ..Default::default()
+        };
+        let (high, mut high_receiver) = channel(16);
+        let (low, mut low_receiver) = channel(16);
+        let sender = InstructionSender::new(high, low, options.media_filter());
+        let video = with_video(
+            tweet(1, "video"),
+            "https://pbs.twimg.com/1.jpg",
+            "https://video.twimg.com/1.mp4",
+        );
 
-    Ok(())
-}
+        for (index, entry) in crate::helpers::media_in_tweet(&video)
+            .unwrap()
+            .into_iter()
+            .enumerate()
+        {
+            let entry = DownloadInstruction::TweetMedia(video.id, index, Box::new(entry));
+            sender.send(entry).await.unwrap();
+        }
+        drop(sender);
 
-async fn fetch_user_followers(
-    id: u64,
-    shared_storage: Arc<Mutex<Storage>>,
-    config: &Config,
-    sender: Sender<DownloadInstruction>,
-    message_sender: Sender<Message>,
-) -> Result<()> {
-    let followers = { shared_storage.lock().await.data().followers.clone() };
-    let ids = fetch_profiles_ids(
-        "Followers",
-        user::followers_ids(id, &config.token).with_page_size(100),
-        shared_storage.clone(),
-        config,
-        sender,
-        followers,
-        message_sender.clone(),
-    )
-    .await?;
-    shared_storage.lock().await.data_mut().followers = ids;
-    Ok(())
-}
+        assert!(high_receiver.recv().await.is_none());
+        assert!(low_receiver.recv().await.is_none());
+    }
 
-async fn fetch_user_follows(
-    id: u64,
-    shared_storage: Arc<Mutex<Storage>>,
-    config: &Config,
-    sender: Sender<DownloadInstruction>,
-    message_sender: Sender<Message>,
-) -> Result<()> {
-    let follows = { shared_storage.lock().await.data().follows.clone() };
-    let ids = fetch_profiles_ids(
-        "Follows",
-        user::friends_ids(id, &config.token).with_page_size(100),
-        shared_storage.clone(),
-        config,
-        sender,
-        follows,
-        message_sender.clone(),
-    )
-    .await?;
-    shared_storage.lock().await.data_mut().follows = ids;
-    Ok(())
-}
+    #[test]
+    fn profiles_missing_from_a_lookup_are_unavailable() {
+        let mut storage = storage("lookup");
+        let skipped = HashMap::from([(4, "bot".to_string())]);
 
-// Helpers
+        let missing = store_lookup(
+            storage.data_mut(),
+            &[2, 3, 4],
+            &[user(2, "returned")],
+            &skipped,
+        );
 
-async fn fetch_profiles_ids(
-    kind: &'static str,
-    mut cursor: cursor::CursorIter<cursor::IDCursor>,
-    shared_storage: Arc<Mutex<Storage>>,
-    config: &Config,
-    sender: Sender<DownloadInstruction>,
-    mut ids: Vec<u64>,
-    message_sender: Sender<Message>,
-) -> Result<Vec<u64>> {
-    msg(kind, &message_sender).await;
-    cursor.next_cursor = config.paging_position(kind).map(|e| e as i64).unwrap_or(-1);
+        assert_eq!(missing, 1);
+        let data = storage.data();
+        assert!(data.profiles.contains_key(&2));
+        assert!(!data.unavailable_profiles.contains_key(&2));
+        assert!(data.unavailable_profiles.contains_key(&3));
+        assert!(!data.unavailable_profiles.contains_key(&4));
+    }
 
-    let is_sync = config.is_sync;
+    #[tokio::test]
+    async fn app_only_skips_the_stages_that_need_a_login() {
+        let config = Config::offline(temp_folder("app-only"), &user(OWNER_ID, "owner"));
+        assert!(config.is_app_only());
+        let (sender, mut receiver) = channel(16);
 
-    loop {
-        if cursor.next_cursor == 0 {
-            break;
+        for stage in LOGIN_STAGES {
+            assert!(!should_run(stage, OWNER_ID, &config, &sender).await);
         }
-        info!("Downloading {kind} before {}", cursor.next_cursor);
-        let called = cursor.call();
-        let resp = match called.await {
-            Ok(n) => n,
-            Err(e) => match e {
-                egg_mode::error::Error::RateLimit(limit) => {
-                    msg("Rate limit for {kind} reached", &message_sender).await;
-                    sleep_until(limit).await;
-                    continue;
-                }
-                _ => {
-                    warn!("Profile Ids Error {e:?}");
-                    continue;
+        assert!(should_run("tweets", OWNER_ID, &config, &sender).await);
+        assert!(should_run("likes", OWNER_ID, &config, &sender).await);
+        drop(sender);
+
+        let mut skipped = 0;
+        while let Some(message) = receiver.recv().await {
+            assert!(matches!(message, Message::Loading(text) if text.contains("need a login")));
+            skipped += 1;
+        }
+        assert_eq!(skipped, LOGIN_STAGES.len());
+    }
+
+    #[test]
+    fn polls_of_a_lookup() {
+        let lookup: PollLookup = serde_json::from_str(
+            r#"{
+                "data": [
+                    {"id": "1", "attachments": {"poll_ids": ["11"]}},
+                    {"id": "2", "attachments": {"poll_ids": ["12"]}},
+                    {"id": "3", "edit_history_tweet_ids": ["3"]}
+                ],
+                "includes": {"polls": [
+                    {
+                        "id": "11",
+                        "options": [
+                            {"position": 2, "label": "No", "votes": 3},
+                            {"position": 1, "label": "Yes", "votes": 5}
+                        ],
+                        "voting_status": "closed",
+                        "end_datetime": "2022-11-01T12:00:00Z",
+                        "duration_minutes": 1440
+                    },
+                    {
+                        "id": "12",
+                        "options": [{"position": 1, "label": "Maybe", "votes": 0}],
+                        "voting_status": "open"
+                    }
+                ]}
+            }"#,
+        )
+        .unwrap();
+
+        let polls: HashMap<u64, Poll> = lookup.polls().into_iter().collect();
+        assert_eq!(polls.len(), 2);
+        let closed = &polls[&1];
+        assert!(closed.is_closed());
+        let labels: Vec<&str> = closed.options.iter().map(|o| o.label.as_str()).collect();
+        assert_eq!(labels, ["Yes", "No"]);
+        assert_eq!(closed.duration_minutes, Some(1440));
+        assert!(closed.end_datetime.is_some());
+        let open = &polls[&2];
+        assert!(!open.is_closed());
+        assert_eq!(open.end_datetime, None);
+
+        let without_polls: PollLookup = serde_json::from_str(r#"{"data": [{"id": "3"}]}"#).unwrap();
+        assert!(without_polls.polls().is_empty());
+    }
+
+    #[test]
+    fn edited_tweets_are_told_apart_from_unedited_ones() {
+        let lookup: PollLookup = serde_json::from_str(
+            r#"{
+                "data": [
+                    {"id": "7", "conversation_id": "7", "edit_history_tweet_ids": ["5", "7"]},
+                    {"id": "8", "conversation_id": "8", "edit_history_tweet_ids": ["8"]}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut storage = storage("edit-histories");
+        let data = storage.data_mut();
+        // Looked up before for its conversation, but not for its edits
+        data.conversation_ids.insert(7, 7);
+        assert!(data.needs_edit_history(7));
+
+        data.store_edit_histories(&[7, 8], &lookup.edit_histories());
+        assert!(data.is_edited(7));
+        assert_eq!(data.edit_history[&7], [5, 7]);
+        // Unedited tweets are only recorded as checked
+        assert!(!data.is_edited(8));
+        assert!(!data.edit_history.contains_key(&8));
+        assert!(!data.needs_edit_history(7));
+        assert!(!data.needs_edit_history(8));
+    }
+
+    #[tokio::test]
+    async fn link_cards_of_a_lookup_are_stored_once() {
+        let lookup: PollLookup = serde_json::from_str(
+            r#"{
+                "data": [
+                    {"id": "1", "entities": {"urls": [{
+                        "expanded_url": "https://t.co/abc",
+                        "unwound_url": "https://www.example.com/post",
+                        "title": "A [post]",
+                        "description": "About *things*",
+                        "images": [{"url": "https://pbs.twimg.com/card_img/1.jpg"}]
+                    }]}},
+                    {"id": "2", "entities": {"urls": [{
+                        "expanded_url": "https://github.com/terhechte/twitvault",
+                        "title": "TwitVault",
+                        "description": ""
+                    }]}},
+                    {"id": "3", "entities": {"urls": [{
+                        "expanded_url": "https://example.com/no-preview"
+                    }]}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let cards = lookup.cards();
+        assert_eq!(
+            cards[0],
+            (
+                1,
+                Card {
+                    url: "https://www.example.com/post".to_string(),
+                    title: "A [post]".to_string(),
+                    description: Some("About *things*".to_string()),
+                    image_url: Some("https://pbs.twimg.com/card_img/1.jpg".to_string()),
+                    vendor: "example.com".to_string(),
                 }
-            },
-        };
+            )
+        );
+        // A card without an image or description
+        assert_eq!(cards[1].1.image_url, None);
+        assert_eq!(cards[1].1.description, None);
+        assert_eq!(cards.len(), 2);
 
-        let new_ids = resp.response.ids.clone();
+        let link = |id| with_url(tweet(id, "see t.co/x"), "t.co/x", "https://example.com");
+        let mut data = Data::new(user(OWNER_ID, "owner"));
+        assert!(data.needs_card(&link(3)));
+        assert!(!data.needs_card(&tweet(4, "no links")));
+        data.store_cards(&[1, 2, 3], &cards);
+        assert_eq!(data.cards.len(), 2);
+        // The tweet without a card isn't looked up again
+        assert_eq!(data.no_cards, HashSet::from([3]));
+        assert!(!data.needs_card(&link(1)) && !data.needs_card(&link(3)));
 
-        if new_ids.is_empty() {
-            break;
+        // Quoted and retweeted tweets are queued for the next lookup
+        let mut config = Config::offline(temp_folder("inner-cards"), &user(OWNER_ID, "owner"));
+        config.set_crawl_options(&CrawlOptions {
+            link_previews: true,
+            ..CrawlOptions::default()
+        });
+        let storage = Arc::new(Mutex::new(storage("inner-cards")));
+        let (high, _high_receiver) = channel(16);
+        let (low, _low_receiver) = channel(16);
+        let sender = InstructionSender::new(high, low, MediaFilter::all());
+        for inner in [link(5), tweet(6, "no links")] {
+            inspect_inner_tweet(&inner, false, &config, &storage, sender.clone())
+                .await
+                .unwrap();
         }
+        assert_eq!(storage.lock().await.data().card_lookups, HashSet::from([5]));
+        storage.lock().await.data_mut().store_cards(&[5], &[]);
+        assert!(storage.lock().await.data().card_lookups.is_empty());
+    }
 
-        let mut unknown_new: Vec<_> = new_ids
-            .iter()
-            .filter(|s| !ids.contains(s))
-            .copied()
-            .collect();
-        let unknown_new_len = unknown_new.len();
+    #[tokio::test]
+    async fn concurrent_profile_lookups_keep_every_profile_once() {
+        let config = Config::offline(temp_folder("concurrent"), &user(OWNER_ID, "owner"));
+        let storage = Arc::new(Mutex::new(storage("concurrent")));
+        storage
+            .lock()
+            .await
+            .data_mut()
+            .insert_profile(user(7, "known"));
 
-        fetch_multiple_profiles_data(&unknown_new, shared_storage.clone(), config, sender.clone())
-            .await?;
+        // Follower pages can contain the same id twice
+        let ids: Vec<u64> = (1..=450).chain(1..=50).collect();
+        let filtered = profiles_to_look_up(&ids, storage.lock().await.data(), &config);
+        assert_eq!(filtered.len(), 449);
 
-        if is_sync {
-            ids.splice(0..0, unknown_new);
-        } else {
-            ids.append(&mut unknown_new);
-        }
+        let running = std::sync::atomic::AtomicUsize::new(0);
+        let most_running = std::sync::atomic::AtomicUsize::new(0);
+        let requested = std::sync::Mutex::new(Vec::new());
+        let lookup = |chunk: Vec<u64>| {
+            let (storage, running, most_running, requested) =
+                (storage.clone(), &running, &most_running, &requested);
+            async move {
+                use std::sync::atomic::Ordering;
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most_running.fetch_max(now, Ordering::SeqCst);
+                requested.lock().unwrap().extend(chunk.iter().copied());
+                tokio::time::sleep(std::time::Duration::from_millis(chunk[0] % 7)).await;
+                // Every tenth user is suspended and not returned
+                let profiles: Vec<_> = chunk
+                    .iter()
+                    .filter(|id| *id % 10 != 0)
+                    .map(|id| user(*id, &format!("user{id}")))
+                    .collect();
+                store_lookup(
+                    storage.lock().await.data_mut(),
+                    &chunk,
+                    &profiles,
+                    &HashMap::new(),
+                );
+                running.fetch_sub(1, Ordering::SeqCst);
+                CrawlResult::Ok(())
+            }
+        };
+        lookup_in_chunks(&filtered, 3, lookup).await.unwrap();
 
-        msg(format!("{kind}: {}", ids.len()), &message_sender).await;
+        let mut requested = requested.into_inner().unwrap();
+        requested.sort_unstable();
+        let expected: Vec<u64> = (1..=450).filter(|id| *id != 7).collect();
+        assert_eq!(requested, expected);
+        assert_eq!(most_running.into_inner(), 3);
+        let storage = storage.lock().await;
+        let data = storage.data();
+        assert_eq!(data.profiles.len(), 405);
+        assert_eq!(data.unavailable_profiles.len(), 45);
+        assert!((1..=450).all(
+            |id| data.profiles.contains_key(&id) ^ data.unavailable_profiles.contains_key(&id)
+        ));
+    }
 
-        // if we have less unknown then new, we ran into known data
-        if is_sync && unknown_new_len < new_ids.len() {
-            break;
-        }
+    #[tokio::test]
+    async fn replies_to_tweets_older_than_the_search_window_are_not_searched() {
+        let now = chrono::Utc::now();
+        let mut recent = tweet(2, "recent");
+        recent.created_at = now - chrono::Duration::days(SEARCH_WINDOW_DAYS - 1);
+        let mut old = tweet(1, "old");
+        old.created_at = now - chrono::Duration::days(SEARCH_WINDOW_DAYS + 1);
+        assert!(within_search_window(&recent, now));
+        assert!(!within_search_window(&old, now));
 
-        handle_rate_limit(&resp.rate_limit_status, kind, message_sender.clone()).await;
-        cursor.next_cursor = resp.response.next_cursor;
-        config.set_paging_position(kind, u64::try_from(cursor.next_cursor).ok());
+        // Without the full-archive search, no call is made and the tweet is
+        // recorded as unsearched
+        let config = Config::offline(temp_folder("old-replies"), &user(OWNER_ID, "owner"));
+        let storage = Arc::new(Mutex::new(storage("old-replies")));
+        let (high, _high_receiver) = channel(16);
+        let (low, _low_receiver) = channel(16);
+        let sender = InstructionSender::new(high, low, MediaFilter::all());
+        let (message_sender, mut message_receiver) = channel(16);
+        fetch_tweet_replies(&old, storage.clone(), &config, &sender, &message_sender)
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.lock().await.data().unsearched_replies,
+            HashSet::from([1])
+        );
+        assert!(message_receiver.try_recv().is_err());
     }
 
-    config.set_paging_position(kind, None);
+    #[tokio::test]
+    async fn a_failing_stage_lets_the_next_stages_run() {
+        let config = Config::offline(temp_folder("stages"), &user(OWNER_ID, "owner"));
+        let storage = Arc::new(Mutex::new(storage("stages")));
+        let saver = SaveThrottle::new(OWNER_ID, config.crawl_options(), PendingMedia::default());
+        let (sender, mut receiver) = channel(16);
 
-    Ok(ids)
-}
+        let failed = CrawlResult::Err(CrawlError::Api("Over capacity".to_string()));
+        // `fetch` goes on with the lists as the failure isn't returned
+        end_stage("followers", failed, &saver, &storage, &config, &sender)
+            .await
+            .unwrap();
+        end_stage("lists", Ok(()), &saver, &storage, &config, &sender)
+            .await
+            .unwrap();
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(Message::StageFailed { stage, .. }) if stage == "followers"
+        ));
+        let stages: Vec<_> = config.failed_stages().into_iter().map(|(s, _)| s).collect();
+        assert_eq!(stages, ["followers"]);
 
-async fn fetch_multiple_profiles_data(
-    ids: &[u64],
-    shared_storage: Arc<Mutex<Storage>>,
-    config: &Config,
-    sender: Sender<DownloadInstruction>,
-) -> Result<()> {
-    // only get profiles we haven't gotten yet
-    let known_ids: HashSet<u64> = shared_storage
-        .lock()
-        .await
-        .data()
-        .profiles
-        .keys()
-        .copied()
-        .collect();
-    let filtered: Vec<_> = ids
-        .iter()
-        .filter(|id| !known_ids.contains(id))
-        .copied()
-        .collect();
-    info!("Downloading {} profiles", filtered.len());
-    let profiles = user::lookup(filtered, &config.token).await?;
-    for profile in profiles.iter() {
-        inspect_profile(profile, sender.clone()).await?;
+        // A cancel still ends the crawl
+        let cancelled = end_stage(
+            "lists",
+            Err(CrawlError::Cancelled),
+            &saver,
+            &storage,
+            &config,
+            &sender,
+        );
+        assert!(matches!(cancelled.await, Err(CrawlError::Cancelled)));
+
+        // A clone of the config, e.g. of the next sync in the UI, starts
+        // without the failures once it crawls
+        let next = config.clone();
+        next.clear_failed_stages();
+        assert!(config.failed_stages().is_empty());
     }
-    shared_storage.lock().await.with_data(move |data| {
-        for profile in &profiles.response {
-            data.profiles.insert(profile.id, profile.clone());
-        }
-    });
-    Ok(())
-}
 
-async fn fetch_lists(
-    id: u64,
-    shared_storage: Arc<Mutex<Storage>>,
-    config: &Config,
-    sender: Sender<DownloadInstruction>,
-    message_sender: Sender<Message>,
-) -> Result<()> {
-    let label = "Lists";
-    msg(label, &message_sender).await;
-    let mut cursor = list::ownerships(id, &config.token).with_page_size(100);
-    cursor.next_cursor = config
-        .paging_position("lists")
-        .map(|e| e as i64)
-        .unwrap_or(-1);
-    loop {
-        let called = cursor.call();
+    #[test]
+    fn profile_lookups_have_at_most_100_ids() {
+        let ids: Vec<u64> = (0..5000).collect();
+        let chunks = lookup_chunks(&ids);
+        assert_eq!(chunks.len(), 50);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.len() == PROFILES_PER_LOOKUP));
+        assert_eq!(chunks.concat(), ids);
 
-        let resp = match called.await {
-            Ok(n) => n,
-            Err(e) => match e {
-                egg_mode::error::Error::RateLimit(limit) => {
-                    msg("Rate limit for Lists reached", &message_sender).await;
-                    sleep_until(limit).await;
-                    continue;
-                }
-                _ => {
-                    warn!("Lists Error {e:?}");
-                    continue;
-                }
-            },
+        assert_eq!(lookup_chunks(&[1, 2, 3]), vec![vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn raw_objects_are_captured_once() {
+        let storage = storage("raw-capture");
+        let archive = storage.raw_archive();
+        let mut raw = RawCapture {
+            archive: Some(archive.clone()),
+            kind: RAW_TWEETS,
+            known: HashSet::new(),
         };
+        let first = serde_json::json!({"id": 1, "full_text": "a"});
+        let second = serde_json::json!({"id": 2, "full_text": "b"});
+        raw.capture(vec![first.clone()]).unwrap();
+        raw.capture(vec![first, second]).unwrap();
 
-        let lists = resp.response.lists;
+        let lines = std::fs::read_to_string(archive.path(RAW_TWEETS)).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+        assert_eq!(archive.ids(RAW_TWEETS).unwrap(), HashSet::from([1, 2]));
+    }
 
-        if lists.is_empty() {
-            break;
+    #[test]
+    fn unavailable_raw_objects_are_recorded() {
+        let storage = storage("raw-unavailable");
+        let archive = storage.raw_archive();
+        let returned = [serde_json::json!({"id": 2})];
+        let missing = missing_ids(&[1, 2, 3], &returned);
+        assert_eq!(missing, [1, 3]);
+
+        archive.append_unavailable(RAW_PROFILES, &missing).unwrap();
+        let unavailable = archive.unavailable_ids(RAW_PROFILES).unwrap();
+        assert_eq!(unavailable, HashSet::from([1, 3]));
+        assert!(archive.unavailable_ids(RAW_TWEETS).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rapid_changes_are_saved_together() {
+        let config = Config::offline(temp_folder("save-throttle"), &user(OWNER_ID, "owner"));
+        let options = crate::config::CrawlOptions {
+            save_interval_secs: Some(3600),
+            save_every_items: Some(10),
+            ..Default::default()
+        };
+        let saver = SaveThrottle::new(OWNER_ID, &options, PendingMedia::default());
+        let shared_storage = Arc::new(Mutex::new(storage("save-throttle")));
+        let saved_count = || saver.state.lock().unwrap().last_count;
+        async fn add_tweet(storage: &Arc<Mutex<Storage>>, id: u64) {
+            storage.lock().await.data_mut().tweets.push(tweet(id, "Hi"));
         }
 
-        for list in lists {
-            info!("Fetching members for list {}", list.full_name);
-            msg(
-                format!("Processing List: {}", list.full_name),
-                &message_sender,
-            )
-            .await;
-            fetch_list_members(
-                list,
-                shared_storage.clone(),
-                config,
-                sender.clone(),
-                message_sender.clone(),
-            )
-            .await?;
+        for id in 0..9 {
+            add_tweet(&shared_storage, id).await;
+            saver.finish_stage("tweets", &shared_storage, &config).await;
         }
+        // Not saved yet, so the stage doesn't count as finished either
+        assert_eq!(saved_count(), 0);
+        assert!(config.finished_stages(OWNER_ID).is_empty());
 
-        handle_rate_limit(&resp.rate_limit_status, "Lists", message_sender.clone()).await;
-        cursor.next_cursor = resp.response.next_cursor;
-        config.set_paging_position("lists", u64::try_from(cursor.next_cursor).ok());
+        add_tweet(&shared_storage, 9).await;
+        saver.save(&shared_storage, &config, false).await;
+        assert_eq!(saved_count(), 10);
+        assert_eq!(config.finished_stages(OWNER_ID).len(), 1);
+
+        add_tweet(&shared_storage, 10).await;
+        saver.save(&shared_storage, &config, false).await;
+        assert_eq!(saved_count(), 10);
+        saver.save(&shared_storage, &config, true).await;
+        assert_eq!(saved_count(), 11);
     }
 
-    config.set_paging_position("lists", None);
-    Ok(())
-}
+    #[tokio::test]
+    async fn reply_search_pages_accumulate() {
+        let page_size = REPLY_SEARCH_PAGE_SIZE as u64;
+        // Two full pages and a partial one, newest first
+        let newest = 2 * page_size + 10;
+        let mut requested = Vec::new();
+        let found = search_pages(5, |max_id| {
+            requested.push(max_id);
+            let below = max_id.map(|id| id + 1).unwrap_or(newest + 1);
+            let page: Vec<Tweet> = (1..below)
+                .rev()
+                .take(REPLY_SEARCH_PAGE_SIZE as usize)
+                .map(|id| tweet(id, "reply"))
+                .collect();
+            async move { Ok(page) }
+        })
+        .await
+        .unwrap();
 
-async fn fetch_list_members(
-    list: list::List,
-    shared_storage: Arc<Mutex<Storage>>,
-    config: &Config,
-    sender: Sender<DownloadInstruction>,
-    message_sender: Sender<Message>,
-) -> Result<()> {
-    // Lists are not really synced, they're just not downloaded if they already exists
-    if config.is_sync {
-        let s = shared_storage.lock().await;
-        if s.data().lists.iter().any(|e| e.list.id == list.id) {
-            info!(
-                "Ignoring list {} because it was already downloaded",
-                &list.name
-            );
-            return Ok(());
-        }
+        assert_eq!(found.len() as u64, newest);
+        assert_eq!(
+            requested,
+            [None, Some(newest - page_size), Some(newest - 2 * page_size)]
+        );
+        let ids: HashSet<u64> = found.iter().map(|tweet| tweet.id).collect();
+        assert_eq!(ids.len(), found.len());
     }
 
-    let list_id = ListID::from_id(list.id);
-    let mut cursor = list::members(list_id, &config.token).with_page_size(100);
-    let paging_key = format!("list-{}", list.id);
-    cursor.next_cursor = config
-        .paging_position(&paging_key)
-        .map(|e| e as i64)
-        .unwrap_or(-1);
-    let mut member_ids = Vec::new();
-    loop {
-        let called = cursor.call();
-        let resp = match called.await {
-            Ok(n) => n,
-            Err(e) => match e {
-                egg_mode::error::Error::RateLimit(limit) => {
-                    msg("Rate limit for Lists Members reached", &message_sender).await;
-                    sleep_until(limit).await;
-                    continue;
-                }
-                _ => {
-                    warn!("Lists Members Error {e:?}");
-                    continue;
-                }
-            },
-        };
+    #[tokio::test]
+    async fn reply_search_stops_after_the_configured_pages() {
+        let mut calls = 0;
+        let found = search_pages(2, |max_id| {
+            calls += 1;
+            let newest = max_id.unwrap_or(1000);
+            let page: Vec<Tweet> = (0..REPLY_SEARCH_PAGE_SIZE as u64)
+                .map(|offset| tweet(newest - offset, "reply"))
+                .collect();
+            async move { Ok(page) }
+        })
+        .await
+        .unwrap();
 
-        if resp.users.is_empty() {
-            break;
-        }
+        assert_eq!(calls, 2);
+        assert_eq!(found.len(), 2 * REPLY_SEARCH_PAGE_SIZE as usize);
+    }
 
-        let mut storage = shared_storage.lock().await;
+    #[test]
+    fn retry_after_is_parsed_as_seconds_or_date() {
+        let now = reset_time(1_700_000_000);
+        let seconds = |secs| Some(std::time::Duration::from_secs(secs));
+        assert_eq!(parse_retry_after("120", now), seconds(120));
+        assert_eq!(parse_retry_after(" 5 ", now), seconds(5));
+        // 1_700_000_000 is Tue, 14 Nov 2023 22:13:20 GMT
+        let date = "Tue, 14 Nov 2023 22:14:20 GMT";
+        assert_eq!(parse_retry_after(date, now), seconds(60));
+        let past = "Tue, 14 Nov 2023 22:00:00 GMT";
+        assert_eq!(parse_retry_after(past, now), seconds(0));
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-3", now), None);
+    }
 
-        info!("Processing {} members", resp.users.len());
-        for member in &resp.users {
-            if let Err(e) = inspect_profile(member, sender.clone()).await {
-                warn!("Could not inspect profile {e:?}");
+    #[tokio::test]
+    async fn bandwidth_limiter_paces_a_stream() {
+        // 10 KB at 20 KB/s, starting with an empty bucket
+        let limiter = BandwidthLimiter::new(Some(20_000));
+        let chunks = futures::stream::iter(vec![vec![0u8; 2_000]; 5]);
+        let started = std::time::Instant::now();
+        let received: usize = chunks
+            .then(|chunk| {
+                let limiter = limiter.clone();
+                async move {
+                    limiter.consume(chunk.len()).await;
+                    chunk.len()
+                }
+            })
+            .fold(0, |total, len| async move { total + len })
+            .await;
+        let elapsed = started.elapsed().as_secs_f64();
+        assert_eq!(received, 10_000);
+        assert!((0.45..2.0).contains(&elapsed), "took {elapsed}s");
+
+        let unlimited = BandwidthLimiter::new(None);
+        let started = std::time::Instant::now();
+        unlimited.consume(10_000_000).await;
+        assert!(started.elapsed().as_millis() < 50);
+    }
+
+    #[tokio::test]
+    async fn bandwidth_limit_is_shared_by_the_workers() {
+        let limiter = BandwidthLimiter::new(Some(20_000));
+        let worker = |limiter: BandwidthLimiter| async move {
+            for _ in 0..5 {
+                limiter.consume(1_000).await;
             }
-            member_ids.push(member.id);
-            storage
-                .data_mut()
-                .profiles
-                .insert(member.id, member.clone());
-        }
+        };
+        let started = std::time::Instant::now();
+        futures::join!(worker(limiter.clone()), worker(limiter.clone()));
+        let elapsed = started.elapsed().as_secs_f64();
+        assert!((0.45..2.0).contains(&elapsed), "took {elapsed}s");
+    }
 
-        handle_rate_limit(
-            &resp.rate_limit_status,
-            "List Members",
-            message_sender.clone(),
-        )
-        .await;
-        cursor.next_cursor = resp.response.next_cursor;
-        config.set_paging_position(&paging_key, u64::try_from(cursor.next_cursor).ok());
+    #[test]
+    fn responses_are_only_searched_for_own_tweets() {
+        let mut config = Config::offline(temp_folder("own-tweets"), &user(OWNER_ID, "owner"));
+        let mut options = config.crawl_options().clone();
+        options.tweet_responses = true;
+        config.set_crawl_options(&options);
+        config.config_data.alt_account_ids = vec![2];
+
+        let authored = |author: Option<u64>| Tweet {
+            user: author.map(|id| Box::new(user(id, "author"))),
+            ..tweet(1, "tweet")
+        };
+
+        assert!(!searches_responses(&authored(None), &config));
+        assert!(searches_responses(&authored(Some(OWNER_ID)), &config));
+        assert!(searches_responses(&authored(Some(2)), &config));
+        assert!(!searches_responses(&authored(Some(3)), &config));
     }
 
-    config.set_paging_position(&paging_key, None);
+    #[test]
+    fn media_names_are_stable_and_unique() {
+        let storage = storage("media-names");
+        let url = "https://pbs.twimg.com/media/1.jpg";
+        let other = "https://pbs.twimg.com/media/2.jpg";
+        let name = |naming, origin, url| media_file_name(naming, origin, url, "jpg", &storage);
 
-    shared_storage.lock().await.data_mut().lists.push(List {
-        name: list.name.clone(),
-        list,
-        members: member_ids,
-    });
+        let hashed = name(MediaNaming::Hash, Some((5, 0)), url);
+        assert_eq!(hashed, name(MediaNaming::Hash, Some((5, 0)), url));
+        assert_ne!(hashed, name(MediaNaming::Hash, Some((5, 0)), other));
 
-    Ok(())
-}
+        let indexed = name(MediaNaming::TweetIndexed, Some((5, 0)), url);
+        assert_eq!(indexed, "5_0.jpg");
+        assert_eq!(indexed, name(MediaNaming::TweetIndexed, Some((5, 0)), url));
+        assert_ne!(indexed, name(MediaNaming::TweetIndexed, Some((5, 1)), url));
+        // Profile media keeps the hash
+        assert_eq!(name(MediaNaming::TweetIndexed, None, url), hashed);
 
-async fn fetch_single_profile(
-    id: u64,
-    shared_storage: Arc<Mutex<Storage>>,
-    config: &Config,
-    sender: Sender<DownloadInstruction>,
-) -> Result<()> {
-    if shared_storage
-        .lock()
-        .await
-        .data()
-        .profiles
-        .contains_key(&id)
-    {
-        return Ok(());
+        // The name is taken by other media of an earlier version of the tweet
+        std::fs::write(storage.media_path(&indexed), b"old").unwrap();
+        let renamed = name(MediaNaming::TweetIndexed, Some((5, 0)), other);
+        assert!(renamed.starts_with("5_0_") && renamed != indexed);
     }
 
-    let user = user::show(id, &config.token).await?;
-    if let Err(e) = inspect_profile(&user, sender).await {
-        warn!("Inspect profile error {e:?}");
+    #[tokio::test]
+    async fn graphs_of_protected_accounts_are_skipped() {
+        let config = Config::offline(temp_folder("protected"), &user(OWNER_ID, "owner"));
+        let storage = Arc::new(Mutex::new(storage("protected")));
+        {
+            let mut storage = storage.lock().await;
+            let data = storage.data_mut();
+            let protected = |id, screen_name| TwitterUser {
+                protected: true,
+                ..user(id, screen_name)
+            };
+            data.insert_profile(protected(OWNER_ID, "owner"));
+            data.insert_profile(protected(2, "private"));
+            data.insert_profile(user(3, "public"));
+        }
+
+        // Without a user login, no protected account counts as followed
+        assert!(graph_hidden(2, &storage, &config).await);
+        assert!(!graph_hidden(OWNER_ID, &storage, &config).await);
+        assert!(!graph_hidden(3, &storage, &config).await);
     }
 
-    shared_storage
-        .lock()
-        .await
-        .data_mut()
-        .profiles
-        .insert(id, user.response);
-    Ok(())
-}
+    #[test]
+    fn profiles_skipped_by_screen_name_are_not_looked_up_again() {
+        let mut config = Config::offline(temp_folder("skipped"), &user(OWNER_ID, "owner"));
+        config.config_data.skip_screen_names = vec!["@SomeBot".to_string()];
+        let mut storage = storage("skipped");
+        let skipped = HashMap::from([(4, "somebot".to_string())]);
+        store_lookup(storage.data_mut(), &[4], &[], &skipped);
 
-pub async fn inspect_tweet(
-    tweet: &Tweet,
-    storage: Arc<Mutex<Storage>>,
-    config: &Config,
-    sender: &Sender<DownloadInstruction>,
-    message_sender: &Sender<Message>,
-) -> Result<()> {
-    if let Err(e) = inspect_inner_tweet(tweet, config, &storage, sender.clone()).await {
-        warn!("Inspect Tweet Error {e:?}");
+        let data = storage.data();
+        assert!(!data.profiles.contains_key(&4));
+        assert!(skipped_before(4, data, &config));
+        assert!(!skipped_before(5, data, &config));
+
+        config.config_data.skip_screen_names.clear();
+        assert!(!skipped_before(4, data, &config));
     }
 
-    if let Some(quoted_tweet) = &tweet.quoted_status {
-        if let Err(e) = inspect_inner_tweet(quoted_tweet, config, &storage, sender.clone()).await {
-            warn!("Inspect Quoted Tweet Error {e:?}");
+    #[tokio::test]
+    async fn rate_limit_waits_are_announced_and_not_repeated() {
+        let limiter = RateLimiter::default();
+        let (sender, mut receiver) = channel(16);
+        let started = std::time::Instant::now();
+        let resume_at = chrono::Utc::now() + chrono::Duration::milliseconds(200);
+
+        // Both stages hit the same limit, the second queues behind the first
+        futures::join!(
+            wait_for_rate_limit(&limiter, "tweets", resume_at, &sender),
+            wait_for_rate_limit(&limiter, "likes", resume_at, &sender),
+        );
+        let elapsed = started.elapsed();
+        assert!(elapsed >= std::time::Duration::from_millis(190));
+        assert!(
+            elapsed < std::time::Duration::from_millis(390),
+            "took {elapsed:?}"
+        );
+        drop(sender);
+
+        let mut messages = Vec::new();
+        while let Some(message) = receiver.recv().await {
+            messages.push(message);
         }
+        assert!(matches!(
+            &messages[0],
+            Message::RateLimited { endpoint, resume_at: at }
+                if endpoint == "tweets" && *at == resume_at
+        ));
+        assert!(matches!(
+            messages.last(),
+            Some(Message::Loading(text)) if text.contains("is over, resuming")
+        ));
     }
 
-    if let Some(retweet) = &tweet.retweeted_status {
-        if let Err(e) = inspect_inner_tweet(retweet, config, &storage, sender.clone()).await {
-            warn!("Inspect Retweet Error {e:?}");
+    #[test]
+    fn timeline_pages_are_collected_in_order_without_archived_tweets() {
+        let ids = |tweets: &[Tweet]| tweets.iter().map(|t| t.id).collect::<Vec<_>>();
+        let pages = [
+            vec![tweet(9, "new"), tweet(8, "new")],
+            vec![tweet(7, "new"), tweet(6, "archived"), tweet(5, "archived")],
+        ];
+
+        // A full pass skips the archived tweets but reads every page
+        let known = HashSet::from([6, 5]);
+        let mut collected = Vec::new();
+        for page in pages.iter() {
+            let (mut fresh, reached) = new_tweets(page, &HashSet::new(), &known);
+            assert!(!reached);
+            collected.append(&mut fresh);
         }
+        assert_eq!(ids(&collected), [9, 8, 7]);
+
+        // A sync stops at the newest archived tweet and keeps the ones before it
+        let (fresh, reached) = new_tweets(&pages[0], &known, &HashSet::new());
+        assert_eq!((ids(&fresh), reached), (vec![9, 8], false));
+        let (fresh, reached) = new_tweets(&pages[1], &known, &HashSet::new());
+        assert_eq!((ids(&fresh), reached), (vec![7], true));
     }
 
-    if config.crawl_options().tweet_responses {
-        // for our own tweets, we search for responses.
-        // but only if we don't have a custom-user
-        if tweet.user.is_none() || tweet.user.as_ref().map(|e| e.id) == Some(config.user_id()) {
-            if let Err(e) =
-                fetch_tweet_replies(tweet, storage.clone(), config, sender, message_sender).await
-            {
-                warn!("Could not fetch replies for tweet {}: {e:?}", tweet.id);
+    #[test]
+    fn a_sync_resumed_after_cancelling_adds_no_duplicates() {
+        let ids = |tweets: &[Tweet]| tweets.iter().map(|t| t.id).collect::<Vec<_>>();
+        let timeline_ids = |data: &Data| data.tweets.iter().map(|t| t.id).collect::<HashSet<_>>();
+        let pages = [
+            vec![tweet(10, "new"), tweet(9, "new")],
+            vec![tweet(8, "new"), tweet(7, "new")],
+            vec![tweet(6, "new"), tweet(5, "new"), tweet(4, "new")],
+            vec![
+                tweet(3, "archived"),
+                tweet(2, "archived"),
+                tweet(1, "archived"),
+            ],
+        ];
+        let mut data = Data::new(user(OWNER_ID, "owner"));
+        data.add_timeline_tweets(pages[3].clone());
+
+        // The sync is cancelled after the first page, which is merged, and
+        // resumes with the second one
+        let (fresh, reached) = new_tweets(&pages[0], &timeline_ids(&data), &HashSet::new());
+        assert!(!reached);
+        data.add_timeline_tweets(fresh);
+
+        let archived = timeline_ids(&data);
+        let mut collected = Vec::new();
+        for page in &pages[1..] {
+            let (mut fresh, reached) = new_tweets(page, &archived, &HashSet::new());
+            collected.append(&mut fresh);
+            if reached {
+                break;
             }
         }
+        assert_eq!(ids(&collected), [8, 7, 6, 5, 4]);
+        data.add_timeline_tweets(collected);
+        assert_eq!(ids(&data.tweets), [10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+
+        // Merging a page again keeps one copy of each tweet
+        data.add_timeline_tweets(pages[0].clone());
+        assert_eq!(data.tweets.len(), 10);
     }
 
-    Ok(())
-}
+    #[test]
+    fn the_tweet_cap_stops_at_the_page_with_the_nth_new_tweet() {
+        let options = CrawlOptions {
+            max_items: Some(4),
+            ..CrawlOptions::default()
+        };
+        let pages = [
+            vec![tweet(12, "new"), tweet(11, "archived"), tweet(10, "new")],
+            vec![tweet(9, "new"), tweet(8, "archived"), tweet(7, "archived")],
+            vec![tweet(6, "new"), tweet(5, "new"), tweet(4, "new")],
+            vec![tweet(3, "new"), tweet(2, "new"), tweet(1, "new")],
+        ];
+        let known = HashSet::from([11, 8, 7]);
 
-async fn inspect_inner_tweet(
-    tweet: &Tweet,
-    config: &Config,
-    storage: &Arc<Mutex<Storage>>,
-    sender: Sender<DownloadInstruction>,
-) -> Result<()> {
-    if config.crawl_options().tweet_profiles {
-        if let Some(user) = &tweet.user {
-            if user.id != config.user_id() {
-                if let Err(e) =
-                    fetch_single_profile(user.id, storage.clone(), config, sender.clone()).await
-                {
-                    warn!("Could not download profile {e:?}");
-                }
+        // Like `fetch_user_tweets` in a full pass, pages are taken whole
+        let mut collected = Vec::new();
+        for page in pages.iter() {
+            let (mut fresh, _) = new_tweets(page, &HashSet::new(), &known);
+            let capped = options.reached_max_items(collected.len() + fresh.len());
+            collected.append(&mut fresh);
+            if capped {
+                break;
             }
         }
+        // The archived tweets don't count, the cap is reached on the third
+        // page and nothing after it is read
+        let ids: Vec<_> = collected.iter().map(|t| t.id).collect();
+        assert_eq!(ids, [12, 10, 9, 6, 5, 4]);
+
+        assert!(!options.reached_max_items(3));
+        assert!(options.reached_max_items(4));
+        assert!(!CrawlOptions::default().reached_max_items(usize::MAX));
     }
 
-    let Some(media) = crate::helpers::media_in_tweet(tweet) else {
-        return Ok(())
-    };
+    #[tokio::test]
+    async fn the_login_is_verified_unless_it_is_app_only() {
+        let mut config = Config::offline(temp_folder("verify"), &user(OWNER_ID, "owner"));
+        let verified = |result: egg_mode::error::Result<TwitterUser>| async move {
+            result.map(|user| egg_mode::Response {
+                rate_limit_status: egg_mode::RateLimit {
+                    limit: 75,
+                    remaining: 74,
+                    reset: 0,
+                },
+                response: user,
+            })
+        };
 
-    for entry in media {
-        if let Err(e) = sender.send(entry).await {
-            warn!("Send Error {e:?}");
+        // App-only tokens have no user, nothing is requested
+        let app_only = verify_login(&config, || async { unreachable!() }).await;
+        assert!(matches!(app_only, Ok(None)));
+
+        config.token = egg_mode::Token::Access {
+            consumer: egg_mode::KeyPair::new("key", "secret"),
+            access: egg_mode::KeyPair::new("token", "secret"),
+        };
+        let valid = verify_login(&config, || verified(Ok(user(OWNER_ID, "owner")))).await;
+        assert!(matches!(valid, Ok(Some(user)) if user.id == OWNER_ID));
+
+        let rejected = egg_mode::error::Error::BadStatus(reqwest::StatusCode::UNAUTHORIZED);
+        let revoked = verify_login(&config, || verified(Err(rejected))).await;
+        assert!(matches!(revoked, Err(CrawlError::Auth(_))));
+    }
+
+    /// The request line and the headers of a request, lowercase
+    fn read_request(stream: &mut std::net::TcpStream) -> String {
+        use std::io::Read;
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let read = stream.read(&mut buffer).unwrap();
+            request.extend_from_slice(&buffer[..read]);
         }
+        String::from_utf8_lossy(&request).to_lowercase()
     }
 
-    Ok(())
-}
+    /// Serves one file for `requests` requests and returns the offsets of
+    /// their range requests. With `wrong_range`, a range request gets the
+    /// whole file as partial content
+    fn serve_file(
+        name: &str,
+        body: &'static [u8],
+        wrong_range: bool,
+        requests: usize,
+    ) -> (String, std::thread::JoinHandle<Vec<Option<u64>>>) {
+        use std::io::Write;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/{name}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut ranges = Vec::new();
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let offset = read_request(&mut stream).lines().find_map(|line| {
+                    let range = line.strip_prefix("range: bytes=")?;
+                    range.trim_end_matches('-').parse::<u64>().ok()
+                });
+                ranges.push(offset);
+                let start = if wrong_range { 0 } else { offset.unwrap_or(0) };
+                let status = match offset {
+                    Some(_) => "206 Partial Content",
+                    None => "200 OK",
+                };
+                let len = body.len();
+                let head = format!(
+                    "HTTP/1.1 {status}\r\nContent-Range: bytes {start}-{}/{len}\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    len - 1,
+                    len - start as usize,
+                );
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(&body[start as usize..]).unwrap();
+            }
+            ranges
+        });
+        (url, server)
+    }
 
-async fn fetch_tweet_replies(
-    tweet: &Tweet,
-    storage: Arc<Mutex<Storage>>,
-    config: &Config,
-    sender: &Sender<DownloadInstruction>,
-    message_sender: &Sender<Message>,
-) -> Result<()> {
-    let search_results = egg_mode::search::search(format!("to:{}", config.screen_name()))
-        .since_tweet(tweet.id)
-        .count(100)
-        .call(&config.token)
-        .await?;
-    handle_rate_limit(
-        &search_results.rate_limit_status,
-        "Tweet Replies",
-        message_sender.clone(),
-    )
-    .await;
+    #[tokio::test]
+    async fn interrupted_downloads_are_resumed() {
+        const VIDEO: &[u8] = b"0123456789";
+        let client = Client::builder().no_proxy().build().unwrap();
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            base_delay: 1,
+            max_delay: 1,
+            jitter: false,
+        };
+        let bandwidth = BandwidthLimiter::default();
+        let path = temp_folder("resume").join("video.mp4");
 
-    msg(
-        format!(
-            "Processing {} responses",
-            search_results.response.statuses.len()
-        ),
-        message_sender,
-    )
-    .await;
+        std::fs::write(part_path(&path), &VIDEO[..4]).unwrap();
+        let (url, server) = serve_file("video.mp4", VIDEO, false, 1);
+        let too_large = download_file(&client, &policy, &bandwidth, None, &url, &path);
+        assert_eq!(too_large.await.unwrap(), None);
+        assert_eq!(server.join().unwrap(), [Some(4)]);
+        assert_eq!(std::fs::read(&path).unwrap(), VIDEO);
+        assert!(!part_path(&path).exists());
 
-    let mut replies = Vec::new();
+        // A response that doesn't continue the partial download starts over
+        std::fs::write(part_path(&path), &VIDEO[..4]).unwrap();
+        let (url, server) = serve_file("video.mp4", VIDEO, true, 2);
+        let too_large = download_file(&client, &policy, &bandwidth, None, &url, &path);
+        assert_eq!(too_large.await.unwrap(), None);
+        assert_eq!(server.join().unwrap(), [Some(4), None]);
+        assert_eq!(std::fs::read(&path).unwrap(), VIDEO);
+    }
 
-    for related_tweet in search_results.response.statuses.into_iter() {
-        if related_tweet.in_reply_to_status_id == Some(tweet.id) {
-            if let Err(e) =
-                inspect_inner_tweet(&related_tweet, config, &storage, sender.clone()).await
-            {
-                warn!("Could not inspect tweet {}: {e:?}", related_tweet.id);
-            }
-            replies.push(related_tweet);
+    async fn download(
+        instruction: DownloadInstruction,
+        pending: &PendingMedia,
+        storage: &Arc<Mutex<Storage>>,
+        config: &Config,
+    ) -> DownloadedMedia {
+        let client = Client::builder().no_proxy().build().unwrap();
+        let outcome = handle_instruction(
+            &client,
+            config.retry_policy(),
+            &BandwidthLimiter::default(),
+            MediaNaming::default(),
+            &config.media_domains(),
+            None,
+            instruction,
+            pending,
+            storage.clone(),
+        )
+        .await;
+        match outcome.unwrap() {
+            Some(DownloadOutcome::Downloaded(downloaded)) => downloaded,
+            outcome => panic!("Unexpected {outcome:?}"),
         }
     }
 
-    if replies.is_empty() {
-        return Ok(());
-    }
+    #[tokio::test]
+    async fn a_quote_depth_of_two_fetches_the_quote_of_the_quote() {
+        let mut config = Config::offline(temp_folder("quote-chain"), &user(OWNER_ID, "owner"));
+        let storage = Arc::new(Mutex::new(storage("quote-chain")));
+        let (high, _high_receiver) = channel(16);
+        let (low, _low_receiver) = channel(16);
+        let sender = InstructionSender::new(high, low, MediaFilter::all());
 
-    let mut shared_storage = storage.lock().await;
-    shared_storage
-        .data_mut()
-        .responses
-        .insert(tweet.id, replies);
+        // 30 quotes 20, which quotes 10, which quotes 30 again. Only the
+        // first level is embedded
+        let mut second = tweet(10, "second level");
+        second.quoted_status_id = Some(30);
+        let mut first = tweet(20, "first level");
+        first.quoted_status_id = Some(10);
+        let mut quote = tweet(30, "quote");
+        quote.quoted_status_id = Some(20);
+        quote.quoted_status = Some(Box::new(first.clone()));
 
-    Ok(())
-}
+        let shown = std::cell::RefCell::new(Vec::new());
+        let show = |id: u64| {
+            shown.borrow_mut().push(id);
+            let found = (id == 10).then(|| second.clone());
+            async move { found }
+        };
 
-async fn inspect_profile(profile: &TwitterUser, sender: Sender<DownloadInstruction>) -> Result<()> {
-    if let Some(background_image) = profile.profile_background_image_url_https.as_ref() {
-        sender
-            .send(DownloadInstruction::ProfileMedia(background_image.clone()))
-            .await?;
-    }
-    if let Some(profile_banner_url) = profile.profile_banner_url.as_ref() {
-        sender
-            .send(DownloadInstruction::ProfileMedia(
-                profile_banner_url.clone(),
-            ))
-            .await?;
+        // The default depth of 1 only keeps the embedded quote
+        follow_quote_chain(&quote, &first, &storage, &config, &sender, show)
+            .await
+            .unwrap();
+        assert!(shown.borrow().is_empty());
+
+        config.set_crawl_options(&CrawlOptions {
+            quote_depth: Some(2),
+            ..CrawlOptions::default()
+        });
+        follow_quote_chain(&quote, &first, &storage, &config, &sender, show)
+            .await
+            .unwrap();
+        assert_eq!(*shown.borrow(), [10]);
+        let quoted = storage.lock().await.data().quoted_tweets.clone();
+        assert_eq!(quoted.keys().collect::<Vec<_>>(), [&10]);
+
+        // Deeper, the chain ends where it quotes itself
+        config.set_crawl_options(&CrawlOptions {
+            quote_depth: Some(5),
+            ..CrawlOptions::default()
+        });
+        follow_quote_chain(&quote, &first, &storage, &config, &sender, show)
+            .await
+            .unwrap();
+        assert_eq!(*shown.borrow(), [10]);
+
+        // and at tweets that are not available
+        storage.lock().await.data_mut().quoted_tweets.clear();
+        let deleted = |id: u64| {
+            shown.borrow_mut().push(id);
+            async move { None }
+        };
+        follow_quote_chain(&quote, &first, &storage, &config, &sender, deleted)
+            .await
+            .unwrap();
+        assert_eq!(*shown.borrow(), [10, 10]);
+        assert!(storage.lock().await.data().quoted_tweets.is_empty());
     }
-    sender
-        .send(DownloadInstruction::ProfileMedia(
-            profile.profile_image_url_https.clone(),
-        ))
-        .await?;
-    Ok(())
-}
 
-async fn handle_instruction(
-    client: &Client,
-    instruction: DownloadInstruction,
-    shared_storage: Arc<Mutex<Storage>>,
-) -> Result<()> {
-    let (extension, url) = match instruction {
-        DownloadInstruction::Image(url) => (extension_for_url(&url), url),
-        DownloadInstruction::Movie(mime, url) => (
-            match mime.subtype().as_str().to_lowercase().as_str() {
-                "mp4" => "mp4".to_string(),
-                "avi" => "avi".to_string(),
-                "3gp" => "3gp".to_string(),
-                "mov" => "mov".to_string(),
-                _ => extension_for_url(&url),
-            },
-            url,
-        ),
-        DownloadInstruction::ProfileMedia(url) => (extension_for_url(&url), url),
-        _ => return Ok(()),
-    };
-    let (absolute_path, relative_path) = {
-        let storage = shared_storage.lock().await;
-        if storage.data().media.contains_key(&url) {
-            return Ok(());
+    #[tokio::test]
+    async fn media_metadata_is_stored_once_the_media_is_downloaded() {
+        let mut config = Config::offline(temp_folder("media-meta"), &user(OWNER_ID, "owner"));
+        config.config_data.media_allowed_domains = Some(vec!["127.0.0.1".to_string()]);
+        let storage = Arc::new(Mutex::new(storage("media-meta")));
+
+        // Inspecting a video tweet only queues the download
+        let (url, server) = serve_file("video.mp4", b"0123456789", false, 1);
+        let video = with_video(tweet(1, "video"), "https://pbs.twimg.com/1.jpg", &url);
+        let (high, mut high_receiver) = channel(16);
+        let (low, _low_receiver) = channel(16);
+        let sender = InstructionSender::new(high, low, MediaFilter::all());
+        let (message_sender, _message_receiver) = channel(16);
+        inspect_tweet(&video, storage.clone(), &config, &sender, &message_sender)
+            .await
+            .unwrap();
+        assert!(storage.lock().await.data().media_meta.is_empty());
+
+        let pending = PendingMedia::default();
+        while let Ok(instruction) = high_receiver.try_recv() {
+            if instruction.url() != Some(url.as_str()) {
+                continue;
+            }
+            let downloaded = download(instruction, &pending, &storage, &config).await;
+            pending.push(downloaded);
         }
-        let mut hasher = DefaultHasher::new();
-        hasher.write(url.as_bytes());
-        let file_name = format!("{}.{extension}", hasher.finish());
-        (storage.media_path(&file_name), file_name)
-    };
+        server.join().unwrap();
 
-    let mut fp = std::fs::File::create(&absolute_path)?;
+        // A PNG gets the dimensions of the file
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(3, 2)
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        let png: &'static [u8] = Box::leak(png.into_inner().into_boxed_slice());
+        let (image_url, server) = serve_file("image.png", png, false, 1);
+        let image = DownloadInstruction::Image(image_url.clone());
+        let downloaded = download(image, &pending, &storage, &config).await;
+        pending.push(downloaded);
+        server.join().unwrap();
 
-    let bytes = client.get(&url).send().await?.bytes().await?;
+        pending.flush(&storage).await;
+        let storage = storage.lock().await;
+        let meta = &storage.data().media_meta;
+        let video_meta = MediaMeta {
+            bytes: 10,
+            width: Some(100),
+            height: Some(100),
+            duration_millis: Some(1000),
+        };
+        assert_eq!(meta.get(&url), Some(&video_meta));
+        let image_meta = MediaMeta {
+            bytes: png.len() as u64,
+            width: Some(3),
+            height: Some(2),
+            duration_millis: None,
+        };
+        assert_eq!(meta.get(&image_url), Some(&image_meta));
+    }
 
-    fp.write_all(&bytes)?;
+    #[tokio::test]
+    async fn media_requests_send_the_configured_user_agent() {
+        use std::io::Write;
+        let folder = temp_folder("user-agent");
+        let mut config = Config::offline(folder.clone(), &user(OWNER_ID, "owner"));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/image.jpg", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                requests.push(read_request(&mut stream));
+                let response =
+                    "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
 
-    shared_storage
-        .lock()
-        .await
-        .data_mut()
-        .media
-        .insert(url, relative_path);
+        let path = folder.join("image.jpg");
+        for user_agent in [None, Some("MyArchive/1.0 (me@example.com)")] {
+            config.config_data.user_agent = user_agent.map(str::to_string);
+            let client = config.http_client().unwrap();
+            let bandwidth = BandwidthLimiter::default();
+            let download = download_file(
+                &client,
+                config.retry_policy(),
+                &bandwidth,
+                None,
+                &url,
+                &path,
+            );
+            assert_eq!(download.await.unwrap(), None);
+            std::fs::remove_file(&path).unwrap();
+        }
 
-    Ok(())
-}
+        let requests = server.join().unwrap();
+        assert!(requests[0].contains("\r\nuser-agent: twitvault/"));
+        assert!(requests[1].contains("\r\nuser-agent: myarchive/1.0 (me@example.com)\r\n"));
+    }
 
-fn extension_for_url(url: &str) -> String {
-    let default = "png".to_string();
-    let Ok(parsed) = url::Url::parse(url) else {
-        return default;
-    };
-    let Some(Some(last_part)) = parsed.path_segments().and_then(|e| e.last().map(|p| PathBuf::from_str(p).ok())) else {
-        return default;
-    };
-    let Some(extension) = last_part.extension().and_then(|e| e.to_str().map(|s| s.to_string())) else {
-        return default
-    };
-    extension
-}
+    #[tokio::test]
+    async fn media_is_only_downloaded_from_allowed_domains() {
+        let folder = temp_folder("media-domains");
+        let mut config = Config::offline(folder.clone(), &user(OWNER_ID, "owner"));
+        config.config_data.media_allowed_domains =
+            Some(vec!["127.0.0.1".to_string(), "example.com".to_string()]);
+        config.config_data.media_blocked_domains = vec!["cdn.example.com".to_string()];
 
-/// If the rate limit for a call is used up, delay that particular call
-async fn handle_rate_limit(limit: &RateLimit, call_info: &'static str, sender: Sender<Message>) {
-    if limit.remaining <= 1 {
-        let seconds = {
-            use std::time::UNIX_EPOCH;
-            match SystemTime::now().duration_since(UNIX_EPOCH) {
-                Ok(n) => (((limit.reset as i64) - n.as_secs() as i64) + 10) as u64,
-                Err(_) => 1000,
-            }
+        // Nothing is requested from a blocked host
+        let shared_storage = Arc::new(Mutex::new(storage("media-domains")));
+        let blocked = DownloadInstruction::Image("https://cdn.example.com/1.jpg".to_string());
+        let outcome = handle_instruction(
+            &Client::builder().no_proxy().build().unwrap(),
+            config.retry_policy(),
+            &BandwidthLimiter::default(),
+            MediaNaming::default(),
+            &config.media_domains(),
+            None,
+            blocked,
+            &PendingMedia::default(),
+            shared_storage,
+        )
+        .await;
+        assert!(outcome.unwrap().is_none());
+
+        // A repair downloads from the hosts that the config allows
+        let (url, server) = serve_file("photo.jpg", b"jpeg", false, 1);
+        let mut storage = storage("media-domains");
+        storage.data_mut().tweets = vec![with_photo(tweet(1, "photo"), &url)];
+        storage
+            .data_mut()
+            .media
+            .insert(url.clone(), "media/1.jpg".to_string());
+        let report = MediaReport {
+            total: 1,
+            missing: vec![url.clone()],
+            corrupt: Vec::new(),
         };
-        info!("Rate limit for {call_info} reached. Waiting {seconds} seconds");
-        if let Err(e) = sender
-            .send(Message::Loading(format!(
-                "Rate limit for {call_info} reached. Waiting {seconds} seconds"
-            )))
+        let storage = re_download_missing(storage, &report, &config)
             .await
-        {
-            warn!("Could not send message: {e:?}");
-        }
-        let wait_duration = tokio::time::Duration::from_secs(seconds);
-        tokio::time::sleep(wait_duration).await;
-    } else {
-        trace!(
-            "Rate limit for {call_info}: {} / {}",
-            limit.remaining,
-            limit.limit
+            .unwrap();
+        let file_name = &storage.data().media[&url];
+        assert_eq!(
+            std::fs::read(storage.media_path(file_name)).unwrap(),
+            b"jpeg"
         );
+        server.join().unwrap();
     }
-}
 
-async fn sleep_until(time: i32) {
-    if time < 0 {
-        return;
+    #[tokio::test]
+    async fn oversized_media_is_skipped_and_recorded() {
+        use std::io::Write;
+        const VIDEO: &[u8] = b"0123456789";
+        let folder = temp_folder("oversized");
+        let mut config = Config::offline(folder.clone(), &user(OWNER_ID, "owner"));
+        config.config_data.media_allowed_domains = Some(vec!["127.0.0.1".to_string()]);
+        let shared_storage = Arc::new(Mutex::new(storage("oversized")));
+        let pending = PendingMedia::default();
+
+        // One response announces its size, the other one only ends
+        let (announced, server) = serve_file("announced.jpg", VIDEO, false, 1);
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let streamed = format!("http://{}/streamed.jpg", listener.local_addr().unwrap());
+        let streaming_server = std::thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            read_request(&mut stream);
+            let head = "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n";
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(VIDEO).unwrap();
+        });
+
+        for url in [&announced, &streamed] {
+            let outcome = handle_instruction(
+                &Client::builder().no_proxy().build().unwrap(),
+                config.retry_policy(),
+                &BandwidthLimiter::default(),
+                MediaNaming::default(),
+                &config.media_domains(),
+                Some(4),
+                DownloadInstruction::Image(url.clone()),
+                &pending,
+                shared_storage.clone(),
+            )
+            .await;
+            match outcome.unwrap() {
+                Some(DownloadOutcome::TooLarge(skipped, bytes)) => pending.skip(skipped, bytes),
+                outcome => panic!("Unexpected {outcome:?}"),
+            }
+        }
+        server.join().unwrap();
+        streaming_server.join().unwrap();
+
+        let mut storage = shared_storage.lock().await;
+        pending.flush_into(storage.data_mut());
+        let data = storage.data();
+        assert!(data.media.is_empty());
+        // The streamed size is what arrived before the download stopped
+        assert_eq!(data.skipped_media.len(), 2);
+        assert_eq!(data.skipped_media[0], (announced, VIDEO.len() as u64));
+        assert_eq!(data.skipped_media[1].0, streamed);
+        assert!(data.skipped_media[1].1 > 4);
+        let files = std::fs::read_dir(storage.media_path("")).unwrap().count();
+        assert_eq!(files, 0);
     }
-    let time = time as u64;
-    let system_time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(time);
-    let seconds = system_time
-        .duration_since(SystemTime::now())
-        .map(|e| e.as_secs())
-        .unwrap_or(1000);
-    let wait_duration = tokio::time::Duration::from_secs(seconds);
-    tokio::time::sleep(wait_duration).await;
 }
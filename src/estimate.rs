@@ -0,0 +1,214 @@
+//! A rough estimate of how many API calls a crawl needs and how long
+//! it takes, without downloading anything
+use std::time::Duration;
+
+use egg_mode::{list, user};
+use tokio::sync::mpsc::Sender;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::types::{CrawlError, Message};
+
+/// The user timeline only returns the latest 3200 tweets
+pub(crate) const MAX_TIMELINE_TWEETS: u64 = 3200;
+/// The mentions timeline only returns the latest 800 mentions
+const MAX_MENTIONS: u64 = 800;
+/// The length of a rate limit window
+const WINDOW: Duration = Duration::from_secs(15 * 60);
+/// A guess for the duration of one API call
+const CALL_DURATION: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone)]
+pub struct StageEstimate {
+    pub name: &'static str,
+    pub calls: u64,
+    pub duration: Duration,
+}
+
+impl StageEstimate {
+    /// `limit` is the number of calls per 15 minute window of the endpoint.
+    /// Once it is used up, the crawler waits for the next window
+    fn new(name: &'static str, calls: u64, limit: u64) -> Self {
+        let waits = calls.saturating_sub(1) / limit.max(1);
+        let duration = (WINDOW * waits as u32).max(CALL_DURATION * calls as u32);
+        Self {
+            name,
+            calls,
+            duration,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CrawlEstimate {
+    pub stages: Vec<StageEstimate>,
+}
+
+impl CrawlEstimate {
+    pub fn calls(&self) -> u64 {
+        self.stages.iter().map(|s| s.calls).sum()
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.stages.iter().map(|s| s.duration).sum()
+    }
+}
+
+impl std::fmt::Display for CrawlEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for stage in &self.stages {
+            writeln!(
+                f,
+                "{}: ~{} calls, ~{} min",
+                stage.name,
+                stage.calls,
+                minutes(stage.duration)
+            )?;
+        }
+        writeln!(
+            f,
+            "total: ~{} calls, ~{} min",
+            self.calls(),
+            minutes(self.duration())
+        )
+    }
+}
+
+fn minutes(duration: Duration) -> u64 {
+    (duration.as_secs() + 59) / 60
+}
+
+fn pages(items: u64, page_size: i32) -> u64 {
+    let page_size = page_size.max(1) as u64;
+    (items + page_size - 1) / page_size
+}
+
+/// Reads the totals from the profile (and the first page of the lists)
+/// and estimates the calls of every enabled stage. A sync usually
+/// needs far fewer calls, as it stops at already archived data.
+pub async fn estimate(user_id: u64, config: &Config) -> Result<CrawlEstimate, CrawlError> {
+    let options = config.crawl_options();
+    let profile = user::show(user_id, &config.token).await?.response;
+    let tweets = (profile.statuses_count.max(0) as u64).min(MAX_TIMELINE_TWEETS);
+    let mut stages = Vec::new();
+
+    if options.tweets {
+        let calls = pages(tweets, options.tweets_page_size());
+        stages.push(StageEstimate::new("Tweets", calls, 900));
+    }
+    if options.tweet_responses {
+        // At most, as the paging stops at the first partial page
+        let calls = tweets * u64::from(options.reply_search_pages());
+        stages.push(StageEstimate::new("Responses", calls, 180));
+    }
+    if options.mentions && config.user_id() == user_id && !config.is_app_only() {
+        let calls = pages(MAX_MENTIONS, options.tweets_page_size());
+        stages.push(StageEstimate::new("Mentions", calls, 75));
+    }
+    if options.likes {
+        let likes = profile.favourites_count.max(0) as u64;
+        stages.push(StageEstimate::new(
+            "Likes",
+            pages(likes, options.tweets_page_size()),
+            75,
+        ));
+    }
+    for (enabled, name, lookups_name, count) in [
+        (
+            options.followers,
+            "Followers",
+            "Follower Profiles",
+            profile.followers_count,
+        ),
+        (
+            options.follows,
+            "Follows",
+            "Follow Profiles",
+            profile.friends_count,
+        ),
+    ] {
+        if !enabled {
+            continue;
+        }
+        let count = count.max(0) as u64;
+        stages.push(StageEstimate::new(
+            name,
+            pages(count, options.profiles_page_size()),
+            15,
+        ));
+        stages.push(StageEstimate::new(lookups_name, pages(count, 100), 900));
+    }
+    if options.lists {
+        let lists = list::ownerships(user_id, &config.token)
+            .with_page_size(100)
+            .call()
+            .await?
+            .response
+            .lists;
+        let member_calls: u64 = lists
+            .iter()
+            .map(|l| pages(l.member_count, options.list_members_page_size()))
+            .sum();
+        stages.push(StageEstimate::new("Lists", 1 + member_calls, 900));
+    }
+    if options.metrics {
+        stages.push(StageEstimate::new("Tweet Metrics", pages(tweets, 100), 900));
+    }
+    // Polls, link previews, edit histories and threads share one lookup
+    if options.v2_lookup() {
+        stages.push(StageEstimate::new("Tweet Polls", pages(tweets, 100), 900));
+    }
+    if options.raw_json {
+        let profiles = (profile.followers_count.max(0) + profile.friends_count.max(0)) as u64;
+        let calls = pages(tweets, 100) + pages(profiles, 100);
+        stages.push(StageEstimate::new("Raw JSON", calls, 900));
+    }
+
+    Ok(CrawlEstimate { stages })
+}
+
+/// A dry run: sends the estimate as `Message::Estimate`, or `Message::Error`
+/// if the totals can't be read. Nothing is downloaded or written
+pub async fn send_estimate(user_id: u64, config: &Config, sender: &Sender<Message>) {
+    let message = match estimate(user_id, config).await {
+        Ok(estimate) => Message::Estimate(estimate),
+        Err(e) => Message::Error(e.into()),
+    };
+    if let Err(e) = sender.send(message).await {
+        warn!("Could not send the estimate: {e:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_the_next_window_when_the_limit_is_used_up() {
+        // 15 calls per window: the 16th has to wait for the next one
+        assert_eq!(
+            StageEstimate::new("Followers", 15, 15).duration,
+            CALL_DURATION * 15
+        );
+        assert_eq!(StageEstimate::new("Followers", 16, 15).duration, WINDOW);
+        assert_eq!(StageEstimate::new("Followers", 31, 15).duration, WINDOW * 2);
+        assert_eq!(pages(3200, 200), 16);
+        assert_eq!(pages(201, 200), 2);
+        assert_eq!(pages(0, 200), 0);
+    }
+
+    #[test]
+    fn estimate_message_lists_every_stage() {
+        let estimate = CrawlEstimate {
+            stages: vec![
+                StageEstimate::new("Tweets", 16, 900),
+                StageEstimate::new("Followers", 16, 15),
+            ],
+        };
+        assert_eq!(estimate.calls(), 32);
+        assert_eq!(
+            Message::Estimate(estimate).to_string(),
+            "Tweets: ~16 calls, ~1 min\nFollowers: ~16 calls, ~15 min\ntotal: ~32 calls, ~16 min\n"
+        );
+    }
+}
@@ -0,0 +1,703 @@
+//! Export the archived data into formats that can be read without TwitVault
+pub mod csv;
+pub mod pdf;
+pub mod rss;
+mod single_file;
+mod snapshot;
+pub mod template;
+pub mod text;
+
+pub use single_file::single_file_html;
+pub use snapshot::snapshot_png;
+pub use template::TweetTemplate;
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use egg_mode::tweet::Tweet;
+use eyre::{bail, Result};
+use tokio::sync::mpsc::Sender;
+use tracing::warn;
+
+use crate::helpers::{
+    escape_html, media_urls_in_tweet, render_tweet_html, truncate_tweet_text, tweet_permalink,
+    EntityLinks,
+};
+use crate::storage::{tweet_lang, Data};
+use crate::types::Message;
+
+/// DejaVu Sans, which covers most scripts but not CJK or emoji. See
+/// `assets/DejaVuSans-LICENSE`
+const FONT: &[u8] = include_bytes!("assets/DejaVuSans.ttf");
+
+/// The length of the tweet text that link previews of an export show
+const PREVIEW_CHARS: usize = 160;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Html,
+    /// One HTML file with the tweets and the small media inlined, that can
+    /// be opened without a server
+    SingleFileHtml,
+    Json,
+    Markdown,
+    /// The followers and follows, one file each
+    Csv,
+    /// The tweets of the owner within the range, with images
+    Pdf(pdf::DateRange),
+    /// The tweets of the owner as plain text, one per line
+    Text { oldest_first: bool },
+    /// The tweets of the owner as an RSS feed
+    Rss,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Html | ExportFormat::SingleFileHtml => "html",
+            ExportFormat::Json => "json",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Pdf(_) => "pdf",
+            ExportFormat::Text { .. } => "txt",
+            ExportFormat::Rss => "xml",
+        }
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Html => f.write_str("HTML"),
+            ExportFormat::SingleFileHtml => f.write_str("Single File HTML"),
+            ExportFormat::Json => f.write_str("JSON"),
+            ExportFormat::Markdown => f.write_str("Markdown"),
+            ExportFormat::Csv => f.write_str("CSV"),
+            ExportFormat::Pdf(_) => f.write_str("PDF"),
+            ExportFormat::Text { .. } => f.write_str("Text"),
+            ExportFormat::Rss => f.write_str("RSS"),
+        }
+    }
+}
+
+/// The collections that an export contains. Everything by default
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportSelection {
+    /// The tweets of the owner and the replies to them
+    pub tweets: bool,
+    pub mentions: bool,
+    pub likes: bool,
+    /// The followers and follows
+    pub followers: bool,
+    pub lists: bool,
+    /// The downloaded images, e.g. in the PDF
+    pub media: bool,
+    /// Only the tweets in this language, e.g. `en` or `und` for the ones
+    /// without a detectable language
+    pub lang: Option<String>,
+}
+
+impl Default for ExportSelection {
+    fn default() -> Self {
+        Self {
+            tweets: true,
+            mentions: true,
+            likes: true,
+            followers: true,
+            lists: true,
+            media: true,
+            lang: None,
+        }
+    }
+}
+
+impl ExportSelection {
+    /// Removes the collections that aren't selected
+    fn apply(&self, data: &mut Data) {
+        // Quoted and retweeted tweets are exported with the tweets that
+        // quote or retweet them
+        let inner = std::mem::take(&mut data.inner_tweets);
+        data.tweets.retain(|tweet| !inner.contains(&tweet.id));
+        if !self.tweets {
+            data.tweets.clear();
+            data.responses.clear();
+        }
+        if !self.mentions {
+            data.mentions.clear();
+        }
+        if !self.likes {
+            data.likes.clear();
+        }
+        if !self.followers {
+            data.followers.clear();
+            data.follows.clear();
+        }
+        if !self.lists {
+            data.lists.clear();
+        }
+        if !self.media {
+            data.media.clear();
+            data.media_meta.clear();
+        }
+        if let Some(code) = &self.lang {
+            let in_lang = |tweet: &Tweet| tweet_lang(tweet).eq_ignore_ascii_case(code);
+            data.tweets.retain(in_lang);
+            data.mentions.retain(in_lang);
+            data.likes.retain(in_lang);
+            for responses in data.responses.values_mut() {
+                responses.retain(in_lang);
+            }
+        }
+    }
+}
+
+/// Export in the background and report the outcome via the `Message` channel
+pub async fn export_in_background(
+    data: Data,
+    format: ExportFormat,
+    selection: ExportSelection,
+    folder: PathBuf,
+    media_folder: PathBuf,
+    template_folder: Option<PathBuf>,
+    sender: Sender<Message>,
+) {
+    let result = tokio::task::spawn_blocking(move || {
+        export(
+            data,
+            format,
+            selection,
+            &folder,
+            &media_folder,
+            template_folder.as_deref(),
+        )
+    })
+    .await;
+    let message = match result {
+        Ok(Ok(path)) => Message::Loading(format!("Exported to {}", path.display())),
+        Ok(Err(e)) => Message::Error(e),
+        Err(e) => Message::Error(eyre::eyre!("Export task failed: {e:?}")),
+    };
+    if let Err(e) = sender.send(message).await {
+        warn!("Could not send export message: {e:?}");
+    }
+}
+
+/// Writes the selected collections of the data into a file in `folder` and
+/// returns the path of the file. Formats that consist of multiple files
+/// return the folder. Formats with images read them from `media_folder`.
+/// The tweet templates are in `template_folder`, or else in the archive
+pub fn export(
+    mut data: Data,
+    format: ExportFormat,
+    selection: ExportSelection,
+    folder: &Path,
+    media_folder: &Path,
+    template_folder: Option<&Path>,
+) -> Result<PathBuf> {
+    selection.apply(&mut data);
+    let data = &data;
+    // The CSV files only contain the followers and follows
+    if format == ExportFormat::Csv {
+        if selection.followers {
+            csv::export(data, folder)?;
+        }
+        return Ok(folder.to_path_buf());
+    }
+    // So that it doesn't replace the normal HTML export
+    let suffix = match format {
+        ExportFormat::SingleFileHtml => "-single",
+        _ => "",
+    };
+    let path = folder.join(format!(
+        "twitvault-{}{suffix}.{}",
+        data.profile.screen_name,
+        format.extension()
+    ));
+    // The archive contains the media folder
+    let template = match format {
+        ExportFormat::Markdown | ExportFormat::Html => {
+            let archive_folder = media_folder.parent().unwrap_or(media_folder);
+            let template_folder = template_folder
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| archive_folder.join(template::TEMPLATE_FOLDER));
+            TweetTemplate::load(&template_folder, format.extension())?
+        }
+        _ => None,
+    };
+    let media_links = template::MediaLinks::new(media_folder, folder);
+    let template = template.as_ref().map(|template| (template, &media_links));
+    let mut fp = std::io::BufWriter::new(std::fs::File::create(&path)?);
+    match format {
+        ExportFormat::Json => serde_json::to_writer_pretty(&mut fp, data)?,
+        ExportFormat::Markdown => write_markdown(data, template, &mut fp)?,
+        ExportFormat::Html => write_html(data, template, &mut fp)?,
+        ExportFormat::SingleFileHtml => single_file_html(data, media_folder, folder, &mut fp)?,
+        ExportFormat::Pdf(range) => pdf::write_pdf(data, range, media_folder, &mut fp)?,
+        ExportFormat::Text { oldest_first } => text::write_text(data, oldest_first, &mut fp)?,
+        ExportFormat::Rss => rss::write_rss(data, &mut fp)?,
+        ExportFormat::Csv => bail!("The CSV export consists of multiple files"),
+    }
+    fp.flush()?;
+    Ok(path)
+}
+
+fn sections(data: &Data) -> [(&'static str, &[Tweet]); 3] {
+    [
+        ("Tweets", &data.tweets),
+        ("Mentions", &data.mentions),
+        ("Likes", &data.likes),
+    ]
+}
+
+fn author<'a>(tweet: &'a Tweet, data: &'a Data) -> &'a str {
+    tweet
+        .user
+        .as_ref()
+        .map(|u| u.screen_name.as_str())
+        .unwrap_or(&data.profile.screen_name)
+}
+
+/// A custom tweet template and the links to the archived media for it
+type Template<'a> = (&'a TweetTemplate, &'a template::MediaLinks);
+
+fn write_markdown(data: &Data, template: Option<Template>, out: &mut impl Write) -> Result<()> {
+    writeln!(out, "# {} (@{})\n", data.profile.name, data.profile.screen_name)?;
+    for (title, tweets) in sections(data) {
+        if tweets.is_empty() {
+            continue;
+        }
+        writeln!(out, "## {title}\n")?;
+        for tweet in tweets {
+            if let Some((template, media_links)) = template {
+                let media = media_urls_in_tweet(tweet)
+                    .iter()
+                    .map(|url| format!("![]({})", markdown_url(&media_links.link(data, url))))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let fields = template::TweetFields {
+                    date: tweet.created_at.format("%Y-%m-%d %H:%M").to_string(),
+                    text: data.text(tweet).to_string(),
+                    handle: author(tweet, data).to_string(),
+                    media,
+                    permalink: tweet_permalink(tweet, &data.profiles),
+                };
+                writeln!(out, "{}", template.render(&fields))?;
+                continue;
+            }
+            let screen_name = author(tweet, data);
+            let via = tweet
+                .source
+                .as_ref()
+                .map(|source| format!(" via [{}]({})", source.name, source.url))
+                .unwrap_or_default();
+            let edited = if data.is_edited(tweet.id) {
+                " (edited)"
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "**@{screen_name}** - [{}]({}){via}{edited}\n",
+                tweet.created_at.format("%Y-%m-%d %H:%M"),
+                tweet_permalink(tweet, &data.profiles)
+            )?;
+            for line in data.text(tweet).lines() {
+                writeln!(out, "> {line}")?;
+            }
+            if let Some(space) = data.spaces.get(&tweet.id) {
+                let title = space.title.as_deref().unwrap_or("Space");
+                writeln!(out, "\n[{title}]({})", space.url)?;
+            }
+            if let Some(card) = data.cards.get(&tweet.id) {
+                writeln!(
+                    out,
+                    "\n[{}]({}) - {}",
+                    escape_markdown(&card.title),
+                    markdown_url(&card.url),
+                    escape_markdown(&card.vendor)
+                )?;
+                if let Some(description) = &card.description {
+                    writeln!(out, "\n{}", escape_markdown(description))?;
+                }
+            }
+            if let Some(poll) = data.polls.get(&tweet.id) {
+                writeln!(out)?;
+                for option in &poll.options {
+                    writeln!(out, "- {}: {} votes", option.label, option.votes)?;
+                }
+            }
+            writeln!(out, "\n---\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// Text from websites, e.g. link previews, that must not be read as Markdown
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '(' | ')' | '#' | '<' | '>' | '!' | '|'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A url as the target of a Markdown link, which ends at a space or `)`
+fn markdown_url(url: &str) -> String {
+    url.replace(' ', "%20")
+        .replace('(', "%28")
+        .replace(')', "%29")
+}
+
+fn write_html(data: &Data, template: Option<Template>, out: &mut impl Write) -> Result<()> {
+    let name = escape_html(&data.profile.name);
+    let screen_name = escape_html(&data.profile.screen_name);
+    // Chat apps and social networks show this when the export is shared
+    let preview = data
+        .tweets
+        .iter()
+        .max_by_key(|tweet| tweet.id)
+        .map(|tweet| escape_html(&truncate_tweet_text(data.text(tweet), PREVIEW_CHARS)))
+        .unwrap_or_default();
+    writeln!(
+        out,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"description\" content=\"{preview}\"><meta property=\"og:description\" content=\"{preview}\"><title>{name} (@{screen_name})</title></head><body>"
+    )?;
+    writeln!(out, "<h1>{name} (@{screen_name})</h1>")?;
+    for (title, tweets) in sections(data) {
+        if tweets.is_empty() {
+            continue;
+        }
+        writeln!(out, "<h2>{title}</h2>")?;
+        for tweet in tweets {
+            if let Some((template, media_links)) = template {
+                let media = media_urls_in_tweet(tweet)
+                    .iter()
+                    .map(|url| {
+                        format!(
+                            "<img src=\"{}\">",
+                            escape_html(&media_links.link(data, url))
+                        )
+                    })
+                    .collect::<String>();
+                let fields = template::TweetFields {
+                    date: tweet.created_at.format("%Y-%m-%d %H:%M").to_string(),
+                    text: render_tweet_html(tweet, EntityLinks::Twitter),
+                    handle: escape_html(author(tweet, data)),
+                    media,
+                    permalink: escape_html(&tweet_permalink(tweet, &data.profiles)),
+                };
+                writeln!(out, "{}", template.render(&fields))?;
+                continue;
+            }
+            let screen_name = escape_html(author(tweet, data));
+            let via = tweet
+                .source
+                .as_ref()
+                .map(|source| {
+                    format!(
+                        " via <a href=\"{}\">{}</a>",
+                        escape_html(&source.url),
+                        escape_html(&source.name)
+                    )
+                })
+                .unwrap_or_default();
+            let edited = if data.is_edited(tweet.id) {
+                " (edited)"
+            } else {
+                ""
+            };
+            let card = data
+                .cards
+                .get(&tweet.id)
+                .map(|card| {
+                    let description = card
+                        .description
+                        .as_ref()
+                        .map(|description| format!("<br>{}", escape_html(description)))
+                        .unwrap_or_default();
+                    format!(
+                        "<blockquote><a href=\"{}\">{}</a> ({}){description}</blockquote>",
+                        escape_html(&card.url),
+                        escape_html(&card.title),
+                        escape_html(&card.vendor)
+                    )
+                })
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "<article><p><strong>@{screen_name}</strong> <a href=\"{}\">{}</a>{via}{edited}</p><p>{}</p>{card}</article><hr>",
+                escape_html(&tweet_permalink(tweet, &data.profiles)),
+                tweet.created_at.format("%Y-%m-%d %H:%M"),
+                render_tweet_html(tweet, EntityLinks::Twitter)
+            )?;
+        }
+    }
+    if !data.lists.is_empty() {
+        writeln!(out, "<h2>Lists</h2>")?;
+    }
+    for list in &data.lists {
+        // So that private lists aren't published by accident
+        let private = if list.is_private() {
+            " <strong>(Private list)</strong>"
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "<article><h3>{}{private}</h3><p>{}</p><p>{} Members, {} Subscribers</p></article><hr>",
+            escape_html(&list.name),
+            escape_html(list.description()),
+            list.member_count(),
+            list.subscriber_count()
+        )?;
+    }
+    writeln!(out, "</body></html>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Card;
+    use crate::test_fixtures::{list, storage, temp_folder, tweet, with_photo};
+    use std::str::FromStr;
+
+    #[test]
+    fn source_is_shown_without_its_anchor_tag() {
+        let mut storage = storage("export-source");
+        let mut sent = tweet(1, "Hello");
+        sent.source = Some(
+            egg_mode::tweet::TweetSource::from_str(
+                r#"<a href="https://mobile.twitter.com" rel="nofollow">Twitter Web App</a>"#,
+            )
+            .unwrap(),
+        );
+        storage.data_mut().tweets = vec![sent];
+
+        let mut markdown = Vec::new();
+        write_markdown(storage.data(), None, &mut markdown).unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+        assert!(markdown.contains(" via [Twitter Web App](https://mobile.twitter.com)"));
+        assert!(!markdown.contains("rel=\"nofollow\""));
+
+        let mut html = Vec::new();
+        write_html(storage.data(), None, &mut html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains(" via <a href=\"https://mobile.twitter.com\">Twitter Web App</a>"));
+    }
+
+    #[test]
+    fn private_lists_are_marked_in_the_html_export() {
+        let mut storage = storage("export-lists");
+        storage.data_mut().lists = vec![
+            list(1, "Friends", "People I know", 3, true),
+            list(2, "Rust", "Rustaceans & co", 12, false),
+        ];
+
+        let mut html = Vec::new();
+        write_html(storage.data(), None, &mut html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains("<h3>Friends <strong>(Private list)</strong></h3>"));
+        assert!(html.contains("<p>People I know</p><p>3 Members, 0 Subscribers</p>"));
+        assert!(html.contains("<h3>Rust</h3><p>Rustaceans &amp; co</p>"));
+        assert!(html.contains("<p>12 Members, 0 Subscribers</p>"));
+    }
+
+    #[test]
+    fn quoted_tweets_are_only_exported_within_their_quote() {
+        let mut storage = storage("export-quotes");
+        let mut quote = tweet(10, "Look at this");
+        quote.quoted_status = Some(Box::new(tweet(5, "Quoted")));
+        let data = storage.data_mut();
+        data.archive_relations(&quote);
+        data.add_timeline_tweets(vec![quote]);
+        assert_eq!(data.tweets.len(), 2);
+
+        let mut data = data.clone();
+        ExportSelection::default().apply(&mut data);
+        let ids: Vec<_> = data.tweets.iter().map(|t| t.id).collect();
+        assert_eq!(ids, [10]);
+    }
+
+    #[test]
+    fn an_export_of_only_the_tweets_omits_the_followers_files() {
+        let mut storage = storage("export-selection");
+        let data = storage.data_mut();
+        data.tweets = vec![tweet(2, "Mine")];
+        data.likes = vec![tweet(1, "Liked")];
+        data.followers = vec![3];
+        data.follows = vec![4];
+        let only_tweets = ExportSelection {
+            tweets: true,
+            mentions: false,
+            likes: false,
+            followers: false,
+            lists: false,
+            media: false,
+            lang: None,
+        };
+
+        let folder = temp_folder("export-selection-out");
+        let media = storage.media_path("");
+        for format in [ExportFormat::Csv, ExportFormat::Json] {
+            export(
+                storage.data().clone(),
+                format,
+                only_tweets.clone(),
+                &folder,
+                &media,
+                None,
+            )
+            .unwrap();
+        }
+        assert!(!folder.join("followers.csv").exists());
+        assert!(!folder.join("follows.csv").exists());
+        let json = std::fs::read_to_string(folder.join("twitvault-owner.json")).unwrap();
+        let exported: Data = serde_json::from_str(&json).unwrap();
+        assert_eq!(exported.tweets.len(), 1);
+        assert!(exported.likes.is_empty());
+        assert!(exported.followers.is_empty() && exported.follows.is_empty());
+
+        // With the followers, the CSV export writes them
+        export(
+            storage.data().clone(),
+            ExportFormat::Csv,
+            ExportSelection::default(),
+            &folder,
+            &media,
+            None,
+        )
+        .unwrap();
+        assert!(folder.join("followers.csv").exists());
+        assert!(folder.join("follows.csv").exists());
+    }
+
+    #[test]
+    fn link_previews_are_escaped_in_the_markdown_export() {
+        let mut storage = storage("export-cards");
+        let data = storage.data_mut();
+        data.tweets = vec![tweet(1, "Look")];
+        data.cards.insert(
+            1,
+            Card {
+                url: "https://en.wikipedia.org/wiki/Rust_(language)".to_string(),
+                title: "Rust [programming] *language*".to_string(),
+                description: Some("# Not a heading".to_string()),
+                image_url: None,
+                vendor: "en.wikipedia.org".to_string(),
+            },
+        );
+
+        let mut markdown = Vec::new();
+        write_markdown(storage.data(), None, &mut markdown).unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+        assert!(markdown.contains(
+            "[Rust \\[programming\\] \\*language\\*](https://en.wikipedia.org/wiki/Rust_%28language%29) - en.wikipedia.org"
+        ));
+        assert!(markdown.contains("\n\\# Not a heading\n"));
+    }
+
+    #[test]
+    fn an_export_in_one_language_keeps_only_its_tweets() {
+        let mut storage = storage("export-lang");
+        let in_lang = |id, lang: Option<&str>| {
+            let mut t = tweet(id, "text");
+            t.lang = lang.map(str::to_string);
+            t
+        };
+        let data = storage.data_mut();
+        data.tweets = vec![
+            in_lang(3, Some("de")),
+            in_lang(2, None),
+            in_lang(1, Some("en")),
+        ];
+        data.likes = vec![in_lang(4, Some("und"))];
+
+        for (lang, expected) in [("en", vec![1]), ("und", vec![2, 4])] {
+            let mut data = storage.data().clone();
+            let selection = ExportSelection {
+                lang: Some(lang.to_string()),
+                ..ExportSelection::default()
+            };
+            selection.apply(&mut data);
+            let ids: Vec<_> = data
+                .tweets
+                .iter()
+                .chain(&data.likes)
+                .map(|t| t.id)
+                .collect();
+            assert_eq!(ids, expected);
+        }
+    }
+
+    #[test]
+    fn the_html_preview_shows_the_start_of_the_newest_tweet() {
+        let mut storage = storage("export-preview");
+        let newest = format!("{} & more", ["word"; 40].join(" "));
+        storage.data_mut().tweets = vec![tweet(1, "older"), tweet(2, &newest)];
+
+        let mut html = Vec::new();
+        write_html(storage.data(), None, &mut html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        let preview = format!("{}…", ["word"; 32].join(" "));
+        assert!(html.contains(&format!(
+            "<meta name=\"description\" content=\"{preview}\">"
+        )));
+        assert!(!html.contains("older\">"));
+    }
+
+    #[test]
+    fn a_custom_template_renders_the_tweets_with_their_archived_media() {
+        let mut storage = storage("export-template");
+        let url = "https://pbs.twimg.com/media/photo.jpg";
+        let data = storage.data_mut();
+        data.tweets = vec![with_photo(tweet(1, "Hello"), url)];
+        data.media.insert(url.to_string(), "photo.jpg".to_string());
+        let media = storage.media_path("");
+        std::fs::write(media.join("photo.jpg"), b"jpg").unwrap();
+
+        let templates = temp_folder("export-template-custom");
+        std::fs::write(
+            templates.join("tweet.md"),
+            "{{handle}} | {{date}} | {{text}} | {{media}} | {{permalink}}",
+        )
+        .unwrap();
+        let folder = temp_folder("export-template-out");
+        let path = export(
+            storage.data().clone(),
+            ExportFormat::Markdown,
+            ExportSelection::default(),
+            &folder,
+            &media,
+            Some(&templates),
+        )
+        .unwrap();
+
+        let markdown = std::fs::read_to_string(path).unwrap();
+        let line = markdown.lines().find(|l| l.contains("Hello")).unwrap();
+        let fields: Vec<_> = line.split(" | ").collect();
+        let tweet = &storage.data().tweets[0];
+        assert_eq!(fields[0], "owner");
+        assert_eq!(
+            fields[1],
+            tweet.created_at.format("%Y-%m-%d %H:%M").to_string()
+        );
+        assert_eq!(fields[2], "Hello");
+        assert_eq!(fields[4], tweet_permalink(tweet, &storage.data().profiles));
+        // The media links to the archived file, not to Twitter
+        let link = fields[3]
+            .strip_prefix("![](")
+            .and_then(|l| l.strip_suffix(')'))
+            .unwrap();
+        assert!(!link.contains("twimg.com"));
+        assert!(folder.join(link).exists());
+    }
+}
@@ -0,0 +1,73 @@
+//! Export the followers and follows as CSV, e.g. for a spreadsheet
+use std::{io::Write, path::Path};
+
+use eyre::Result;
+
+use crate::storage::Data;
+
+const HEADER: &str = "id,screen_name,name,followers_count,following_count,created_at";
+
+/// Writes `followers.csv` and `follows.csv` into `folder`
+pub fn export(data: &Data, folder: &Path) -> Result<()> {
+    for (file_name, ids) in [
+        ("followers.csv", &data.followers),
+        ("follows.csv", &data.follows),
+    ] {
+        let mut fp = std::io::BufWriter::new(std::fs::File::create(folder.join(file_name))?);
+        write_profiles(data, ids, &mut fp)?;
+        fp.flush()?;
+    }
+    Ok(())
+}
+
+/// Writes one row per id. Ids without a downloaded profile only have the id column
+pub fn write_profiles(data: &Data, ids: &[u64], out: &mut impl Write) -> Result<()> {
+    writeln!(out, "{HEADER}")?;
+    for id in ids {
+        match data.profiles.get(id) {
+            Some(profile) => writeln!(
+                out,
+                "{id},{},{},{},{},{}",
+                escape(&profile.screen_name),
+                escape(&profile.name),
+                profile.followers_count,
+                profile.friends_count,
+                profile.created_at.to_rfc3339()
+            )?,
+            None => writeln!(out, "{id},,,,,")?,
+        }
+    }
+    Ok(())
+}
+
+/// Quote a field if it contains a separator, a quote or a line break
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{storage, user};
+
+    #[test]
+    fn ids_without_profile_keep_their_row() {
+        let mut storage = storage("csv");
+        let mut profile = user(2, "friend");
+        profile.name = "Doe, \"Jane\"".to_string();
+        storage.data_mut().profiles.insert(2, profile.clone());
+
+        let mut out = Vec::new();
+        write_profiles(storage.data(), &[2, 3], &mut out).unwrap();
+
+        let created_at = profile.created_at.to_rfc3339();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{HEADER}\n2,friend,\"Doe, \"\"Jane\"\"\",0,0,{created_at}\n3,,,,,\n")
+        );
+    }
+}
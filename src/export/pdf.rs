@@ -0,0 +1,272 @@
+//! Export the tweets of the owner as a printable PDF, oldest first
+use std::io::Write;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use egg_mode::tweet::Tweet;
+use eyre::Result;
+use printpdf::image_crate::{self, DynamicImage};
+use printpdf::{
+    Image, ImageTransform, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference,
+    PdfLayerReference,
+};
+use rusttype::Font;
+use tracing::warn;
+
+use super::FONT;
+use crate::crawler::DownloadInstruction;
+use crate::helpers::media_in_tweet;
+use crate::storage::Data;
+
+const PAGE_WIDTH: f64 = 210.0;
+const PAGE_HEIGHT: f64 = 297.0;
+const MARGIN: f64 = 20.0;
+const FONT_SIZE: f64 = 10.0;
+const LINE_HEIGHT: f64 = 5.0;
+/// Roughly what fits into a line of the font at `FONT_SIZE`
+const CHARS_PER_LINE: usize = 80;
+/// Images are scaled down to at most this size in mm
+const IMAGE_SIZE: f64 = 60.0;
+/// ... and to at most this many pixels, to keep the file small
+const IMAGE_PIXELS: u32 = 600;
+/// The resolution printpdf assumes for images
+const IMAGE_DPI: f64 = 300.0;
+
+/// Only tweets created within the range (inclusive) are exported. Open
+/// ends are unbounded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    fn contains(&self, tweet: &Tweet) -> bool {
+        let date = tweet.created_at.date_naive();
+        self.from.map(|from| date >= from).unwrap_or(true)
+            && self.to.map(|to| date <= to).unwrap_or(true)
+    }
+}
+
+/// Writes the tweets of the owner within `range`. Images are loaded from
+/// `media_folder`. Media that wasn't downloaded or can't be decoded is
+/// replaced by a caption. The text is set in the embedded `FONT`, the
+/// characters it lacks (e.g. emoji) are replaced by `�` with a warning
+pub fn write_pdf(
+    data: &Data,
+    range: DateRange,
+    media_folder: &Path,
+    out: &mut impl Write,
+) -> Result<()> {
+    let title = format!("{} (@{})", data.profile.name, data.profile.screen_name);
+    let (document, page, layer) =
+        PdfDocument::new(&title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Tweets");
+    // There is no bold variant, so headings are only larger
+    let regular = document.add_external_font(FONT)?;
+    let bold = regular.clone();
+    let glyphs = Font::try_from_bytes(FONT).ok_or_else(|| eyre::eyre!("Invalid font"))?;
+    let mut writer = PageWriter {
+        layer: document.get_page(page).get_layer(layer),
+        document: &document,
+        font: glyphs,
+        replaced: 0,
+        y: PAGE_HEIGHT - MARGIN,
+    };
+
+    writer.line(&title, &bold, FONT_SIZE * 1.6);
+    writer.space(LINE_HEIGHT);
+
+    let mut tweets: Vec<&Tweet> = data.tweets.iter().filter(|t| range.contains(t)).collect();
+    tweets.sort_by_key(|t| t.id);
+    for tweet in tweets {
+        writer.line(
+            &tweet.created_at.format("%Y-%m-%d %H:%M").to_string(),
+            &bold,
+            FONT_SIZE,
+        );
+        for line in wrap(data.text(tweet), CHARS_PER_LINE) {
+            writer.line(&line, &regular, FONT_SIZE);
+        }
+        for instruction in media_in_tweet(tweet).unwrap_or_default() {
+            match instruction {
+                DownloadInstruction::Image(url) => {
+                    let image = data
+                        .media
+                        .get(&url)
+                        .and_then(|file_name| image_crate::open(media_folder.join(file_name)).ok());
+                    match image {
+                        Some(image) => writer.image(image),
+                        None => writer.line("[Image not archived]", &regular, FONT_SIZE * 0.8),
+                    }
+                }
+                DownloadInstruction::Movie(..) => writer.line("[Video]", &regular, FONT_SIZE * 0.8),
+                _ => (),
+            }
+        }
+        writer.space(LINE_HEIGHT);
+    }
+
+    if writer.replaced > 0 {
+        warn!(
+            "The PDF font lacks {} characters of the tweets (e.g. emoji or CJK), they are shown as �",
+            writer.replaced
+        );
+    }
+    document.save(&mut std::io::BufWriter::new(out))?;
+    Ok(())
+}
+
+/// Keeps track of the position on the current page and starts a new
+/// page when it is full
+struct PageWriter<'a> {
+    document: &'a PdfDocumentReference,
+    layer: PdfLayerReference,
+    /// To find the characters that the embedded font lacks
+    font: Font<'static>,
+    /// How many characters were replaced so far
+    replaced: usize,
+    y: f64,
+}
+
+impl<'a> PageWriter<'a> {
+    fn ensure_space(&mut self, height: f64) {
+        if self.y - height >= MARGIN {
+            return;
+        }
+        let (page, layer) = self
+            .document
+            .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Tweets");
+        self.layer = self.document.get_page(page).get_layer(layer);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn space(&mut self, height: f64) {
+        self.y -= height;
+    }
+
+    fn line(&mut self, text: &str, font: &IndirectFontRef, size: f64) {
+        let height = LINE_HEIGHT * size / FONT_SIZE;
+        self.ensure_space(height);
+        self.y -= height;
+        let (text, replaced) = printable(text, &self.font);
+        self.replaced += replaced;
+        self.layer
+            .use_text(text, size, Mm(MARGIN), Mm(self.y), font);
+    }
+
+    fn image(&mut self, image: DynamicImage) {
+        let image = DynamicImage::ImageRgb8(image.thumbnail(IMAGE_PIXELS, IMAGE_PIXELS).to_rgb8());
+        let mm_per_pixel = 25.4 / IMAGE_DPI;
+        let (width, height) = (
+            image.width() as f64 * mm_per_pixel,
+            image.height() as f64 * mm_per_pixel,
+        );
+        let scale = (IMAGE_SIZE / width).min(IMAGE_SIZE / height);
+        let scaled_height = height * scale;
+        self.ensure_space(scaled_height + LINE_HEIGHT / 2.0);
+        self.y -= scaled_height + LINE_HEIGHT / 2.0;
+        Image::from_dynamic_image(&image).add_to_layer(
+            self.layer.clone(),
+            ImageTransform {
+                translate_x: Some(Mm(MARGIN)),
+                translate_y: Some(Mm(self.y)),
+                scale_x: Some(scale),
+                scale_y: Some(scale),
+                dpi: Some(IMAGE_DPI),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+/// The text with the characters that `font` has no glyph for replaced,
+/// and how many were replaced. Control characters are dropped
+fn printable(text: &str, font: &Font) -> (String, usize) {
+    let mut replaced = 0;
+    let text = text
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| match font.glyph(c).id().0 {
+            0 => {
+                replaced += 1;
+                char::REPLACEMENT_CHARACTER
+            }
+            _ => c,
+        })
+        .collect();
+    (text, replaced)
+}
+
+/// Break the text into lines of at most `width` chars, at spaces if possible
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let mut word = word.to_string();
+            // Words longer than a line (e.g. links) are split
+            while word.chars().count() > width {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                let split = word
+                    .char_indices()
+                    .nth(width)
+                    .map(|(i, _)| i)
+                    .unwrap_or(word.len());
+                lines.push(word[..split].to_string());
+                word = word[split..].to_string();
+            }
+            let needed =
+                line.chars().count() + word.chars().count() + usize::from(!line.is_empty());
+            if needed > width && !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{storage, tweet, with_photo};
+
+    #[test]
+    fn writes_a_pdf_with_the_embedded_font() {
+        let mut storage = storage("pdf");
+        let photo = "https://pbs.twimg.com/media/missing.jpg";
+        storage.data_mut().tweets = vec![
+            tweet(2, "Grüße, Привет, Γειά σου"),
+            with_photo(tweet(1, "A photo 📷"), photo),
+        ];
+        let media_folder = storage.media_path("");
+
+        let mut pdf = Vec::new();
+        write_pdf(
+            storage.data(),
+            DateRange::default(),
+            &media_folder,
+            &mut pdf,
+        )
+        .unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+        let tail = String::from_utf8_lossy(&pdf[pdf.len() - 32..]).to_string();
+        assert!(tail.contains("%%EOF"));
+        let contents = String::from_utf8_lossy(&pdf);
+        assert!(contents.contains("DejaVuSans"));
+    }
+
+    #[test]
+    fn characters_without_glyph_are_replaced_and_counted() {
+        let font = Font::try_from_bytes(FONT).unwrap();
+        let (text, replaced) = printable("Grüße Привет 🦀 日本\t", &font);
+        assert_eq!(text, "Grüße Привет � ��");
+        assert_eq!(replaced, 3);
+    }
+}
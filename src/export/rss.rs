@@ -0,0 +1,77 @@
+//! Export the tweets of the owner as an RSS feed, e.g. to follow the
+//! archive in a feed reader
+use std::io::Write;
+
+use egg_mode::tweet::Tweet;
+use eyre::Result;
+
+use crate::helpers::{escape_html, truncate_tweet_text, tweet_permalink};
+use crate::storage::Data;
+
+/// Feed readers show the title in their lists
+const TITLE_CHARS: usize = 80;
+
+/// The summary of a tweet. Texts with expanded links can be longer than a
+/// tweet
+const DESCRIPTION_CHARS: usize = 400;
+
+/// Writes an RSS 2.0 feed with one item per tweet, newest first
+pub fn write_rss(data: &Data, out: &mut impl Write) -> Result<()> {
+    let profile = &data.profile;
+    let profile_url = format!("https://twitter.com/{}", profile.screen_name);
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(out, "<rss version=\"2.0\"><channel>")?;
+    writeln!(
+        out,
+        "<title>{} (@{})</title><link>{}</link><description>{}</description>",
+        escape_html(&profile.name),
+        escape_html(&profile.screen_name),
+        escape_html(&profile_url),
+        escape_html(profile.description.as_deref().unwrap_or_default())
+    )?;
+    let mut tweets: Vec<&Tweet> = data.tweets.iter().collect();
+    tweets.sort_by(|a, b| b.id.cmp(&a.id));
+    for tweet in tweets {
+        let text = data
+            .text(tweet)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let permalink = escape_html(&tweet_permalink(tweet, &data.profiles));
+        writeln!(
+            out,
+            "<item><title>{}</title><link>{permalink}</link><guid>{permalink}</guid><pubDate>{}</pubDate><description>{}</description></item>",
+            escape_html(&truncate_tweet_text(&text, TITLE_CHARS)),
+            tweet.created_at.to_rfc2822(),
+            escape_html(&truncate_tweet_text(&text, DESCRIPTION_CHARS))
+        )?;
+    }
+    writeln!(out, "</channel></rss>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{storage, tweet};
+
+    #[test]
+    fn items_have_truncated_titles_and_escaped_text() {
+        let mut storage = storage("export-rss");
+        let long = ["word"; 20].join(" ");
+        storage.data_mut().tweets = vec![tweet(1, "Fish & <chips>"), tweet(2, &long)];
+
+        let mut out = Vec::new();
+        write_rss(storage.data(), &mut out).unwrap();
+        let rss = String::from_utf8(out).unwrap();
+
+        let items: Vec<_> = rss.lines().filter(|l| l.starts_with("<item>")).collect();
+        assert_eq!(items.len(), 2);
+        // Cut at a word, within the length of the title
+        let title = format!("<title>{}…</title>", ["word"; 16].join(" "));
+        assert!(items[0].contains(&title), "{}", items[0]);
+        assert!(items[0].contains(&format!("<description>{long}</description>")));
+        assert!(items[1].contains("<title>Fish &amp; &lt;chips&gt;</title>"));
+        assert!(items[1].contains("<pubDate>Sun, 13 Sep 2020 12:27:40 +0000</pubDate>"));
+    }
+}
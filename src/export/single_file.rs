@@ -0,0 +1,291 @@
+//! Export the tweets into one HTML file that can be opened by a double
+//! click and shared. The tweets are embedded as JSON and rendered by a small
+//! script, small media is inlined as `data:` urls
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use eyre::Result;
+use serde::Serialize;
+
+use super::{author, sections};
+use crate::helpers::{
+    escape_html, media_urls_in_tweet, render_tweet_html, tweet_permalink, EntityLinks,
+};
+use crate::storage::Data;
+
+/// Media files up to this size are inlined. Larger ones are copied into the
+/// `media` folder next to the HTML file and referenced relative to it
+pub const INLINE_MEDIA_MAX_BYTES: u64 = 512 * 1024;
+
+/// Once this much media is inlined, the remaining files are referenced like
+/// the large ones, so that browsers can still open the file
+pub const INLINE_MEDIA_TOTAL_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The folder next to the HTML file for media that is too large to inline
+const EXTERNAL_MEDIA_FOLDER: &str = "media";
+
+#[derive(Serialize)]
+struct Embedded {
+    tweets: Vec<EmbeddedTweet>,
+    /// By file name, so that media in several tweets is only embedded once
+    media: BTreeMap<String, EmbeddedMedia>,
+}
+
+#[derive(Serialize)]
+struct EmbeddedTweet {
+    section: &'static str,
+    author: String,
+    date: String,
+    url: String,
+    /// Escaped and with linked entities, see `render_tweet_html`. Mentions
+    /// and hashtags link to `#user-<id>` and `#hashtag-<tag>`, which filter
+    /// the tweets
+    html: String,
+    /// The file names of the media in `Embedded::media`
+    media: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct EmbeddedMedia {
+    /// A `data:` url or a path relative to the HTML file
+    src: String,
+    video: bool,
+}
+
+/// Writes the tweets of `data` as one HTML file into `out`. The media is
+/// read from `media_folder`, media above `INLINE_MEDIA_MAX_BYTES` is copied
+/// into a `media` folder in `folder`, which is where the HTML file goes
+pub fn single_file_html(
+    data: &Data,
+    media_folder: &Path,
+    folder: &Path,
+    out: &mut impl Write,
+) -> Result<()> {
+    let mut embedder = MediaEmbedder {
+        media_folder,
+        folder,
+        inlined_bytes: 0,
+        media: BTreeMap::new(),
+    };
+    let mut tweets = Vec::new();
+    for (section, section_tweets) in sections(data) {
+        for tweet in section_tweets {
+            let mut media = Vec::new();
+            for file_name in media_urls_in_tweet(tweet)
+                .iter()
+                .filter_map(|url| data.media.get(url))
+            {
+                if embedder.embed(file_name)? {
+                    media.push(file_name.clone());
+                }
+            }
+            tweets.push(EmbeddedTweet {
+                section,
+                author: author(tweet, data).to_string(),
+                date: tweet.created_at.format("%Y-%m-%d %H:%M").to_string(),
+                url: tweet_permalink(tweet, &data.profiles),
+                html: render_tweet_html(tweet, EntityLinks::Local),
+                media,
+            });
+        }
+    }
+    let embedded = Embedded {
+        tweets,
+        media: embedder.media,
+    };
+    // `</script>` within a tweet would end the script element early
+    let json = serde_json::to_string(&embedded)?.replace("</", "<\\/");
+    let title = format!(
+        "{} (@{})",
+        escape_html(&data.profile.name),
+        escape_html(&data.profile.screen_name)
+    );
+    writeln!(
+        out,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title><style>{STYLE}</style></head><body>"
+    )?;
+    writeln!(out, "<h1>{title}</h1>")?;
+    writeln!(
+        out,
+        "<input id=\"filter\" type=\"search\" placeholder=\"Filter\" autocomplete=\"off\"><div id=\"tweets\"></div>"
+    )?;
+    writeln!(out, "<script id=\"data\" type=\"application/json\">{json}</script>")?;
+    writeln!(out, "<script>{SCRIPT}</script></body></html>")?;
+    Ok(())
+}
+
+struct MediaEmbedder<'a> {
+    media_folder: &'a Path,
+    folder: &'a Path,
+    inlined_bytes: u64,
+    media: BTreeMap<String, EmbeddedMedia>,
+}
+
+impl<'a> MediaEmbedder<'a> {
+    /// Adds the media file as a `data:` url, or copies it next to the export
+    /// if it is too large. `false` if the file wasn't downloaded
+    fn embed(&mut self, file_name: &str) -> Result<bool> {
+        if self.media.contains_key(file_name) {
+            return Ok(true);
+        }
+        let path = self.media_folder.join(file_name);
+        let Ok(meta) = std::fs::metadata(&path) else {
+            return Ok(false);
+        };
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let (mime, video) = match extension.as_str() {
+            "png" => ("image/png", false),
+            "gif" => ("image/gif", false),
+            "webp" => ("image/webp", false),
+            "mp4" => ("video/mp4", true),
+            _ => ("image/jpeg", false),
+        };
+        let src = if meta.len() <= INLINE_MEDIA_MAX_BYTES
+            && self.inlined_bytes + meta.len() <= INLINE_MEDIA_TOTAL_MAX_BYTES
+        {
+            self.inlined_bytes += meta.len();
+            let bytes = std::fs::read(&path)?;
+            format!("data:{mime};base64,{}", base64::encode(bytes))
+        } else {
+            self.copy_external(&path, file_name)?
+        };
+        self.media
+            .insert(file_name.to_string(), EmbeddedMedia { src, video });
+        Ok(true)
+    }
+
+    /// Copies the file into the media folder next to the export and returns
+    /// its relative path. When exporting into the archive, that is the
+    /// folder the file is already in
+    fn copy_external(&self, path: &Path, file_name: &str) -> Result<String> {
+        let relative = Path::new(EXTERNAL_MEDIA_FOLDER).join(file_name);
+        let destination = self.folder.join(&relative);
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Copying a file onto itself truncates it
+        let same_file = match (path.canonicalize(), destination.canonicalize()) {
+            (Ok(source), Ok(destination)) => source == destination,
+            _ => false,
+        };
+        if !same_file {
+            std::fs::copy(path, &destination)?;
+        }
+        // Forward slashes, also on Windows
+        Ok(relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"))
+    }
+}
+
+const STYLE: &str = "body{font-family:sans-serif;max-width:40rem;margin:auto;padding:1rem}\
+article{border-bottom:1px solid #ddd;padding:.5rem 0}\
+article img,article video{max-width:100%;display:block;margin-top:.5rem}\
+#filter{width:100%;padding:.4rem;margin-bottom:1rem}";
+
+/// Renders the embedded tweets, grouped by section, and filters them by
+/// their text. The text is already escaped HTML
+const SCRIPT: &str = r#"
+const { tweets, media } = JSON.parse(document.getElementById("data").textContent);
+const container = document.getElementById("tweets");
+function render(needle) {
+  container.innerHTML = "";
+  let section = null;
+  for (const tweet of tweets) {
+    if (needle && !tweet.html.toLowerCase().includes(needle)) continue;
+    if (tweet.section !== section) {
+      section = tweet.section;
+      const heading = document.createElement("h2");
+      heading.textContent = section;
+      container.appendChild(heading);
+    }
+    const article = document.createElement("article");
+    const header = document.createElement("p");
+    const strong = document.createElement("strong");
+    strong.textContent = "@" + tweet.author + " ";
+    const link = document.createElement("a");
+    link.href = tweet.url;
+    link.textContent = tweet.date;
+    header.append(strong, link);
+    const text = document.createElement("p");
+    text.innerHTML = tweet.html;
+    article.append(header, text);
+    for (const file of tweet.media) {
+      const { src, video } = media[file];
+      const element = document.createElement(video ? "video" : "img");
+      element.src = src;
+      if (video) element.controls = true;
+      article.appendChild(element);
+    }
+    container.appendChild(article);
+  }
+}
+document.getElementById("filter").addEventListener("input", (event) => render(event.target.value.toLowerCase()));
+// Mentions and hashtags link to `#user-<id>` and `#hashtag-<tag>`, which
+// are part of the html of every tweet that contains them
+function renderHash() {
+  const needle = decodeURIComponent(location.hash).toLowerCase();
+  document.getElementById("filter").value = needle;
+  render(needle);
+}
+window.addEventListener("hashchange", renderHash);
+renderHash();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{storage, temp_folder, tweet, with_photo};
+
+    fn export(data: &Data, media_folder: &Path, folder: &Path) -> String {
+        let mut html = Vec::new();
+        single_file_html(data, media_folder, folder, &mut html).unwrap();
+        String::from_utf8(html).unwrap()
+    }
+
+    #[test]
+    fn small_images_are_inlined_once() {
+        let mut storage = storage("single-file-small");
+        let media_folder = temp_folder("single-file-small-media");
+        std::fs::write(media_folder.join("1.png"), b"png").unwrap();
+        let data = storage.data_mut();
+        let url = "https://pbs.twimg.com/media/1.png";
+        data.tweets = vec![
+            with_photo(tweet(1, "first"), url),
+            with_photo(tweet(2, "second"), url),
+        ];
+        data.media.insert(url.to_string(), "1.png".to_string());
+
+        let folder = temp_folder("single-file-small-export");
+        let html = export(storage.data(), &media_folder, &folder);
+        let data_url = format!("data:image/png;base64,{}", base64::encode(b"png"));
+        assert_eq!(html.matches(&data_url).count(), 1);
+        assert!(!html.contains(&format!("{EXTERNAL_MEDIA_FOLDER}/1.png")));
+        assert!(!folder.join(EXTERNAL_MEDIA_FOLDER).exists());
+    }
+
+    #[test]
+    fn an_export_into_the_archive_leaves_its_large_media_intact() {
+        let mut storage = storage("single-file-archive");
+        let archive = temp_folder("single-file-archive-root");
+        let media_folder = archive.join(EXTERNAL_MEDIA_FOLDER);
+        std::fs::create_dir_all(&media_folder).unwrap();
+        let large = vec![7u8; INLINE_MEDIA_MAX_BYTES as usize + 1];
+        std::fs::write(media_folder.join("1.jpg"), &large).unwrap();
+        let data = storage.data_mut();
+        let url = "https://pbs.twimg.com/media/1.jpg";
+        data.tweets = vec![with_photo(tweet(1, "large"), url)];
+        data.media.insert(url.to_string(), "1.jpg".to_string());
+
+        let html = export(storage.data(), &media_folder, &archive);
+        assert!(html.contains("\"src\":\"media/1.jpg\""));
+        assert_eq!(std::fs::read(media_folder.join("1.jpg")).unwrap(), large);
+    }
+}
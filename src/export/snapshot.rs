@@ -0,0 +1,201 @@
+//! A shareable PNG card that summarizes the archived account
+use std::io::{Seek, Write};
+
+use eyre::Result;
+use image::{imageops::FilterType, ImageOutputFormat, Rgb, RgbImage};
+use imageproc::drawing::{draw_filled_circle_mut, draw_filled_rect_mut, draw_text_mut};
+use imageproc::rect::Rect;
+use rusttype::{Font, Scale};
+
+use super::FONT;
+use crate::storage::Storage;
+
+/// The size of a Twitter summary card
+const WIDTH: u32 = 1200;
+const HEIGHT: u32 = 628;
+const MARGIN: i32 = 60;
+const AVATAR_SIZE: u32 = 200;
+/// Roughly what fits into a line of the bio at `BIO_SIZE`
+const BIO_CHARS_PER_LINE: usize = 60;
+const BIO_LINES: usize = 3;
+const NAME_SIZE: f32 = 56.0;
+const BIO_SIZE: f32 = 28.0;
+const COUNT_SIZE: f32 = 40.0;
+const LABEL_SIZE: f32 = 24.0;
+
+const BACKGROUND: Rgb<u8> = Rgb([21, 32, 43]);
+const FOREGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const MUTED: Rgb<u8> = Rgb([139, 152, 165]);
+const ACCENT: Rgb<u8> = Rgb([29, 155, 240]);
+
+/// Renders the card: the avatar, name, handle and bio of the profile, the
+/// archived tweet, follower and follow counts and the date of the last
+/// backup. Without a downloaded avatar, a placeholder is drawn
+pub fn snapshot_png(storage: &Storage, out: &mut (impl Write + Seek)) -> Result<()> {
+    let font = Font::try_from_bytes(FONT).ok_or_else(|| eyre::eyre!("Invalid font"))?;
+    let data = storage.data();
+    let profile = &data.profile;
+    let mut card = RgbImage::from_pixel(WIDTH, HEIGHT, BACKGROUND);
+    draw_filled_rect_mut(&mut card, Rect::at(0, 0).of_size(WIDTH, 12), ACCENT);
+
+    let avatar = data
+        .media
+        .get(&profile.profile_image_url_https)
+        .and_then(|file_name| image::open(storage.media_path(file_name)).ok());
+    match avatar {
+        Some(avatar) => {
+            let avatar = avatar
+                .resize_to_fill(AVATAR_SIZE, AVATAR_SIZE, FilterType::Lanczos3)
+                .to_rgb8();
+            image::imageops::overlay(&mut card, &avatar, MARGIN as i64, MARGIN as i64);
+        }
+        None => {
+            let radius = AVATAR_SIZE as i32 / 2;
+            let center = (MARGIN + radius, MARGIN + radius);
+            draw_filled_circle_mut(&mut card, center, radius, MUTED);
+            let initial: String = profile.name.chars().take(1).collect();
+            draw_text_mut(
+                &mut card,
+                BACKGROUND,
+                center.0 - radius / 3,
+                center.1 - radius / 2,
+                Scale::uniform(radius as f32),
+                &font,
+                &initial,
+            );
+        }
+    }
+
+    let text_x = MARGIN * 2 + AVATAR_SIZE as i32;
+    let mut y = MARGIN;
+    draw_text_mut(
+        &mut card,
+        FOREGROUND,
+        text_x,
+        y,
+        Scale::uniform(NAME_SIZE),
+        &font,
+        &profile.name,
+    );
+    y += NAME_SIZE as i32 + 8;
+    let handle = format!("@{}", profile.screen_name);
+    draw_text_mut(
+        &mut card,
+        MUTED,
+        text_x,
+        y,
+        Scale::uniform(BIO_SIZE),
+        &font,
+        &handle,
+    );
+    y += BIO_SIZE as i32 + 24;
+    let bio = profile.description.as_deref().unwrap_or_default();
+    for line in bio_lines(bio) {
+        draw_text_mut(
+            &mut card,
+            FOREGROUND,
+            text_x,
+            y,
+            Scale::uniform(BIO_SIZE),
+            &font,
+            &line,
+        );
+        y += BIO_SIZE as i32 + 8;
+    }
+
+    let counts = [
+        (data.timeline_tweet_count(), "Tweets"),
+        (data.followers.len(), "Followers"),
+        (data.follows.len(), "Following"),
+    ];
+    let column_width = (WIDTH as i32 - MARGIN * 2) / counts.len() as i32;
+    let counts_y = HEIGHT as i32 - MARGIN - 140;
+    for (index, (count, label)) in counts.iter().enumerate() {
+        let x = MARGIN + column_width * index as i32;
+        let count = count.to_string();
+        draw_text_mut(
+            &mut card,
+            FOREGROUND,
+            x,
+            counts_y,
+            Scale::uniform(COUNT_SIZE),
+            &font,
+            &count,
+        );
+        draw_text_mut(
+            &mut card,
+            MUTED,
+            x,
+            counts_y + COUNT_SIZE as i32 + 4,
+            Scale::uniform(LABEL_SIZE),
+            &font,
+            label,
+        );
+    }
+
+    let archived = storage
+        .previous_backup()
+        .unwrap_or_else(chrono::Utc::now)
+        .with_timezone(&chrono::Local)
+        .format("Archived with TwitVault on %Y-%m-%d")
+        .to_string();
+    draw_text_mut(
+        &mut card,
+        MUTED,
+        MARGIN,
+        HEIGHT as i32 - MARGIN - LABEL_SIZE as i32,
+        Scale::uniform(LABEL_SIZE),
+        &font,
+        &archived,
+    );
+
+    card.write_to(out, ImageOutputFormat::Png)?;
+    Ok(())
+}
+
+/// The bio broken into at most `BIO_LINES` lines at spaces, with an
+/// ellipsis if it is longer
+fn bio_lines(bio: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in bio.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + word.chars().count() + 1 > BIO_CHARS_PER_LINE
+        {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    if lines.len() > BIO_LINES {
+        lines.truncate(BIO_LINES);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{storage, tweet};
+
+    #[test]
+    fn writes_a_png_card_without_a_downloaded_avatar() {
+        let mut storage = storage("snapshot");
+        storage.data_mut().tweets = vec![tweet(1, "hello")];
+
+        let mut png = std::io::Cursor::new(Vec::new());
+        snapshot_png(&storage, &mut png).unwrap();
+
+        let png = png.into_inner();
+        assert!(png.starts_with(b"\x89PNG"));
+        let card = image::load_from_memory(&png).unwrap();
+        assert_eq!((card.width(), card.height()), (WIDTH, HEIGHT));
+    }
+}
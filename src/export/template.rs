@@ -0,0 +1,193 @@
+//! User supplied templates for the tweets of the Markdown and HTML exports.
+//! A template is text with `{{field}}` placeholders, e.g.
+//! `**{{handle}}** {{date}}: {{text}}`
+use std::path::{Component, Path};
+
+use eyre::{bail, Result, WrapErr};
+
+use crate::storage::Data;
+
+/// The folder in the archive that contains the templates, `tweet.md` for
+/// the Markdown and `tweet.html` for the HTML export. The config's
+/// `export_templates` can point to another folder
+pub const TEMPLATE_FOLDER: &str = "templates";
+
+/// The fields that a template can use
+pub const FIELDS: [&str; 5] = ["date", "text", "handle", "media", "permalink"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Date,
+    Text,
+    Handle,
+    Media,
+    Permalink,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "date" => Some(Field::Date),
+            "text" => Some(Field::Text),
+            "handle" => Some(Field::Handle),
+            "media" => Some(Field::Media),
+            "permalink" => Some(Field::Permalink),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// The values of the fields for one tweet. They are inserted as they are,
+/// so for HTML they have to be escaped already
+#[derive(Clone, Debug, Default)]
+pub struct TweetFields {
+    pub date: String,
+    pub text: String,
+    pub handle: String,
+    /// The media of the tweet, formatted for the export
+    pub media: String,
+    pub permalink: String,
+}
+
+/// A parsed template, see the module documentation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TweetTemplate {
+    parts: Vec<Part>,
+}
+
+impl TweetTemplate {
+    /// Fails for unknown fields and unclosed placeholders, with the line and
+    /// column of the placeholder
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let offset = source.len() - rest.len() + start;
+            let Some(end) = rest[start..].find("}}") else {
+                bail!("Unclosed `{{{{` at {}", position(source, offset))
+            };
+            let name = rest[start + 2..start + end].trim();
+            let Some(field) = Field::parse(name) else {
+                bail!(
+                    "Unknown field `{{{{{name}}}}}` at {}. Available fields: {}",
+                    position(source, offset),
+                    FIELDS.join(", ")
+                )
+            };
+            parts.push(Part::Field(field));
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    /// The template in `template_folder` for the file extension of the
+    /// export, e.g. `md`. `None` if there is none
+    pub fn load(template_folder: &Path, extension: &str) -> Result<Option<Self>> {
+        let path = template_folder.join(format!("tweet.{extension}"));
+        if !path.exists() {
+            return Ok(None);
+        }
+        let source = std::fs::read_to_string(&path)?;
+        Self::parse(&source)
+            .map(Some)
+            .wrap_err_with(|| format!("Invalid template {}", path.display()))
+    }
+
+    pub fn render(&self, fields: &TweetFields) -> String {
+        let mut output = String::new();
+        for part in &self.parts {
+            output.push_str(match part {
+                Part::Text(text) => text,
+                Part::Field(Field::Date) => &fields.date,
+                Part::Field(Field::Text) => &fields.text,
+                Part::Field(Field::Handle) => &fields.handle,
+                Part::Field(Field::Media) => &fields.media,
+                Part::Field(Field::Permalink) => &fields.permalink,
+            });
+        }
+        output
+    }
+}
+
+/// Links to the archived media files from an export, so that `{{media}}`
+/// shows them without Twitter
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MediaLinks {
+    /// The media folder, relative to the export folder if possible
+    folder: String,
+}
+
+impl MediaLinks {
+    pub fn new(media_folder: &Path, export_folder: &Path) -> Self {
+        let media_folder = media_folder
+            .canonicalize()
+            .unwrap_or_else(|_| media_folder.to_path_buf());
+        let export_folder = export_folder
+            .canonicalize()
+            .unwrap_or_else(|_| export_folder.to_path_buf());
+        let folder = relative_path(&export_folder, &media_folder)
+            .unwrap_or_else(|| media_folder.to_string_lossy().replace('\\', "/"));
+        Self { folder }
+    }
+
+    /// The archived file of the media, or its url if it wasn't downloaded
+    pub fn link(&self, data: &Data, url: &str) -> String {
+        match data.media.get(url) {
+            Some(file_name) => format!("{}/{file_name}", self.folder),
+            None => url.to_string(),
+        }
+    }
+}
+
+/// `to` relative to `from` with forward slashes, e.g. `../media`. `None` if
+/// they have different roots, e.g. other drives on Windows
+fn relative_path(from: &Path, to: &Path) -> Option<String> {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let roots = to
+        .iter()
+        .take_while(|c| matches!(c, Component::Prefix(_) | Component::RootDir))
+        .count();
+    if common < roots {
+        return None;
+    }
+    let parts: Vec<String> = from[common..]
+        .iter()
+        .map(|_| "..".to_string())
+        .chain(
+            to[common..]
+                .iter()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+        )
+        .collect();
+    if parts.is_empty() {
+        return Some(".".to_string());
+    }
+    Some(parts.join("/"))
+}
+
+/// `line 2, column 5` for a byte offset, both starting at 1
+fn position(source: &str, offset: usize) -> String {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map(|line| line.chars().count())
+        .unwrap_or_default()
+        + 1;
+    format!("line {line}, column {column}")
+}
@@ -0,0 +1,74 @@
+//! Export the tweets of the owner as plain text, one tweet per line, e.g.
+//! as input for other tools
+use std::io::Write;
+
+use egg_mode::tweet::Tweet;
+use eyre::Result;
+
+use crate::storage::Data;
+
+/// Writes `[date] text` per tweet. Retweets are written as
+/// `[date] RT @handle: text` with the text of the retweeted tweet. Line
+/// breaks within a tweet become spaces
+pub fn write_text(data: &Data, oldest_first: bool, out: &mut impl Write) -> Result<()> {
+    let mut tweets: Vec<&Tweet> = data.tweets.iter().collect();
+    tweets.sort_by_key(|tweet| tweet.id);
+    if !oldest_first {
+        tweets.reverse();
+    }
+    for tweet in tweets {
+        writeln!(
+            out,
+            "[{}] {}",
+            tweet.created_at.format("%Y-%m-%d %H:%M"),
+            line(data, tweet)
+        )?;
+    }
+    Ok(())
+}
+
+fn line(data: &Data, tweet: &Tweet) -> String {
+    let text = match &tweet.retweeted_status {
+        Some(retweeted) => {
+            let handle = retweeted
+                .user
+                .as_ref()
+                .map(|user| user.screen_name.as_str())
+                .unwrap_or_default();
+            format!("RT @{handle}: {}", data.text(retweeted))
+        }
+        None => data.text(tweet).to_string(),
+    };
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{storage, tweet, user};
+
+    #[test]
+    fn retweets_are_prefixed_and_the_order_follows_the_flag() {
+        let mut storage = storage("export-text");
+        let mut retweet = tweet(2, "RT @alice: Hello");
+        retweet.retweeted_status = Some(Box::new(Tweet {
+            user: Some(Box::new(user(2, "alice"))),
+            ..tweet(1, "Hello\nworld")
+        }));
+        storage.data_mut().tweets = vec![tweet(3, "Newest"), retweet];
+
+        let export = |oldest_first| {
+            let mut out = Vec::new();
+            write_text(storage.data(), oldest_first, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            export(false),
+            "[2020-09-13 12:29] Newest\n[2020-09-13 12:28] RT @alice: Hello world\n"
+        );
+        assert_eq!(
+            export(true),
+            "[2020-09-13 12:28] RT @alice: Hello world\n[2020-09-13 12:29] Newest\n"
+        );
+    }
+}
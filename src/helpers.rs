@@ -1,5 +1,15 @@
-use crate::{config::Config, crawler::DownloadInstruction};
-use egg_mode::tweet::Tweet;
+use std::collections::HashMap;
+
+use crate::{
+    config::Config,
+    crawler::DownloadInstruction,
+    storage::{MediaMeta, Storage},
+};
+use egg_mode::{
+    entities::{MediaEntity, VideoInfo, VideoVariant},
+    tweet::Tweet,
+    user::TwitterUser,
+};
 use tracing::warn;
 
 pub fn media_in_tweet(tweet: &Tweet) -> Option<Vec<DownloadInstruction>> {
@@ -10,22 +20,11 @@ pub fn media_in_tweet(tweet: &Tweet) -> Option<Vec<DownloadInstruction>> {
     for media in &entities.media {
         match &media.video_info {
             Some(n) => {
-                let mut selected_variant = n.variants.first();
-                for variant in &n.variants {
-                    match (
-                        variant.content_type.subtype(),
-                        &selected_variant.map(|e| e.bitrate),
-                    ) {
-                        (mime::MP4, Some(bitrate)) if bitrate < &variant.bitrate => {
-                            selected_variant = Some(variant)
-                        }
-                        _ => (),
-                    }
-                }
-                let Some(variant) = selected_variant else { continue };
+                let Some(variant) = best_variant(n) else { continue };
                 output.push(DownloadInstruction::Movie(
                     variant.content_type.clone(),
                     variant.url.clone(),
+                    video_meta(media, n),
                 ))
             }
             None => output.push(DownloadInstruction::Image(media.media_url_https.clone())),
@@ -35,6 +34,260 @@ pub fn media_in_tweet(tweet: &Tweet) -> Option<Vec<DownloadInstruction>> {
     Some(output)
 }
 
+/// The variant of a video that is downloaded: the MP4 with the highest bitrate
+fn best_variant(info: &VideoInfo) -> Option<&VideoVariant> {
+    let mut selected_variant = info.variants.first();
+    for variant in &info.variants {
+        match (
+            variant.content_type.subtype(),
+            &selected_variant.map(|e| e.bitrate),
+        ) {
+            (mime::MP4, Some(bitrate)) if bitrate < &variant.bitrate => {
+                selected_variant = Some(variant)
+            }
+            _ => (),
+        }
+    }
+    selected_variant
+}
+
+/// The dimensions and length of the videos and GIFs in a tweet, by the url
+/// of the variant that is downloaded
+pub fn video_meta_in_tweet(tweet: &Tweet) -> Vec<(String, MediaMeta)> {
+    let Some(entities) = &tweet.extended_entities else { return Vec::new() };
+    entities
+        .media
+        .iter()
+        .filter_map(|media| {
+            let info = media.video_info.as_ref()?;
+            let variant = best_variant(info)?;
+            Some((variant.url.clone(), video_meta(media, info)))
+        })
+        .collect()
+}
+
+/// The dimensions and length of a video as the tweet describes it
+fn video_meta(media: &MediaEntity, info: &VideoInfo) -> MediaMeta {
+    let size = &media.sizes.large;
+    MediaMeta {
+        bytes: 0,
+        width: u32::try_from(size.w).ok(),
+        height: u32::try_from(size.h).ok(),
+        duration_millis: info.duration_millis.and_then(|d| u32::try_from(d).ok()),
+    }
+}
+
+/// The urls of all media in a tweet, including quoted tweets and retweets
+pub fn media_urls_in_tweet(tweet: &Tweet) -> Vec<String> {
+    let mut urls = Vec::new();
+    let nested = [tweet.quoted_status.as_deref(), tweet.retweeted_status.as_deref()];
+    for t in std::iter::once(tweet).chain(nested.into_iter().flatten()) {
+        for instruction in media_in_tweet(t).unwrap_or_default() {
+            match instruction {
+                DownloadInstruction::Image(url)
+                | DownloadInstruction::Movie(_, url, _)
+                | DownloadInstruction::ProfileMedia(url) => urls.push(url),
+                DownloadInstruction::TweetMedia(..) | DownloadInstruction::Done => (),
+            }
+        }
+    }
+    urls
+}
+
+/// The link to the tweet on Twitter. The handle is taken from the tweet's
+/// user or from `profiles`. If it is unknown (e.g. for imported tweets),
+/// Twitter's handle-less `i/web/status` link is used
+pub fn tweet_permalink(tweet: &Tweet, profiles: &HashMap<u64, TwitterUser>) -> String {
+    let handle = tweet.user.as_ref().and_then(|user| {
+        if !user.screen_name.is_empty() {
+            Some(user.screen_name.as_str())
+        } else {
+            profiles.get(&user.id).map(|p| p.screen_name.as_str())
+        }
+    });
+    match handle {
+        Some(handle) => format!("https://twitter.com/{handle}/status/{}", tweet.id),
+        None => format!("https://twitter.com/i/web/status/{}", tweet.id),
+    }
+}
+
+/// The text of the tweet with all `t.co` links replaced by the urls
+/// they point to. `None` if the tweet doesn't contain any links
+pub fn expanded_text(tweet: &Tweet) -> Option<String> {
+    let mut replacements: Vec<((usize, usize), &str)> = tweet
+        .entities
+        .urls
+        .iter()
+        .map(|url| {
+            let target = url.expanded_url.as_ref().unwrap_or(&url.display_url);
+            (url.range, target.as_str())
+        })
+        .collect();
+    if let Some(media) = tweet.entities.media.as_ref() {
+        replacements.extend(
+            media
+                .iter()
+                .map(|media| (media.range, media.expanded_url.as_str())),
+        );
+    }
+    if replacements.is_empty() {
+        return None;
+    }
+    replacements.sort_by_key(|(range, _)| range.0);
+
+    let text = &tweet.text;
+    let mut output = String::with_capacity(text.len());
+    let mut current = 0;
+    for ((start, end), target) in replacements {
+        // Overlapping or broken ranges would panic while slicing
+        let (Some(before), Some(_)) = (text.get(current..start), text.get(start..end)) else {
+            continue;
+        };
+        output.push_str(before);
+        output.push_str(target);
+        current = end;
+    }
+    output.push_str(&text[current..]);
+    Some(output)
+}
+
+/// Where the mentions and hashtags of `render_tweet_html` link to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EntityLinks {
+    /// The profiles and hashtag pages on twitter.com
+    #[default]
+    Twitter,
+    /// Anchors in the same document, `#user-<id>` and `#hashtag-<tag>`
+    Local,
+}
+
+/// The text of the tweet as HTML. Mentions, hashtags and links become
+/// anchors, links show their expanded url. Everything else is escaped
+pub fn render_tweet_html(tweet: &Tweet, links: EntityLinks) -> String {
+    // The range in the text, the target and the visible text
+    let mut anchors: Vec<((usize, usize), String, String)> = Vec::new();
+    let text = &tweet.text;
+    for tag in &tweet.entities.hashtags {
+        let href = match links {
+            EntityLinks::Twitter => format!("https://twitter.com/hashtag/{}", tag.text),
+            EntityLinks::Local => format!("#hashtag-{}", tag.text.to_lowercase()),
+        };
+        anchors.push((tag.range, href, format!("#{}", tag.text)));
+    }
+    for mention in &tweet.entities.user_mentions {
+        let href = match links {
+            EntityLinks::Twitter => format!("https://twitter.com/{}", mention.screen_name),
+            EntityLinks::Local => format!("#user-{}", mention.id),
+        };
+        anchors.push((mention.range, href, format!("@{}", mention.screen_name)));
+    }
+    for url in &tweet.entities.urls {
+        let href = url.expanded_url.as_ref().unwrap_or(&url.url).clone();
+        anchors.push((url.range, href, url.display_url.clone()));
+    }
+    for media in tweet.entities.media.iter().flatten() {
+        anchors.push((
+            media.range,
+            media.expanded_url.clone(),
+            media.display_url.clone(),
+        ));
+    }
+    anchors.sort_by_key(|(range, _, _)| range.0);
+
+    let mut output = String::with_capacity(text.len() * 2);
+    let mut current = 0;
+    for ((start, end), href, label) in anchors {
+        // Overlapping or broken ranges would panic while slicing
+        let (Some(before), Some(_)) = (text.get(current..start), text.get(start..end)) else {
+            continue;
+        };
+        output.push_str(&escape_html(before));
+        output.push_str(&format!(
+            "<a href=\"{}\">{}</a>",
+            escape_html(&href),
+            escape_html(&label)
+        ));
+        current = end;
+    }
+    output.push_str(&escape_html(&text[current..]));
+    output.replace('\n', "<br>")
+}
+
+/// Escapes text for HTML content and double quoted attributes
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Shorten `text` to at most `max_chars` characters including the `…`.
+/// It never cuts inside an emoji sequence (e.g. skin tones, `ZWJ` families
+/// or the letter pairs of flags) and never inside a link, mention or hashtag: those are dropped completely
+/// instead of leaving half of them
+pub fn truncate_tweet_text(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    if max_chars == 0 {
+        return String::new();
+    }
+    // Byte offset after `max_chars - 1` chars, leaving room for the ellipsis
+    let mut cut = text
+        .char_indices()
+        .nth(max_chars - 1)
+        .map(|(index, _)| index)
+        .unwrap_or(text.len());
+
+    // Joiners, variation selectors, skin tones and combining marks belong
+    // to the character before them
+    let is_extending = |c: char| {
+        matches!(c, '\u{200D}' | '\u{FE00}'..='\u{FE0F}' | '\u{1F3FB}'..='\u{1F3FF}')
+            || matches!(c, '\u{0300}'..='\u{036F}' | '\u{E0020}'..='\u{E007F}')
+    };
+    // Flags are pairs of regional indicator letters
+    let is_regional = |c: char| matches!(c, '\u{1F1E6}'..='\u{1F1FF}');
+    loop {
+        let (before, after) = text.split_at(cut);
+        let next_extends = after.chars().next().map(is_extending).unwrap_or(false);
+        let previous_joins = before.ends_with('\u{200D}');
+        let regional_before = before.chars().rev().take_while(|c| is_regional(*c)).count();
+        let splits_flag = regional_before % 2 == 1 && after.starts_with(is_regional);
+        if !(next_extends || previous_joins || splits_flag) {
+            break;
+        }
+        match before.char_indices().last() {
+            Some((index, _)) => cut = index,
+            None => break,
+        }
+    }
+
+    // The word the cut falls into, if the cut is not at a word boundary
+    let before = &text[..cut];
+    let in_word =
+        !before.ends_with(char::is_whitespace) && !text[cut..].starts_with(char::is_whitespace);
+    if in_word {
+        let word_start = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(index, c)| index + c.len_utf8())
+            .unwrap_or(0);
+        let word = &text[word_start..];
+        let is_entity = word.starts_with("http://")
+            || word.starts_with("https://")
+            || word.starts_with('@')
+            || word.starts_with('#');
+        if is_entity {
+            cut = word_start;
+        }
+    }
+
+    let mut truncated = text[..cut].trim_end().to_string();
+    truncated.push('…');
+    truncated
+}
+
 pub async fn delete_tweet(tweet_id: u64, config: &Config) -> Result<bool, String> {
     egg_mode::tweet::delete(tweet_id, &config.token)
         .await
@@ -45,6 +298,18 @@ pub async fn delete_tweet(tweet_id: u64, config: &Config) -> Result<bool, String
         })
 }
 
+/// Remove a tweet from the archive on disk and return the updated storage
+pub fn remove_archived_tweet(tweet_id: u64, config: &Config) -> Result<Storage, String> {
+    let result = Storage::open(config.actual_storage_path()).and_then(|mut storage| {
+        storage.remove_tweet(tweet_id)?;
+        Ok(storage)
+    });
+    result.map_err(|e| {
+        warn!("Could not remove tweet from archive: {e:?}");
+        format!("{e:?}")
+    })
+}
+
 /// Sorta cross-platform way of opening a file
 pub fn open_file(path: &str) {
     use std::process::Command;
@@ -63,3 +328,94 @@ pub fn open_file(path: &str) {
         Command::new("open").args(["-R", path]).spawn().ok();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{tweet, user, with_mention, with_url};
+
+    #[test]
+    fn expands_every_link() {
+        let linked = tweet(1, "Read https://t.co/a and https://t.co/b!");
+        let linked = with_url(linked, "https://t.co/a", "https://example.com/a");
+        let linked = with_url(linked, "https://t.co/b", "https://example.org/b");
+        assert_eq!(
+            expanded_text(&linked).as_deref(),
+            Some("Read https://example.com/a and https://example.org/b!")
+        );
+        // The original text is kept
+        assert_eq!(linked.text, "Read https://t.co/a and https://t.co/b!");
+
+        assert_eq!(expanded_text(&tweet(2, "No links")), None);
+    }
+
+    #[test]
+    fn permalinks_fall_back_to_the_web_status() {
+        let mut profiles = HashMap::new();
+        assert_eq!(
+            tweet_permalink(&tweet(1, "Hi"), &profiles),
+            "https://twitter.com/owner/status/1"
+        );
+
+        // Without a handle in the tweet, the archived profile has it
+        let mut nameless = tweet(2, "Hi");
+        nameless.user.as_mut().unwrap().screen_name.clear();
+        profiles.insert(1, user(1, "archived"));
+        assert_eq!(
+            tweet_permalink(&nameless, &profiles),
+            "https://twitter.com/archived/status/2"
+        );
+
+        profiles.clear();
+        assert_eq!(
+            tweet_permalink(&nameless, &profiles),
+            "https://twitter.com/i/web/status/2"
+        );
+        let mut authorless = tweet(3, "Hi");
+        authorless.user = None;
+        assert_eq!(
+            tweet_permalink(&authorless, &profiles),
+            "https://twitter.com/i/web/status/3"
+        );
+    }
+
+    #[test]
+    fn truncation_keeps_emoji_and_cjk_whole() {
+        assert_eq!(truncate_tweet_text("short", 10), "short");
+        assert_eq!(truncate_tweet_text("日本語のツイートです", 5), "日本語の…");
+        // A family is one emoji made of seven chars joined by ZWJ
+        let family = "ab 👨\u{200D}👩\u{200D}👧\u{200D}👦 cd";
+        assert_eq!(truncate_tweet_text(family, 6), "ab…");
+        assert_eq!(truncate_tweet_text("ab 👍🏽 cd", 5), "ab…");
+        assert_eq!(truncate_tweet_text("🇩🇪🇫🇷🇯🇵", 4), "🇩🇪…");
+        assert_eq!(truncate_tweet_text("🇩🇪🇫🇷🇯🇵", 5), "🇩🇪🇫🇷…");
+    }
+
+    #[test]
+    fn truncation_drops_partial_links() {
+        let text = "Look at https://example.com/long/path please";
+        assert_eq!(truncate_tweet_text(text, 20), "Look at…");
+        assert_eq!(truncate_tweet_text("Hi @someone there", 7), "Hi…");
+        assert_eq!(truncate_tweet_text("Some words here", 8), "Some wo…");
+    }
+
+    #[test]
+    fn tweet_html_escapes_the_text_around_the_entities() {
+        let text = "@friend <script>alert(\"hi\")</script> & https://t.co/a";
+        let friend = user(2, "friend");
+        let linked = with_url(
+            with_mention(tweet(1, text), &friend),
+            "https://t.co/a",
+            "https://example.com/?a=1&b=\"2\"",
+        );
+
+        assert_eq!(
+            render_tweet_html(&linked, EntityLinks::Twitter),
+            "<a href=\"https://twitter.com/friend\">@friend</a> \
+             &lt;script&gt;alert(&quot;hi&quot;)&lt;/script&gt; &amp; \
+             <a href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\">example.com/?a=1&amp;b=&quot;2&quot;</a>"
+        );
+        assert!(render_tweet_html(&linked, EntityLinks::Local)
+            .starts_with("<a href=\"#user-2\">@friend</a> &lt;script&gt;"));
+    }
+}
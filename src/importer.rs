@@ -52,15 +52,22 @@ pub async fn import_archive(
     }
 
     let known_ids: HashSet<u64> = storage.data().tweets.iter().map(|e| e.id).collect();
-    // get a copy of all known tweets so we can insert new ones and in the
-    // end sort them all
-    let mut tweets = storage.data().tweets.clone();
+    // the new tweets are added in the end, sorted with the known ones
+    let mut tweets = Vec::new();
 
     let shared_storage = Arc::new(Mutex::new(storage));
 
     let cloned_storage = shared_storage.clone();
-    let (instruction_task, instruction_sender) =
-        crate::crawler::create_instruction_handler(config.crawl_options().media, cloned_storage);
+    let (instruction_task, instruction_sender) = crate::crawler::create_instruction_handler(
+        config.crawl_options().media_filter(),
+        config.retry_policy().clone(),
+        config.bandwidth_limiter(),
+        config.config_data.media_naming,
+        config.media_domains(),
+        config.config_data.max_media_bytes,
+        config.http_client()?,
+        cloned_storage,
+    );
 
     let (message_sender, _) = channel::<Message>(4096);
 
@@ -95,8 +102,6 @@ pub async fn import_archive(
         }
     }
 
-    tweets.sort_by(|a, b| b.id.cmp(&a.id));
-
     info!("Waiting for Media downloads");
     if let Err(e) = instruction_sender
         .send(crate::crawler::DownloadInstruction::Done)
@@ -111,11 +116,13 @@ pub async fn import_archive(
         warn!("Error executing instructions: {e:?}");
     }
 
-    info!("imported {new_tweets} new tweets. Total: {}", tweets.len());
-
     let mut new_storage = shared_storage.lock_owned().await.clone();
 
-    new_storage.data_mut().tweets = tweets;
+    new_storage.data_mut().add_timeline_tweets(tweets);
+    info!(
+        "imported {new_tweets} new tweets. Total: {}",
+        new_storage.data().timeline_tweet_count()
+    );
 
     Ok(new_storage)
 }
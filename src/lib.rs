@@ -0,0 +1,24 @@
+//! Archive the data of a Twitter account.
+//!
+//! [`TwitVault`] runs a backup for a [`config::Config`] and reports the
+//! progress as a stream of [`types::Message`]. The result is a
+//! [`storage::Storage`] that can be searched via [`search`] or
+//! exported via [`export`].
+pub mod config;
+pub mod crawler;
+pub mod estimate;
+pub mod export;
+mod helpers;
+pub mod importer;
+mod notify;
+pub mod search;
+pub mod storage;
+#[cfg(test)]
+mod test_fixtures;
+pub mod types;
+/// The desktop app of the binary, not meant to be used by other tools
+#[doc(hidden)]
+pub mod ui;
+mod vault;
+
+pub use vault::TwitVault;
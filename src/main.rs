@@ -1,83 +1,238 @@
-mod config;
-mod crawler;
-mod helpers;
-mod importer;
-mod search;
-mod storage;
-mod types;
-mod ui;
-
 use clap::{ArgMatches, Command};
 use eyre::{bail, Result};
-use tokio::{
-    sync::mpsc::{channel, Receiver},
-    task::JoinHandle,
-};
+use futures::{Stream, StreamExt};
 use tracing::{info, warn};
 
-use config::Config;
-use storage::Storage;
+use twitvault::config::{self, Config};
+use twitvault::storage::{self, Storage};
+use twitvault::{crawler, estimate, importer, types, ui, TwitVault};
 
 use std::{
     path::{Path, PathBuf},
     str::FromStr,
+    time::Instant,
 };
 
-use crate::types::Message;
+use twitvault::types::Message;
+
+/// The exit code of a crawl or sync that stopped because the token was rejected
+const AUTH_EXIT_CODE: i32 = 3;
+/// The exit code of a crawl or sync that was stopped via Ctrl-C
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+/// The version of the `--json` output. Increased when fields are removed or change
+const JSON_OUTPUT_VERSION: u32 = 1;
+
+/// Ends the process with the exit code after the runtime shut down, without
+/// printing anything. The command already reported the problem
+#[derive(Debug)]
+struct ExitCode(i32);
+
+impl std::fmt::Display for ExitCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Exit code {}", self.0)
+    }
+}
+
+impl std::error::Error for ExitCode {}
+
+fn main() -> Result<()> {
+    match run() {
+        Err(error) => match error.downcast_ref::<ExitCode>() {
+            Some(ExitCode(code)) => std::process::exit(*code),
+            None => Err(error),
+        },
+        Ok(()) => Ok(()),
+    }
+}
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    setup_tracing();
+async fn run() -> Result<()> {
     let name = "TwitVault";
 
-    // check if we have a path to a custom storage
+    // check if we have a path to a custom storage or a selected account
     let raw_args: Vec<_> = std::env::args().collect();
-    let (config, storage, storage_path) =
-        match (raw_args.get(1).map(|e| e.as_str()), raw_args.get(2)) {
-            (Some("--custom-archive"), Some(custom)) => {
-                let custom_path = PathBuf::from_str(custom)?;
-                if !custom_path.exists() {
-                    std::fs::create_dir_all(&custom_path)
-                        .expect("Expect to be able to create the data directory");
-                }
-                println!("Try opening Storage: {}", &custom_path.display());
-                let config = config::Config::open(Some(custom_path.clone())).ok();
-                let storage = Storage::open(custom_path.clone());
-                (config, storage, custom_path)
-            }
-            _ => {
-                let storage_path = config::Config::storage_path(None);
-                if !storage_path.exists() {
-                    std::fs::create_dir_all(&storage_path)
-                        .expect("Expect to be able to create the data directory");
-                }
-                println!("Try opening Storage: {}", storage_path.display());
-                let config = config::Config::open(None).ok();
-                let storage = Storage::open(&storage_path);
-                (config, storage, storage_path)
+    let arg_value = |name: &str| {
+        let position = raw_args.iter().position(|arg| arg == name)?;
+        raw_args.get(position + 1)
+    };
+    if let Some(dir) = arg_value("--archive-dir") {
+        Config::set_base_directory(PathBuf::from_str(dir)?);
+    }
+    let mut accounts = Vec::new();
+    let mut migration_error = None;
+    let custom_path = match (arg_value("--custom-archive"), arg_value("--account")) {
+        (Some(custom), _) => Some(PathBuf::from_str(custom)?),
+        (None, Some(screen_name)) => Some(Config::account_path(screen_name)),
+        _ => {
+            migration_error = Config::migrate_legacy_archive().err();
+            accounts = Config::accounts();
+            // With only one account, there's nothing to pick
+            match accounts.as_slice() {
+                [account] => Some(account.path.clone()),
+                _ => None,
             }
-        };
+        }
+    };
+    let storage_path = Config::storage_path(custom_path.clone());
+    if !storage_path.exists() {
+        std::fs::create_dir_all(&storage_path)
+            .expect("Expect to be able to create the data directory");
+    }
+
+    let log_file = raw_args.iter().any(|arg| arg == "--log-file");
+    // Keep stdout for the JSON summary
+    let json_output = raw_args.iter().any(|arg| arg == "--json");
+    setup_tracing(log_file.then_some(storage_path.as_path()), json_output);
+    if let Some(e) = migration_error {
+        warn!("Could not move the archive into the accounts folder: {e:?}");
+    }
+    if !json_output {
+        println!("Try opening Storage: {}", storage_path.display());
+    }
+    let config = Config::open(custom_path.clone()).ok();
+    let archive_exists = Storage::exists(&storage_path);
+
+    let account_names: Vec<_> = accounts.iter().map(|a| a.screen_name.as_str()).collect();
+    let account_arg = clap::Arg::new("account")
+        .long("account")
+        .help(format!(
+            "The screen name of the account to open. Known accounts: {}",
+            account_names.join(", ")
+        ))
+        .required(false);
+    let archive_dir_arg = clap::Arg::new("archive-dir")
+        .long("archive-dir")
+        .help("The folder for all accounts and settings. Defaults to $TWITVAULT_DIR or the app data folder")
+        .required(false)
+        .global(true);
+    let dry_run_arg = clap::Arg::new("dry-run")
+        .long("dry-run")
+        .help("Only estimate the number of API calls and the duration, don't download anything")
+        .action(clap::ArgAction::SetTrue);
+    let archive_account_cmd = Command::new("archive-account")
+        .about("Archive the public tweets, followers and follows of another account into its own folder")
+        .arg(clap::Arg::new("screen-name").required(true))
+        .arg(dry_run_arg.clone())
+        .args(resume_args());
+    let open_cmd = Command::new("open")
+        .about("Browse an existing archive without logging in, e.g. a copy from another computer")
+        .arg(
+            clap::Arg::new("path")
+                .help("The archive folder, or the _data.json or _data.sqlite file in it")
+                .required(true),
+        );
+    let status_cmd = Command::new("status")
+        .about("Check whether Twitter is reachable and accepts the login");
+    let json_arg = clap::Arg::new("json")
+        .long("json")
+        .help("Print a JSON summary of a crawl or sync on stdout, logs go to stderr")
+        .action(clap::ArgAction::SetTrue)
+        .global(true);
+    let log_file_arg = clap::Arg::new("log-file")
+        .long("log-file")
+        .help("Write a JSON-lines log of the run into the logs folder of the archive")
+        .action(clap::ArgAction::SetTrue)
+        .global(true);
 
-    let cmd = match &storage {
-        Ok(existing) => clap::Command::new(name)
+    let cmd = match archive_exists {
+        true => clap::Command::new(name)
             .bin_name(name)
             .after_help(format!(
-                "Found an existing storage at {} for {}",
-                existing.root_folder.display(),
-                existing.data().profile.screen_name
+                "Found an existing storage at {}",
+                storage_path.display()
             ))
             .arg(clap::Arg::new("custom-archive")
             .long("custom-archive")
             .help("Absolute path to a different archive folder")
             .required(false))
+            .arg(account_arg.clone())
+            .arg(log_file_arg.clone())
+            .arg(json_arg.clone())
+            .arg(archive_dir_arg.clone())
             .subcommand_required(false)
-            .subcommand(Command::new("sync"))
+            .subcommand(archive_account_cmd.clone())
+            .subcommand(open_cmd.clone())
+            .subcommand(
+                Command::new("sync")
+                    .arg(dry_run_arg.clone())
+                    .args(resume_args()),
+            )
             .subcommand(
                 Command::new("import")
                     .arg(clap::Arg::new("archive-path").required(true).short('c')),
             )
-            .subcommand(Command::new("inspect")),
-        Err(_) => clap::Command::new(name)
+            .subcommand(Command::new("inspect"))
+            .subcommand(
+                Command::new("gc")
+                    .about("Merge duplicate media files and delete the ones that are not used anymore"),
+            )
+            .subcommand(
+                Command::new("relationships")
+                    .about("List mutuals and accounts that don't follow back"),
+            )
+            .subcommand(
+                Command::new("sync-media")
+                    .about("Copy new and changed media files into another folder")
+                    .arg(clap::Arg::new("destination").required(true))
+                    .arg(
+                        clap::Arg::new("delete")
+                            .long("delete")
+                            .help("Delete files in the destination that were removed from the archive")
+                            .action(clap::ArgAction::SetTrue),
+                    ),
+            )
+            .subcommand(
+                Command::new("hashtags")
+                    .about("List the hashtags you used most")
+                    .arg(
+                        clap::Arg::new("count")
+                            .short('n')
+                            .help("How many hashtags to list")
+                            .default_value("20"),
+                    ),
+            )
+            .subcommand(
+                Command::new("snapshot")
+                    .about("Render a PNG card that summarizes the archived account")
+                    .arg(
+                        clap::Arg::new("output")
+                            .short('o')
+                            .help("Where to write the PNG, twitvault-<screen name>.png by default"),
+                    ),
+            )
+            .subcommand(status_cmd.clone())
+            .subcommand(
+                Command::new("convert-sqlite")
+                    .about("Store the archive in a SQLite database instead of a JSON file"),
+            )
+            .subcommand(
+                Command::new("backfill")
+                    .about("Download the missing profiles of tweet authors and mentioned users"),
+            )
+            .subcommand(
+                Command::new("refresh-profiles")
+                    .about("Download the profiles again that were downloaded a while ago")
+                    .arg(
+                        clap::Arg::new("older-than")
+                            .long("older-than")
+                            .help("Only profiles downloaded longer ago than this, e.g. 30d, 2w or 12h")
+                            .default_value("30d"),
+                    ),
+            )
+            .subcommand(
+                Command::new("refresh-list")
+                    .about("Download the members of one list again")
+                    .arg(clap::Arg::new("list-id").required(true)),
+            )
+            .subcommand(
+                Command::new("verify").arg(
+                    clap::Arg::new("repair")
+                        .long("repair")
+                        .help("Download missing or corrupt media again")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+            ),
+        false => clap::Command::new(name)
             .bin_name(name)
             .after_help(format!(
                 "Found no existing storage at {}",
@@ -87,45 +242,151 @@ async fn main() -> Result<()> {
             .long("custom-archive")
             .help("Absolute path to a different archive folder")
             .required(false))
+            .arg(account_arg.clone())
+            .arg(log_file_arg.clone())
+            .arg(json_arg.clone())
+            .arg(archive_dir_arg.clone())
             .subcommand_required(false)
+            .subcommand(archive_account_cmd.clone())
+            .subcommand(open_cmd.clone())
+            .subcommand(status_cmd.clone())
             .subcommand(
                 Command::new("crawl")
                     .arg(clap::Arg::new("custom-user")
                     .help("Don't crawl the data of the authenticated user, but instead of the given custom-user which is the Twitter user id such as 6473172. You can find the id for a user via this website: https://tweeterid.com")
-                    .required(false).short('u')),
+                    .required(false).short('u'))
+                    .arg(clap::Arg::new("app-only")
+                    .long("app-only")
+                    .help("Don't log in, but archive the public data of the custom-user with an app-only token")
+                    .action(clap::ArgAction::SetTrue))
+                    .arg(dry_run_arg.clone())
+                    .args(resume_args()),
             ),
     };
 
     let matches = cmd.get_matches();
+    // Another archive is opened, not the one of the account
+    if let Some(("open", matches)) = matches.subcommand() {
+        return action_open(matches).await;
+    }
+    // The UI opens the archive in the background so that the window appears right away
+    let open_in_ui = matches.subcommand().is_none() && archive_exists && config.is_some();
+    let storage = match open_in_ui {
+        true => Err(eyre::eyre!("The archive is opened by the UI")),
+        false => Storage::open(&storage_path),
+    };
     match (matches.subcommand(), storage, config) {
         // Try to crawl with a pre-defined config
         (Some(("crawl", custom)), Err(_), Some(config)) => {
             action_crawl(&config, &storage_path, custom).await?
         }
+        // Crawl a public account without logging in
+        (Some(("crawl", custom)), Err(_), None) if custom.get_flag("app-only") => {
+            let Some(Ok(user_id)) = custom
+                .get_one::<String>("custom-user")
+                .map(|n| n.parse::<u64>()) else {
+                bail!("An app-only crawl needs the id of a user via -u")
+            };
+            let config = Config::load_app_only(custom_path.clone(), user_id).await?;
+            if !custom.get_flag("dry-run") {
+                config.save()?;
+            }
+            action_crawl(&config, &config.actual_storage_path(), custom).await?
+        }
         // If there's no config, perform the login dance in the terminal, then crawl
         (Some(("crawl", custom)), Err(_), None) => {
-            let config = Config::load(Some(storage_path.clone()))
+            let config = Config::load(custom_path.clone())
                 .await
                 .expect("Could not create config");
             action_crawl(&config, &storage_path, custom).await?
         }
+        // Archive a different public account with the login
+        (Some(("archive-account", matches)), _, Some(config)) => {
+            action_archive_account(&config, matches).await?
+        }
+        (Some(("archive-account", matches)), _, None) => {
+            let config = Config::load(custom_path.clone()).await?;
+            action_archive_account(&config, matches).await?
+        }
+        // Check whether Twitter is down or the login was rejected
+        (Some(("status", matches)), _, Some(config)) => action_status(&config, matches).await?,
+        (Some(("status", _)), _, None) => bail!("Not logged in, run the crawl first"),
         // Import a Twitter archive
         (Some(("import", archive)), Ok(storage), Some(config)) => {
             action_import(&config, storage, archive).await?
         }
         // For an existing storage, inspect it
         (Some(("inspect", _)), Ok(storage), _) => action_inspect(&storage).await?,
+        // For an existing storage, compare followers and follows
+        (Some(("relationships", _)), Ok(storage), _) => {
+            print!("{}", storage.relationship_report())
+        }
+        // For an existing storage, copy the media into another folder
+        (Some(("sync-media", matches)), Ok(storage), _) => action_sync_media(&storage, matches)?,
+        // For an existing storage, list the most used hashtags
+        (Some(("hashtags", matches)), Ok(storage), _) => action_hashtags(&storage, matches)?,
+        // For an existing storage, render a profile card
+        (Some(("snapshot", matches)), Ok(storage), _) => action_snapshot(&storage, matches)?,
+        // For an existing storage, switch to the SQLite backend
+        (Some(("convert-sqlite", _)), Ok(storage), _) => action_convert_sqlite(storage)?,
+        // For an existing storage, delete unused media files
+        (Some(("gc", _)), Ok(storage), _) => action_gc(storage).await?,
+        // For an existing storage, check the media files
+        (Some(("verify", matches)), Ok(storage), config) => {
+            action_verify(storage, config.as_ref(), matches).await?
+        }
+        // For an existing storage, download missing profiles
+        (Some(("backfill", _)), Ok(storage), Some(config)) => {
+            action_backfill(&config, storage).await?
+        }
+        // For an existing storage, download outdated profiles again
+        (Some(("refresh-profiles", matches)), Ok(storage), Some(config)) => {
+            action_refresh_profiles(&config, storage, matches).await?
+        }
+        // For an existing storage, download the members of a list again
+        (Some(("refresh-list", matches)), Ok(storage), Some(config)) => {
+            action_refresh_list(&config, storage, matches).await?
+        }
         // For an existing storage, sync it
-        (Some(("sync", _)), Ok(storage), Some(config)) => action_sync(&config, storage).await?,
+        (Some(("sync", matches)), Ok(storage), Some(config)) => {
+            action_sync(&config, storage, matches).await?
+        }
         // In all other cases, show the UI
         (_, optional_storage, optional_config) => {
-            action_ui(optional_storage.ok(), optional_config).await?
+            let archive_path = open_in_ui.then(|| storage_path.clone());
+            action_ui(
+                optional_storage.ok(),
+                archive_path,
+                optional_config,
+                accounts,
+            )
+            .await?
         }
     };
 
     Ok(())
 }
 
+/// Exits with `AUTH_EXIT_CODE` if the login was rejected, and with 1 for
+/// all other problems
+async fn action_status(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let status = crawler::api_status(config).await;
+    if matches.get_flag("json") {
+        let summary = serde_json::json!({
+            "status": status.label(),
+            "message": status.to_string(),
+        });
+        println!("{summary}");
+    } else {
+        println!("{status}");
+    }
+    match status {
+        types::ApiStatus::Ok => Ok(()),
+        types::ApiStatus::AuthFailed(_) => Err(ExitCode(AUTH_EXIT_CODE).into()),
+        _ => Err(ExitCode(1).into()),
+    }
+}
+
 async fn action_import(config: &Config, storage: Storage, matches: &ArgMatches) -> Result<()> {
     let Some(path) = matches.get_one::<String>("archive-path") else {
         bail!("Missing parameter --archive-path [...]")
@@ -148,7 +409,6 @@ async fn action_crawl(config: &Config, _storage_path: &Path, matches: &ArgMatche
         None => config.user_id(),
     };
     info!("Crawling");
-    let (sender, receiver) = channel(256);
 
     // In custom-user mode, disable responses and mentions
     let mut config = config.clone();
@@ -159,78 +419,582 @@ async fn action_crawl(config: &Config, _storage_path: &Path, matches: &ArgMatche
         config.set_crawl_options(&options);
     }
 
-    crawler::crawl_new_storage(config, sender, user_id).await?;
-    let storage = log_task(receiver).await??;
-    if let Err(e) = storage.save() {
-        warn!("Could not save storage {e:?}");
+    if matches.get_flag("dry-run") {
+        return print_estimate(user_id, &config, matches).await;
+    }
+
+    reset_if_fresh(&config, matches);
+    let started = Instant::now();
+    cancel_on_ctrl_c(&config);
+    let vault = TwitVault::new(config.clone()).with_user(user_id);
+    let result = log_messages(vault.crawl()).await;
+    report_run(result, &config, started, matches).await
+}
+
+/// With `--dry-run`, only the estimate of the crawl is printed, with
+/// `--json` as one JSON object
+async fn print_estimate(user_id: u64, config: &Config, matches: &ArgMatches) -> Result<()> {
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+    estimate::send_estimate(user_id, config, &sender).await;
+    match receiver.recv().await {
+        Some(Message::Estimate(estimate)) if matches.get_flag("json") => {
+            println!("{}", estimate_summary(&estimate));
+            Ok(())
+        }
+        Some(Message::Estimate(estimate)) => {
+            print!("{estimate}");
+            Ok(())
+        }
+        Some(Message::Error(e)) => Err(e),
+        _ => bail!("The estimate failed"),
+    }
+}
+
+/// `--resume` and `--fresh` of the commands that crawl
+fn resume_args() -> [clap::Arg; 2] {
+    [
+        clap::Arg::new("resume")
+            .long("resume")
+            .help("Continue an interrupted crawl where it stopped (default)")
+            .conflicts_with("fresh")
+            .action(clap::ArgAction::SetTrue),
+        clap::Arg::new("fresh")
+            .long("fresh")
+            .help("Start the crawl from the beginning. The archived data is kept")
+            .action(clap::ArgAction::SetTrue),
+    ]
+}
+
+/// With `--fresh`, the crawl starts from the beginning instead of continuing
+/// at the saved paging positions and finished stages
+fn reset_if_fresh(config: &Config, matches: &ArgMatches) {
+    if matches.get_flag("fresh") {
+        info!("Starting a fresh crawl, the archived data is kept");
+        config.reset_crawl_positions();
+    }
+}
+
+async fn action_archive_account(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let Some(screen_name) = matches.get_one::<String>("screen-name") else {
+        bail!("Missing parameter screen-name")
+    };
+    let config = config.for_public_account(screen_name).await?;
+    if matches.get_flag("dry-run") {
+        return print_estimate(config.archived_user_id(), &config, matches).await;
+    }
+    config.save()?;
+    info!("Archiving {screen_name}");
+    reset_if_fresh(&config, matches);
+    let started = Instant::now();
+    cancel_on_ctrl_c(&config);
+    let vault = TwitVault::new(config.clone());
+    let result = log_messages(vault.backup()).await;
+    report_run(result, &config, started, matches).await
+}
+
+async fn action_sync(config: &Config, storage: Storage, matches: &ArgMatches) -> Result<()> {
+    if matches.get_flag("dry-run") {
+        info!("A sync stops at already archived data, so it needs fewer calls than this");
+        return print_estimate(config.archived_user_id(), config, matches).await;
     }
+    info!("Syncing");
+    reset_if_fresh(config, matches);
+    let started = Instant::now();
+    cancel_on_ctrl_c(config);
+    let vault = TwitVault::new(config.clone()).with_storage(storage);
+    let result = log_messages(vault.backup()).await;
+    report_run(result, config, started, matches).await
+}
+
+async fn action_refresh_list(config: &Config, storage: Storage, matches: &ArgMatches) -> Result<()> {
+    let list_id = match matches.get_one::<String>("list-id").map(|n| n.parse::<u64>()) {
+        Some(Ok(n)) => n,
+        Some(Err(e)) => bail!("The given list-id could not be parsed: {e:?}"),
+        None => bail!("Missing parameter list-id"),
+    };
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(256);
+    let log_task = tokio::spawn(async move {
+        while let Some(message) = receiver.recv().await {
+            info!("{message}");
+        }
+    });
+    let storage = crawler::refresh_list(list_id, config, storage, sender).await?;
+    log_task.await?;
+    storage.save()?;
     action_inspect(&storage).await?;
     Ok(())
 }
 
-async fn action_sync(config: &Config, storage: Storage) -> Result<()> {
-    info!("Syncing");
-    let mut config = config.clone();
-    config.is_sync = true;
-    let (sender, receiver) = channel(256);
-    crawler::crawl_into_storage(config.user_id(), config.clone(), storage, sender).await?;
-    let storage = log_task(receiver).await??;
+async fn action_backfill(config: &Config, storage: Storage) -> Result<()> {
+    let missing = storage.data().missing_profile_ids().len();
+    info!("Downloading {missing} missing profiles");
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(256);
+    let log_task = tokio::spawn(async move {
+        while let Some(message) = receiver.recv().await {
+            info!("{message}");
+        }
+    });
+    let storage = crawler::backfill_profiles(config, storage, sender).await?;
+    log_task.await?;
     storage.save()?;
     action_inspect(&storage).await?;
     Ok(())
 }
 
-fn log_task(mut receiver: Receiver<Message>) -> JoinHandle<Result<Storage>> {
-    tokio::spawn(async move {
+async fn action_refresh_profiles(
+    config: &Config,
+    storage: Storage,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let Some(older_than) = matches.get_one::<String>("older-than") else {
+        bail!("Missing parameter --older-than")
+    };
+    let Some(older_than) = parse_age(older_than) else {
+        bail!("The given age {older_than} could not be parsed, use e.g. 30d, 2w or 12h")
+    };
+    let stale = storage
+        .data()
+        .stale_profile_ids(older_than, chrono::Utc::now())
+        .len();
+    info!("Downloading {stale} outdated profiles");
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(256);
+    let log_task = tokio::spawn(async move {
         while let Some(message) = receiver.recv().await {
-            match message {
-                Message::Initial => {
-                    info!("Starting");
-                }
-                Message::Finished(m) => {
-                    return Ok(m);
-                }
-                Message::Loading(n) => {
-                    info!("Loading {n:?}");
-                }
-                Message::Error(error) => {
-                    return Err(error);
+            info!("{message}");
+        }
+    });
+    let storage = crawler::refresh_stale_profiles(config, storage, older_than, sender).await?;
+    log_task.await?;
+    storage.save()?;
+    action_inspect(&storage).await?;
+    Ok(())
+}
+
+/// `30d`, `2w` or `12h`. A number without a unit is in days
+fn parse_age(age: &str) -> Option<chrono::Duration> {
+    let age = age.trim();
+    let (number, unit) = match age.char_indices().last()? {
+        (index, unit) if unit.is_ascii_alphabetic() => (&age[..index], unit),
+        _ => (age, 'd'),
+    };
+    let number = i64::from(number.trim().parse::<u32>().ok()?);
+    match unit.to_ascii_lowercase() {
+        'h' => Some(chrono::Duration::hours(number)),
+        'd' => Some(chrono::Duration::days(number)),
+        'w' => Some(chrono::Duration::weeks(number)),
+        _ => None,
+    }
+}
+
+/// The first Ctrl-C stops the crawl of `config` after the current page and
+/// saves the archive. A second one quits right away
+fn cancel_on_ctrl_c(config: &Config) {
+    let config = config.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        warn!("Stopping after the current page. Press Ctrl-C again to quit without saving");
+        config.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+    });
+}
+
+async fn log_messages(messages: impl Stream<Item = Message>) -> Result<Storage> {
+    let mut messages = Box::pin(messages);
+    while let Some(message) = messages.next().await {
+        match message {
+            Message::Initial => {
+                info!("Starting");
+            }
+            Message::Finished(m) => {
+                return Ok(m);
+            }
+            Message::Loading(n) => {
+                info!("Loading {n:?}");
+            }
+            rate_limited @ Message::RateLimited { .. } => {
+                info!("{rate_limited}");
+            }
+            failed @ Message::StageFailed { .. } => {
+                warn!("{failed}");
+            }
+            Message::Warning(warning) => {
+                warn!("{warning}");
+            }
+            estimate @ Message::Estimate(_) => {
+                info!("{estimate}");
+            }
+            Message::Error(error) => {
+                if let Some(crawl_error) = error.downcast_ref::<types::CrawlError>() {
+                    warn!("{}", crawl_error.guidance());
                 }
+                return Err(error);
+            }
+        }
+    }
+    Err(eyre::eyre!("Invalid Loop Break"))
+}
+
+/// Save and summarize the crawled archive. With `--json`, the summary is one
+/// JSON object on stdout (see `JSON_OUTPUT_VERSION`), also for a failed crawl.
+/// Failures exit with a non-zero code, `AUTH_EXIT_CODE` if the login was rejected
+async fn report_run(
+    result: Result<Storage>,
+    config: &Config,
+    started: Instant,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let json = matches.get_flag("json");
+    let result = result.and_then(|storage| storage.save().map(|_| storage));
+    let storage = match result {
+        Ok(storage) => storage,
+        Err(error) => {
+            let crawl_error = error.downcast_ref::<types::CrawlError>();
+            let is_cancelled = matches!(crawl_error, Some(types::CrawlError::Cancelled));
+            if json {
+                let status = if is_cancelled { "cancelled" } else { "failed" };
+                let summary = run_summary(
+                    status,
+                    &config.actual_storage_path(),
+                    started.elapsed().as_secs(),
+                    None,
+                    &[error.to_string()],
+                );
+                println!("{summary}");
+            }
+            if is_cancelled {
+                // The archive was saved, the next run resumes
+                warn!("{error}");
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+            let is_auth = matches!(crawl_error, Some(types::CrawlError::Auth(_)));
+            if is_auth {
+                // The paging positions are kept, so the next run resumes
+                warn!("{error}");
+                std::process::exit(AUTH_EXIT_CODE);
             }
+            return Err(error);
         }
-        Err(eyre::eyre!("Invalid Loop Break"))
+    };
+
+    if !json {
+        action_inspect(&storage).await?;
+        print_backup_diff(&storage);
+        return Ok(());
+    }
+    let stats = storage.stats();
+    // Failed stages didn't stop the crawl, their data is missing or incomplete
+    let errors: Vec<String> = config
+        .failed_stages()
+        .into_iter()
+        .map(|(stage, error)| format!("{stage}: {error}"))
+        .collect();
+    let status = if errors.is_empty() { "finished" } else { "partial" };
+    let summary = run_summary(
+        status,
+        &storage.root_folder,
+        started.elapsed().as_secs(),
+        Some(&stats),
+        &errors,
+    );
+    println!("{summary}");
+    Ok(())
+}
+
+/// The `--json` summary of a run. Without stats, e.g. for a failed crawl,
+/// the counts are empty
+fn run_summary(
+    status: &str,
+    archive: &Path,
+    duration_seconds: u64,
+    stats: Option<&storage::ArchiveStats>,
+    errors: &[String],
+) -> serde_json::Value {
+    let counts = match stats {
+        Some(stats) => serde_json::json!({
+            "tweets": stats.tweets,
+            "mentions": stats.mentions,
+            "responses": stats.responses,
+            "likes": stats.likes,
+            "followers": stats.followers,
+            "follows": stats.follows,
+            "profiles": stats.profiles,
+            "lists": stats.lists,
+            "media": stats.media_files,
+        }),
+        None => serde_json::json!({}),
+    };
+    serde_json::json!({
+        "version": JSON_OUTPUT_VERSION,
+        "status": status,
+        "archive": archive,
+        "duration_seconds": duration_seconds,
+        "counts": counts,
+        "errors": errors,
+    })
+}
+
+/// The `--json` output of `--dry-run`, with the `estimate` status
+fn estimate_summary(estimate: &estimate::CrawlEstimate) -> serde_json::Value {
+    let stages: Vec<_> = estimate
+        .stages
+        .iter()
+        .map(|stage| {
+            serde_json::json!({
+                "name": stage.name,
+                "calls": stage.calls,
+                "duration_seconds": stage.duration.as_secs(),
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "version": JSON_OUTPUT_VERSION,
+        "status": "estimate",
+        "calls": estimate.calls(),
+        "duration_seconds": estimate.duration().as_secs(),
+        "stages": stages,
     })
 }
 
 async fn action_inspect(storage: &Storage) -> Result<()> {
-    println!("tweets: {}", storage.data().tweets.len());
-    println!("mentions: {}", storage.data().mentions.len());
-    println!("responses: {}", storage.data().responses.len());
-    println!("profiles: {}", storage.data().profiles.len());
-    println!("followers: {}", storage.data().followers.len());
-    println!("follows: {}", storage.data().follows.len());
-    println!("lists: {}", storage.data().lists.len());
+    print!("{}", storage.stats());
     for list in storage.data().lists.iter() {
         println!(" {} members: {}", list.name, list.members.len());
     }
-    println!("media: {}", storage.data().media.len());
     Ok(())
 }
 
-async fn action_ui(storage: Option<Storage>, config: Option<Config>) -> Result<()> {
-    ui::run_ui(storage, config);
+/// What changed since the previous crawl, if there was one
+fn print_backup_diff(storage: &Storage) {
+    if let Some(since) = storage.previous_backup() {
+        print!("{}", storage.diff_since(since));
+    }
+}
+
+fn action_sync_media(storage: &Storage, matches: &ArgMatches) -> Result<()> {
+    let Some(destination) = matches.get_one::<String>("destination") else {
+        bail!("Missing parameter destination")
+    };
+    let report = storage.sync_media(Path::new(destination), matches.get_flag("delete"))?;
+    print!("{report}");
+    Ok(())
+}
+
+fn action_hashtags(storage: &Storage, matches: &ArgMatches) -> Result<()> {
+    let count = match matches.get_one::<String>("count").map(|n| n.parse::<usize>()) {
+        Some(Ok(n)) => n,
+        Some(Err(e)) => bail!("The given count could not be parsed: {e:?}"),
+        None => 20,
+    };
+    for (tag, tweets) in storage.top_hashtags(count) {
+        println!("#{tag}: {tweets}");
+    }
     Ok(())
 }
 
-pub fn setup_tracing() {
+fn action_snapshot(storage: &Storage, matches: &ArgMatches) -> Result<()> {
+    let path = match matches.get_one::<String>("output") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(format!(
+            "twitvault-{}.png",
+            storage.data().profile.screen_name
+        )),
+    };
+    let mut file = std::fs::File::create(&path)?;
+    twitvault::export::snapshot_png(storage, &mut file)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn action_convert_sqlite(mut storage: Storage) -> Result<()> {
+    use twitvault::storage::{backend::FILE_SQLITE, sqlite::SqliteBackend};
+    if storage.backend_name() == "SQLite" {
+        bail!("The archive is already stored in SQLite")
+    }
+    let path = storage.root_folder.join(FILE_SQLITE);
+    storage.convert_to(std::sync::Arc::new(SqliteBackend::new(path.clone())))?;
+    println!("Converted the archive into {}", path.display());
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn action_convert_sqlite(_storage: Storage) -> Result<()> {
+    bail!("This TwitVault was built without SQLite support, build it with `--features sqlite`")
+}
+
+async fn action_gc(mut storage: Storage) -> Result<()> {
+    let dedup = storage.dedup_media()?;
+    println!(
+        "merged duplicate media files: {} ({} bytes reclaimed)",
+        dedup.files_removed, dedup.bytes_reclaimed
+    );
+    let removed = storage.gc_media()?;
+    println!("removed media files: {removed}");
+    Ok(())
+}
+
+async fn action_verify(
+    storage: Storage,
+    config: Option<&Config>,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let report = storage.verify_media();
+    print_media_report(&report);
+    if report.is_ok() || !matches.get_flag("repair") {
+        return Ok(());
+    }
+    info!("Downloading missing media");
+    let config = match config {
+        Some(config) => config.clone(),
+        None => Config::for_archive(storage.root_folder.clone(), &storage.data().profile),
+    };
+    let storage = crawler::re_download_missing(storage, &report, &config).await?;
+    storage.save()?;
+    print_media_report(&storage.verify_media());
+    Ok(())
+}
+
+fn print_media_report(report: &storage::MediaReport) {
+    println!("media: {}", report.total);
+    println!("missing: {}", report.missing.len());
+    for url in &report.missing {
+        println!(" {url}");
+    }
+    println!("corrupt: {}", report.corrupt.len());
+    for url in &report.corrupt {
+        println!(" {url}");
+    }
+}
+
+async fn action_open(matches: &ArgMatches) -> Result<()> {
+    let Some(path) = matches.get_one::<String>("path") else {
+        bail!("Missing parameter path")
+    };
+    let storage = Storage::open_existing(path)?;
+    let config = Config::for_archive(storage.root_folder.clone(), &storage.data().profile);
+    action_ui(Some(storage), None, Some(config), Vec::new()).await
+}
+
+async fn action_ui(
+    storage: Option<Storage>,
+    archive_path: Option<PathBuf>,
+    config: Option<Config>,
+    accounts: Vec<config::Account>,
+) -> Result<()> {
+    ui::run_ui(storage, archive_path, config, accounts);
+    Ok(())
+}
+
+/// Logs to stdout and, if a `log_folder` is given, also as JSON lines into
+/// a timestamped file in its `logs` subfolder
+pub fn setup_tracing(log_folder: Option<&Path>, to_stderr: bool) {
+    use tracing_subscriber::fmt::writer::BoxMakeWriter;
     use tracing_subscriber::prelude::*;
     use tracing_subscriber::{filter::EnvFilter, fmt};
 
     let env_filter = EnvFilter::new("hyper=info,twitvault=debug");
 
+    let json_layer = log_folder.and_then(|folder| {
+        let folder = folder.join("logs");
+        let file_name = format!("twitvault-{}.jsonl", chrono::Utc::now().format("%Y%m%d-%H%M%S"));
+        let file = std::fs::create_dir_all(&folder)
+            .and_then(|_| std::fs::File::create(folder.join(&file_name)));
+        match file {
+            Ok(file) => Some(fmt::layer().json().with_writer(std::sync::Mutex::new(file))),
+            Err(e) => {
+                eprintln!("Could not create log file in {}: {e:?}", folder.display());
+                None
+            }
+        }
+    });
+
+    let writer = if to_stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
     let collector = tracing_subscriber::registry()
-        .with(fmt::layer().with_writer(std::io::stdout))
+        .with(fmt::layer().with_writer(writer))
+        .with(json_layer)
         .with(env_filter);
 
     tracing::subscriber::set_global_default(collector).expect("Unable to set a global collector");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ages_are_parsed_with_days_as_the_default_unit() {
+        assert_eq!(parse_age("30d"), Some(chrono::Duration::days(30)));
+        assert_eq!(parse_age(" 2W "), Some(chrono::Duration::weeks(2)));
+        assert_eq!(parse_age("12h"), Some(chrono::Duration::hours(12)));
+        assert_eq!(parse_age("7"), Some(chrono::Duration::days(7)));
+        for invalid in ["", "d", "30m", "-3d", "1.5d"] {
+            assert_eq!(parse_age(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn json_summary_is_one_parseable_line() {
+        let stats = storage::ArchiveStats {
+            tweets: 3,
+            media_files: 2,
+            ..Default::default()
+        };
+        let errors = ["likes: rate limited".to_string()];
+        let summary = run_summary("partial", Path::new("/archive"), 5, Some(&stats), &errors);
+
+        let line = summary.to_string();
+        assert!(!line.contains('\n'));
+        let parsed: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed["version"], JSON_OUTPUT_VERSION);
+        assert_eq!(parsed["status"], "partial");
+        assert_eq!(parsed["archive"], "/archive");
+        assert_eq!(parsed["counts"]["tweets"], 3);
+        assert_eq!(parsed["counts"]["media"], 2);
+        assert_eq!(parsed["errors"][0], "likes: rate limited");
+
+        let failed = run_summary("failed", Path::new("/archive"), 0, None, &[]);
+        assert_eq!(failed["counts"], serde_json::json!({}));
+    }
+
+    #[test]
+    fn the_json_estimate_lists_the_stages() {
+        let estimate = estimate::CrawlEstimate {
+            stages: vec![estimate::StageEstimate {
+                name: "Tweets",
+                calls: 4,
+                duration: std::time::Duration::from_secs(2),
+            }],
+        };
+        let summary = estimate_summary(&estimate);
+
+        assert!(!summary.to_string().contains('\n'));
+        assert_eq!(summary["version"], JSON_OUTPUT_VERSION);
+        assert_eq!(summary["status"], "estimate");
+        assert_eq!(summary["calls"], 4);
+        assert_eq!(summary["stages"][0]["name"], "Tweets");
+        assert_eq!(summary["stages"][0]["duration_seconds"], 2);
+    }
+
+    #[test]
+    fn only_fresh_starts_the_crawl_over() {
+        let is_fresh = |flags: &[&str]| {
+            let args = std::iter::once("sync").chain(flags.iter().copied());
+            let matches = Command::new("sync")
+                .args(resume_args())
+                .try_get_matches_from(args);
+            matches.map(|matches| matches.get_flag("fresh"))
+        };
+        assert!(!is_fresh(&[]).unwrap());
+        assert!(!is_fresh(&["--resume"]).unwrap());
+        assert!(is_fresh(&["--fresh"]).unwrap());
+        assert!(is_fresh(&["--resume", "--fresh"]).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Notify a webhook when a crawl finished or failed
+use std::time::Duration;
+
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::storage::Storage;
+use crate::types::CrawlError;
+
+/// Post a summary of the finished crawl to the configured webhook
+pub async fn crawl_finished(config: &Config, storage: &Storage, duration: Duration) {
+    let stats = storage.stats();
+    let summary = format!(
+        "{} tweets, {} mentions, {} likes, {} followers, {} follows, {} media files",
+        stats.tweets, stats.mentions, stats.likes, stats.followers, stats.follows, stats.media_files
+    );
+    // The stages that failed, the crawl continued after them
+    let errors: Vec<String> = config
+        .failed_stages()
+        .into_iter()
+        .map(|(stage, error)| format!("{stage}: {error}"))
+        .collect();
+    let payload = json!({
+        "status": "finished",
+        "account": config.screen_name(),
+        "duration_seconds": duration.as_secs(),
+        "summary": summary,
+        "errors": errors,
+        "tweets": stats.tweets,
+        "mentions": stats.mentions,
+        "likes": stats.likes,
+        "followers": stats.followers,
+        "follows": stats.follows,
+        "media": stats.media_files,
+    });
+    notify(config, "finished", &summary, &errors, duration, payload).await
+}
+
+/// Post the error of the failed crawl to the configured webhook
+pub async fn crawl_failed(config: &Config, error: &CrawlError, duration: Duration) {
+    let summary = error.to_string();
+    let payload = json!({
+        "status": "failed",
+        "account": config.screen_name(),
+        "duration_seconds": duration.as_secs(),
+        "summary": summary,
+        "error": summary,
+        "errors": [summary],
+    });
+    let errors = [summary.clone()];
+    notify(config, "failed", &summary, &errors, duration, payload).await
+}
+
+/// Failing to notify is only logged, it doesn't fail the crawl
+async fn notify(
+    config: &Config,
+    status: &str,
+    summary: &str,
+    errors: &[String],
+    duration: Duration,
+    payload: serde_json::Value,
+) {
+    let Some(url) = &config.config_data.completion_webhook else {
+        return;
+    };
+    let body = match &config.config_data.webhook_template {
+        Some(template) => render_template(
+            template,
+            &[
+                ("status", status),
+                ("account", config.screen_name()),
+                ("summary", summary),
+                ("errors", &errors.join("; ")),
+                ("duration", &format!("{} min", duration.as_secs() / 60)),
+            ],
+        ),
+        None => payload.to_string(),
+    };
+    let result = match config.http_client() {
+        Ok(client) => client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| eyre::eyre!(e)),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(_) => info!("Notified {url}"),
+        Err(e) => warn!("Could not notify {url}: {e:?}"),
+    }
+}
+
+/// Replaces `{name}` with the JSON-escaped value, so that the template
+/// can be the JSON a Slack or Discord webhook expects, e.g.
+/// `{"text": "TwitVault {status}: {summary}"}`
+fn render_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut output = template.to_string();
+    for (name, value) in values {
+        // Without the quotes of the JSON string, which are always there
+        let escaped = serde_json::to_string(value).unwrap_or_else(|_| "\"\"".to_string());
+        output = output.replace(&format!("{{{name}}}"), &escaped[1..escaped.len() - 1]);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_values_are_json_escaped() {
+        let body = render_template(
+            r#"{"text": "{account} {status}: {summary} ({errors})"}"#,
+            &[
+                ("account", "owner"),
+                ("status", "finished"),
+                ("summary", r#"say "hi""#),
+                ("errors", "likes: failed\nagain"),
+            ],
+        );
+        let parsed: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            parsed["text"],
+            "owner finished: say \"hi\" (likes: failed\nagain)"
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use std::{collections::HashMap, ops::Range};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
 
 use crate::storage::{Data, TweetId, UserId};
 use egg_mode::{tweet::Tweet, user::TwitterUser};
@@ -63,17 +66,17 @@ pub fn search(term: String, data: &Data, options: Options) -> Vec<SearchResult>
     };
 
     if options.tweets {
-        search_tweets(&regex, &data.tweets, &mut results);
+        search_tweets(&regex, data, &data.tweets, &mut results);
     }
     if options.mentions {
-        search_tweets(&regex, &data.mentions, &mut results);
+        search_tweets(&regex, data, &data.mentions, &mut results);
     }
     if options.likes {
-        search_tweets(&regex, &data.likes, &mut results);
+        search_tweets(&regex, data, &data.likes, &mut results);
     }
     if options.responses {
         for i in data.responses.values() {
-            search_tweets(&regex, i, &mut results);
+            search_tweets(&regex, data, i, &mut results);
         }
     }
     if options.profiles {
@@ -120,13 +123,18 @@ fn make_regex(term: String) -> Option<Regex> {
     Regex::new(&phrase).ok()
 }
 
-fn search_tweets(regex: &Regex, tweets: &[Tweet], into: &mut HashMap<Kind, SearchResult>) {
+fn search_tweets(
+    regex: &Regex,
+    data: &Data,
+    tweets: &[Tweet],
+    into: &mut HashMap<Kind, SearchResult>,
+) {
     for tweet in tweets {
         let mut descriptions = Vec::new();
         if let Some(ref user) = tweet.user {
             search_profile(regex, user, &mut descriptions);
         }
-        search_tweet(regex, tweet, &mut descriptions);
+        search_tweet(regex, data, tweet, &mut descriptions);
         if !descriptions.is_empty() {
             descriptions.sort_by(|a, b| a.rank.cmp(&b.rank));
             let rank = descriptions.iter().map(|s| s.rank).sum();
@@ -161,14 +169,14 @@ fn search_profile(regex: &Regex, user: &TwitterUser, descriptions: &mut Vec<Desc
     }
 }
 
-fn search_tweet(regex: &Regex, tweet: &Tweet, descriptions: &mut Vec<Description>) {
-    if let Some(m) = make_results(&tweet.text, regex, "Tweet Text", 4) {
+fn search_tweet(regex: &Regex, data: &Data, tweet: &Tweet, descriptions: &mut Vec<Description>) {
+    if let Some(m) = make_results(data.text(tweet), regex, "Tweet Text", 4) {
         descriptions.push(m);
     }
     if let Some(quoted) = tweet
         .quoted_status
         .as_ref()
-        .map(|q| &q.text)
+        .map(|q| data.text(q))
         .and_then(|s| make_results(s, regex, "Tweet Quoted Text", 1))
     {
         descriptions.push(quoted);
@@ -194,3 +202,92 @@ fn make_results(
         })
     }
 }
+
+/// The lowercased words of tweet texts and the tweets that contain them, so
+/// that filtering a timeline only looks at the tweets that can match
+#[derive(Debug, Default)]
+pub struct TextIndex {
+    words: HashMap<String, Vec<TweetId>>,
+    tweets: usize,
+}
+
+impl TextIndex {
+    pub fn new<'a>(tweets: impl IntoIterator<Item = &'a Tweet>) -> Self {
+        let mut index = Self::default();
+        for tweet in tweets {
+            index.tweets += 1;
+            let text = tweet.text.to_lowercase();
+            let mut words: Vec<&str> = text.split_whitespace().collect();
+            words.sort_unstable();
+            words.dedup();
+            for word in words {
+                index
+                    .words
+                    .entry(word.to_string())
+                    .or_default()
+                    .push(tweet.id);
+            }
+        }
+        index
+    }
+
+    /// The number of indexed tweets
+    pub fn len(&self) -> usize {
+        self.tweets
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tweets == 0
+    }
+
+    /// The tweets that can contain `needle` (case insensitive): every word of
+    /// the needle is part of one of their words. The caller checks the
+    /// actual text, a needle across words can still fail.
+    /// `None` if the needle has no words, i.e. every tweet matches
+    pub fn candidates(&self, needle: &str) -> Option<HashSet<TweetId>> {
+        let needle = needle.to_lowercase();
+        let mut candidates: Option<HashSet<TweetId>> = None;
+        for part in needle.split_whitespace() {
+            let matching: HashSet<TweetId> = self
+                .words
+                .iter()
+                .filter(|(word, _)| word.contains(part))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect();
+            candidates = Some(match candidates {
+                Some(previous) => previous.intersection(&matching).copied().collect(),
+                None => matching,
+            });
+        }
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::tweet;
+
+    #[test]
+    fn candidates_contain_every_word() {
+        let tweets = vec![
+            tweet(1, "Hello World"),
+            tweet(2, "hello there"),
+            tweet(3, "Goodbye, world!"),
+        ];
+        let index = TextIndex::new(&tweets);
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.candidates("WORLD"), Some(HashSet::from([1, 3])));
+        assert_eq!(index.candidates("hell wor"), Some(HashSet::from([1])));
+        assert_eq!(index.candidates("missing"), Some(HashSet::new()));
+        assert_eq!(index.candidates("  "), None);
+    }
+
+    #[test]
+    fn candidates_include_needles_across_words() {
+        let tweets = vec![tweet(1, "one two three")];
+        let index = TextIndex::new(&tweets);
+        // A filter of `ne tw` matches the text `one two`
+        assert_eq!(index.candidates("ne tw"), Some(HashSet::from([1])));
+    }
+}
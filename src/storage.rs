@@ -1,20 +1,73 @@
+pub mod backend;
+pub mod manifest;
+pub mod media_sync;
+pub mod preview;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
 use egg_mode::{list, tweet::Tweet, user::TwitterUser};
-use eyre::Result;
+use eyre::{bail, Result};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap, HashSet},
+    io::Write,
     path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
 };
+use tracing::{info, warn};
+
+use crate::config::CrawlOptions;
+use crate::helpers::media_urls_in_tweet;
+use backend::{StorageBackend, TweetKind};
+use manifest::ArchiveManifest;
+use media_sync::{MediaManifest, MediaSyncReport};
 
 /// The folder locations for the different data
 const FOLDER_MEDIA: &str = "media";
-const FILE_ROOT: &str = "_data.json";
+/// The unmodified API responses, one ndjson file per kind
+const FOLDER_RAW: &str = "raw";
+/// The followers and list members at the end of every crawl
+const FOLDER_BACKUPS: &str = "backups";
+
+/// The version of the serialized `Data`. Archives without a
+/// version are version 1
+pub const DATA_VERSION: u32 = 5;
+
+/// Migrations from version `n` to `n + 1`, index 0 migrates version 1
+const MIGRATIONS: &[fn(&mut serde_json::Value)] = &[
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+];
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct List {
     pub name: String,
     pub list: list::List,
     pub members: Vec<UserId>,
+    /// Private lists are only visible to their creator
+    #[serde(default)]
+    pub private: bool,
+}
+
+impl List {
+    pub fn description(&self) -> &str {
+        &self.list.description
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+
+    pub fn member_count(&self) -> u64 {
+        self.list.member_count
+    }
+
+    pub fn subscriber_count(&self) -> u64 {
+        self.list.subscriber_count
+    }
 }
 
 impl PartialEq for List {
@@ -25,15 +78,177 @@ impl PartialEq for List {
 
 impl Eq for List {}
 
+/// The outcome of `Storage::dedup_media`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MediaDedupReport {
+    pub files_removed: usize,
+    pub bytes_reclaimed: u64,
+}
+
+/// What is known about a media file, e.g. to lay it out before it is
+/// loaded. The size and the dimensions of images are read from the
+/// downloaded file, the dimensions and length of videos come from the tweet
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MediaMeta {
+    /// The size of the file, 0 until it was downloaded
+    pub bytes: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// The length of videos and GIFs
+    pub duration_millis: Option<u32>,
+}
+
 pub type UserId = u64;
 pub type TweetId = u64;
 pub type UrlString = String;
 
+/// The engagement numbers of a tweet at a given point in time
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Unix timestamp of when the snapshot was taken
+    pub timestamp: i64,
+    pub favorite_count: i32,
+    pub retweet_count: i32,
+    /// The API doesn't return reply counts, so this is the number
+    /// of archived responses at the time of the snapshot
+    pub reply_count: Option<usize>,
+}
+
+/// A poll attached to a tweet
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Poll {
+    pub options: Vec<PollOption>,
+    /// `open` or `closed`. Open polls are updated on the next crawl
+    pub voting_status: String,
+    pub end_datetime: Option<chrono::DateTime<chrono::Utc>>,
+    pub duration_minutes: Option<u32>,
+}
+
+impl Poll {
+    pub fn is_closed(&self) -> bool {
+        self.voting_status == "closed"
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PollOption {
+    pub position: u32,
+    pub label: String,
+    pub votes: u64,
+}
+
+/// The location a tweet was sent from
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Place {
+    pub name: String,
+    pub full_name: String,
+    pub country: String,
+    pub country_code: String,
+    /// The exact location as (longitude, latitude), if it was shared
+    pub coordinates: Option<(f64, f64)>,
+    /// The corners of the area of the place as (longitude, latitude)
+    pub bounding_box: Vec<(f64, f64)>,
+}
+
+impl Place {
+    /// `None` for tweets that are not geotagged
+    pub fn from_tweet(tweet: &Tweet) -> Option<Self> {
+        let place = tweet.place.as_ref()?;
+        Some(Place {
+            name: place.name.clone(),
+            full_name: place.full_name.clone(),
+            country: place.country.clone(),
+            country_code: place.country_code.clone(),
+            coordinates: tweet.coordinates,
+            bounding_box: place.bounding_box.clone(),
+        })
+    }
+}
+
+/// The link preview of a tweet, as shown below the text on Twitter
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Card {
+    /// The website the link points to, without the `t.co` redirect
+    pub url: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// The preview image, if the website has one. The downloaded
+    /// file is in `media` by this url
+    pub image_url: Option<String>,
+    /// The domain of the website, e.g. `github.com`
+    pub vendor: String,
+}
+
+/// A Twitter Space that was shared in a tweet. Only the metadata is
+/// archived, the audio can't be downloaded
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SpaceCard {
+    pub id: String,
+    pub url: String,
+    pub title: Option<String>,
+    pub host_ids: Vec<UserId>,
+    /// `live`, `scheduled` or `ended`. `None` until the Space was looked up
+    pub state: Option<String>,
+}
+
+impl SpaceCard {
+    /// `None` for tweets that don't link a Space
+    pub fn from_tweet(tweet: &Tweet) -> Option<Self> {
+        tweet.entities.urls.iter().find_map(|url| {
+            let expanded = url.expanded_url.as_ref()?;
+            let parsed = url::Url::parse(expanded).ok()?;
+            let host = parsed.host_str()?;
+            if host != "twitter.com" && host != "mobile.twitter.com" {
+                return None;
+            }
+            let mut segments = parsed.path_segments()?;
+            let (Some("i"), Some("spaces"), Some(id)) = (segments.next(), segments.next(), segments.next()) else {
+                return None
+            };
+            if id.is_empty() {
+                return None;
+            }
+            Some(SpaceCard {
+                id: id.to_string(),
+                url: format!("https://twitter.com/i/spaces/{id}"),
+                title: None,
+                host_ids: Vec::new(),
+                state: None,
+            })
+        })
+    }
+}
+
+/// When a profile was first downloaded and when it was last refreshed
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProfileSeen {
+    pub first_seen: chrono::DateTime<chrono::Utc>,
+    pub last_seen: chrono::DateTime<chrono::Utc>,
+}
+
+/// How many followers or follows the profile reported when a crawl archived
+/// them, and how many were archived
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GraphCount {
+    pub reported: usize,
+    pub archived: usize,
+}
+
+impl GraphCount {
+    /// See `count_delta`
+    pub fn delta(&self) -> i64 {
+        count_delta(self.reported, self.archived)
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Data {
+    /// The schema version of the archive
+    pub version: u32,
     /// The profile of the owner
     pub profile: TwitterUser,
-    /// The tweets of the owner
+    /// The tweets of the owner, and the tweets they quote or retweet, see
+    /// `inner_tweets`
     pub tweets: Vec<Tweet>,
     /// Mentions of the owner
     pub mentions: Vec<Tweet>,
@@ -52,12 +267,161 @@ pub struct Data {
     /// - Tweet Media: ExtendedUrlString
     /// - Profiles: Various Urls
     pub media: HashMap<UrlString, String>,
+    /// The size, dimensions and length of media, by url like `media`
+    #[serde(default)]
+    pub media_meta: HashMap<UrlString, MediaMeta>,
     /// The likes the user performed
     #[serde(default)]
     pub likes: Vec<Tweet>,
+    /// Engagement snapshots for the tweets of the owner
+    #[serde(default)]
+    pub metrics: HashMap<TweetId, Vec<Snapshot>>,
+    /// Tweets of the owner that disappeared from the timeline and when that
+    /// was noticed. The last known copy stays in `tweets`
+    #[serde(default)]
+    pub deleted_tweets: Vec<(TweetId, chrono::DateTime<chrono::Utc>)>,
+    /// The text of tweets with the `t.co` links replaced by the urls they
+    /// point to. The original text stays in the tweet
+    #[serde(default)]
+    pub expanded_texts: HashMap<TweetId, String>,
+    /// The polls of archived tweets
+    #[serde(default)]
+    pub polls: HashMap<TweetId, Poll>,
+    /// The places of geotagged tweets
+    #[serde(default)]
+    pub places: HashMap<TweetId, Place>,
+    /// The Spaces shared in tweets
+    #[serde(default)]
+    pub spaces: HashMap<TweetId, SpaceCard>,
+    /// The link previews of tweets
+    #[serde(default)]
+    pub cards: HashMap<TweetId, Card>,
+    /// Tweets with links that were looked up and have no link preview, so
+    /// that they are not looked up again
+    #[serde(default)]
+    pub no_cards: HashSet<TweetId>,
+    /// Quoted and retweeted tweets whose link preview is looked up with the
+    /// next lookup of the archived tweets
+    #[serde(default)]
+    pub card_lookups: HashSet<TweetId>,
+    /// The id of the conversation (the first tweet of a thread) a tweet
+    /// belongs to. Only known for tweets that were looked up via the v2 API
+    #[serde(default)]
+    pub conversation_ids: HashMap<TweetId, TweetId>,
+    /// The quoted tweet of a quote tweet, by the id of the quote tweet. The
+    /// quoted tweet is also stored in `tweets`
+    #[serde(default)]
+    pub quotes: HashMap<TweetId, TweetId>,
+    /// The original tweet of a retweet, by the id of the retweet
+    #[serde(default)]
+    pub retweets: HashMap<TweetId, TweetId>,
+    /// The tweets in `tweets` that are only archived because an archived
+    /// tweet quotes or retweets them, not because they are on the timeline
+    #[serde(default)]
+    pub inner_tweets: HashSet<TweetId>,
+    /// Quoted tweets that the API didn't embed because they are quoted by a
+    /// quoted tweet, by their id. See `CrawlOptions::quote_depth`
+    #[serde(default)]
+    pub quoted_tweets: HashMap<TweetId, Tweet>,
+    /// The ids of all versions of edited tweets, oldest first, by the id of
+    /// the archived version. Tweets that weren't edited aren't in here
+    #[serde(default)]
+    pub edit_history: HashMap<TweetId, Vec<TweetId>>,
+    /// The earlier versions of edited tweets, oldest first, by the id of the
+    /// archived version. See `CrawlOptions::tweet_edits`
+    #[serde(default)]
+    pub tweet_edits: HashMap<TweetId, Vec<Tweet>>,
+    /// The tweets whose edit history was looked up. The ones that weren't
+    /// edited are only recorded here
+    #[serde(default)]
+    pub edits_checked: HashSet<TweetId>,
+    /// The tweets that mention a user, by the id of the mentioned user
+    #[serde(default)]
+    pub mentions_index: HashMap<UserId, Vec<TweetId>>,
+    /// The tweets that use a hashtag, by the lowercased hashtag without `#`
+    #[serde(default)]
+    pub hashtag_index: HashMap<String, Vec<TweetId>>,
+    /// When the entries of `profiles` were downloaded
+    #[serde(default)]
+    pub profile_seen: HashMap<UserId, ProfileSeen>,
+    /// Suspended, deleted or protected accounts that could not be
+    /// downloaded, with the reason
+    #[serde(default)]
+    pub unavailable_profiles: HashMap<UserId, String>,
+    /// Accounts that were skipped because of their screen name (see
+    /// `skip_screen_names`), with it, so they aren't looked up again
+    #[serde(default)]
+    pub skipped_profiles: HashMap<UserId, String>,
+    /// The ends of the last crawls, oldest first. See `BackupSnapshot`
+    #[serde(default)]
+    pub backups: Vec<BackupMarker>,
+    /// Own tweets whose replies weren't searched, as they are older than the
+    /// ~7 days of the standard search
+    #[serde(default)]
+    pub unsearched_replies: HashSet<TweetId>,
+    /// Media that was not downloaded as it is larger than `max_media_bytes`,
+    /// with its size in bytes
+    #[serde(default)]
+    pub skipped_media: Vec<(UrlString, u64)>,
+    /// The options of the last crawl, for the manifest
+    #[serde(default)]
+    pub crawl_options: Option<CrawlOptions>,
+    /// The followers of the last crawl that archived them, compared with the
+    /// count of the profile. See `record_graph_counts`
+    #[serde(default)]
+    pub followers_count: Option<GraphCount>,
+    /// Like `followers_count`, for the follows
+    #[serde(default)]
+    pub follows_count: Option<GraphCount>,
 }
 
 impl Data {
+    /// An empty archive of `profile`
+    pub fn new(profile: TwitterUser) -> Self {
+        Self {
+            version: DATA_VERSION,
+            profile,
+            tweets: Default::default(),
+            mentions: Default::default(),
+            responses: Default::default(),
+            profiles: Default::default(),
+            followers: Default::default(),
+            follows: Default::default(),
+            lists: Default::default(),
+            media: Default::default(),
+            media_meta: Default::default(),
+            likes: Default::default(),
+            metrics: Default::default(),
+            deleted_tweets: Default::default(),
+            expanded_texts: Default::default(),
+            polls: Default::default(),
+            places: Default::default(),
+            spaces: Default::default(),
+            cards: Default::default(),
+            no_cards: Default::default(),
+            card_lookups: Default::default(),
+            conversation_ids: Default::default(),
+            quotes: Default::default(),
+            retweets: Default::default(),
+            inner_tweets: Default::default(),
+            quoted_tweets: Default::default(),
+            edit_history: Default::default(),
+            tweet_edits: Default::default(),
+            edits_checked: Default::default(),
+            mentions_index: Default::default(),
+            hashtag_index: Default::default(),
+            profile_seen: Default::default(),
+            unavailable_profiles: Default::default(),
+            skipped_profiles: Default::default(),
+            backups: Default::default(),
+            unsearched_replies: Default::default(),
+            skipped_media: Default::default(),
+            crawl_options: Default::default(),
+            followers_count: Default::default(),
+            follows_count: Default::default(),
+        }
+    }
+
     pub fn any_tweet(&self, id: TweetId) -> Option<&Tweet> {
         for tweets in [&self.tweets, &self.mentions, &self.likes] {
             for t in tweets {
@@ -75,59 +439,844 @@ impl Data {
         }
         None
     }
+
+    /// Store a downloaded profile. A profile that was already stored
+    /// only gets its `last_seen` updated
+    pub fn insert_profile(&mut self, profile: TwitterUser) {
+        let now = chrono::Utc::now();
+        self.profile_seen
+            .entry(profile.id)
+            .and_modify(|seen| seen.last_seen = now)
+            .or_insert(ProfileSeen {
+                first_seen: now,
+                last_seen: now,
+            });
+        self.profiles.insert(profile.id, profile);
+    }
+
+    /// The text of the tweet with expanded links, if there is one
+    pub fn text<'a>(&'a self, tweet: &'a Tweet) -> &'a str {
+        self.expanded_texts
+            .get(&tweet.id)
+            .map(|text| text.as_str())
+            .unwrap_or(&tweet.text)
+    }
+
+    /// All media urls that are still used by a tweet or a profile
+    fn referenced_media(&self) -> HashSet<&str> {
+        let mut referenced = HashSet::new();
+        let responses = self.responses.values().flatten();
+        let all_tweets = self
+            .tweets
+            .iter()
+            .chain(self.mentions.iter())
+            .chain(self.likes.iter())
+            .chain(responses)
+            .chain(self.quoted_tweets.values())
+            .chain(self.tweet_edits.values().flatten());
+        for tweet in all_tweets {
+            for url in media_urls_in_tweet(tweet) {
+                if let Some((key, _)) = self.media.get_key_value(&url) {
+                    referenced.insert(key.as_str());
+                }
+            }
+        }
+        for card in self.cards.values() {
+            if let Some(url) = &card.image_url {
+                referenced.insert(url.as_str());
+            }
+        }
+        for profile in self.profiles.values().chain(std::iter::once(&self.profile)) {
+            let urls = [
+                Some(&profile.profile_image_url_https),
+                profile.profile_banner_url.as_ref(),
+                profile.profile_background_image_url_https.as_ref(),
+            ];
+            for url in urls.into_iter().flatten() {
+                referenced.insert(url.as_str());
+            }
+        }
+        referenced
+    }
+
+    /// The entries of `candidates` which are in `media` but
+    /// not referenced by any tweet or profile anymore
+    pub fn orphaned_media(&self, candidates: &[UrlString]) -> Vec<UrlString> {
+        let referenced = self.referenced_media();
+        candidates
+            .iter()
+            .filter(|url| self.media.contains_key(*url) && !referenced.contains(url.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    /// All tweets and retweets from the given user, newest first.
+    /// Imported tweets don't have a user, they belong to the owner
+    pub fn tweets_by(&self, id: UserId) -> Vec<&Tweet> {
+        let is_owner = self.profile.id == id;
+        let mut found: Vec<&Tweet> = Vec::new();
+        let responses = self.responses.values().flatten();
+        for tweet in self.tweets.iter().chain(self.mentions.iter()).chain(responses) {
+            let author = tweet.user.as_ref().map(|u| u.id);
+            if author == Some(id) || (author.is_none() && is_owner) {
+                found.push(tweet);
+            } else if let Some(retweet) = &tweet.retweeted_status {
+                if retweet.user.as_ref().map(|u| u.id) == Some(id) {
+                    found.push(tweet);
+                }
+            }
+        }
+        found.sort_by(|a, b| b.id.cmp(&a.id));
+        found.dedup_by_key(|t| t.id);
+        found
+    }
+
+    /// All archived tweets of the conversation of `tweet`, oldest first.
+    /// Without a known conversation id, the replies are followed up and
+    /// the responses down from the tweet instead
+    pub fn conversation<'a>(&'a self, tweet: &'a Tweet) -> Vec<&'a Tweet> {
+        let mut found: Vec<&Tweet> = match self.conversation_ids.get(&tweet.id) {
+            Some(conversation_id) => self
+                .all_tweets()
+                .filter(|t| self.conversation_ids.get(&t.id) == Some(conversation_id))
+                .collect(),
+            None => {
+                let mut found = self.ancestors(tweet);
+                found.push(tweet);
+                let mut pending = vec![tweet.id];
+                while let Some(id) = pending.pop() {
+                    for response in self.responses.get(&id).into_iter().flatten() {
+                        if !found.iter().any(|t| t.id == response.id) {
+                            found.push(response);
+                            pending.push(response.id);
+                        }
+                    }
+                }
+                found
+            }
+        };
+        found.sort_by_key(|t| t.id);
+        found.dedup_by_key(|t| t.id);
+        found
+    }
+
+    /// The archived tweets that `tweet` replies to, directly or further up
+    /// the thread, oldest first. If the reply chain has a gap, the first
+    /// tweet of the conversation is still included if it is archived
+    pub fn ancestors<'a>(&'a self, tweet: &'a Tweet) -> Vec<&'a Tweet> {
+        let mut found: Vec<&Tweet> = Vec::new();
+        let mut current = tweet;
+        while let Some(parent) = current
+            .in_reply_to_status_id
+            .and_then(|id| self.any_tweet(id))
+        {
+            // Protect against cycles in broken data
+            if parent.id == tweet.id || found.iter().any(|t| t.id == parent.id) {
+                break;
+            }
+            found.push(parent);
+            current = parent;
+        }
+        let root = self
+            .conversation_ids
+            .get(&tweet.id)
+            .filter(|root| **root != tweet.id && !found.iter().any(|t| t.id == **root))
+            .and_then(|root| self.any_tweet(*root));
+        found.extend(root);
+        found.reverse();
+        found
+    }
+
+    /// The authors of archived tweets and the users they mention or reply to
+    /// without a profile in `profiles` or `unavailable_profiles`
+    pub fn missing_profile_ids(&self) -> Vec<UserId> {
+        let mut referenced: HashSet<UserId> = HashSet::new();
+        let nested = self.all_tweets().flat_map(|tweet| {
+            let inner = [
+                tweet.quoted_status.as_deref(),
+                tweet.retweeted_status.as_deref(),
+            ];
+            std::iter::once(tweet).chain(inner.into_iter().flatten())
+        });
+        for tweet in nested {
+            referenced.extend(tweet.user.as_ref().map(|user| user.id));
+            referenced.extend(tweet.in_reply_to_user_id);
+            referenced.extend(
+                tweet
+                    .entities
+                    .user_mentions
+                    .iter()
+                    .map(|mention| mention.id),
+            );
+        }
+        let mut missing: Vec<UserId> = referenced
+            .into_iter()
+            .filter(|id| {
+                *id != self.profile.id
+                    && !self.profiles.contains_key(id)
+                    && !self.unavailable_profiles.contains_key(id)
+            })
+            .collect();
+        missing.sort_unstable();
+        missing
+    }
+
+    /// The stored profiles that were last downloaded longer than `older_than`
+    /// before `now`. Profiles from before `profile_seen` existed count as
+    /// stale, unavailable profiles are left out
+    pub fn stale_profile_ids(
+        &self,
+        older_than: chrono::Duration,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<UserId> {
+        let mut stale: Vec<UserId> = self
+            .profiles
+            .keys()
+            .filter(|id| !self.unavailable_profiles.contains_key(id))
+            .filter(|id| {
+                self.profile_seen
+                    .get(id)
+                    .map(|seen| now - seen.last_seen > older_than)
+                    .unwrap_or(true)
+            })
+            .copied()
+            .collect();
+        stale.sort_unstable();
+        stale
+    }
+
+    /// The archived tweets that mention the given user, newest first
+    pub fn tweets_mentioning(&self, id: UserId) -> Vec<&Tweet> {
+        match self.mentions_index.get(&id) {
+            Some(ids) => self.tweets_with_ids(ids),
+            None => Vec::new(),
+        }
+    }
+
+    /// The archived tweets with the given hashtag (in any case), newest first
+    pub fn tweets_with_hashtag(&self, tag: &str) -> Vec<&Tweet> {
+        match self.hashtag_index.get(&normalize_hashtag(tag)) {
+            Some(ids) => self.tweets_with_ids(ids),
+            None => Vec::new(),
+        }
+    }
+
+    /// The `n` hashtags the owner used most in their tweets with the number
+    /// of tweets, most used first
+    pub fn top_hashtags(&self, n: usize) -> Vec<(String, usize)> {
+        // Quoted and retweeted tweets are by others
+        let own: HashSet<TweetId> = self
+            .tweets
+            .iter()
+            .map(|t| t.id)
+            .filter(|id| !self.inner_tweets.contains(id))
+            .collect();
+        let mut counts: Vec<(String, usize)> = self
+            .hashtag_index
+            .iter()
+            .map(|(tag, ids)| (tag.clone(), ids.iter().filter(|id| own.contains(id)).count()))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(n);
+        counts
+    }
+
+    /// Whether the tweet was edited after it was posted
+    pub fn is_edited(&self, id: TweetId) -> bool {
+        self.edit_history.contains_key(&id)
+    }
+
+    /// The archived tweets in the given language, newest first. Tweets
+    /// without a language are in `und` (undetermined)
+    pub fn tweets_in_lang(&self, code: &str) -> Vec<&Tweet> {
+        let mut found: Vec<&Tweet> = self
+            .all_tweets()
+            .filter(|tweet| tweet_lang(tweet).eq_ignore_ascii_case(code))
+            .collect();
+        found.sort_by(|a, b| b.id.cmp(&a.id));
+        found.dedup_by_key(|t| t.id);
+        found
+    }
+
+    /// The languages of the archived tweets with the number of tweets,
+    /// most used first
+    pub fn languages(&self) -> Vec<(String, usize)> {
+        let mut seen: HashSet<TweetId> = HashSet::new();
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for tweet in self.all_tweets().filter(|tweet| seen.insert(tweet.id)) {
+            *counts.entry(tweet_lang(tweet).to_string()).or_default() += 1;
+        }
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts
+    }
+
+    fn tweets_with_ids(&self, ids: &[TweetId]) -> Vec<&Tweet> {
+        let ids: HashSet<TweetId> = ids.iter().copied().collect();
+        let responses = self.responses.values().flatten();
+        let mut found: Vec<&Tweet> = self
+            .tweets
+            .iter()
+            .chain(self.mentions.iter())
+            .chain(self.likes.iter())
+            .chain(responses)
+            .filter(|tweet| ids.contains(&tweet.id))
+            .collect();
+        found.sort_by(|a, b| b.id.cmp(&a.id));
+        found.dedup_by_key(|t| t.id);
+        found
+    }
+
+    fn all_tweets(&self) -> impl Iterator<Item = &Tweet> {
+        self.tweets
+            .iter()
+            .chain(self.mentions.iter())
+            .chain(self.likes.iter())
+            .chain(self.responses.values().flatten())
+    }
+
+    /// Index the mentions and hashtags of all archived tweets, for archives
+    /// from before the indexes existed
+    fn index_all_entities(&mut self) {
+        let mentions: Vec<(UserId, TweetId)> = self
+            .all_tweets()
+            .flat_map(|tweet| {
+                tweet
+                    .entities
+                    .user_mentions
+                    .iter()
+                    .map(|mention| (mention.id, tweet.id))
+            })
+            .collect();
+        for (user_id, tweet_id) in mentions {
+            insert_index(&mut self.mentions_index, user_id, tweet_id);
+        }
+        let hashtags: Vec<(String, TweetId)> = self
+            .all_tweets()
+            .flat_map(|tweet| {
+                tweet
+                    .entities
+                    .hashtags
+                    .iter()
+                    .map(|hashtag| (normalize_hashtag(&hashtag.text), tweet.id))
+            })
+            .collect();
+        for (tag, tweet_id) in hashtags {
+            insert_index(&mut self.hashtag_index, tag, tweet_id);
+        }
+    }
+
+    /// Index the quotes and retweets of all archived tweets, for archives
+    /// from before the indexes existed
+    fn archive_all_relations(&mut self) {
+        let relating: Vec<Tweet> = self
+            .all_tweets()
+            .filter(|tweet| tweet.quoted_status.is_some() || tweet.retweeted_status.is_some())
+            .cloned()
+            .collect();
+        for tweet in &relating {
+            self.archive_relations(tweet);
+        }
+    }
+
+    /// Index which tweet a tweet quotes or retweets and store that tweet
+    /// with the tweets, so that it can be shown offline
+    pub fn archive_relations(&mut self, tweet: &Tweet) {
+        self.index_relations(tweet);
+        let inner = [&tweet.quoted_status, &tweet.retweeted_status];
+        for inner in inner.into_iter().flatten() {
+            if self.tweets.iter().any(|t| t.id == inner.id) {
+                continue;
+            }
+            // Newest first, like the timeline
+            let position = self
+                .tweets
+                .iter()
+                .position(|t| t.id < inner.id)
+                .unwrap_or(self.tweets.len());
+            self.tweets.insert(position, inner.as_ref().clone());
+            self.inner_tweets.insert(inner.id);
+        }
+    }
+
+    /// Add tweets of the timeline of the owner. Copies of them that were
+    /// archived as quoted or retweeted tweets are replaced
+    pub fn add_timeline_tweets(&mut self, tweets: Vec<Tweet>) {
+        let ids: HashSet<TweetId> = tweets.iter().map(|t| t.id).collect();
+        self.tweets.retain(|t| !ids.contains(&t.id));
+        self.inner_tweets.retain(|id| !ids.contains(id));
+        self.tweets.extend(tweets);
+        self.tweets.sort_by(|a, b| b.id.cmp(&a.id));
+    }
+
+    /// Whether the link preview of the tweet is still to be looked up.
+    /// Only tweets with links can have one
+    pub fn needs_card(&self, tweet: &Tweet) -> bool {
+        !tweet.entities.urls.is_empty()
+            && !self.cards.contains_key(&tweet.id)
+            && !self.no_cards.contains(&tweet.id)
+    }
+
+    /// Store the link previews of a lookup of `looked_up`. The looked up
+    /// tweets without one are recorded in `no_cards`
+    pub fn store_cards(&mut self, looked_up: &[TweetId], cards: &[(TweetId, Card)]) {
+        for id in looked_up {
+            self.card_lookups.remove(id);
+            self.no_cards.insert(*id);
+        }
+        for (id, card) in cards {
+            self.no_cards.remove(id);
+            self.cards.insert(*id, card.clone());
+        }
+    }
+
+    /// Records how many of the followers and follows that the profile
+    /// reports were archived, for the ones that the crawl archived. Returns
+    /// the recorded counts
+    pub fn record_graph_counts(
+        &mut self,
+        options: &CrawlOptions,
+    ) -> Vec<(&'static str, GraphCount)> {
+        let mut recorded = Vec::new();
+        if options.followers {
+            let count = GraphCount {
+                reported: self.profile.followers_count.max(0) as usize,
+                archived: self.followers.len(),
+            };
+            self.followers_count = Some(count);
+            recorded.push(("followers", count));
+        }
+        if options.follows {
+            let count = GraphCount {
+                reported: self.profile.friends_count.max(0) as usize,
+                archived: self.follows.len(),
+            };
+            self.follows_count = Some(count);
+            recorded.push(("follows", count));
+        }
+        recorded
+    }
+
+    /// Whether the edit history of the tweet is still to be looked up
+    pub fn needs_edit_history(&self, id: TweetId) -> bool {
+        !self.edits_checked.contains(&id)
+    }
+
+    /// Store the edit histories of a lookup of `looked_up`. `histories` only
+    /// contains the edited tweets
+    pub fn store_edit_histories(
+        &mut self,
+        looked_up: &[TweetId],
+        histories: &[(TweetId, Vec<TweetId>)],
+    ) {
+        self.edits_checked.extend(looked_up);
+        for (id, history) in histories {
+            self.edit_history.insert(*id, history.clone());
+        }
+    }
+
+    /// The newest archived tweet of the timeline of the owner
+    pub fn newest_timeline_tweet(&self) -> Option<&Tweet> {
+        self.tweets
+            .iter()
+            .find(|t| !self.inner_tweets.contains(&t.id))
+    }
+
+    /// The number of archived tweets of the timeline of the owner
+    pub fn timeline_tweet_count(&self) -> usize {
+        self.tweets.len().saturating_sub(self.inner_tweets.len())
+    }
+
+    /// Record which tweet a tweet quotes or retweets
+    pub fn index_relations(&mut self, tweet: &Tweet) {
+        // Quoted tweets of quoted tweets are only known by their id
+        let quoted_id = tweet.quoted_status.as_ref().map(|quoted| quoted.id);
+        if let Some(quoted_id) = quoted_id.or(tweet.quoted_status_id) {
+            self.quotes.insert(tweet.id, quoted_id);
+        }
+        if let Some(retweeted) = &tweet.retweeted_status {
+            self.retweets.insert(tweet.id, retweeted.id);
+        }
+    }
+
+    /// The tweet that the tweet with the id quotes. The archived copy is
+    /// preferred over the one embedded in the quote tweet
+    pub fn quoted_tweet(&self, id: TweetId) -> Option<&Tweet> {
+        let quoted_id = self.quotes.get(&id)?;
+        self.any_tweet(*quoted_id)
+            .or_else(|| self.any_tweet(id)?.quoted_status.as_deref())
+            .or_else(|| self.quoted_tweets.get(quoted_id))
+    }
+
+    /// The tweet that the tweet with the id retweets, like `quoted_tweet`
+    pub fn retweeted_tweet(&self, id: TweetId) -> Option<&Tweet> {
+        let retweeted_id = self.retweets.get(&id)?;
+        self.any_tweet(*retweeted_id)
+            .or_else(|| self.any_tweet(id)?.retweeted_status.as_deref())
+    }
+
+    /// The archived tweets that quote the tweet with the id, newest first
+    pub fn quoted_by(&self, id: TweetId) -> Vec<&Tweet> {
+        let mut quoting: Vec<&Tweet> = self
+            .quotes
+            .iter()
+            .filter(|(_, quoted_id)| **quoted_id == id)
+            .filter_map(|(quote_id, _)| self.any_tweet(*quote_id))
+            .collect();
+        quoting.sort_by(|a, b| b.id.cmp(&a.id));
+        quoting
+    }
+
+    /// Record the users that a tweet mentions and the hashtags it uses
+    pub fn index_entities(&mut self, tweet: &Tweet) {
+        for mention in &tweet.entities.user_mentions {
+            insert_index(&mut self.mentions_index, mention.id, tweet.id);
+        }
+        for hashtag in &tweet.entities.hashtags {
+            insert_index(
+                &mut self.hashtag_index,
+                normalize_hashtag(&hashtag.text),
+                tweet.id,
+            );
+        }
+    }
+}
+
+fn insert_index<K: std::hash::Hash + Eq>(
+    index: &mut HashMap<K, Vec<TweetId>>,
+    key: K,
+    tweet_id: TweetId,
+) {
+    let tweet_ids = index.entry(key).or_default();
+    if !tweet_ids.contains(&tweet_id) {
+        tweet_ids.push(tweet_id);
+    }
+}
+
+/// The language code Twitter uses for tweets without a detectable language
+pub const UNDETERMINED_LANG: &str = "und";
+
+/// The language code of the tweet, `und` if Twitter didn't determine one
+pub fn tweet_lang(tweet: &Tweet) -> &str {
+    match tweet.lang.as_deref() {
+        Some(lang) if !lang.is_empty() => lang,
+        _ => UNDETERMINED_LANG,
+    }
+}
+
+/// Hashtags are case insensitive, also outside of ASCII, so `#Ärger` and
+/// `#ÄRGER` are the same tag
+fn normalize_hashtag(tag: &str) -> String {
+    tag.trim_start_matches('#').to_lowercase()
+}
+
+/// How many backup snapshots are kept. Every snapshot contains all follower ids
+const MAX_BACKUPS: usize = 10;
+
+/// The end of a crawl. The followers and list members at that time are
+/// in a `BackupSnapshot` file, so that they don't grow the archive
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BackupMarker {
+    pub finished_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The state of the archive at the end of a crawl, to compare later crawls with
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BackupSnapshot {
+    pub followers: Vec<UserId>,
+    /// The members of every list, by list id
+    pub list_members: HashMap<u64, Vec<UserId>>,
+}
+
+/// What changed in the archive since a given time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupDiff {
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Tweets of the owner that were created since then
+    pub new_tweets: Vec<TweetId>,
+    pub followers_gained: Vec<UserId>,
+    pub followers_lost: Vec<UserId>,
+    /// The new members by list name
+    pub new_list_members: Vec<(String, Vec<UserId>)>,
+}
+
+impl BackupDiff {
+    pub fn is_empty(&self) -> bool {
+        self.new_tweets.is_empty()
+            && self.followers_gained.is_empty()
+            && self.followers_lost.is_empty()
+            && self.new_list_members.is_empty()
+    }
+}
+
+impl std::fmt::Display for BackupDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(since) = self.since {
+            writeln!(f, "since {}:", since.format("%Y-%m-%d %H:%M"))?;
+        }
+        writeln!(f, "new tweets: {}", self.new_tweets.len())?;
+        writeln!(f, "followers gained: {}", self.followers_gained.len())?;
+        writeln!(f, "followers lost: {}", self.followers_lost.len())?;
+        for (name, members) in &self.new_list_members {
+            writeln!(f, "new members in {name}: {}", members.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of checking the downloaded media files
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MediaReport {
+    /// The number of checked media entries
+    pub total: usize,
+    /// Entries whose file doesn't exist
+    pub missing: Vec<UrlString>,
+    /// Entries whose file is empty, e.g. an interrupted download
+    pub corrupt: Vec<UrlString>,
+}
+
+impl MediaReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.corrupt.is_empty()
+    }
+
+    /// The urls of all broken entries
+    pub fn broken(&self) -> impl Iterator<Item = &UrlString> {
+        self.missing.iter().chain(self.corrupt.iter())
+    }
+}
+
+/// A summary of the archived data
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ArchiveStats {
+    pub tweets: usize,
+    pub mentions: usize,
+    pub responses: usize,
+    pub likes: usize,
+    pub followers: usize,
+    pub follows: usize,
+    /// The followers count of the profile when the last crawl archived the
+    /// followers
+    pub followers_reported: Option<usize>,
+    /// The follows count of the profile when the last crawl archived the
+    /// follows
+    pub follows_reported: Option<usize>,
+    pub profiles: usize,
+    pub lists: usize,
+    pub media_files: usize,
+    /// The size of the downloaded media on disk
+    pub media_bytes: u64,
+    pub oldest_tweet: Option<chrono::DateTime<chrono::Utc>>,
+    pub newest_tweet: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ArchiveStats {
+    /// How many followers the profile reports that are not archived.
+    /// Negative if the archive has more, e.g. if the count of the profile
+    /// is older than the followers
+    pub fn missing_followers(&self) -> Option<i64> {
+        self.followers_reported
+            .map(|reported| count_delta(reported, self.followers))
+    }
+
+    /// Like `missing_followers`, for the follows
+    pub fn missing_follows(&self) -> Option<i64> {
+        self.follows_reported
+            .map(|reported| count_delta(reported, self.follows))
+    }
+}
+
+/// The difference between the count of a profile and the archived entries.
+/// They differ for deleted, suspended or protected accounts, which Twitter
+/// counts but doesn't return, and if a crawl stopped early
+pub fn count_delta(reported: usize, stored: usize) -> i64 {
+    reported as i64 - stored as i64
+}
+
+impl std::fmt::Display for ArchiveStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "tweets: {}", self.tweets)?;
+        writeln!(f, "mentions: {}", self.mentions)?;
+        writeln!(f, "responses: {}", self.responses)?;
+        writeln!(f, "likes: {}", self.likes)?;
+        writeln!(f, "profiles: {}", self.profiles)?;
+        writeln!(f, "followers: {}", self.followers)?;
+        if let Some(reported) = self.followers_reported.filter(|r| *r != self.followers) {
+            writeln!(f, "followers reported by the profile: {reported}")?;
+        }
+        writeln!(f, "follows: {}", self.follows)?;
+        if let Some(reported) = self.follows_reported.filter(|r| *r != self.follows) {
+            writeln!(f, "follows reported by the profile: {reported}")?;
+        }
+        writeln!(f, "lists: {}", self.lists)?;
+        writeln!(
+            f,
+            "media: {} ({:.1} MB)",
+            self.media_files,
+            self.media_bytes as f64 / 1_000_000.0
+        )?;
+        if let (Some(oldest), Some(newest)) = (self.oldest_tweet, self.newest_tweet) {
+            writeln!(
+                f,
+                "tweets from {} to {}",
+                oldest.format("%Y-%m-%d"),
+                newest.format("%Y-%m-%d")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Who follows back and who doesn't. Every entry contains the id
+/// and, if the profile was downloaded, the screen name.
+#[derive(Debug, Clone, Default)]
+pub struct RelationshipReport {
+    /// Accounts that follow and are followed
+    pub mutuals: Vec<(UserId, Option<String>)>,
+    /// Accounts that are followed but don't follow back
+    pub not_following_back: Vec<(UserId, Option<String>)>,
+    /// Followers that are not followed
+    pub not_followed_back: Vec<(UserId, Option<String>)>,
+}
+
+impl std::fmt::Display for RelationshipReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (title, entries) in [
+            ("mutuals", &self.mutuals),
+            ("not following back", &self.not_following_back),
+            ("not followed back", &self.not_followed_back),
+        ] {
+            writeln!(f, "{title}: {}", entries.len())?;
+            for (id, screen_name) in entries {
+                match screen_name {
+                    Some(screen_name) => writeln!(f, " @{screen_name}")?,
+                    None => writeln!(f, " {id}")?,
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Storage {
     pub root_folder: PathBuf,
-    data_path: PathBuf,
+    backend: Arc<dyn StorageBackend>,
     data: Data,
+    /// Only the preview of the archive is loaded, see `open_preview`
+    preview: bool,
 }
 
 impl Storage {
-    fn storage_for_data(path: impl AsRef<Path>, data: Data) -> Result<Self> {
+    fn storage_for_data(
+        path: impl AsRef<Path>,
+        backend: Arc<dyn StorageBackend>,
+        data: Data,
+    ) -> Result<Self> {
         let root_folder = path.as_ref().to_path_buf();
-        if !root_folder.exists() {
-            std::fs::create_dir(&root_folder)?;
-        }
         if !root_folder.join(FOLDER_MEDIA).exists() {
-            std::fs::create_dir(&root_folder.join(FOLDER_MEDIA))?;
+            std::fs::create_dir_all(root_folder.join(FOLDER_MEDIA))?;
         }
-        let data_path = root_folder.join(FILE_ROOT);
         Ok(Storage {
             root_folder,
-            data_path,
+            backend,
             data,
+            preview: false,
         })
     }
 
     pub fn media_path(&self, filename: &str) -> PathBuf {
-        self.root_folder.join(FOLDER_MEDIA).join(filename)
+        self.backend.media_path(filename)
     }
 
+    /// Write and remove a file in the media folder to find out if
+    /// media can be downloaded into it
+    pub fn probe_media_folder(&self) -> Result<()> {
+        let probe = self.media_path(".write-probe");
+        std::fs::write(&probe, b"twitvault")?;
+        std::fs::remove_file(&probe)?;
+        Ok(())
+    }
+
+    /// A new archive with the default backend, which is JSON unless the
+    /// folder already contains a SQLite archive
     pub fn new(profile: TwitterUser, path: impl AsRef<Path>) -> Result<Self> {
-        Self::storage_for_data(
-            path,
-            Data {
-                profile,
-                tweets: Default::default(),
-                mentions: Default::default(),
-                responses: Default::default(),
-                profiles: Default::default(),
-                followers: Default::default(),
-                follows: Default::default(),
-                lists: Default::default(),
-                media: Default::default(),
-                likes: Default::default(),
-            },
-        )
+        let backend = backend::detect(path.as_ref());
+        Self::storage_for_data(path, backend, Data::new(profile))
     }
 
+    /// Whether there is an archive in `path`, without loading it
+    pub fn exists(path: impl AsRef<Path>) -> bool {
+        backend::detect(path.as_ref()).exists()
+    }
+
+    /// Opens the archive in the folder `path`, or the folder of the archive
+    /// file `path`. Unlike `open`, the errors explain what went wrong
+    pub fn open_existing(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let folder = match path.is_file() {
+            true => path.parent().unwrap_or(path),
+            false => path,
+        };
+        if !Self::exists(folder) {
+            bail!("There is no TwitVault archive in {}", folder.display());
+        }
+        Self::open(folder).map_err(|e| {
+            eyre::eyre!(
+                "The archive in {} could not be read, it may be damaged: {e}",
+                folder.display()
+            )
+        })
+    }
+
+    /// Loads the whole archive. This blocks for a while for large archives,
+    /// so the UI calls it in the background
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        let data_path = path.as_ref().join(FILE_ROOT);
-        let input = std::fs::read(&data_path)?;
-        let data: Data = serde_json::from_slice(&input)?;
-        Self::storage_for_data(path, data)
+        let started = Instant::now();
+        let backend = backend::detect(path.as_ref());
+        let mut value = backend.load()?;
+        let version = migrate(&mut value)?;
+        let mut data: Data = serde_json::from_value(value)?;
+        migrate_data(&mut data, version);
+        info!(
+            "Opened the {} archive with {} tweets in {}ms",
+            backend.name(),
+            data.tweets.len(),
+            started.elapsed().as_millis()
+        );
+        Self::storage_for_data(path, backend, data)
+    }
+
+    /// Opens only the preview of the archive in the folder `path`, which
+    /// takes the same time for any archive size. `None` if the archive was
+    /// last saved without one. The preview can't be saved
+    pub fn open_preview(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        let Some(data) = preview::read(path.as_ref())? else {
+            return Ok(None);
+        };
+        let backend = backend::detect(path.as_ref());
+        let mut storage = Self::storage_for_data(path, backend, data)?;
+        storage.preview = true;
+        Ok(Some(storage))
+    }
+
+    /// Whether only the preview of the archive is loaded
+    pub fn is_preview(&self) -> bool {
+        self.preview
+    }
+
+    /// The name of the backend the archive is saved with
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    /// Save the archive with a different backend from now on. The data of
+    /// the previous backend is left untouched
+    pub fn convert_to(&mut self, backend: Arc<dyn StorageBackend>) -> Result<()> {
+        backend.save(&self.data)?;
+        self.backend = backend;
+        Ok(())
     }
 
     pub fn data(&self) -> &Data {
@@ -142,6 +1291,433 @@ impl Storage {
         action(&mut self.data)
     }
 
+    /// Replace the tweet with the same id in the list of `kind`, or add it
+    /// as the newest one, and write it right away if the backend can
+    pub fn upsert_tweet(&mut self, kind: TweetKind, tweet: Tweet) -> Result<()> {
+        self.backend.upsert_tweet(kind, &tweet)?;
+        let tweets = match kind {
+            TweetKind::Tweet => &mut self.data.tweets,
+            TweetKind::Mention => &mut self.data.mentions,
+            TweetKind::Like => &mut self.data.likes,
+            TweetKind::Response(parent_id) => self.data.responses.entry(parent_id).or_default(),
+        };
+        match tweets.iter_mut().find(|existing| existing.id == tweet.id) {
+            Some(existing) => *existing = tweet,
+            None => tweets.insert(0, tweet),
+        }
+        Ok(())
+    }
+
+    /// Counts for all the collections and the size of the media on disk
+    pub fn stats(&self) -> ArchiveStats {
+        let media_bytes = self
+            .data
+            .media
+            .values()
+            .filter_map(|file_name| std::fs::metadata(self.media_path(file_name)).ok())
+            .map(|meta| meta.len())
+            .sum();
+        self.stats_with_media_bytes(media_bytes)
+    }
+
+    /// `stats` with the size of the media recorded in `media_meta`, which
+    /// doesn't read the media folder. Media downloaded before the sizes
+    /// were recorded counts as empty
+    fn recorded_stats(&self) -> ArchiveStats {
+        let data = &self.data;
+        let media_bytes = data
+            .media
+            .keys()
+            .filter_map(|url| data.media_meta.get(url))
+            .map(|meta| meta.bytes)
+            .sum();
+        self.stats_with_media_bytes(media_bytes)
+    }
+
+    fn stats_with_media_bytes(&self, media_bytes: u64) -> ArchiveStats {
+        let data = &self.data;
+        let dates = data
+            .tweets
+            .iter()
+            .filter(|t| !data.inner_tweets.contains(&t.id))
+            .map(|t| t.created_at);
+        ArchiveStats {
+            tweets: data.timeline_tweet_count(),
+            mentions: data.mentions.len(),
+            responses: data.responses.values().map(|r| r.len()).sum(),
+            likes: data.likes.len(),
+            followers: data.followers.len(),
+            follows: data.follows.len(),
+            followers_reported: data.followers_count.map(|count| count.reported),
+            follows_reported: data.follows_count.map(|count| count.reported),
+            profiles: data.profiles.len(),
+            lists: data.lists.len(),
+            media_files: data.media.len(),
+            media_bytes,
+            oldest_tweet: dates.clone().min(),
+            newest_tweet: dates.max(),
+        }
+    }
+
+    pub fn top_hashtags(&self, n: usize) -> Vec<(String, usize)> {
+        self.data.top_hashtags(n)
+    }
+
+    pub fn tweets_in_lang(&self, code: &str) -> Vec<&Tweet> {
+        self.data.tweets_in_lang(code)
+    }
+
+    /// Remember the current followers and list members, so that the next
+    /// crawl can be compared with this one, and the options of the crawl
+    pub fn record_backup(&mut self, options: &CrawlOptions) -> Result<()> {
+        self.data.crawl_options = Some(options.clone());
+        let marker = BackupMarker {
+            finished_at: chrono::Utc::now(),
+        };
+        let snapshot = BackupSnapshot {
+            followers: self.data.followers.clone(),
+            list_members: self
+                .data
+                .lists
+                .iter()
+                .map(|list| (list.list.id, list.members.clone()))
+                .collect(),
+        };
+        let path = self.backup_path(&marker);
+        std::fs::create_dir_all(self.root_folder.join(FOLDER_BACKUPS))?;
+        std::fs::write(&path, serde_json::to_vec(&snapshot)?)?;
+
+        self.data.backups.push(marker);
+        let excess = self.data.backups.len().saturating_sub(MAX_BACKUPS);
+        for marker in self.data.backups.drain(..excess).collect::<Vec<_>>() {
+            let path = self.backup_path(&marker);
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("Could not remove {}: {e:?}", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    fn backup_path(&self, marker: &BackupMarker) -> PathBuf {
+        let file_name = format!("{}.json", marker.finished_at.timestamp_millis());
+        self.root_folder.join(FOLDER_BACKUPS).join(file_name)
+    }
+
+    /// The followers and list members at the time of `marker`, if the
+    /// snapshot file is still there
+    pub fn backup_snapshot(&self, marker: &BackupMarker) -> Option<BackupSnapshot> {
+        let path = self.backup_path(marker);
+        let input = std::fs::read(&path).ok()?;
+        match serde_json::from_slice(&input) {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                warn!("Could not read {}: {e:?}", path.display());
+                None
+            }
+        }
+    }
+
+    /// When the crawl before the most recent one finished
+    pub fn previous_backup(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        let backups = &self.data.backups;
+        backups.len().checked_sub(2).map(|i| backups[i].finished_at)
+    }
+
+    /// The new tweets since `timestamp` and the followers and list members
+    /// compared to the last backup before it. Without such a backup, only
+    /// the tweets are compared
+    pub fn diff_since(&self, timestamp: chrono::DateTime<chrono::Utc>) -> BackupDiff {
+        let data = &self.data;
+        let mut new_tweets: Vec<TweetId> = data
+            .tweets
+            .iter()
+            .filter(|tweet| !data.inner_tweets.contains(&tweet.id))
+            .filter(|tweet| tweet.created_at > timestamp)
+            .map(|tweet| tweet.id)
+            .collect();
+        new_tweets.sort_unstable_by(|a, b| b.cmp(a));
+        let mut diff = BackupDiff {
+            since: Some(timestamp),
+            new_tweets,
+            ..Default::default()
+        };
+        let Some(baseline) = data
+            .backups
+            .iter()
+            .rev()
+            .find(|marker| marker.finished_at <= timestamp)
+            .and_then(|marker| self.backup_snapshot(marker)) else {
+            return diff
+        };
+
+        let previous: HashSet<UserId> = baseline.followers.iter().copied().collect();
+        let current: HashSet<UserId> = data.followers.iter().copied().collect();
+        diff.followers_gained = data
+            .followers
+            .iter()
+            .filter(|id| !previous.contains(id))
+            .copied()
+            .collect();
+        diff.followers_lost = baseline
+            .followers
+            .iter()
+            .filter(|id| !current.contains(id))
+            .copied()
+            .collect();
+
+        for list in &data.lists {
+            let previous: HashSet<UserId> = baseline
+                .list_members
+                .get(&list.list.id)
+                .map(|members| members.iter().copied().collect())
+                .unwrap_or_default();
+            let added: Vec<UserId> = list
+                .members
+                .iter()
+                .filter(|id| !previous.contains(id))
+                .copied()
+                .collect();
+            if !added.is_empty() {
+                diff.new_list_members.push((list.name.clone(), added));
+            }
+        }
+        diff
+    }
+
+    /// Compare the followers and follows
+    pub fn relationship_report(&self) -> RelationshipReport {
+        let data = &self.data;
+        let followers: HashSet<UserId> = data.followers.iter().copied().collect();
+        let follows: HashSet<UserId> = data.follows.iter().copied().collect();
+        let resolve = |id: &UserId| (*id, data.profiles.get(id).map(|p| p.screen_name.clone()));
+        let (mutuals, not_following_back) = data
+            .follows
+            .iter()
+            .map(resolve)
+            .partition(|(id, _)| followers.contains(id));
+        let not_followed_back = data
+            .followers
+            .iter()
+            .filter(|id| !follows.contains(id))
+            .map(resolve)
+            .collect();
+        RelationshipReport {
+            mutuals,
+            not_following_back,
+            not_followed_back,
+        }
+    }
+
+    /// Check that every file in `media` exists and is not empty
+    pub fn verify_media(&self) -> MediaReport {
+        let mut report = MediaReport {
+            total: self.data.media.len(),
+            ..Default::default()
+        };
+        for (url, file_name) in &self.data.media {
+            match std::fs::metadata(self.media_path(file_name)) {
+                Err(_) => report.missing.push(url.clone()),
+                Ok(meta) if meta.len() == 0 => report.corrupt.push(url.clone()),
+                Ok(_) => (),
+            }
+        }
+        report.missing.sort();
+        report.corrupt.sort();
+        report
+    }
+
+    /// Delete the media that no tweet or profile references anymore: its
+    /// entries in `media` and the files in the media folder that no remaining
+    /// entry points to. Partial downloads are left to the crawler, which
+    /// continues or starts them over. The archive is saved before, so that
+    /// its index never points at deleted files. Returns the number of
+    /// deleted files
+    pub fn gc_media(&mut self) -> Result<usize> {
+        let referenced = self.data.referenced_media();
+        let orphaned: Vec<UrlString> = self
+            .data
+            .media
+            .keys()
+            .filter(|url| !referenced.contains(url.as_str()))
+            .cloned()
+            .collect();
+        for url in &orphaned {
+            self.data.media.remove(url);
+            self.data.media_meta.remove(url);
+        }
+
+        // A file that is still in `media` is never deleted
+        let known: HashSet<&str> = self.data.media.values().map(|e| e.as_str()).collect();
+        let mut unknown_paths = Vec::new();
+        for entry in std::fs::read_dir(self.root_folder.join(FOLDER_MEDIA))? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            if known.contains(file_name.as_ref()) || file_name.ends_with(".part") {
+                continue;
+            }
+            unknown_paths.push(entry.path());
+        }
+
+        if !orphaned.is_empty() {
+            self.save()?;
+        }
+        let mut removed = 0;
+        for path in unknown_paths {
+            match std::fs::remove_file(&path) {
+                Ok(_) => removed += 1,
+                Err(e) => warn!("Could not remove {}: {e:?}", path.display()),
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Twitter serves some media under several urls (e.g. with size suffixes
+    /// or query parameters), so identical files can be downloaded more than
+    /// once. Of files with the same contents, all urls are pointed to one
+    /// file and the others are deleted. The archive is saved before, so that
+    /// its index never points at deleted files
+    pub fn dedup_media(&mut self) -> Result<MediaDedupReport> {
+        let file_names: BTreeSet<String> = self.data.media.values().cloned().collect();
+        let mut groups: HashMap<(u64, String), Vec<String>> = HashMap::new();
+        for file_name in file_names {
+            let path = self.media_path(&file_name);
+            let Ok(metadata) = std::fs::metadata(&path) else { continue };
+            let hash = media_sync::hash_file(&path)?;
+            groups
+                .entry((metadata.len(), hash))
+                .or_default()
+                .push(file_name);
+        }
+
+        let mut report = MediaDedupReport::default();
+        let mut duplicate_paths = Vec::new();
+        for ((size, _), file_names) in groups {
+            // `file_names` is sorted, so the first file is kept
+            let Some((canonical, duplicates)) = file_names.split_first() else { continue };
+            let canonical_path = self.media_path(canonical);
+            for duplicate in duplicates {
+                let path = self.media_path(duplicate);
+                // The hash could collide
+                if !media_sync::files_equal(&canonical_path, &path)? {
+                    continue;
+                }
+                for file_name in self.data.media.values_mut() {
+                    if file_name == duplicate {
+                        *file_name = canonical.clone();
+                    }
+                }
+                duplicate_paths.push(path);
+                report.files_removed += 1;
+                report.bytes_reclaimed += size;
+            }
+        }
+        if duplicate_paths.is_empty() {
+            return Ok(report);
+        }
+        self.save()?;
+        for path in duplicate_paths {
+            std::fs::remove_file(&path)?;
+        }
+        Ok(report)
+    }
+
+    /// Size and hash of all files in the media folder, reusing the hashes of
+    /// the `previous` manifest for unchanged files
+    pub fn export_media_manifest(&self, previous: &MediaManifest) -> Result<MediaManifest> {
+        MediaManifest::for_folder(&self.root_folder.join(FOLDER_MEDIA), previous)
+    }
+
+    /// Copy the new and changed media files into `destination`, e.g. a
+    /// folder on a NAS. See `media_sync::sync_media`
+    pub fn sync_media(&self, destination: &Path, delete_removed: bool) -> Result<MediaSyncReport> {
+        let previous = MediaManifest::at_destination(destination);
+        let manifest = self.export_media_manifest(&previous)?;
+        media_sync::sync_media(
+            &manifest,
+            &previous,
+            &self.root_folder.join(FOLDER_MEDIA),
+            destination,
+            delete_removed,
+        )
+    }
+
+    /// Remove a tweet and the replies to it from the archive including the
+    /// media that is not used by any other tweet. The archive is saved before
+    /// the files are deleted, so that its index never points at deleted
+    /// files. Returns the removed media urls
+    pub fn remove_tweet(&mut self, id: TweetId) -> Result<Vec<UrlString>> {
+        let Some(tweet) = self.data.any_tweet(id).cloned() else {
+            bail!("Unknown tweet {id}")
+        };
+        let mut candidates = media_urls_in_tweet(&tweet);
+        if let Some(card) = self.data.cards.remove(&id) {
+            candidates.extend(card.image_url);
+        }
+        self.data.no_cards.remove(&id);
+        self.data.card_lookups.remove(&id);
+
+        for tweets in [
+            &mut self.data.tweets,
+            &mut self.data.mentions,
+            &mut self.data.likes,
+        ] {
+            tweets.retain(|t| t.id != id);
+        }
+        for reply in self.data.responses.remove(&id).unwrap_or_default() {
+            candidates.extend(media_urls_in_tweet(&reply));
+        }
+        for replies in self.data.responses.values_mut() {
+            replies.retain(|t| t.id != id);
+        }
+        self.data.responses.retain(|_, replies| !replies.is_empty());
+        self.data.metrics.remove(&id);
+        self.data.expanded_texts.remove(&id);
+        self.data.polls.remove(&id);
+        self.data.places.remove(&id);
+        self.data.spaces.remove(&id);
+        self.data.conversation_ids.remove(&id);
+        self.data.quotes.remove(&id);
+        self.data.retweets.remove(&id);
+        self.data.inner_tweets.remove(&id);
+        self.data.unsearched_replies.remove(&id);
+        self.data.edit_history.remove(&id);
+        self.data.edits_checked.remove(&id);
+        for edit in self.data.tweet_edits.remove(&id).unwrap_or_default() {
+            candidates.extend(media_urls_in_tweet(&edit));
+        }
+        for tweet_ids in self.data.mentions_index.values_mut() {
+            tweet_ids.retain(|t| *t != id);
+        }
+        self.data.mentions_index.retain(|_, tweet_ids| !tweet_ids.is_empty());
+        for tweet_ids in self.data.hashtag_index.values_mut() {
+            tweet_ids.retain(|t| *t != id);
+        }
+        self.data.hashtag_index.retain(|_, tweet_ids| !tweet_ids.is_empty());
+
+        let orphans = self.data.orphaned_media(&candidates);
+        let mut file_names = Vec::new();
+        for url in &orphans {
+            self.data.media_meta.remove(url);
+            file_names.extend(self.data.media.remove(url));
+        }
+        // Merged duplicates share a file, see `dedup_media`
+        file_names.retain(|file_name| !self.data.media.values().any(|other| other == file_name));
+
+        self.save()?;
+        for file_name in file_names {
+            let path = self.media_path(&file_name);
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("Could not remove {}: {e:?}", path.display());
+            }
+        }
+
+        Ok(orphans)
+    }
+
     pub fn resolver(&self) -> MediaResolver {
         MediaResolver {
             root_folder: self.root_folder.join(FOLDER_MEDIA),
@@ -149,14 +1725,185 @@ impl Storage {
         }
     }
 
+    /// The raw API objects of the archive. The handle doesn't borrow the
+    /// storage, so that the files can be read and written without its lock
+    pub fn raw_archive(&self) -> RawArchive {
+        RawArchive {
+            folder: self.root_folder.join(FOLDER_RAW),
+        }
+    }
+
     // Blocking write
+    /// Saves the data and then updates the preview and the manifest, so that
+    /// they never describe data that wasn't written. The data is saved even
+    /// if they can't be written
     pub fn save(&self) -> Result<()> {
-        use std::fs::OpenOptions;
-        let outfile = OpenOptions::new()
+        if self.preview {
+            bail!("Only the preview of the archive is loaded, it can't be saved");
+        }
+        self.backend.save(&self.data)?;
+        if let Err(e) = preview::write(&self.data, &self.root_folder) {
+            warn!("Could not write the preview of the archive: {e:?}");
+        }
+        if let Err(e) = ArchiveManifest::new(self).write(&self.root_folder) {
+            warn!("Could not write the manifest of the archive: {e:?}");
+        }
+        Ok(())
+    }
+}
+
+/// Upgrade an older archive layout to the current `DATA_VERSION`. Returns
+/// the version the archive had
+fn migrate(value: &mut serde_json::Value) -> Result<u32> {
+    let version = value
+        .get("version")
+        .and_then(|v| v.as_u64())
+        .map(|v| (v as u32).max(1))
+        .unwrap_or(1);
+    if version > DATA_VERSION {
+        bail!(
+            "The archive has version {version} but this TwitVault only supports up to version {DATA_VERSION}. Please update TwitVault."
+        );
+    }
+    for migration in &MIGRATIONS[(version as usize - 1)..] {
+        migration(value);
+    }
+    if let Some(object) = value.as_object_mut() {
+        object.insert("version".to_string(), DATA_VERSION.into());
+    }
+    Ok(version)
+}
+
+/// The migrations that need the parsed tweets, for an archive that had
+/// `version` before `migrate`
+fn migrate_data(data: &mut Data, version: u32) {
+    if version < 4 {
+        data.index_all_entities();
+    }
+    if version < 5 {
+        data.archive_all_relations();
+    }
+}
+
+/// Version 1 archives might lack the `likes` and `metrics`
+fn migrate_v1_to_v2(value: &mut serde_json::Value) {
+    let Some(object) = value.as_object_mut() else { return };
+    object
+        .entry("likes")
+        .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+    object
+        .entry("metrics")
+        .or_insert_with(|| serde_json::Value::Object(Default::default()));
+}
+
+/// Profiles were stored without timestamps, so the time of the
+/// migration is the best guess
+fn migrate_v2_to_v3(value: &mut serde_json::Value) {
+    let Some(object) = value.as_object_mut() else { return };
+    let now = serde_json::to_value(chrono::Utc::now()).unwrap_or_default();
+    let seen: serde_json::Map<String, serde_json::Value> = object
+        .get("profiles")
+        .and_then(|profiles| profiles.as_object())
+        .map(|profiles| {
+            profiles
+                .keys()
+                .map(|id| {
+                    let entry = serde_json::json!({ "first_seen": now, "last_seen": now });
+                    (id.clone(), entry)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    object
+        .entry("profile_seen")
+        .or_insert(serde_json::Value::Object(seen));
+}
+
+/// Version 4 indexes the mentions and hashtags of the tweets. The indexes
+/// are built from the parsed tweets by `migrate_data`
+fn migrate_v3_to_v4(_value: &mut serde_json::Value) {}
+
+/// Version 5 indexes the quotes and retweets and stores the quoted and
+/// retweeted tweets with the tweets, also done by `migrate_data`
+fn migrate_v4_to_v5(_value: &mut serde_json::Value) {}
+
+/// The `raw` folder with the JSON of the tweets and profiles as the API
+/// returned it, one ndjson file per kind (e.g. `tweets`)
+#[derive(Clone, Debug)]
+pub struct RawArchive {
+    folder: PathBuf,
+}
+
+impl RawArchive {
+    /// The ndjson file with the raw API objects of a kind
+    pub fn path(&self, kind: &str) -> PathBuf {
+        self.folder.join(format!("{kind}.ndjson"))
+    }
+
+    /// The file with the ids of a kind that the API didn't return, one per line
+    fn unavailable_path(&self, kind: &str) -> PathBuf {
+        self.folder.join(format!("{kind}.unavailable"))
+    }
+
+    /// The ids of the raw objects of a kind that were already written
+    pub fn ids(&self, kind: &str) -> Result<HashSet<u64>> {
+        let path = self.path(kind);
+        if !path.exists() {
+            return Ok(HashSet::new());
+        }
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut ids = HashSet::new();
+        for line in std::io::BufRead::lines(reader) {
+            let value: serde_json::Value = serde_json::from_str(&line?)?;
+            if let Some(id) = value.get("id").and_then(|id| id.as_u64()) {
+                ids.insert(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    /// The ids of a kind that a lookup didn't return, so that they aren't
+    /// requested again
+    pub fn unavailable_ids(&self, kind: &str) -> Result<HashSet<u64>> {
+        let path = self.unavailable_path(kind);
+        if !path.exists() {
+            return Ok(HashSet::new());
+        }
+        let ids = std::fs::read_to_string(path)?
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect();
+        Ok(ids)
+    }
+
+    /// Append raw API objects of a kind, one JSON object per line
+    pub fn append(&self, kind: &str, values: &[serde_json::Value]) -> Result<()> {
+        let mut writer = self.appender(self.path(kind))?;
+        for value in values {
+            serde_json::to_writer(&mut writer, value)?;
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Record ids of a kind that the API didn't return
+    pub fn append_unavailable(&self, kind: &str, ids: &[u64]) -> Result<()> {
+        let mut writer = self.appender(self.unavailable_path(kind))?;
+        for id in ids {
+            writeln!(writer, "{id}")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn appender(&self, path: PathBuf) -> Result<std::io::BufWriter<std::fs::File>> {
+        std::fs::create_dir_all(&self.folder)?;
+        let file = std::fs::OpenOptions::new()
             .create(true)
-            .write(true)
-            .open(&self.data_path)?;
-        Ok(serde_json::to_writer(outfile, &self.data)?)
+            .append(true)
+            .open(path)?;
+        Ok(std::io::BufWriter::new(file))
     }
 }
 
@@ -180,7 +1927,638 @@ impl<'a> MediaResolver<'a> {
         {
             let found = self.media.get(url)?;
             let path = self.root_folder.join(found);
+            // The download might have failed or the file was removed
+            if !path.exists() {
+                return None;
+            }
             Some(path.display().to_string())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{
+        storage, tweet, user, with_hashtag, with_mention, with_photo, with_url,
+    };
+
+    #[test]
+    fn followers_missing_from_the_profile_count_are_recorded() {
+        let mut storage = storage("graph-counts");
+        let data = storage.data_mut();
+        data.profile.followers_count = 10;
+        data.profile.friends_count = 3;
+        data.followers = (1..=7).collect();
+        data.follows = (1..=5).collect();
+        assert_eq!(storage.stats().missing_followers(), None);
+
+        let options = CrawlOptions::default();
+        let recorded = storage.data_mut().record_graph_counts(&options);
+        let count = |reported, archived| GraphCount { reported, archived };
+        assert_eq!(
+            recorded,
+            [("followers", count(10, 7)), ("follows", count(3, 5))]
+        );
+        assert_eq!(recorded[0].1.delta(), 3);
+        // The count of the profile is older than the follows
+        assert_eq!(recorded[1].1.delta(), -2);
+        assert_eq!(count_delta(10, 7), 3);
+
+        // A newer profile doesn't change the count of the crawl
+        storage.data_mut().profile.followers_count = 99;
+        let stats = storage.stats();
+        assert_eq!(stats.missing_followers(), Some(3));
+        assert_eq!(stats.missing_follows(), Some(-2));
+        assert!(stats
+            .to_string()
+            .contains("followers reported by the profile: 10"));
+
+        let without_follows = CrawlOptions {
+            follows: false,
+            ..Default::default()
+        };
+        storage.data_mut().follows_count = None;
+        storage.data_mut().record_graph_counts(&without_follows);
+        assert_eq!(storage.stats().missing_follows(), None);
+    }
+
+    #[test]
+    fn remove_tweet_keeps_media_of_other_tweets() {
+        let mut storage = storage("remove-tweet");
+        let shared = "https://pbs.twimg.com/media/shared.jpg";
+        let own = "https://pbs.twimg.com/media/own.jpg";
+        let first = with_photo(with_photo(tweet(1, "first"), shared), own);
+        let second = with_photo(tweet(2, "second"), shared);
+        storage.data_mut().tweets = vec![second, first];
+        for (url, file_name) in [(shared, "shared.jpg"), (own, "own.jpg")] {
+            std::fs::write(storage.media_path(file_name), b"image").unwrap();
+            storage
+                .data_mut()
+                .media
+                .insert(url.to_string(), file_name.to_string());
+        }
+
+        assert_eq!(storage.remove_tweet(1).unwrap(), vec![own.to_string()]);
+        assert!(!storage.media_path("own.jpg").exists());
+        assert!(storage.media_path("shared.jpg").exists());
+        assert_eq!(storage.data().tweets.len(), 1);
+
+        assert_eq!(storage.remove_tweet(2).unwrap(), vec![shared.to_string()]);
+        assert!(!storage.media_path("shared.jpg").exists());
+        assert!(storage.data().media.is_empty());
+        assert!(storage.remove_tweet(2).is_err());
+    }
+
+    #[test]
+    fn removing_a_tweet_removes_the_media_of_its_replies() {
+        let mut storage = storage("remove-replies");
+        let photo = "https://pbs.twimg.com/media/reply.jpg";
+        storage.data_mut().tweets = vec![tweet(1, "question")];
+        let reply = with_photo(tweet(2, "answer"), photo);
+        storage.data_mut().responses.insert(1, vec![reply]);
+        std::fs::write(storage.media_path("reply.jpg"), b"image").unwrap();
+        storage
+            .data_mut()
+            .media
+            .insert(photo.to_string(), "reply.jpg".to_string());
+
+        assert_eq!(storage.remove_tweet(1).unwrap(), vec![photo.to_string()]);
+        assert!(!storage.media_path("reply.jpg").exists());
+        assert!(storage.data().responses.is_empty());
+
+        // The removal is saved
+        let opened = Storage::open(&storage.root_folder).unwrap();
+        assert!(opened.data().tweets.is_empty());
+        assert!(opened.data().media.is_empty());
+    }
+
+    #[test]
+    fn identical_media_files_are_merged_after_saving() {
+        let mut storage = storage("dedup");
+        let files = [
+            ("https://pbs.twimg.com/media/a.jpg", "a.jpg", "same"),
+            (
+                "https://pbs.twimg.com/media/a.jpg?name=large",
+                "b.jpg",
+                "same",
+            ),
+            ("https://pbs.twimg.com/media/c.jpg", "c.jpg", "other"),
+        ];
+        for (url, file_name, contents) in files {
+            std::fs::write(storage.media_path(file_name), contents).unwrap();
+            storage
+                .data_mut()
+                .media
+                .insert(url.to_string(), file_name.to_string());
+        }
+
+        let report = storage.dedup_media().unwrap();
+        assert_eq!(
+            report,
+            MediaDedupReport {
+                files_removed: 1,
+                bytes_reclaimed: 4,
+            }
+        );
+        assert!(!storage.media_path("b.jpg").exists());
+        assert!(storage.media_path("c.jpg").exists());
+
+        // The saved index points both urls at the kept file
+        let opened = Storage::open(&storage.root_folder).unwrap();
+        let media = &opened.data().media;
+        assert_eq!(
+            media["https://pbs.twimg.com/media/a.jpg?name=large"],
+            "a.jpg"
+        );
+        assert_eq!(media["https://pbs.twimg.com/media/a.jpg"], "a.jpg");
+        assert_eq!(media["https://pbs.twimg.com/media/c.jpg"], "c.jpg");
+        assert_eq!(storage.dedup_media().unwrap(), MediaDedupReport::default());
+    }
+
+    #[test]
+    fn only_media_without_references_is_collected() {
+        let mut storage = storage("gc-media");
+        let referenced = "https://pbs.twimg.com/media/referenced.jpg";
+        let unreferenced = "https://pbs.twimg.com/media/unreferenced.jpg";
+        storage.data_mut().tweets = vec![with_photo(tweet(1, "photo"), referenced)];
+        for (url, file_name) in [(referenced, "referenced.jpg"), (unreferenced, "listed.jpg")] {
+            storage
+                .data_mut()
+                .media
+                .insert(url.to_string(), file_name.to_string());
+        }
+        for file_name in [
+            "referenced.jpg",
+            "listed.jpg",
+            "unknown.jpg",
+            "video.mp4.part",
+        ] {
+            std::fs::write(storage.media_path(file_name), "data").unwrap();
+        }
+
+        // The file of the unreferenced url and the unknown file
+        assert_eq!(storage.gc_media().unwrap(), 2);
+        assert!(storage.media_path("referenced.jpg").exists());
+        assert!(!storage.media_path("listed.jpg").exists());
+        assert!(!storage.media_path("unknown.jpg").exists());
+        // A download that can still be continued
+        assert!(storage.media_path("video.mp4.part").exists());
+
+        // The saved index only contains the referenced url
+        let opened = Storage::open(&storage.root_folder).unwrap();
+        let media = &opened.data().media;
+        assert_eq!(media.len(), 1);
+        assert_eq!(media[referenced], "referenced.jpg");
+    }
+
+    #[test]
+    fn orphaned_media_ignores_unknown_and_referenced_urls() {
+        let mut storage = storage("orphans");
+        let kept = "https://pbs.twimg.com/media/kept.jpg";
+        storage.data_mut().tweets = vec![with_photo(tweet(1, "kept"), kept)];
+        storage
+            .data_mut()
+            .media
+            .insert(kept.to_string(), "kept.jpg".to_string());
+        storage.data_mut().media.insert(
+            "https://example.com/gone.jpg".to_string(),
+            "gone.jpg".to_string(),
+        );
+        let candidates = vec![
+            kept.to_string(),
+            "https://example.com/gone.jpg".to_string(),
+            "https://example.com/unknown.jpg".to_string(),
+        ];
+        assert_eq!(
+            storage.data().orphaned_media(&candidates),
+            vec!["https://example.com/gone.jpg".to_string()]
+        );
+    }
+
+    #[test]
+    fn opens_a_version_1_archive() {
+        let mut storage = storage("migrate-v1");
+        storage.data_mut().profiles.insert(2, user(2, "friend"));
+        // Version 1 had no version, likes, metrics or profile timestamps
+        let mut value = serde_json::to_value(storage.data()).unwrap();
+        let object = value.as_object_mut().unwrap();
+        for key in ["version", "likes", "metrics", "profile_seen"] {
+            object.remove(key);
+        }
+        std::fs::write(
+            storage.root_folder.join(backend::FILE_JSON),
+            serde_json::to_vec(&value).unwrap(),
+        )
+        .unwrap();
+
+        let opened = Storage::open(&storage.root_folder).unwrap();
+        assert_eq!(opened.data().version, DATA_VERSION);
+        assert!(opened.data().likes.is_empty());
+        assert!(opened.data().profile_seen.contains_key(&2));
+    }
+
+    #[test]
+    fn refuses_archives_of_newer_versions() {
+        let mut value = serde_json::json!({ "version": DATA_VERSION + 1 });
+        let error = migrate(&mut value).unwrap_err();
+        assert!(error.to_string().contains("Please update TwitVault"));
+    }
+
+    #[test]
+    fn place_of_geotagged_tweets_only() {
+        let mut geotagged = tweet(1, "Here");
+        geotagged.coordinates = Some((13.4, 52.5));
+        geotagged.place = Some(egg_mode::place::Place {
+            id: "3078869807f9dd36".to_string(),
+            attributes: HashMap::new(),
+            bounding_box: vec![(13.1, 52.3), (13.8, 52.3), (13.8, 52.7), (13.1, 52.7)],
+            country: "Germany".to_string(),
+            country_code: "DE".to_string(),
+            full_name: "Berlin, Germany".to_string(),
+            name: "Berlin".to_string(),
+            place_type: egg_mode::place::PlaceType::City,
+            contained_within: None,
+        });
+
+        let place = Place::from_tweet(&geotagged).unwrap();
+        assert_eq!(place.name, "Berlin");
+        assert_eq!(place.full_name, "Berlin, Germany");
+        assert_eq!(place.country_code, "DE");
+        assert_eq!(place.coordinates, Some((13.4, 52.5)));
+        assert_eq!(place.bounding_box.len(), 4);
+
+        assert_eq!(Place::from_tweet(&tweet(2, "Somewhere")), None);
+    }
+
+    #[test]
+    fn space_card_of_a_tweet_sharing_a_space() {
+        let shared = with_url(
+            tweet(1, "Join us https://t.co/space"),
+            "https://t.co/space",
+            "https://twitter.com/i/spaces/1YqGoAbCdEfGh",
+        );
+        let card = SpaceCard::from_tweet(&shared).unwrap();
+        assert_eq!(card.id, "1YqGoAbCdEfGh");
+        assert_eq!(card.url, "https://twitter.com/i/spaces/1YqGoAbCdEfGh");
+        assert_eq!(card.title, None);
+
+        let other = with_url(
+            tweet(2, "Read https://t.co/blog"),
+            "https://t.co/blog",
+            "https://example.com/i/spaces/1",
+        );
+        assert!(SpaceCard::from_tweet(&other).is_none());
+        assert!(SpaceCard::from_tweet(&tweet(3, "No links")).is_none());
+    }
+
+    #[test]
+    fn mentions_index_lists_every_mentioned_user() {
+        let mut storage = storage("mentions-index");
+        let (alice, bob) = (user(2, "alice"), user(3, "bob"));
+        let both = with_mention(with_mention(tweet(1, "@alice @bob"), &alice), &bob);
+        let only_bob = with_mention(tweet(2, "@bob"), &bob);
+        for tweet in [&both, &only_bob] {
+            storage.data_mut().index_entities(tweet);
+        }
+        storage.data_mut().tweets = vec![only_bob, both];
+
+        let ids = |id| -> Vec<u64> {
+            storage
+                .data()
+                .tweets_mentioning(id)
+                .iter()
+                .map(|t| t.id)
+                .collect()
+        };
+        assert_eq!(ids(2), [1]);
+        assert_eq!(ids(3), [2, 1]);
+        assert!(ids(4).is_empty());
+    }
+
+    #[test]
+    fn version_3_archives_get_their_mentions_indexed() {
+        let mut storage = storage("migrate-v3");
+        let mentioned = user(2, "alice");
+        storage.data_mut().tweets = vec![with_mention(tweet(1, "@alice"), &mentioned)];
+        let mut value = serde_json::to_value(storage.data()).unwrap();
+        value["version"] = 3.into();
+        std::fs::write(
+            storage.root_folder.join(backend::FILE_JSON),
+            serde_json::to_vec(&value).unwrap(),
+        )
+        .unwrap();
+
+        let opened = Storage::open(&storage.root_folder).unwrap();
+        assert_eq!(opened.data().version, DATA_VERSION);
+        assert_eq!(opened.data().tweets_mentioning(2).len(), 1);
+    }
+
+    /// A tweet by `@alice` and a tweet of the owner that quotes or retweets it
+    fn relating(id: TweetId, inner_id: TweetId, retweet: bool) -> (Tweet, Tweet) {
+        let inner = Tweet {
+            user: Some(Box::new(user(2, "alice"))),
+            ..tweet(inner_id, "inner")
+        };
+        let mut outer = tweet(id, "outer");
+        if retweet {
+            outer.retweeted_status = Some(Box::new(inner.clone()));
+        } else {
+            outer.quoted_status_id = Some(inner_id);
+            outer.quoted_status = Some(Box::new(inner.clone()));
+        }
+        (outer, inner)
+    }
+
+    #[test]
+    fn quotes_and_retweets_are_indexed_and_stored() {
+        let mut data = storage("relations").data().clone();
+        let (quote, _) = relating(10, 5, false);
+        let (retweet, _) = relating(11, 6, true);
+        data.archive_relations(&quote);
+        data.archive_relations(&retweet);
+
+        assert_eq!(data.quotes, HashMap::from([(10, 5)]));
+        assert_eq!(data.retweets, HashMap::from([(11, 6)]));
+        assert_eq!(data.quoted_tweet(10).map(|t| t.id), Some(5));
+        let ids = |data: &Data| data.tweets.iter().map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(ids(&data), [6, 5]);
+        assert!(data.newest_timeline_tweet().is_none());
+
+        data.add_timeline_tweets(vec![retweet, quote]);
+        assert_eq!(ids(&data), [11, 10, 6, 5]);
+        assert_eq!(data.newest_timeline_tweet().map(|t| t.id), Some(11));
+        assert_eq!(data.timeline_tweet_count(), 2);
+        let quoting: Vec<_> = data.quoted_by(5).iter().map(|t| t.id).collect();
+        assert_eq!(quoting, [10]);
+
+        // Inspecting a tweet twice doesn't store its quoted tweet twice, and
+        // the timeline replaces the copy if it shows up there
+        data.archive_relations(&relating(10, 5, false).0);
+        data.add_timeline_tweets(vec![tweet(5, "mine after all")]);
+        assert_eq!(ids(&data), [11, 10, 6, 5]);
+        assert_eq!(data.inner_tweets, HashSet::from([6]));
+        assert_eq!(data.timeline_tweet_count(), 3);
+    }
+
+    #[test]
+    fn version_4_archives_get_their_quotes_and_retweets_indexed() {
+        let mut storage = storage("migrate-v4");
+        let (quote, _) = relating(10, 5, false);
+        let (retweet, _) = relating(11, 6, true);
+        storage.data_mut().tweets = vec![retweet, quote];
+        let mut value = serde_json::to_value(storage.data()).unwrap();
+        value["version"] = 4.into();
+        std::fs::write(
+            storage.root_folder.join(backend::FILE_JSON),
+            serde_json::to_vec(&value).unwrap(),
+        )
+        .unwrap();
+
+        let opened = Storage::open(&storage.root_folder).unwrap();
+        let data = opened.data();
+        assert_eq!(data.version, DATA_VERSION);
+        assert_eq!(data.quotes.get(&10), Some(&5));
+        assert_eq!(data.retweets.get(&11), Some(&6));
+        assert_eq!(data.inner_tweets, HashSet::from([5, 6]));
+        assert_eq!(opened.stats().tweets, 2);
+    }
+
+    #[test]
+    fn the_preview_opens_as_fast_for_any_archive_size() {
+        let mut sizes = Vec::new();
+        for count in [200, 20_000] {
+            let mut storage = storage(&format!("preview-{count}"));
+            storage.data_mut().tweets = (0..count).rev().map(|id| tweet(id, "tweet")).collect();
+            storage.save().unwrap();
+
+            let started = Instant::now();
+            let preview = Storage::open_preview(&storage.root_folder)
+                .unwrap()
+                .unwrap();
+            assert!(started.elapsed().as_millis() < 1000);
+            assert!(preview.is_preview());
+            assert_eq!(preview.data().tweets.len(), preview::PREVIEW_TWEETS);
+            assert_eq!(preview.data().tweets[0].id, count - 1);
+            assert!(preview.save().is_err());
+            let file = storage.root_folder.join(preview::FILE_PREVIEW);
+            sizes.push(std::fs::metadata(file).unwrap().len());
+        }
+        // Only the ids of the tweets differ
+        assert!(sizes[1] < sizes[0] + sizes[0] / 10);
+    }
+
+    #[test]
+    fn tweets_are_filtered_by_language_with_undetermined_ones_apart() {
+        let in_lang = |id, lang: Option<&str>| {
+            let mut t = tweet(id, "text");
+            t.lang = lang.map(str::to_string);
+            t
+        };
+        let mut storage = storage("languages");
+        let data = storage.data_mut();
+        data.tweets = vec![
+            in_lang(6, Some("en")),
+            in_lang(5, Some("de")),
+            in_lang(4, Some("und")),
+            in_lang(3, None),
+        ];
+        data.mentions = vec![in_lang(2, Some("en")), in_lang(1, Some(""))];
+        // A liked own tweet counts once
+        data.likes = vec![in_lang(6, Some("en"))];
+
+        let ids = |tweets: Vec<&Tweet>| tweets.iter().map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(ids(storage.tweets_in_lang("en")), [6, 2]);
+        assert_eq!(ids(storage.tweets_in_lang("EN")), [6, 2]);
+        assert_eq!(ids(storage.tweets_in_lang("de")), [5]);
+        assert_eq!(ids(storage.tweets_in_lang(UNDETERMINED_LANG)), [4, 3, 1]);
+        assert!(storage.tweets_in_lang("fr").is_empty());
+        assert_eq!(
+            storage.data().languages(),
+            [
+                (UNDETERMINED_LANG.to_string(), 3),
+                ("en".to_string(), 2),
+                ("de".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn hashtags_in_any_case_are_counted_together() {
+        let mut storage = storage("hashtags");
+        let tweets = vec![
+            with_hashtag(tweet(4, "#rust"), "rust"),
+            with_hashtag(tweet(3, "#Rust"), "Rust"),
+            with_hashtag(tweet(2, "#RUST"), "RUST"),
+            with_hashtag(tweet(1, "#Straße"), "STRASSE"),
+            with_hashtag(tweet(0, "#Ünïcode"), "Ünïcode"),
+            // Quoted by the owner, not their own
+            with_hashtag(tweet(5, "#rust"), "rust"),
+        ];
+        for tweet in &tweets {
+            storage.data_mut().index_entities(tweet);
+        }
+        storage.data_mut().tweets = tweets;
+        storage.data_mut().inner_tweets.insert(5);
+
+        let top = storage.data().top_hashtags(2);
+        assert_eq!(top, [("rust".to_string(), 3), ("strasse".to_string(), 1)]);
+        assert_eq!(storage.data().tweets_with_hashtag("#rUsT").len(), 4);
+        assert_eq!(storage.data().tweets_with_hashtag("üNÏCODE").len(), 1);
+    }
+
+    #[test]
+    fn quoted_tweets_are_not_new_tweets_of_the_owner() {
+        let mut storage = storage("backup-diff-inner");
+        storage.data_mut().tweets = vec![tweet(2, "own"), tweet(1, "quoted")];
+        storage.data_mut().inner_tweets.insert(1);
+
+        let since = storage.data().tweets[1].created_at - chrono::Duration::days(1);
+        assert_eq!(storage.diff_since(since).new_tweets, [2]);
+    }
+
+    #[test]
+    fn followers_are_compared_with_the_last_backup() {
+        let mut storage = storage("backup-diff");
+        storage.data_mut().followers = vec![1, 2, 3];
+        storage.record_backup(&Default::default()).unwrap();
+        storage.data_mut().followers = vec![2, 3, 4, 5];
+
+        let diff = storage.diff_since(chrono::Utc::now());
+        assert_eq!(diff.followers_gained, [4, 5]);
+        assert_eq!(diff.followers_lost, [1]);
+
+        // The follower ids are kept out of the archive itself
+        storage.save().unwrap();
+        let data = std::fs::read_to_string(storage.root_folder.join("_data.json")).unwrap();
+        let backups = serde_json::from_str::<serde_json::Value>(&data).unwrap()["backups"].clone();
+        assert!(!backups.to_string().contains("followers"));
+        let snapshot = storage.backup_snapshot(&storage.data().backups[0]);
+        assert_eq!(snapshot.unwrap().followers, [1, 2, 3]);
+    }
+
+    #[test]
+    fn probe_fails_for_a_read_only_media_folder() {
+        let storage = storage("read-only-media");
+        storage.probe_media_folder().unwrap();
+
+        let folder = storage.root_folder.join(FOLDER_MEDIA);
+        let mut permissions = std::fs::metadata(&folder).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&folder, permissions.clone()).unwrap();
+        let result = storage.probe_media_folder();
+        // Privileged users can write into read-only folders anyway
+        let writable = std::fs::write(folder.join("check"), b"").is_ok();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        std::fs::set_permissions(&folder, permissions).unwrap();
+        assert_eq!(result.is_ok(), writable);
+        assert!(!folder.join(".write-probe").exists());
+
+        // A file where the folder should be can't be written into by anyone
+        std::fs::remove_dir_all(&folder).unwrap();
+        std::fs::write(&folder, b"").unwrap();
+        assert!(storage.probe_media_folder().is_err());
+    }
+
+    fn reply(id: TweetId, parent: TweetId) -> Tweet {
+        let mut reply = tweet(id, "reply");
+        reply.in_reply_to_status_id = Some(parent);
+        reply
+    }
+
+    #[test]
+    fn conversations_group_tweets_by_their_conversation_id() {
+        let mut storage = storage("conversations");
+        // 3 replies to 1, but 2 is missing, so the chain has a gap
+        let data = storage.data_mut();
+        data.tweets = vec![reply(4, 3), reply(3, 2), tweet(1, "root")];
+        data.likes = vec![tweet(9, "other")];
+        data.mentions = vec![reply(5, 1)];
+        for id in [1, 3, 4, 5] {
+            data.conversation_ids.insert(id, 1);
+        }
+        data.conversation_ids.insert(9, 9);
+
+        let data = storage.data();
+        let ids = |tweets: Vec<&Tweet>| tweets.iter().map(|t| t.id).collect::<Vec<_>>();
+        let latest = data.any_tweet(4).unwrap();
+        assert_eq!(ids(data.conversation(latest)), [1, 3, 4, 5]);
+        // The sibling 5 is not an ancestor, the root is despite the gap
+        assert_eq!(ids(data.ancestors(latest)), [1, 3]);
+        assert!(data.ancestors(data.any_tweet(1).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn without_conversation_ids_the_reply_chain_is_followed() {
+        let mut storage = storage("reply-chain");
+        let data = storage.data_mut();
+        data.tweets = vec![reply(3, 2), reply(2, 1), tweet(1, "root")];
+        data.responses.insert(3, vec![reply(5, 3)]);
+        data.responses.insert(1, vec![reply(4, 1)]);
+
+        let data = storage.data();
+        let ids = |tweets: Vec<&Tweet>| tweets.iter().map(|t| t.id).collect::<Vec<_>>();
+        let tweet = data.any_tweet(3).unwrap();
+        assert_eq!(ids(data.ancestors(tweet)), [1, 2]);
+        assert_eq!(ids(data.conversation(tweet)), [1, 2, 3, 5]);
+    }
+
+    #[test]
+    fn only_missing_profiles_are_backfilled() {
+        let mut storage = storage("backfill");
+        let data = storage.data_mut();
+        let mut answer = with_mention(tweet(1, "hi"), &user(2, "known"));
+        answer.in_reply_to_user_id = Some(5);
+        data.tweets = vec![
+            answer,
+            with_mention(tweet(2, "hi"), &user(3, "suspended")),
+            with_mention(tweet(3, "hi"), &user(4, "missing")),
+        ];
+        data.likes = vec![Tweet {
+            user: Some(Box::new(user(6, "author"))),
+            ..tweet(4, "liked")
+        }];
+        data.insert_profile(user(2, "known"));
+        data.unavailable_profiles.insert(3, "Suspended".to_string());
+
+        assert_eq!(storage.data().missing_profile_ids(), [4, 5, 6]);
+    }
+
+    #[test]
+    fn reinserted_profiles_only_update_last_seen() {
+        let mut storage = storage("profile-seen");
+        let data = storage.data_mut();
+        data.insert_profile(user(2, "friend"));
+        let first = data.profile_seen[&2];
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        data.insert_profile(user(2, "renamed"));
+
+        let seen = &data.profile_seen[&2];
+        assert_eq!(seen.first_seen, first.first_seen);
+        assert!(seen.last_seen > first.last_seen);
+        assert_eq!(data.profiles[&2].screen_name, "renamed");
+    }
+
+    #[test]
+    fn only_profiles_last_seen_before_the_age_are_stale() {
+        let mut storage = storage("stale-profiles");
+        let data = storage.data_mut();
+        for id in 2..=5 {
+            data.insert_profile(user(id, "friend"));
+        }
+        let now = chrono::Utc::now();
+        let long_ago = now - chrono::Duration::days(40);
+        data.profile_seen.get_mut(&3).unwrap().last_seen = long_ago;
+        // Stored before `profile_seen` existed
+        data.profile_seen.remove(&4);
+        data.profile_seen.get_mut(&5).unwrap().last_seen = long_ago;
+        data.unavailable_profiles.insert(5, "Suspended".to_string());
+
+        let stale = storage
+            .data()
+            .stale_profile_ids(chrono::Duration::days(30), now);
+        assert_eq!(stale, [3, 4]);
+    }
+}
@@ -0,0 +1,112 @@
+//! Where the `Data` of an archive is persisted. The media files are always
+//! stored as files in the `media` folder, independent of the backend
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use egg_mode::tweet::Tweet;
+use eyre::Result;
+
+use super::{Data, TweetId, FOLDER_MEDIA};
+
+/// The archive as one JSON file
+pub const FILE_JSON: &str = "_data.json";
+/// The archive as a SQLite database
+pub const FILE_SQLITE: &str = "_data.sqlite";
+
+pub trait StorageBackend: std::fmt::Debug + Send + Sync {
+    /// Shown to the user, e.g. `JSON`
+    fn name(&self) -> &'static str;
+
+    /// Whether there is an archive to load
+    fn exists(&self) -> bool;
+
+    /// The serialized `Data`. It is returned as a JSON value so that
+    /// archives of older versions can be migrated before deserializing
+    fn load(&self) -> Result<serde_json::Value>;
+
+    /// Blocking write of the whole archive
+    fn save(&self, data: &Data) -> Result<()>;
+
+    /// Blocking write of a single new or changed tweet. Backends that can
+    /// only write the whole archive write it with the next `save`
+    fn upsert_tweet(&self, kind: TweetKind, tweet: &Tweet) -> Result<()>;
+
+    /// Where the downloaded media file `file_name` is stored
+    fn media_path(&self, file_name: &str) -> PathBuf;
+}
+
+/// The list of `Data` that a tweet is stored in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TweetKind {
+    Tweet,
+    Mention,
+    Like,
+    /// A response to the tweet with the id
+    Response(TweetId),
+}
+
+/// The media folder next to the archive file `data_path`
+fn media_folder(data_path: &Path) -> PathBuf {
+    data_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(FOLDER_MEDIA)
+}
+
+/// The backend of an existing archive in `root_folder`. Archives are
+/// JSON unless there is a SQLite database
+pub fn detect(root_folder: &Path) -> Arc<dyn StorageBackend> {
+    #[cfg(feature = "sqlite")]
+    {
+        let sqlite = super::sqlite::SqliteBackend::new(root_folder.join(FILE_SQLITE));
+        if sqlite.exists() {
+            return Arc::new(sqlite);
+        }
+    }
+    Arc::new(JsonBackend::new(root_folder.join(FILE_JSON)))
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonBackend {
+    data_path: PathBuf,
+}
+
+impl JsonBackend {
+    pub fn new(data_path: PathBuf) -> Self {
+        Self { data_path }
+    }
+}
+
+impl StorageBackend for JsonBackend {
+    fn name(&self) -> &'static str {
+        "JSON"
+    }
+
+    fn exists(&self) -> bool {
+        self.data_path.exists()
+    }
+
+    fn load(&self) -> Result<serde_json::Value> {
+        let input = std::fs::read(&self.data_path)?;
+        Ok(serde_json::from_slice(&input)?)
+    }
+
+    fn save(&self, data: &Data) -> Result<()> {
+        use std::fs::OpenOptions;
+        let outfile = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.data_path)?;
+        Ok(serde_json::to_writer(outfile, data)?)
+    }
+
+    fn upsert_tweet(&self, _kind: TweetKind, _tweet: &Tweet) -> Result<()> {
+        // The JSON file can only be written as a whole
+        Ok(())
+    }
+
+    fn media_path(&self, file_name: &str) -> PathBuf {
+        media_folder(&self.data_path).join(file_name)
+    }
+}
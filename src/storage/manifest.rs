@@ -0,0 +1,176 @@
+//! A small description of the archive next to the data, so that tools and
+//! people looking at the folder know what is inside without loading it
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{ArchiveStats, Storage, UserId, DATA_VERSION};
+use crate::config::CrawlOptions;
+
+/// The manifest is written into the archive folder on every save
+pub const FILE_MANIFEST: &str = "manifest.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ArchiveManifest {
+    /// The `DATA_VERSION` of the archive
+    pub schema_version: u32,
+    /// The TwitVault version that wrote the archive
+    pub generator: String,
+    /// e.g. `JSON`
+    pub backend: String,
+    pub owner_id: UserId,
+    pub owner_screen_name: String,
+    pub counts: ManifestCounts,
+    pub media_files: usize,
+    /// The size of the downloaded media, as recorded with the downloads
+    pub media_bytes: u64,
+    /// When the last crawl finished
+    pub crawled_at: Option<DateTime<Utc>>,
+    /// The options of the last crawl
+    pub crawl_options: Option<CrawlOptions>,
+    pub saved_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ManifestCounts {
+    pub tweets: usize,
+    pub mentions: usize,
+    pub responses: usize,
+    pub likes: usize,
+    pub followers: usize,
+    pub follows: usize,
+    pub profiles: usize,
+    pub lists: usize,
+}
+
+impl ArchiveManifest {
+    pub fn new(storage: &Storage) -> Self {
+        let data = storage.data();
+        let ArchiveStats {
+            tweets,
+            mentions,
+            responses,
+            likes,
+            followers,
+            follows,
+            profiles,
+            lists,
+            media_files,
+            media_bytes,
+            ..
+        } = storage.recorded_stats();
+        Self {
+            schema_version: DATA_VERSION,
+            generator: concat!("twitvault ", env!("CARGO_PKG_VERSION")).to_string(),
+            backend: storage.backend_name().to_string(),
+            owner_id: data.profile.id,
+            owner_screen_name: data.profile.screen_name.clone(),
+            counts: ManifestCounts {
+                tweets,
+                mentions,
+                responses,
+                likes,
+                followers,
+                follows,
+                profiles,
+                lists,
+            },
+            media_files,
+            media_bytes,
+            crawled_at: data.backups.last().map(|backup| backup.finished_at),
+            crawl_options: data.crawl_options.clone(),
+            saved_at: Utc::now(),
+        }
+    }
+
+    /// Writes the manifest into `root_folder`. It is written to a temporary
+    /// file first so that readers never see a half written manifest
+    pub fn write(&self, root_folder: &Path) -> Result<()> {
+        let path = root_folder.join(FILE_MANIFEST);
+        let temporary = root_folder.join(format!("{FILE_MANIFEST}.tmp"));
+        std::fs::write(&temporary, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+
+    /// The manifest in `root_folder`, if there is one
+    pub fn read(root_folder: &Path) -> Result<Option<Self>> {
+        let path = root_folder.join(FILE_MANIFEST);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let input = std::fs::read(&path)?;
+        Ok(Some(serde_json::from_slice(&input)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MediaMeta;
+    use crate::test_fixtures::{list, storage, tweet, user};
+
+    #[test]
+    fn the_manifest_describes_the_saved_archive() {
+        let mut storage = storage("manifest");
+        let data = storage.data_mut();
+        data.tweets = vec![tweet(2, "Second"), tweet(1, "First")];
+        data.mentions = vec![tweet(3, "@owner")];
+        data.likes = vec![tweet(4, "Liked")];
+        data.followers = vec![2, 3];
+        data.follows = vec![2];
+        data.profiles.insert(2, user(2, "friend"));
+        data.lists = vec![list(1, "Friends", "", 1, false)];
+        data.crawl_options = Some(CrawlOptions::default());
+        for (url, bytes) in [
+            ("https://pbs.twimg.com/1.jpg", 100),
+            ("https://pbs.twimg.com/2.jpg", 50),
+        ] {
+            data.media.insert(url.to_string(), format!("{bytes}.jpg"));
+            let meta = MediaMeta {
+                bytes,
+                ..MediaMeta::default()
+            };
+            data.media_meta.insert(url.to_string(), meta);
+        }
+        storage.save().unwrap();
+
+        let manifest = ArchiveManifest::read(&storage.root_folder)
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.schema_version, DATA_VERSION);
+        assert_eq!(
+            (manifest.owner_id, manifest.owner_screen_name.as_str()),
+            (1, "owner")
+        );
+        assert_eq!(
+            manifest.counts,
+            ManifestCounts {
+                tweets: 2,
+                mentions: 1,
+                responses: 0,
+                likes: 1,
+                followers: 2,
+                follows: 1,
+                profiles: 1,
+                lists: 1,
+            }
+        );
+        // The sizes are the recorded ones, the files don't exist
+        assert_eq!((manifest.media_files, manifest.media_bytes), (2, 150));
+        assert_eq!(manifest.crawl_options, Some(CrawlOptions::default()));
+    }
+
+    #[test]
+    fn the_archive_is_saved_if_the_manifest_cant_be_written() {
+        let mut storage = storage("manifest-fails");
+        std::fs::create_dir_all(storage.root_folder.join(FILE_MANIFEST).join("blocked")).unwrap();
+        storage.data_mut().tweets = vec![tweet(1, "Saved")];
+        storage.save().unwrap();
+
+        let opened = Storage::open(&storage.root_folder).unwrap();
+        assert_eq!(opened.data().tweets.len(), 1);
+    }
+}
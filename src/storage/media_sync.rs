@@ -0,0 +1,277 @@
+//! Copy the media folder to another location (e.g. a NAS), only
+//! transferring the files that changed since the last sync
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+/// The manifest of the previous sync is kept in the destination
+const MANIFEST_FILE: &str = ".twitvault-manifest.json";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MediaManifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The path relative to the media folder
+    pub path: String,
+    pub size: u64,
+    /// The modification time in seconds since the epoch. While it and the
+    /// size are unchanged, the file isn't hashed again
+    #[serde(default)]
+    pub modified: Option<u64>,
+    /// The SHA-256 of the file contents
+    pub hash: String,
+}
+
+/// What a `sync_media` run did
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MediaSyncReport {
+    pub copied: usize,
+    pub unchanged: usize,
+    pub deleted: usize,
+}
+
+impl std::fmt::Display for MediaSyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "copied: {}", self.copied)?;
+        writeln!(f, "unchanged: {}", self.unchanged)?;
+        writeln!(f, "deleted: {}", self.deleted)
+    }
+}
+
+impl MediaManifest {
+    /// Size and hash of all files in the media folder. The hashes of the
+    /// `previous` manifest are reused for files with the same size and
+    /// modification time
+    pub fn for_folder(folder: &Path, previous: &MediaManifest) -> Result<Self> {
+        let previous: HashMap<&str, &ManifestEntry> = previous
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(folder)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let path = entry.file_name().to_string_lossy().to_string();
+            // A download in progress, see `crawler::download_file`
+            if path.ends_with(".part") {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            let size = metadata.len();
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|since_epoch| since_epoch.as_secs());
+            let hash = match previous.get(path.as_str()) {
+                Some(known)
+                    if known.size == size && modified.is_some() && known.modified == modified =>
+                {
+                    known.hash.clone()
+                }
+                _ => hash_file(&entry.path())?,
+            };
+            entries.push(ManifestEntry {
+                path,
+                size,
+                modified,
+                hash,
+            });
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self { entries })
+    }
+
+    /// The manifest of the previous sync into `destination`, empty if there
+    /// was none
+    pub fn at_destination(destination: &Path) -> Self {
+        Self::read(&destination.join(MANIFEST_FILE))
+    }
+
+    fn read(path: &Path) -> Self {
+        std::fs::File::open(path)
+            .map_err(|e| eyre::eyre!("{e:?}"))
+            .and_then(|f| serde_json::from_reader(f).map_err(|e| eyre::eyre!("{e:?}")))
+            .unwrap_or_default()
+    }
+}
+
+/// Copy the files of the manifest from `source` into `destination` if they
+/// are new or changed since the `previous` sync. Files that are no longer in
+/// the manifest are deleted at the destination if `delete_removed` is set
+pub fn sync_media(
+    manifest: &MediaManifest,
+    previous: &MediaManifest,
+    source: &Path,
+    destination: &Path,
+    delete_removed: bool,
+) -> Result<MediaSyncReport> {
+    std::fs::create_dir_all(destination)?;
+    let manifest_path = destination.join(MANIFEST_FILE);
+    let previous: HashMap<String, ManifestEntry> = previous
+        .entries
+        .iter()
+        .map(|entry| (entry.path.clone(), entry.clone()))
+        .collect();
+
+    let mut report = MediaSyncReport::default();
+    for entry in &manifest.entries {
+        let target = destination.join(&entry.path);
+        let unchanged = previous
+            .get(&entry.path)
+            .map(|known| known.size == entry.size && known.hash == entry.hash)
+            .unwrap_or(false);
+        if unchanged && target.exists() {
+            report.unchanged += 1;
+            continue;
+        }
+        std::fs::copy(source.join(&entry.path), &target)?;
+        report.copied += 1;
+    }
+
+    let current: HashSet<&str> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+    if delete_removed {
+        for path in previous
+            .keys()
+            .filter(|path| !current.contains(path.as_str()))
+        {
+            let target = destination.join(path);
+            if !target.exists() {
+                continue;
+            }
+            match std::fs::remove_file(&target) {
+                Ok(_) => report.deleted += 1,
+                Err(e) => warn!("Could not remove {}: {e:?}", target.display()),
+            }
+        }
+    }
+
+    // Without deleting, the removed files stay part of the destination
+    let mut written = manifest.clone();
+    if !delete_removed {
+        written.entries.extend(
+            previous
+                .values()
+                .filter(|entry| !current.contains(entry.path.as_str()))
+                .cloned(),
+        );
+    }
+    serde_json::to_writer(std::fs::File::create(manifest_path)?, &written)?;
+    Ok(report)
+}
+
+pub(super) fn hash_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Whether the files have the same contents, compared byte by byte
+pub(super) fn files_equal(a: &Path, b: &Path) -> Result<bool> {
+    let (mut a, mut b) = (std::fs::File::open(a)?, std::fs::File::open(b)?);
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    let mut buffer_a = [0u8; 64 * 1024];
+    let mut buffer_b = [0u8; 64 * 1024];
+    loop {
+        let read = a.read(&mut buffer_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buffer_b[..read])?;
+        if buffer_a[..read] != buffer_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::temp_folder;
+
+    #[test]
+    fn hashes_are_sha256() {
+        let folder = temp_folder("sha256");
+        let path = folder.join("abc.txt");
+        std::fs::write(&path, "abc").unwrap();
+        assert_eq!(
+            hash_file(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn unchanged_files_are_not_hashed_again() {
+        let folder = temp_folder("manifest-cache");
+        std::fs::write(folder.join("1.jpg"), "image").unwrap();
+        let first = MediaManifest::for_folder(&folder, &MediaManifest::default()).unwrap();
+        assert_eq!(first.entries.len(), 1);
+        assert!(first.entries[0].modified.is_some());
+
+        // A cached hash is only used while size and modification time match
+        let mut cached = first.clone();
+        cached.entries[0].hash = "cached".to_string();
+        let second = MediaManifest::for_folder(&folder, &cached).unwrap();
+        assert_eq!(second.entries[0].hash, "cached");
+
+        cached.entries[0].size += 1;
+        let third = MediaManifest::for_folder(&folder, &cached).unwrap();
+        assert_eq!(third.entries[0].hash, first.entries[0].hash);
+    }
+
+    #[test]
+    fn only_new_and_changed_files_are_copied() {
+        let source = temp_folder("sync-source");
+        let destination = temp_folder("sync-destination");
+        std::fs::write(source.join("1.jpg"), "one").unwrap();
+        std::fs::write(source.join("2.jpg"), "two").unwrap();
+        let sync = || {
+            let previous = MediaManifest::at_destination(&destination);
+            let manifest = MediaManifest::for_folder(&source, &previous).unwrap();
+            sync_media(&manifest, &previous, &source, &destination, true).unwrap()
+        };
+
+        assert_eq!(sync().copied, 2);
+        assert_eq!(sync().unchanged, 2);
+
+        std::fs::write(source.join("2.jpg"), "2").unwrap();
+        std::fs::remove_file(source.join("1.jpg")).unwrap();
+        let report = sync();
+        assert_eq!((report.copied, report.deleted), (1, 1));
+        assert_eq!(std::fs::read(destination.join("2.jpg")).unwrap(), b"2");
+        assert!(!destination.join("1.jpg").exists());
+    }
+
+    #[test]
+    fn partial_downloads_are_not_synced() {
+        let folder = temp_folder("manifest-part");
+        std::fs::write(folder.join("1.jpg"), "image").unwrap();
+        std::fs::write(folder.join("2.mp4.part"), "vid").unwrap();
+
+        let manifest = MediaManifest::for_folder(&folder, &MediaManifest::default()).unwrap();
+        let paths: Vec<&str> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["1.jpg"]);
+    }
+}
@@ -0,0 +1,69 @@
+//! The newest tweets of the archive with their media, written next to the
+//! data on every save. Opening it takes the same time for any archive size,
+//! so the UI shows it while the whole archive loads in the background
+use std::path::Path;
+
+use eyre::Result;
+
+use super::Data;
+use crate::helpers::media_urls_in_tweet;
+
+pub const FILE_PREVIEW: &str = "preview.json";
+
+/// The number of tweets in the preview, enough for the first pages of the
+/// tweet list
+pub const PREVIEW_TWEETS: usize = 100;
+
+/// The profile of the owner and the newest `PREVIEW_TWEETS` tweets of `data`
+pub fn preview_data(data: &Data) -> Data {
+    let mut preview = Data::new(data.profile.clone());
+    preview.version = data.version;
+    preview.crawl_options = data.crawl_options.clone();
+    preview.tweets = data
+        .tweets
+        .iter()
+        .filter(|t| !data.inner_tweets.contains(&t.id))
+        .take(PREVIEW_TWEETS)
+        .cloned()
+        .collect();
+    for tweet in &preview.tweets {
+        for url in media_urls_in_tweet(tweet) {
+            if let Some(file_name) = data.media.get(&url) {
+                preview.media.insert(url.clone(), file_name.clone());
+            }
+            if let Some(meta) = data.media_meta.get(&url) {
+                preview.media_meta.insert(url, meta.clone());
+            }
+        }
+        if let Some(text) = data.expanded_texts.get(&tweet.id) {
+            preview.expanded_texts.insert(tweet.id, text.clone());
+        }
+        if let Some(card) = data.cards.get(&tweet.id) {
+            preview.cards.insert(tweet.id, card.clone());
+        }
+        if let Some(poll) = data.polls.get(&tweet.id) {
+            preview.polls.insert(tweet.id, poll.clone());
+        }
+    }
+    preview
+}
+
+/// Writes the preview of `data` into `root_folder`, through a temporary
+/// file like the manifest
+pub fn write(data: &Data, root_folder: &Path) -> Result<()> {
+    let path = root_folder.join(FILE_PREVIEW);
+    let temporary = root_folder.join(format!("{FILE_PREVIEW}.tmp"));
+    std::fs::write(&temporary, serde_json::to_vec(&preview_data(data))?)?;
+    std::fs::rename(&temporary, &path)?;
+    Ok(())
+}
+
+/// The preview in `root_folder`, if there is one
+pub fn read(root_folder: &Path) -> Result<Option<Data>> {
+    let path = root_folder.join(FILE_PREVIEW);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let input = std::fs::read(&path)?;
+    Ok(Some(serde_json::from_slice(&input)?))
+}
@@ -0,0 +1,467 @@
+//! Stores the archive in a SQLite database, so that the tweets, profiles and
+//! media can be queried with SQL. Everything else is kept as one JSON
+//! document in the `archive` table
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use egg_mode::tweet::Tweet;
+use eyre::{eyre, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde_json::{Map, Value};
+
+use super::backend::{StorageBackend, TweetKind};
+use super::{Data, UrlString, UserId, FOLDER_MEDIA};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS archive (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    version INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS tweets (
+    id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    -- The tweet a response responds to
+    parent_id INTEGER,
+    position INTEGER NOT NULL,
+    user_id INTEGER,
+    created_at TEXT NOT NULL,
+    text TEXT NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS tweets_by_kind ON tweets (kind, parent_id, position);
+CREATE INDEX IF NOT EXISTS tweets_by_id ON tweets (id);
+CREATE TABLE IF NOT EXISTS profiles (
+    id INTEGER PRIMARY KEY,
+    screen_name TEXT NOT NULL,
+    name TEXT NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS media (
+    url TEXT PRIMARY KEY,
+    path TEXT NOT NULL
+);
+";
+
+/// The `Data` fields that are stored in their own tables
+const TWEET_KINDS: [&str; 3] = ["tweets", "mentions", "likes"];
+const RESPONSES: &str = "responses";
+const PROFILES: &str = "profiles";
+const MEDIA: &str = "media";
+
+/// A tweet row: its kind, the tweet it responds to and its id
+type TweetKey = (&'static str, Option<u64>, u64);
+
+/// What the database contains, so that a save only writes the rows that
+/// changed. Tweets are stored with their position and the hash of their JSON
+#[derive(Debug, Default)]
+struct Written {
+    tweets: HashMap<TweetKey, (i64, u64)>,
+    profiles: HashMap<UserId, u64>,
+    media: HashMap<UrlString, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteBackend {
+    path: PathBuf,
+    /// `None` until the archive was loaded or saved once
+    written: Arc<Mutex<Option<Written>>>,
+}
+
+impl SqliteBackend {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            written: Default::default(),
+        }
+    }
+
+    fn connection(&self) -> Result<Connection> {
+        let connection = Connection::open(&self.path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(connection)
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    fn name(&self) -> &'static str {
+        "SQLite"
+    }
+
+    fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    fn load(&self) -> Result<Value> {
+        let connection = self.connection()?;
+        let document: Option<String> = connection
+            .query_row("SELECT data FROM archive WHERE id = 0", [], |row| row.get(0))
+            .optional()?;
+        let Some(document) = document else {
+            return Err(eyre!("The database {} contains no archive", self.path.display()))
+        };
+        let mut value: Value = serde_json::from_str(&document)?;
+        let Some(object) = value.as_object_mut() else {
+            return Err(eyre!("The archive in {} is invalid", self.path.display()))
+        };
+
+        let mut written = Written::default();
+        let mut statement = connection.prepare(
+            "SELECT kind, parent_id, json, id, position FROM tweets ORDER BY kind, parent_id, position",
+        )?;
+        let mut rows = statement.query([])?;
+        let mut responses = Map::new();
+        while let Some(row) = rows.next()? {
+            let kind: String = row.get(0)?;
+            let parent_id: Option<i64> = row.get(1)?;
+            let json: String = row.get(2)?;
+            let id: i64 = row.get(3)?;
+            let position: i64 = row.get(4)?;
+            if let Some(kind) = known_kind(&kind) {
+                let key = (kind, parent_id.map(|id| id as u64), id as u64);
+                written.tweets.insert(key, (position, hash(&json)));
+            }
+            let tweet: Value = serde_json::from_str(&json)?;
+            let tweets = match parent_id {
+                Some(parent_id) if kind == RESPONSES => responses
+                    .entry(parent_id.to_string())
+                    .or_insert_with(|| Value::Array(Vec::new())),
+                _ => object
+                    .entry(kind)
+                    .or_insert_with(|| Value::Array(Vec::new())),
+            };
+            if let Some(tweets) = tweets.as_array_mut() {
+                tweets.push(tweet);
+            }
+        }
+        object.insert(RESPONSES.to_string(), Value::Object(responses));
+
+        let mut statement = connection.prepare("SELECT id, json FROM profiles")?;
+        let mut rows = statement.query([])?;
+        let mut profiles = Map::new();
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let json: String = row.get(1)?;
+            written.profiles.insert(id as u64, hash(&json));
+            profiles.insert(id.to_string(), serde_json::from_str(&json)?);
+        }
+        object.insert(PROFILES.to_string(), Value::Object(profiles));
+
+        let mut statement = connection.prepare("SELECT url, path FROM media")?;
+        let mut rows = statement.query([])?;
+        let mut media = Map::new();
+        while let Some(row) = rows.next()? {
+            let url: String = row.get(0)?;
+            let path: String = row.get(1)?;
+            written.media.insert(url.clone(), path.clone());
+            media.insert(url, Value::String(path));
+        }
+        object.insert(MEDIA.to_string(), Value::Object(media));
+
+        for kind in TWEET_KINDS {
+            object
+                .entry(kind)
+                .or_insert_with(|| Value::Array(Vec::new()));
+        }
+        *self.written.lock().unwrap() = Some(written);
+        Ok(value)
+    }
+
+    fn save(&self, data: &Data) -> Result<()> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction()?;
+        let mut written = self.written.lock().unwrap();
+        // Without knowing what the database contains, everything is rewritten
+        let previous = match written.take() {
+            Some(previous) => previous,
+            None => {
+                transaction.execute_batch(
+                    "DELETE FROM tweets; DELETE FROM profiles; DELETE FROM media;",
+                )?;
+                Written::default()
+            }
+        };
+        let current = write_changes(&transaction, data, previous)?;
+
+        // Everything that doesn't have its own table
+        let mut document = serde_json::to_value(data)?;
+        if let Some(object) = document.as_object_mut() {
+            for key in TWEET_KINDS.into_iter().chain([RESPONSES, PROFILES, MEDIA]) {
+                object.remove(key);
+            }
+        }
+        transaction.execute(
+            "INSERT OR REPLACE INTO archive (id, version, data) VALUES (0, ?1, ?2)",
+            params![data.version, serde_json::to_string(&document)?],
+        )?;
+        transaction.commit()?;
+        *written = Some(current);
+        Ok(())
+    }
+
+    fn upsert_tweet(&self, kind: TweetKind, tweet: &Tweet) -> Result<()> {
+        let connection = self.connection()?;
+        let (kind, parent_id) = match kind {
+            TweetKind::Tweet => (TWEET_KINDS[0], None),
+            TweetKind::Mention => (TWEET_KINDS[1], None),
+            TweetKind::Like => (TWEET_KINDS[2], None),
+            TweetKind::Response(parent_id) => (RESPONSES, Some(parent_id)),
+        };
+        let key = (kind, parent_id, tweet.id);
+        let json = serde_json::to_string(tweet)?;
+        let mut written = self.written.lock().unwrap();
+        let known = written
+            .as_ref()
+            .and_then(|written| written.tweets.get(&key))
+            .map(|(position, _)| *position);
+        // New tweets are the newest ones, so they go first
+        let position = match known {
+            Some(position) => position,
+            None => connection.query_row(
+                "SELECT IFNULL(MIN(position), 0) - 1 FROM tweets WHERE kind = ?1 AND parent_id IS ?2",
+                params![kind, parent_id.map(|id| id as i64)],
+                |row| row.get(0),
+            )?,
+        };
+        write_tweet(&connection, key, position, tweet, &json)?;
+        if let Some(written) = written.as_mut() {
+            written.tweets.insert(key, (position, hash(&json)));
+        }
+        Ok(())
+    }
+
+    fn media_path(&self, file_name: &str) -> PathBuf {
+        self.path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(FOLDER_MEDIA)
+            .join(file_name)
+    }
+}
+
+/// Writes the rows that differ from `previous` and deletes the ones that
+/// are gone. Returns what the database contains afterwards
+fn write_changes(transaction: &Transaction, data: &Data, previous: Written) -> Result<Written> {
+    let mut current = Written::default();
+
+    let lists = TWEET_KINDS
+        .into_iter()
+        .zip([&data.tweets, &data.mentions, &data.likes])
+        .map(|(kind, tweets)| (kind, None, tweets))
+        .chain(
+            data.responses
+                .iter()
+                .map(|(parent_id, tweets)| (RESPONSES, Some(*parent_id), tweets)),
+        );
+    for (kind, parent_id, tweets) in lists {
+        // Counted from the end, so that adding newer tweets at the front
+        // keeps the positions of the older ones
+        let len = tweets.len() as i64;
+        for (index, tweet) in tweets.iter().enumerate() {
+            let key = (kind, parent_id, tweet.id);
+            let position = index as i64 - len;
+            let json = serde_json::to_string(tweet)?;
+            let entry = (position, hash(&json));
+            if previous.tweets.get(&key) != Some(&entry) {
+                write_tweet(transaction, key, position, tweet, &json)?;
+            }
+            current.tweets.insert(key, entry);
+        }
+    }
+    for (kind, parent_id, id) in previous.tweets.keys() {
+        if !current.tweets.contains_key(&(*kind, *parent_id, *id)) {
+            transaction.execute(
+                "DELETE FROM tweets WHERE kind = ?1 AND parent_id IS ?2 AND id = ?3",
+                params![kind, parent_id.map(|id| id as i64), *id as i64],
+            )?;
+        }
+    }
+
+    for profile in data.profiles.values() {
+        let json = serde_json::to_string(profile)?;
+        let json_hash = hash(&json);
+        if previous.profiles.get(&profile.id) != Some(&json_hash) {
+            transaction.execute(
+                "INSERT OR REPLACE INTO profiles (id, screen_name, name, json) VALUES (?1, ?2, ?3, ?4)",
+                params![profile.id as i64, profile.screen_name, profile.name, json],
+            )?;
+        }
+        current.profiles.insert(profile.id, json_hash);
+    }
+    for id in previous.profiles.keys() {
+        if !current.profiles.contains_key(id) {
+            transaction.execute("DELETE FROM profiles WHERE id = ?1", params![*id as i64])?;
+        }
+    }
+
+    for (url, path) in &data.media {
+        if previous.media.get(url) != Some(path) {
+            transaction.execute(
+                "INSERT OR REPLACE INTO media (url, path) VALUES (?1, ?2)",
+                params![url, path],
+            )?;
+        }
+        current.media.insert(url.clone(), path.clone());
+    }
+    for url in previous.media.keys() {
+        if !current.media.contains_key(url) {
+            transaction.execute("DELETE FROM media WHERE url = ?1", params![url])?;
+        }
+    }
+    Ok(current)
+}
+
+/// Replaces the row of the tweet, or inserts it
+fn write_tweet(
+    connection: &Connection,
+    (kind, parent_id, id): TweetKey,
+    position: i64,
+    tweet: &Tweet,
+    json: &str,
+) -> Result<()> {
+    let parent_id = parent_id.map(|id| id as i64);
+    connection
+        .prepare_cached("DELETE FROM tweets WHERE kind = ?1 AND parent_id IS ?2 AND id = ?3")?
+        .execute(params![kind, parent_id, id as i64])?;
+    connection
+        .prepare_cached(
+            "INSERT INTO tweets (id, kind, parent_id, position, user_id, created_at, text, json)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )?
+        .execute(params![
+            id as i64,
+            kind,
+            parent_id,
+            position,
+            tweet.user.as_ref().map(|user| user.id as i64),
+            tweet.created_at.to_rfc3339(),
+            tweet.text,
+            json
+        ])?;
+    Ok(())
+}
+
+/// The `&'static` name of a tweet kind read from the database
+fn known_kind(kind: &str) -> Option<&'static str> {
+    TWEET_KINDS
+        .into_iter()
+        .chain([RESPONSES])
+        .find(|known| *known == kind)
+}
+
+/// Only compared within one run, so the hasher may change between versions
+fn hash(json: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    json.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::backend::FILE_SQLITE;
+    use crate::storage::Storage;
+    use crate::test_fixtures::{storage, tweet, user};
+
+    fn ids(tweets: &[Tweet]) -> Vec<u64> {
+        tweets.iter().map(|tweet| tweet.id).collect()
+    }
+
+    fn sqlite_storage(name: &str) -> (Storage, SqliteBackend) {
+        let mut storage = storage(name);
+        let backend = SqliteBackend::new(storage.root_folder.join(FILE_SQLITE));
+        storage.convert_to(Arc::new(backend.clone())).unwrap();
+        (storage, backend)
+    }
+
+    #[test]
+    fn archives_round_trip() {
+        let (mut storage, _) = sqlite_storage("sqlite-round-trip");
+        let data = storage.data_mut();
+        data.tweets = vec![tweet(3, "third"), tweet(1, "first"), tweet(2, "second")];
+        data.mentions = vec![tweet(10, "@owner hi")];
+        data.likes = vec![tweet(20, "liked")];
+        data.responses.insert(3, vec![tweet(31, "reply")]);
+        data.profiles.insert(2, user(2, "friend"));
+        data.media.insert(
+            "https://pbs.twimg.com/a.jpg".to_string(),
+            "a.jpg".to_string(),
+        );
+        data.followers = vec![2];
+        storage.save().unwrap();
+
+        let opened = Storage::open(&storage.root_folder).unwrap();
+        assert_eq!(opened.backend_name(), "SQLite");
+        let data = opened.data();
+        assert_eq!(ids(&data.tweets), [3, 1, 2]);
+        assert_eq!(ids(&data.mentions), [10]);
+        assert_eq!(ids(&data.likes), [20]);
+        assert_eq!(ids(&data.responses[&3]), [31]);
+        assert_eq!(data.profiles[&2].screen_name, "friend");
+        assert_eq!(data.media["https://pbs.twimg.com/a.jpg"], "a.jpg");
+        assert_eq!(data.followers, [2]);
+    }
+
+    #[test]
+    fn saves_only_write_the_changes() {
+        let (mut storage, backend) = sqlite_storage("sqlite-incremental");
+        storage.data_mut().tweets = vec![tweet(3, "three"), tweet(2, "two"), tweet(1, "one")];
+        storage.save().unwrap();
+
+        // An untouched row that is written again would lose this text
+        let connection = backend.connection().unwrap();
+        connection
+            .execute("UPDATE tweets SET text = 'untouched' WHERE id = 1", [])
+            .unwrap();
+
+        let tweets = &mut storage.data_mut().tweets;
+        tweets.insert(0, tweet(4, "four"));
+        tweets[1].text = "three, edited".to_string();
+        tweets.retain(|tweet| tweet.id != 2);
+        storage.save().unwrap();
+
+        let text = |id: i64| -> Option<String> {
+            connection
+                .query_row("SELECT text FROM tweets WHERE id = ?1", [id], |row| {
+                    row.get(0)
+                })
+                .optional()
+                .unwrap()
+        };
+        assert_eq!(text(1).as_deref(), Some("untouched"));
+        assert_eq!(text(2), None);
+        assert_eq!(text(3).as_deref(), Some("three, edited"));
+        assert_eq!(text(4).as_deref(), Some("four"));
+
+        let opened = Storage::open(&storage.root_folder).unwrap();
+        assert_eq!(ids(&opened.data().tweets), [4, 3, 1]);
+    }
+
+    #[test]
+    fn upserted_tweets_are_written_right_away() {
+        let (mut storage, _) = sqlite_storage("sqlite-upsert");
+        let replies = vec![tweet(5, "reply")];
+        storage.data_mut().responses.insert(1, replies);
+        storage.save().unwrap();
+
+        let mut edited = tweet(5, "reply, edited");
+        edited.favorite_count = 3;
+        let newer = tweet(6, "newer reply");
+        let kind = TweetKind::Response(1);
+        storage.upsert_tweet(kind, edited).unwrap();
+        storage.upsert_tweet(kind, newer).unwrap();
+        let liked = tweet(7, "liked");
+        storage.upsert_tweet(TweetKind::Like, liked).unwrap();
+
+        // Without saving the storage
+        let opened = Storage::open(&storage.root_folder).unwrap();
+        let responses = &opened.data().responses[&1];
+        assert_eq!(ids(responses), [6, 5]);
+        assert_eq!(responses[1].favorite_count, 3);
+        assert_eq!(ids(&opened.data().likes), [7]);
+        assert_eq!(ids(&storage.data().responses[&1]), [6, 5]);
+    }
+}
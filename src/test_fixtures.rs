@@ -0,0 +1,241 @@
+//! Tweets, profiles and archives for the unit tests
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::TimeZone;
+use egg_mode::entities::{
+    HashtagEntity, MediaEntity, MediaSize, MediaSizes, MediaType, MentionEntity, ResizeMode,
+    UrlEntity, VideoInfo, VideoVariant,
+};
+use egg_mode::tweet::{ExtendedTweetEntities, Tweet, TweetEntities};
+use egg_mode::user::{TwitterUser, UserEntities, UserEntityDetail};
+
+use crate::storage::{List, Storage};
+
+/// The owner of the archives of `storage` and author of `tweet`
+pub const OWNER_ID: u64 = 1;
+
+pub fn user(id: u64, screen_name: &str) -> TwitterUser {
+    TwitterUser {
+        contributors_enabled: false,
+        created_at: chrono::Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+        default_profile: true,
+        default_profile_image: true,
+        description: None,
+        entities: UserEntities {
+            description: UserEntityDetail { urls: Vec::new() },
+            url: None,
+        },
+        favourites_count: 0,
+        follow_request_sent: None,
+        followers_count: 0,
+        friends_count: 0,
+        geo_enabled: false,
+        id,
+        is_translator: false,
+        lang: None,
+        listed_count: 0,
+        location: None,
+        name: screen_name.to_string(),
+        profile_background_color: String::new(),
+        profile_background_image_url: None,
+        profile_background_image_url_https: None,
+        profile_background_tile: None,
+        profile_banner_url: None,
+        profile_image_url: format!("http://pbs.twimg.com/profile_images/{id}.jpg"),
+        profile_image_url_https: format!("https://pbs.twimg.com/profile_images/{id}.jpg"),
+        profile_link_color: String::new(),
+        profile_sidebar_border_color: String::new(),
+        profile_sidebar_fill_color: String::new(),
+        profile_text_color: String::new(),
+        profile_use_background_image: false,
+        protected: false,
+        screen_name: screen_name.to_string(),
+        show_all_inline_media: None,
+        status: None,
+        statuses_count: 0,
+        time_zone: None,
+        url: None,
+        utc_offset: None,
+        verified: false,
+        withheld_in_countries: None,
+        withheld_scope: None,
+    }
+}
+
+/// A tweet of the owner without entities. Higher ids are newer
+pub fn tweet(id: u64, text: &str) -> Tweet {
+    Tweet {
+        coordinates: None,
+        created_at: chrono::Utc
+            .timestamp_opt(1_600_000_000 + id as i64 * 60, 0)
+            .unwrap(),
+        current_user_retweet: None,
+        display_text_range: None,
+        entities: TweetEntities {
+            hashtags: Vec::new(),
+            symbols: Vec::new(),
+            urls: Vec::new(),
+            user_mentions: Vec::new(),
+            media: None,
+        },
+        extended_entities: None,
+        favorite_count: 0,
+        favorited: None,
+        filter_level: None,
+        id,
+        in_reply_to_user_id: None,
+        in_reply_to_screen_name: None,
+        in_reply_to_status_id: None,
+        lang: None,
+        place: None,
+        possibly_sensitive: None,
+        quoted_status_id: None,
+        quoted_status: None,
+        retweet_count: 0,
+        retweeted: None,
+        retweeted_status: None,
+        source: None,
+        text: text.to_string(),
+        truncated: false,
+        user: Some(Box::new(user(OWNER_ID, "owner"))),
+        withheld_copyright: false,
+        withheld_in_countries: None,
+        withheld_scope: None,
+    }
+}
+
+/// `tweet` mentioning `mentioned`
+pub fn with_mention(mut tweet: Tweet, mentioned: &TwitterUser) -> Tweet {
+    tweet.entities.user_mentions.push(MentionEntity {
+        id: mentioned.id,
+        range: (0, mentioned.screen_name.len() + 1),
+        name: mentioned.name.clone(),
+        screen_name: mentioned.screen_name.clone(),
+    });
+    tweet
+}
+
+/// `tweet` with the hashtag `tag`, without the `#`
+pub fn with_hashtag(mut tweet: Tweet, tag: &str) -> Tweet {
+    tweet.entities.hashtags.push(HashtagEntity {
+        range: (0, tag.len() + 1),
+        text: tag.to_string(),
+    });
+    tweet
+}
+
+/// `tweet` with a link from `short_url`, which its text contains, to
+/// `expanded_url`
+pub fn with_url(mut tweet: Tweet, short_url: &str, expanded_url: &str) -> Tweet {
+    let start = tweet.text.find(short_url).unwrap();
+    tweet.entities.urls.push(UrlEntity {
+        display_url: expanded_url.trim_start_matches("https://").to_string(),
+        expanded_url: Some(expanded_url.to_string()),
+        range: (start, start + short_url.len()),
+        url: short_url.to_string(),
+    });
+    tweet
+}
+
+/// `tweet` with an image at `media_url`
+pub fn with_photo(tweet: Tweet, media_url: &str) -> Tweet {
+    with_media(tweet, media(media_url, MediaType::Photo, None))
+}
+
+/// `tweet` with a video, `variant_url` is its only mp4 variant
+pub fn with_video(tweet: Tweet, thumbnail_url: &str, variant_url: &str) -> Tweet {
+    let video_info = VideoInfo {
+        aspect_ratio: (16, 9),
+        duration_millis: Some(1000),
+        variants: vec![VideoVariant {
+            bitrate: Some(832_000),
+            content_type: "video/mp4".parse().unwrap(),
+            url: variant_url.to_string(),
+        }],
+    };
+    with_media(
+        tweet,
+        media(thumbnail_url, MediaType::Video, Some(video_info)),
+    )
+}
+
+fn with_media(mut tweet: Tweet, entity: MediaEntity) -> Tweet {
+    tweet
+        .entities
+        .media
+        .get_or_insert_with(Vec::new)
+        .push(entity.clone());
+    tweet
+        .extended_entities
+        .get_or_insert_with(|| ExtendedTweetEntities { media: Vec::new() })
+        .media
+        .push(entity);
+    tweet
+}
+
+fn media(media_url: &str, media_type: MediaType, video_info: Option<VideoInfo>) -> MediaEntity {
+    let size = MediaSize {
+        w: 100,
+        h: 100,
+        resize: ResizeMode::Fit,
+    };
+    MediaEntity {
+        display_url: "pic.twitter.com/abc".to_string(),
+        expanded_url: "https://twitter.com/owner/status/1/photo/1".to_string(),
+        id: 1,
+        range: (0, 23),
+        media_url: media_url.to_string(),
+        media_url_https: media_url.to_string(),
+        sizes: MediaSizes {
+            thumb: size,
+            small: size,
+            medium: size,
+            large: size,
+        },
+        source_status_id: None,
+        media_type,
+        url: "https://t.co/abc".to_string(),
+        video_info,
+        ext_alt_text: None,
+    }
+}
+
+/// A list of the owner with `member_count` members and no subscribers
+pub fn list(id: u64, name: &str, description: &str, member_count: u64, private: bool) -> List {
+    List {
+        name: name.to_string(),
+        list: egg_mode::list::List {
+            name: name.to_string(),
+            user: user(OWNER_ID, "owner"),
+            slug: name.to_lowercase(),
+            id,
+            subscriber_count: 0,
+            member_count,
+            full_name: format!("@owner/{}", name.to_lowercase()),
+            description: description.to_string(),
+            uri: format!("/owner/lists/{}", name.to_lowercase()),
+            created_at: chrono::Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+        },
+        members: Vec::new(),
+        private,
+    }
+}
+
+/// An empty folder in the temp directory, a new one for every call
+pub fn temp_folder(name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let folder = std::env::temp_dir().join(format!(
+        "twitvault-test-{name}-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let _ = std::fs::remove_dir_all(&folder);
+    std::fs::create_dir_all(&folder).unwrap();
+    folder
+}
+
+/// An empty archive of `@owner` in a new temporary folder
+pub fn storage(name: &str) -> Storage {
+    Storage::new(user(OWNER_ID, "owner"), temp_folder(name)).unwrap()
+}
@@ -1,5 +1,6 @@
 use eyre::Report;
 
+use crate::estimate::CrawlEstimate;
 use crate::storage::Storage;
 
 #[derive(Debug)]
@@ -7,6 +8,19 @@ pub enum Message {
     Initial,
     Finished(Storage),
     Loading(String),
+    /// Crawling pauses until the rate limit of the endpoint resets. A
+    /// `Loading` message follows once it resumes
+    RateLimited {
+        endpoint: String,
+        resume_at: chrono::DateTime<chrono::Utc>,
+    },
+    /// A stage of the crawl failed. The crawl continues with the next stage
+    StageFailed { stage: String, error: String },
+    /// The result of a dry run, nothing was downloaded
+    Estimate(CrawlEstimate),
+    /// Something the user should know that doesn't stop the crawl, like low
+    /// disk space. Unlike `Loading`, it stays visible until the crawl ends
+    Warning(String),
     Error(Report),
 }
 
@@ -16,7 +30,237 @@ impl std::fmt::Display for Message {
             Message::Initial => f.write_str("Initial"),
             Message::Finished(_) => f.write_str("Finished"),
             Message::Loading(e) => f.write_fmt(format_args!("{}", e)),
+            Message::RateLimited {
+                endpoint,
+                resume_at,
+            } => write!(
+                f,
+                "Rate limit for {endpoint} reached. Resuming at {}",
+                resume_at.with_timezone(&chrono::Local).format("%H:%M:%S")
+            ),
+            Message::StageFailed { stage, error } => {
+                write!(f, "Could not archive the {stage}, continuing: {error}")
+            }
+            Message::Estimate(estimate) => write!(f, "{estimate}"),
+            Message::Warning(warning) => f.write_str(warning),
             Message::Error(e) => f.write_fmt(format_args!("{}", e)),
         }
     }
 }
+
+/// The ways in which a crawl can fail
+#[derive(Debug)]
+pub enum CrawlError {
+    /// The token was rejected. A new login is required
+    Auth(String),
+    /// The rate limit was hit. Contains the unix timestamp when it resets
+    RateLimited(i32),
+    /// Twitter or a media server could not be reached
+    Network(String),
+    /// Reading or writing the archive failed
+    Io(std::io::Error),
+    /// Any other error returned by the API or the crawler itself
+    Api(String),
+    /// The crawl was stopped via `Config::cancel`. The archive was saved
+    Cancelled,
+}
+
+impl CrawlError {
+    /// A hint for the user on how to resolve the error
+    pub fn guidance(&self) -> &'static str {
+        match self {
+            CrawlError::Auth(_) => "Please re-authenticate and try again",
+            CrawlError::RateLimited(_) => {
+                "Twitter's rate limit was reached. Please wait a while and try again"
+            }
+            CrawlError::Network(_) => "Please check your network connection and proxy settings",
+            CrawlError::Io(_) => "Please check that the archive folder is writable and the disk is not full",
+            CrawlError::Api(_) => "Please try again later",
+            CrawlError::Cancelled => "Run the crawl again to continue where it stopped",
+        }
+    }
+}
+
+impl std::fmt::Display for CrawlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrawlError::Auth(e) => write!(f, "Authentication failed: {e}"),
+            CrawlError::RateLimited(reset) => write!(f, "Rate limited until {reset}"),
+            CrawlError::Network(e) => write!(f, "Network error: {e}"),
+            CrawlError::Io(e) => write!(f, "IO error: {e}"),
+            CrawlError::Api(e) => write!(f, "API error: {e}"),
+            CrawlError::Cancelled => write!(f, "The crawl was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for CrawlError {}
+
+impl From<egg_mode::error::Error> for CrawlError {
+    fn from(error: egg_mode::error::Error) -> Self {
+        use egg_mode::error::Error;
+        match error {
+            Error::RateLimit(reset) => CrawlError::RateLimited(reset),
+            // 32: Could not authenticate, 89: Invalid or expired token
+            Error::TwitterError(_, ref errors)
+                if errors.errors.iter().any(|e| e.code == 32 || e.code == 89) =>
+            {
+                CrawlError::Auth(error.to_string())
+            }
+            Error::BadStatus(status) if status.as_u16() == 401 => {
+                CrawlError::Auth(error.to_string())
+            }
+            Error::NetError(e) => CrawlError::Network(e.to_string()),
+            Error::IOError(e) => CrawlError::Io(e),
+            other => CrawlError::Api(other.to_string()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for CrawlError {
+    fn from(error: reqwest::Error) -> Self {
+        CrawlError::Network(error.to_string())
+    }
+}
+
+impl From<std::io::Error> for CrawlError {
+    fn from(error: std::io::Error) -> Self {
+        CrawlError::Io(error)
+    }
+}
+
+impl From<Report> for CrawlError {
+    fn from(error: Report) -> Self {
+        CrawlError::Api(format!("{error:?}"))
+    }
+}
+
+impl<T> From<tokio::sync::mpsc::error::SendError<T>> for CrawlError {
+    fn from(error: tokio::sync::mpsc::error::SendError<T>) -> Self {
+        CrawlError::Api(format!("Internal channel closed: {error}"))
+    }
+}
+
+impl From<tokio::task::JoinError> for CrawlError {
+    fn from(error: tokio::task::JoinError) -> Self {
+        CrawlError::Api(format!("Internal task failed: {error}"))
+    }
+}
+
+/// Whether Twitter answers the requests of the token, see `crawler::api_status`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ApiStatus {
+    Ok,
+    /// Contains the unix timestamp when the rate limit resets
+    RateLimited(i32),
+    /// The token was rejected. A new login is required
+    AuthFailed(String),
+    /// Twitter answered with an error or an unexpected response
+    ServerError(String),
+    /// Twitter could not be reached
+    Network(String),
+}
+
+impl ApiStatus {
+    /// Classifies the error of a request
+    pub fn from_error(error: &egg_mode::error::Error) -> Self {
+        use egg_mode::error::Error;
+        match error {
+            Error::RateLimit(reset) => ApiStatus::RateLimited(*reset),
+            // 88: Rate limit exceeded
+            Error::TwitterError(_, errors) if errors.errors.iter().any(|e| e.code == 88) => {
+                ApiStatus::RateLimited(0)
+            }
+            // 32: Could not authenticate, 89: Invalid or expired token
+            Error::TwitterError(_, errors)
+                if errors.errors.iter().any(|e| e.code == 32 || e.code == 89) =>
+            {
+                ApiStatus::AuthFailed(error.to_string())
+            }
+            Error::BadStatus(status) if status.as_u16() == 401 => {
+                ApiStatus::AuthFailed(error.to_string())
+            }
+            Error::BadStatus(status) if status.as_u16() == 429 => ApiStatus::RateLimited(0),
+            Error::NetError(_) => ApiStatus::Network(error.to_string()),
+            other => ApiStatus::ServerError(other.to_string()),
+        }
+    }
+
+    /// A short label for the status bar
+    pub fn label(&self) -> &'static str {
+        match self {
+            ApiStatus::Ok => "Twitter OK",
+            ApiStatus::RateLimited(_) => "Rate Limited",
+            ApiStatus::AuthFailed(_) => "Login Failed",
+            ApiStatus::ServerError(_) => "Twitter Error",
+            ApiStatus::Network(_) => "Offline",
+        }
+    }
+}
+
+impl std::fmt::Display for ApiStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiStatus::Ok => f.write_str("Twitter is reachable and accepts the login"),
+            ApiStatus::RateLimited(0) => f.write_str("The rate limit was reached"),
+            ApiStatus::RateLimited(reset) => write!(f, "The rate limit was reached until {reset}"),
+            ApiStatus::AuthFailed(e) => write!(f, "The login was rejected: {e}"),
+            ApiStatus::ServerError(e) => write!(f, "Twitter returned an error: {e}"),
+            ApiStatus::Network(e) => write!(f, "Twitter could not be reached: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egg_mode::error::{Error, TwitterErrors};
+    use reqwest::{header::HeaderMap, StatusCode};
+
+    /// The error body of a rejected v1.1 request
+    fn twitter_error(code: i32, message: &str) -> Error {
+        let body = serde_json::json!({ "errors": [{ "code": code, "message": message }] });
+        let errors: TwitterErrors = serde_json::from_value(body).unwrap();
+        Error::TwitterError(HeaderMap::new(), errors)
+    }
+
+    #[test]
+    fn request_errors_are_classified() {
+        let status = |error: Error| ApiStatus::from_error(&error);
+
+        assert_eq!(
+            status(Error::RateLimit(1_700_000_000)),
+            ApiStatus::RateLimited(1_700_000_000)
+        );
+        assert_eq!(
+            status(twitter_error(88, "Rate limit exceeded")),
+            ApiStatus::RateLimited(0)
+        );
+        assert_eq!(
+            status(Error::BadStatus(StatusCode::TOO_MANY_REQUESTS)),
+            ApiStatus::RateLimited(0)
+        );
+
+        for code in [32, 89] {
+            let error = status(twitter_error(code, "Invalid or expired token."));
+            assert!(matches!(error, ApiStatus::AuthFailed(message) if message.contains("expired")));
+        }
+        assert!(matches!(
+            status(Error::BadStatus(StatusCode::UNAUTHORIZED)),
+            ApiStatus::AuthFailed(_)
+        ));
+
+        assert!(matches!(
+            status(Error::BadStatus(StatusCode::SERVICE_UNAVAILABLE)),
+            ApiStatus::ServerError(message) if message.contains("503")
+        ));
+        assert!(matches!(
+            status(twitter_error(131, "Internal error")),
+            ApiStatus::ServerError(_)
+        ));
+        assert!(matches!(
+            status(Error::InvalidResponse("unexpected body", None)),
+            ApiStatus::ServerError(_)
+        ));
+    }
+}
@@ -0,0 +1,87 @@
+#![allow(non_snake_case)]
+
+use dioxus::prelude::*;
+
+use crate::config::{Account, Config};
+use crate::storage::Storage;
+
+use super::helpers::{Box, NextButton};
+use super::types::LoadingState;
+
+/// Pick one of the archived accounts or start a new one
+#[inline_props]
+pub fn AccountPickerComponent(
+    cx: Scope,
+    accounts: Vec<Account>,
+    loading_state: UseState<LoadingState>,
+) -> Element {
+    let accounts_rendered = accounts.iter().map(|account| {
+        let screen_name = &account.screen_name;
+        let path = account.path.display().to_string();
+        rsx!(button {
+            class: "list-group-item list-group-item-action",
+            r#type: "button",
+            onclick: move |_| loading_state.set(open_account(account)),
+            strong {
+                "@{screen_name}"
+            }
+            small {
+                class: "d-block text-muted",
+                "{path}"
+            }
+        })
+    });
+
+    cx.render(rsx!(Box {
+        title: "Accounts",
+        p {
+            class: "lead",
+            "Which archive do you want to open?"
+        }
+        div {
+            class: "list-group mb-3",
+            accounts_rendered
+        }
+        NextButton {
+            title: "Archive a new account",
+            kind: "button",
+            onclick: move |_| loading_state.set(LoadingState::Login),
+        }
+        OpenArchiveButton {
+            loading_state: loading_state.clone()
+        }
+    }))
+}
+
+/// Open the archive if there is one, otherwise continue with the setup
+fn open_account(account: &Account) -> LoadingState {
+    let Ok(config) = Config::open(Some(account.path.clone())) else {
+        return LoadingState::Login
+    };
+    if Storage::exists(&account.path) {
+        LoadingState::Opening(account.path.clone(), Some(config))
+    } else {
+        LoadingState::Setup(config)
+    }
+}
+
+/// Browse an existing archive without logging in, e.g. a copy from another computer
+#[inline_props]
+pub fn OpenArchiveButton(cx: Scope, loading_state: UseState<LoadingState>) -> Element {
+    cx.render(rsx!(div {
+        class: "d-grid gap-2 mt-3",
+        button {
+            class: "btn btn-outline-secondary",
+            r#type: "button",
+            onclick: move |_| {
+                let Some(folder) = rfd::FileDialog::new()
+                    .set_title("Choose an archive folder")
+                    .pick_folder() else {
+                    return
+                };
+                loading_state.set(LoadingState::Opening(folder, None));
+            },
+            "Open an Existing Archive"
+        }
+    }))
+}
@@ -1,26 +1,38 @@
 #![allow(non_snake_case)]
 use std::cell::Cell;
+use std::path::PathBuf;
 
 use dioxus::desktop::tao::dpi::LogicalSize;
 use dioxus::desktop::tao::window::WindowBuilder;
 use dioxus::desktop::use_window;
+use dioxus::fermi::use_atom_state;
 use dioxus::prelude::*;
 
-use crate::config::Config;
+use crate::config::{Account, Config};
 use crate::storage::Storage;
 
-use super::loading_component::LoadingComponent;
+use super::account_component::AccountPickerComponent;
+use super::loading_component::{LoadingComponent, OpeningComponent};
 use super::login_component::LoginComponent;
 use super::main_component::MainComponent;
 use super::setup_component::SetupComponent;
 use super::types::{LoadingState, StorageWrapper};
 
-pub fn run_ui(storage: Option<Storage>, config: Option<Config>) {
+/// With a `config`, an existing archive at `archive_path` is opened in the
+/// background after the window appeared. Otherwise `storage` is shown
+pub fn run_ui(
+    storage: Option<Storage>,
+    archive_path: Option<PathBuf>,
+    config: Option<Config>,
+    accounts: Vec<Account>,
+) {
     dioxus::desktop::launch_with_props(
         App,
         AppProps {
             storage: Cell::new(storage),
+            archive_path: Cell::new(archive_path),
             config: Cell::new(config),
+            accounts: Cell::new(accounts),
         },
         |c| {
             c.with_window(default_menu).with_window(|w| {
@@ -40,14 +52,23 @@ pub fn run_ui(storage: Option<Storage>, config: Option<Config>) {
     );
 }
 
+/// Whether the dark theme is active
+pub static DARK_MODE: Atom<bool> = |_| false;
+
+/// Set when a component changed the storage on disk
+pub static UPDATED_STORAGE: Atom<Option<StorageWrapper>> = |_| None;
+
+/// Set when the whole archive finished loading after its preview was shown
+pub static OPENED_STORAGE: Atom<Option<StorageWrapper>> = |_| None;
+
 struct AppProps {
     storage: Cell<Option<Storage>>,
+    archive_path: Cell<Option<PathBuf>>,
     config: Cell<Option<Config>>,
+    accounts: Cell<Vec<Account>>,
 }
 
 fn App(cx: Scope<AppProps>) -> Element {
-    let loading_state = use_state(&cx, LoadingState::default);
-
     let storage: &UseState<Option<StorageWrapper>> = {
         let initial = cx.props.storage.take();
         use_state(&cx, || initial.map(StorageWrapper::new))
@@ -58,7 +79,80 @@ fn App(cx: Scope<AppProps>) -> Element {
         use_state(&cx, || initial)
     };
 
+    let loading_state = {
+        let accounts = cx.props.accounts.take();
+        let archive_path = cx.props.archive_path.take();
+        // If there are multiple accounts and none is open, offer them
+        let initial = match (storage.get(), archive_path, config.get()) {
+            (None, Some(path), Some(c)) => LoadingState::Opening(path, Some(c.clone())),
+            (None, _, _) if accounts.len() > 1 => LoadingState::PickAccount(accounts),
+            _ => LoadingState::default(),
+        };
+        use_state(&cx, || initial)
+    };
+
+    let updated_storage = use_atom_state(&cx, UPDATED_STORAGE);
+    if let Some(wrapper) = updated_storage.current().as_ref() {
+        storage.set(Some(wrapper.clone()));
+        updated_storage.set(None);
+    }
+
+    // Unless an action already replaced the preview with a saved archive
+    let opened_storage = use_atom_state(&cx, OPENED_STORAGE);
+    if let Some(wrapper) = opened_storage.current().as_ref() {
+        if storage.get().as_ref().map(|s| s.is_preview()) != Some(false) {
+            storage.set(Some(wrapper.clone()));
+        }
+        opened_storage.set(None);
+    }
+
+    // Read the saved theme once on startup
+    let dark_mode = use_atom_state(&cx, DARK_MODE);
+    let theme_loaded = cx.use_hook(|_| false);
+    if !*theme_loaded {
+        *theme_loaded = true;
+        if config.get().as_ref().map(|c| c.config_data.dark_mode) == Some(true) {
+            dark_mode.set(true);
+        }
+    }
+    let theme = if *dark_mode.current() { "dark" } else { "light" };
+
     let view = match (storage.get(), loading_state.get(), config.get()) {
+        (_, LoadingState::PickAccount(accounts), _) => cx.render(rsx! {
+            StartFlowContainer {
+                AccountPickerComponent {
+                    accounts: accounts.clone(),
+                    loading_state: loading_state.clone()
+                }
+            }
+        }),
+        (_, LoadingState::Opening(path, Some(c)), _) => cx.render(rsx! {
+            StartFlowContainer {
+                OpeningComponent {
+                    path: path.clone(),
+                    config: c.clone(),
+                    loading_state: loading_state.clone()
+                }
+            }
+        }),
+        (_, LoadingState::Opening(path, None), _) => cx.render(rsx! {
+            StartFlowContainer {
+                OpeningComponent {
+                    path: path.clone(),
+                    loading_state: loading_state.clone()
+                }
+            }
+        }),
+        (_, LoadingState::Loaded(wrapper, c), _) => {
+            config.set(Some(c.clone()));
+            storage.set(Some(wrapper.clone()));
+            loading_state.set(LoadingState::default());
+            cx.render(rsx! {
+                span {
+                    // "Done"
+                }
+            })
+        }
         (Some(n), _, Some(c)) => cx.render(rsx!(div {
             MainComponent {
                 storage: n.clone(),
@@ -103,15 +197,6 @@ fn App(cx: Scope<AppProps>) -> Element {
                 }
             }
         }),
-        (None, LoadingState::Loaded(wrapper, c), _) => {
-            config.set(Some(c.clone()));
-            storage.set(Some(wrapper.clone()));
-            cx.render(rsx! {
-                span {
-                    // "Done"
-                }
-            })
-        }
     };
 
     let is_loaded = storage.is_some();
@@ -140,6 +225,7 @@ fn App(cx: Scope<AppProps>) -> Element {
 
     rsx!(cx, main {
         class: "{main_class}",
+        "data-bs-theme": "{theme}",
         view
 
         div {
@@ -184,6 +270,7 @@ const fn style_html() -> &'static str {
     concat!(
         "<style>",
         include_str!("../assets/bootstrap.min.css"),
+        include_str!("../assets/dark.css"),
         "</style>"
     )
 }
@@ -0,0 +1,284 @@
+#![allow(non_snake_case)]
+
+use dioxus::prelude::*;
+use dioxus_heroicons::solid::Shape;
+use dioxus_heroicons::Icon;
+use tokio::sync::mpsc::channel;
+
+use crate::config::Config;
+use crate::export::{export_in_background, pdf::DateRange, ExportFormat, ExportSelection};
+use crate::types::Message;
+
+use super::helpers::InlineCheckbox;
+use super::types::StorageWrapper;
+
+/// The export entry in the navigation with a dropdown for the formats
+#[inline_props]
+pub fn ExportComponent(cx: Scope, storage: StorageWrapper, config: Config) -> Element {
+    let running = use_state(&cx, || false);
+    let status: &UseState<Option<Message>> = use_state(&cx, || None);
+    // `yyyy-mm-dd` from the date inputs, empty for no bound
+    let pdf_from = use_state(&cx, String::new);
+    let pdf_to = use_state(&cx, String::new);
+    let selection = use_state(&cx, ExportSelection::default);
+    let languages = use_state(&cx, || storage.data().languages());
+
+    let start = move |format: ExportFormat| {
+        if *running.get() {
+            return;
+        }
+        let Some(folder) = rfd::FileDialog::new()
+            .set_title("Choose an export folder")
+            .pick_folder() else {
+            return
+        };
+        running.set(true);
+        let (sender, mut receiver) = channel(16);
+        tokio::spawn(export_in_background(
+            storage.data().clone(),
+            format,
+            selection.get().clone(),
+            folder,
+            storage.media_folder(),
+            config.config_data.export_templates.clone(),
+            sender,
+        ));
+        let running = running.clone();
+        let status = status.clone();
+        cx.spawn(async move {
+            if let Some(message) = receiver.recv().await {
+                status.set(Some(message));
+            }
+            running.set(false);
+        });
+    };
+
+    let (toggle_class, label) = if *running.get() {
+        ("opacity-50 pe-none", "Exporting")
+    } else {
+        ("", "Export")
+    };
+
+    let all_languages = selection.get().lang.is_none();
+    let language_options = languages.get().iter().map(|(code, count)| {
+        let selected = selection.get().lang.as_deref() == Some(code.as_str());
+        rsx!(option {
+            value: "{code}",
+            selected: "{selected}",
+            "{code} ({count})"
+        })
+    });
+
+    let status_item = status.get().as_ref().map(|message| {
+        let class = match message {
+            Message::Error(_) => "dropdown-item-text text-danger",
+            _ => "dropdown-item-text text-muted",
+        };
+        rsx!(
+            li {
+                hr {
+                    class: "dropdown-divider"
+                }
+            }
+            li {
+                small {
+                    class: "{class}",
+                    "{message}"
+                }
+            }
+        )
+    });
+
+    cx.render(rsx!(div {
+        class: "dropend m-2 p-2 flex-column d-inline-flex align-items-center",
+        div {
+            class: "d-inline-flex flex-column align-items-center {toggle_class}",
+            style: "cursor: pointer",
+            "data-bs-toggle": "dropdown",
+            "data-bs-auto-close": "outside",
+            "aria-expanded": "false",
+            Icon {
+                icon: Shape::Download,
+                fill: "white",
+                size: 20
+            }
+            span {
+                class: "text-light",
+                style: "font-size: .55rem",
+                "{label}"
+            }
+        }
+        ul {
+            class: "dropdown-menu",
+            li {
+                div {
+                    class: "px-3 py-1",
+                    small {
+                        class: "text-muted d-block",
+                        "Include"
+                    }
+                    InlineCheckbox {
+                        label: "Tweets",
+                        name: "export-tweets",
+                        checked: selection.get().tweets,
+                        onclick: move |_| selection.modify(|s| ExportSelection { tweets: !s.tweets, ..s.clone() }),
+                    }
+                    InlineCheckbox {
+                        label: "Mentions",
+                        name: "export-mentions",
+                        checked: selection.get().mentions,
+                        onclick: move |_| selection.modify(|s| ExportSelection { mentions: !s.mentions, ..s.clone() }),
+                    }
+                    InlineCheckbox {
+                        label: "Likes",
+                        name: "export-likes",
+                        checked: selection.get().likes,
+                        onclick: move |_| selection.modify(|s| ExportSelection { likes: !s.likes, ..s.clone() }),
+                    }
+                    InlineCheckbox {
+                        label: "Followers",
+                        name: "export-followers",
+                        checked: selection.get().followers,
+                        onclick: move |_| selection.modify(|s| ExportSelection { followers: !s.followers, ..s.clone() }),
+                    }
+                    InlineCheckbox {
+                        label: "Lists",
+                        name: "export-lists",
+                        checked: selection.get().lists,
+                        onclick: move |_| selection.modify(|s| ExportSelection { lists: !s.lists, ..s.clone() }),
+                    }
+                    InlineCheckbox {
+                        label: "Media",
+                        name: "export-media",
+                        checked: selection.get().media,
+                        onclick: move |_| selection.modify(|s| ExportSelection { media: !s.media, ..s.clone() }),
+                    }
+                    select {
+                        class: "form-select form-select-sm mt-1",
+                        title: "Language",
+                        oninput: move |evt| {
+                            let lang = Some(evt.value.clone()).filter(|code| !code.is_empty());
+                            selection.modify(|s| ExportSelection { lang: lang.clone(), ..s.clone() });
+                        },
+                        option {
+                            value: "",
+                            selected: "{all_languages}",
+                            "All languages"
+                        }
+                        language_options
+                    }
+                }
+            }
+            li {
+                hr {
+                    class: "dropdown-divider"
+                }
+            }
+            li {
+                a {
+                    class: "dropdown-item",
+                    href: "#",
+                    onclick: move |_| start(ExportFormat::Html),
+                    "HTML"
+                }
+            }
+            li {
+                a {
+                    class: "dropdown-item",
+                    href: "#",
+                    onclick: move |_| start(ExportFormat::SingleFileHtml),
+                    "HTML (Single File)"
+                }
+            }
+            li {
+                a {
+                    class: "dropdown-item",
+                    href: "#",
+                    onclick: move |_| start(ExportFormat::Json),
+                    "JSON"
+                }
+            }
+            li {
+                a {
+                    class: "dropdown-item",
+                    href: "#",
+                    onclick: move |_| start(ExportFormat::Markdown),
+                    "Markdown"
+                }
+            }
+            li {
+                a {
+                    class: "dropdown-item",
+                    href: "#",
+                    onclick: move |_| start(ExportFormat::Csv),
+                    "Followers / Follows (CSV)"
+                }
+            }
+            li {
+                a {
+                    class: "dropdown-item",
+                    href: "#",
+                    onclick: move |_| start(ExportFormat::Text { oldest_first: false }),
+                    "Plain Text, newest first"
+                }
+            }
+            li {
+                a {
+                    class: "dropdown-item",
+                    href: "#",
+                    onclick: move |_| start(ExportFormat::Text { oldest_first: true }),
+                    "Plain Text, oldest first"
+                }
+            }
+            li {
+                a {
+                    class: "dropdown-item",
+                    href: "#",
+                    onclick: move |_| start(ExportFormat::Rss),
+                    "RSS Feed"
+                }
+            }
+            li {
+                hr {
+                    class: "dropdown-divider"
+                }
+            }
+            li {
+                div {
+                    class: "px-3 py-1",
+                    small {
+                        class: "text-muted",
+                        "PDF of your Tweets, optionally from / to"
+                    }
+                    input {
+                        class: "form-control form-control-sm mt-1",
+                        r#type: "date",
+                        value: "{pdf_from}",
+                        oninput: move |evt| pdf_from.set(evt.value.clone())
+                    }
+                    input {
+                        class: "form-control form-control-sm mt-1",
+                        r#type: "date",
+                        value: "{pdf_to}",
+                        oninput: move |evt| pdf_to.set(evt.value.clone())
+                    }
+                }
+            }
+            li {
+                a {
+                    class: "dropdown-item",
+                    href: "#",
+                    onclick: move |_| {
+                        let parse = |value: &str| chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").ok();
+                        start(ExportFormat::Pdf(DateRange {
+                            from: parse(pdf_from.get()),
+                            to: parse(pdf_to.get()),
+                        }))
+                    },
+                    "PDF"
+                }
+            }
+            status_item
+        }
+    }))
+}
@@ -0,0 +1,85 @@
+#![allow(non_snake_case)]
+
+use dioxus::prelude::*;
+
+use crate::config::Config;
+
+use super::helpers::{BottomSpacer, ShowMoreButton};
+use super::tweet_component::TweetComponent;
+use super::types::StorageWrapper;
+
+/// How many of the most used hashtags are listed
+const TOP_HASHTAGS: usize = 50;
+
+/// The most used hashtags of the owner and the tweets of a selected hashtag
+#[inline_props]
+pub fn HashtagComponent(cx: Scope, storage: StorageWrapper, config: Config) -> Element {
+    let page_size = 50;
+    let page = use_state(&cx, || page_size);
+    // Not kept in a state, so that it is recomputed once a sync finished
+    let top = storage.top_hashtags(TOP_HASHTAGS);
+    let no_hashtags = top.is_empty();
+    let selected: &UseState<Option<String>> = use_state(&cx, || None);
+
+    let tags_rendered = top.iter().map(|(tag, count)| {
+        let class = if selected.get().as_deref() == Some(tag.as_str()) {
+            "list-group-item list-group-item-action d-flex justify-content-between active"
+        } else {
+            "list-group-item list-group-item-action d-flex justify-content-between"
+        };
+        let cloned = tag.clone();
+        rsx!(a {
+            class: "{class}",
+            href: "#",
+            onclick: move |_| {
+                selected.set(Some(cloned.clone()));
+                page.set(page_size);
+            },
+            "#{tag}"
+            span {
+                class: "badge bg-secondary rounded-pill",
+                "{count}"
+            }
+        })
+    });
+
+    let tweets = selected
+        .get()
+        .as_ref()
+        .map(|tag| storage.data().tweets_with_hashtag(tag))
+        .unwrap_or_default();
+    let has_more = tweets.len() > *page.get();
+    let tweets_rendered = tweets.into_iter().take(*page.get()).map(|tweet| {
+        let responses = storage.data().responses.get(&tweet.id).map(|e| e.len());
+        cx.render(rsx!(TweetComponent {
+            tweet: tweet,
+            media: storage.resolver(),
+            user: &storage.data().profile,
+            profiles: &storage.data().profiles,
+            responses: responses,
+            edited: storage.data().is_edited(tweet.id),
+            config: config
+        }))
+    });
+
+    cx.render(rsx!(div {
+        h5 {
+            style: "margin-top: 10px; margin-bottom: 5px; margin-left: 15px; font-weight: bold; color: slategray;",
+            "Your Top Hashtags"
+        }
+        no_hashtags.then(|| rsx!(p {
+            class: "text-muted m-3",
+            "Your archived tweets don't use any hashtags."
+        }))
+        div {
+            class: "list-group m-2",
+            tags_rendered
+        }
+        tweets_rendered
+        ShowMoreButton {
+            visible: has_more,
+            onclick: move |_| page.set(page.get() + page_size)
+        }
+        BottomSpacer {}
+    }))
+}
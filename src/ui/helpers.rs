@@ -116,3 +116,31 @@ pub fn BottomSpacer(cx: Scope) -> Element {
         style: "margin-bottom: 150px;"
     }))
 }
+
+/// A small checkbox that is shown next to others in one line
+#[inline_props]
+pub fn InlineCheckbox<'a>(
+    cx: Scope,
+    label: &'static str,
+    name: &'static str,
+    checked: bool,
+    onclick: EventHandler<'a, MouseEvent>,
+) -> Element {
+    cx.render(rsx!(div {
+        class: "form-check form-check-inline",
+        input {
+            class: "form-check-input",
+            onclick: move |evt| onclick.call(evt),
+            r#type: "checkbox",
+            name: "{name}",
+            checked: "{checked}",
+            id: "{name}"
+        }
+        label {
+            style: "font-size: 13px;",
+            class: "form-check-label",
+            r#for: "{name}",
+            "{label}"
+        }
+    }))
+}
@@ -1,14 +1,20 @@
 #![allow(non_snake_case)]
 
 use dioxus::{fermi::use_atom_state, prelude::*};
+use tracing::warn;
 
+use crate::config::Config;
 use crate::{storage::List, ui::main_component::ColumnState};
 
+use super::app::UPDATED_STORAGE;
 use super::main_component::COLUMN2;
+use super::types::StorageWrapper;
 
 #[derive(Props)]
 pub struct ListListProps<'a> {
     lists: &'a [List],
+    config: &'a Config,
+    storage: &'a StorageWrapper,
 }
 
 pub fn ListListComponent<'a>(cx: Scope<'a, ListListProps>) -> Element<'a> {
@@ -16,7 +22,13 @@ pub fn ListListComponent<'a>(cx: Scope<'a, ListListProps>) -> Element<'a> {
         .props
         .lists
         .iter()
-        .map(|list| cx.render(rsx!(ListComponent { list: list })));
+        .map(|list| {
+            cx.render(rsx!(ListComponent {
+                list: list,
+                config: cx.props.config,
+                storage: cx.props.storage
+            }))
+        });
 
     cx.render(rsx!(div {
         h5 { 
@@ -31,16 +43,28 @@ pub fn ListListComponent<'a>(cx: Scope<'a, ListListProps>) -> Element<'a> {
 #[derive(Props)]
 struct ListProps<'a> {
     list: &'a List,
+    config: &'a Config,
+    storage: &'a StorageWrapper,
 }
 
 fn ListComponent<'a>(cx: Scope<'a, ListProps>) -> Element<'a> {
     let column2 = use_atom_state(&cx, COLUMN2);
+    let updated_storage = use_atom_state(&cx, UPDATED_STORAGE);
+    let running = use_state(&cx, || false);
+    let error: &UseState<Option<String>> = use_state(&cx, || None);
     let name = &cx.props.list.name;
     let creator = &cx.props.list.list.user.screen_name;
     let creator_id = &cx.props.list.list.user.id;
-    let subscribers = &cx.props.list.list.subscriber_count;
-    let members = &cx.props.list.list.member_count;
-    let description = &cx.props.list.list.description;
+    let subscribers = cx.props.list.subscriber_count();
+    let members = cx.props.list.member_count();
+    let description = cx.props.list.description();
+    let private_badge = cx.props.list.is_private().then(|| {
+        rsx!(span {
+            class: "badge bg-warning text-dark ms-2",
+            title: "Only visible to its creator. Exports include it, too",
+            "Private"
+        })
+    });
 
     let twitter_button = rsx!(a {
         class: "card-link",
@@ -61,6 +85,52 @@ fn ListComponent<'a>(cx: Scope<'a, ListProps>) -> Element<'a> {
         "Open"
     });
 
+    // An archive that was opened without a login can't be refreshed, nor
+    // its preview
+    let refresh = if cx.props.config.is_offline() || cx.props.storage.is_preview() {
+        None
+    } else if *running.get() {
+        Some(rsx!(span {
+            class: "card-link text-muted",
+            "Refreshing"
+        }))
+    } else {
+        Some(rsx!(a {
+            href: "#",
+            class: "card-link",
+            onclick: move |_| {
+                running.set(true);
+                let list_id = cx.props.list.list.id;
+                let config = cx.props.config.clone();
+                let running = running.clone();
+                let error = error.clone();
+                let updated_storage = updated_storage.clone();
+                let storage = cx.props.storage.clone();
+                cx.spawn(async move {
+                    let (sender, mut receiver) = tokio::sync::mpsc::channel(256);
+                    tokio::spawn(async move { while receiver.recv().await.is_some() {} });
+                    let result = storage
+                        .update(|storage| async move {
+                            crate::crawler::refresh_list(list_id, &config, storage, sender).await
+                        })
+                        .await;
+                    match result {
+                        Ok(storage) => {
+                            error.set(None);
+                            updated_storage.set(Some(StorageWrapper::new(storage)));
+                        }
+                        Err(e) => {
+                            warn!("Could not refresh list {e:?}");
+                            error.set(Some(e.to_string()));
+                        }
+                    }
+                    running.set(false);
+                });
+            },
+            "Refresh"
+        }))
+    };
+
     cx.render(rsx!(div {
         class: "card",
         style: "margin-bottom: 10px",
@@ -69,6 +139,7 @@ fn ListComponent<'a>(cx: Scope<'a, ListProps>) -> Element<'a> {
             h5 {
                 class: "card-title",
                 "{name}"
+                private_badge
             }
             h6 {
                 class: "card-subtitle mb-2 text-muted",
@@ -87,12 +158,17 @@ fn ListComponent<'a>(cx: Scope<'a, ListProps>) -> Element<'a> {
                 class: "card-text",
                 "{subscribers} Subscribers, {members} Members"
             }
+            error.get().as_ref().map(|e| rsx!(div {
+                class: "alert alert-danger",
+                "{e}"
+            }))
         }
         div {
             class: "card-footer",
             twitter_button
             twitter_button_a
             open
+            refresh
         }
     }))
 }
@@ -1,15 +1,20 @@
 #![allow(non_snake_case)]
 
+use std::path::PathBuf;
+
+use dioxus::fermi::use_atom_state;
 use dioxus::prelude::*;
 
 use tokio::sync::mpsc::channel;
 use tracing::warn;
 
 use crate::config::Config;
+use crate::storage::Storage;
 
-use crate::types::Message;
+use crate::types::{CrawlError, Message};
 
-use super::helpers::{Box, Spinner};
+use super::app::OPENED_STORAGE;
+use super::helpers::{Box, NextButton, Spinner};
 use super::types::LoadingState;
 use super::types::StorageWrapper;
 
@@ -21,17 +26,24 @@ pub fn LoadingComponent(
 ) -> Element {
     let appeared = cx.use_hook(|_| false);
     let message_state = use_state(&cx, || Message::Initial);
+    // Stay visible while the progress messages change
+    let warnings: &UseState<Vec<String>> = use_state(&cx, Vec::new);
 
-    let user_id = config.user_id();
+    let user_id = config.archived_user_id();
 
     let (sender, mut receiver) = channel(4096);
     if !*appeared {
         *appeared = true;
         let cloned_config = config.clone();
         cx.spawn(async move {
-            if let Err(e) = crate::crawler::crawl_new_storage(cloned_config, sender, user_id).await
+            // e.g. a rejected token, before the crawl started
+            if let Err(e) =
+                crate::crawler::crawl_new_storage(cloned_config, sender.clone(), user_id).await
             {
                 warn!("Error {e:?}");
+                if let Err(e) = sender.send(Message::Error(e)).await {
+                    warn!("Could not send error {e:?}");
+                }
             }
         });
     }
@@ -42,14 +54,38 @@ pub fn LoadingComponent(
         "Importing..."
     };
 
+    // Stages that already finished in an interrupted previous crawl
+    let finished_stages = config.finished_stages(user_id);
+    let resume_info = (!finished_stages.is_empty()).then(|| {
+        let stages_rendered = finished_stages.iter().map(|(stage, finished)| {
+            let finished = finished.with_timezone(&chrono::Local).format("%d/%m/%y %H:%M");
+            rsx!(li {
+                "{stage}: {finished}"
+            })
+        });
+        rsx!(div {
+            class: "alert alert-secondary",
+            "Resuming the previous crawl. These stages already finished and are skipped:"
+            ul {
+                class: "mb-0",
+                stages_rendered
+            }
+        })
+    });
+
     let cloned_config = config.clone();
 
     let future = use_future(&cx, (), move |_| {
         let message_state = message_state.clone();
         let loading_state = loading_state.clone();
+        let warnings = warnings.clone();
         async move {
             while let Some(msg) = receiver.recv().await {
                 let finished = match msg {
+                    Message::Warning(warning) => {
+                        warnings.with_mut(|warnings| warnings.push(warning));
+                        false
+                    }
                     Message::Finished(o) => {
                         loading_state.set(LoadingState::Loaded(
                             StorageWrapper::new(o),
@@ -70,14 +106,29 @@ pub fn LoadingComponent(
     });
 
     let ui = match message_state.get() {
-        Message::Error(e) => rsx!(div {
-            class: "alert alert-warning",
+        Message::Error(e) => {
+            let crawl_error = e.downcast_ref::<CrawlError>();
+            let guidance = crawl_error.map(|e| e.guidance());
+            // The crawl continues from the saved paging positions after the login
+            let login = matches!(crawl_error, Some(CrawlError::Auth(_))).then(|| {
+                rsx!(NextButton {
+                    title: "Log in again",
+                    kind: "button",
+                    onclick: move |_| loading_state.set(LoadingState::Login),
+                })
+            });
+            rsx!(div {
+                class: "alert alert-warning",
                 h3 {
                     "Uh oh. Something went wrong",
                 }
+                guidance.map(|guidance| rsx!(p {
+                    strong { "{guidance}" }
+                }))
                 "{e:?}"
-            }
-        ),
+                login
+            })
+        }
         Message::Finished(_) => rsx!(div {
             // This should never appear here
         }),
@@ -90,6 +141,39 @@ pub fn LoadingComponent(
                 title: format!("{msg}")
             }
         }),
+        Message::RateLimited {
+            endpoint,
+            resume_at,
+        } => rsx!(div {
+            class: "alert alert-info",
+            h3 {
+                "{label}"
+            }
+            RateLimitCountdown {
+                endpoint: endpoint.clone(),
+                resume_at: *resume_at
+            }
+        }),
+        failed @ Message::StageFailed { .. } => rsx!(div {
+            class: "alert alert-warning",
+            h3 {
+                "{label}"
+            }
+            "{failed}"
+        }),
+        Message::Warning(warning) => rsx!(div {
+            class: "alert alert-warning",
+            "{warning}"
+        }),
+        estimate @ Message::Estimate(_) => rsx!(div {
+            class: "alert alert-info",
+            h3 {
+                "{label}"
+            }
+            pre {
+                "{estimate}"
+            }
+        }),
         Message::Initial => rsx!(div {
             class: "alert alert-info",
             h3 {
@@ -98,6 +182,13 @@ pub fn LoadingComponent(
         }),
     };
 
+    let warnings_rendered = warnings.get().iter().map(|warning| {
+        rsx!(div {
+            class: "alert alert-warning",
+            "{warning}"
+        })
+    });
+
     let value = match future.value() {
         Some(_) => "Done!",
         None => "Note: This can take a long time. Depending on your tweets, followers and lists, up to hours.",
@@ -109,6 +200,8 @@ pub fn LoadingComponent(
             class: "card",
             div {
                 class: "card-body",
+                resume_info
+                warnings_rendered
                 ui
                 div {
                     class: "alert alert-info",
@@ -118,3 +211,117 @@ pub fn LoadingComponent(
         }
     }))
 }
+
+/// Counts down the seconds until crawling resumes after a rate limit
+#[inline_props]
+fn RateLimitCountdown(
+    cx: Scope,
+    endpoint: String,
+    resume_at: chrono::DateTime<chrono::Utc>,
+) -> Element {
+    let now = use_state(&cx, chrono::Utc::now);
+    use_future(&cx, (), |_| {
+        let now = now.clone();
+        async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                now.set(chrono::Utc::now());
+            }
+        }
+    });
+
+    let remaining = (*resume_at - *now.get()).num_seconds().max(0);
+    let (minutes, seconds) = (remaining / 60, remaining % 60);
+
+    cx.render(rsx!(Spinner {
+        title: format!("Twitter's rate limit for {endpoint} was reached. Resuming in {minutes}:{seconds:02}")
+    }))
+}
+
+/// Opens an existing archive off the UI thread, so that the window
+/// appears right away even for large archives
+#[inline_props]
+pub fn OpeningComponent(
+    cx: Scope,
+    path: PathBuf,
+    config: Option<Config>,
+    loading_state: UseState<LoadingState>,
+) -> Element {
+    let error = use_state(&cx, || Option::<String>::None);
+    let opened_storage = use_atom_state(&cx, OPENED_STORAGE);
+
+    // Spawned forever, as the component is gone once the preview is shown
+    let started = cx.use_hook(|_| false);
+    if !*started {
+        *started = true;
+        let path = path.clone();
+        let config = config.clone();
+        let loading_state = loading_state.clone();
+        let error = error.clone();
+        let opened_storage = opened_storage.clone();
+        cx.spawn_forever(async move {
+            let config_for = |storage: &Storage| {
+                config.clone().unwrap_or_else(|| {
+                    Config::for_archive(storage.root_folder.clone(), &storage.data().profile)
+                })
+            };
+
+            // The newest tweets first, the whole archive follows
+            let preview_path = path.clone();
+            let preview = tokio::task::spawn_blocking(move || Storage::open_preview(preview_path))
+                .await
+                .map_err(|e| eyre::eyre!("{e:?}"))
+                .and_then(|result| result);
+            let has_preview = match preview {
+                Ok(Some(storage)) => {
+                    let config = config_for(&storage);
+                    loading_state.set(LoadingState::Loaded(StorageWrapper::new(storage), config));
+                    true
+                }
+                Ok(None) => false,
+                Err(e) => {
+                    warn!("Could not open the preview of the archive {e:?}");
+                    false
+                }
+            };
+
+            let result = tokio::task::spawn_blocking(move || Storage::open_existing(path)).await;
+            match (result, has_preview) {
+                (Ok(Ok(storage)), true) => opened_storage.set(Some(StorageWrapper::new(storage))),
+                (Ok(Ok(storage)), false) => {
+                    let config = config_for(&storage);
+                    loading_state.set(LoadingState::Loaded(StorageWrapper::new(storage), config))
+                }
+                (Ok(Err(e)), false) => error.set(Some(e.to_string())),
+                (Err(e), false) => error.set(Some(format!("Opening the archive failed: {e:?}"))),
+                (Ok(Err(e)), true) => warn!("Could not open the archive {e:?}"),
+                (Err(e), true) => warn!("Opening the archive failed: {e:?}"),
+            }
+        });
+    }
+
+    let ui = match error.get() {
+        Some(e) => rsx!(div {
+            class: "alert alert-warning",
+            h3 {
+                "Could not open the archive"
+            }
+            p {
+                "{e}"
+            }
+            NextButton {
+                title: "Back",
+                kind: "button",
+                onclick: move |_| loading_state.set(LoadingState::Login),
+            }
+        }),
+        None => rsx!(Spinner {
+            title: "Opening the archive...".to_string()
+        }),
+    };
+
+    cx.render(rsx!(Box {
+        title: "Opening",
+        ui
+    }))
+}
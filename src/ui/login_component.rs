@@ -7,6 +7,7 @@ use tracing::warn;
 
 use crate::config::{Config, RequestData};
 
+use super::account_component::OpenArchiveButton;
 use super::helpers::{Box, NextButton, Spinner};
 use super::types::LoadingState;
 
@@ -20,7 +21,7 @@ enum LoginState {
 #[derive(Clone)]
 enum LoginStateResult {
     RequestData(RequestData),
-    LoggedIn(Config),
+    LoggedIn(Box<Config>),
     Error(String),
 }
 
@@ -52,7 +53,7 @@ pub fn LoginComponent(cx: Scope, loading_state: UseState<LoadingState>) -> Eleme
                 LoginState::EnteredPin(data, pin) => data
                     .validate(&pin)
                     .await
-                    .map(LoginStateResult::LoggedIn)
+                    .map(|config| LoginStateResult::LoggedIn(Box::new(config)))
                     .unwrap_or_else(|e| LoginStateResult::Error(e.to_string())),
                 LoginState::LoadingPin(n) => LoginStateResult::RequestData(n),
             }
@@ -130,6 +131,16 @@ pub fn LoginComponent(cx: Scope, loading_state: UseState<LoadingState>) -> Eleme
                         kind: "submit",
                         onclick: move |_| { },
                     }
+
+                    button {
+                        class: "btn btn-link",
+                        r#type: "button",
+                        onclick: move |_| {
+                            login_state.set(LoginState::Initial);
+                            state_machine.restart();
+                        },
+                        "Cancel and start over"
+                    }
                 }
             }
         }),
@@ -147,7 +158,7 @@ pub fn LoginComponent(cx: Scope, loading_state: UseState<LoadingState>) -> Eleme
                 title: "Next",
                 kind: "button",
                 onclick: move |_| {
-                    loading_state.set(LoadingState::Setup(c.clone()));
+                    loading_state.set(LoadingState::Setup(c.as_ref().clone()));
                 },
             }
         }),
@@ -170,7 +181,7 @@ pub fn LoginComponent(cx: Scope, loading_state: UseState<LoadingState>) -> Eleme
             title: "Invalid Pin",
             div {
                 class: "alert alert-danger",
-                "{e:?}"
+                "{e}"
             }
             NextButton {
                 title: "Try Again",
@@ -208,5 +219,8 @@ pub fn LoginComponent(cx: Scope, loading_state: UseState<LoadingState>) -> Eleme
 
     cx.render(rsx! { div {
         ui
+        OpenArchiveButton {
+            loading_state: loading_state.clone()
+        }
     }})
 }
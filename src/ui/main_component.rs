@@ -4,10 +4,15 @@ use dioxus::fermi::use_atom_state;
 use dioxus::prelude::*;
 use dioxus_heroicons::solid::Shape;
 use dioxus_heroicons::Icon;
+use tracing::warn;
 
 use crate::config::Config;
+use crate::crawler::api_status;
 use crate::storage::{List, TweetId, UserId};
+use crate::types::ApiStatus;
 
+use super::app::DARK_MODE;
+use super::export_component::ExportComponent;
 use super::primary_column::MainColumn;
 use super::secondary_column::SecondaryColumn;
 use super::types::{LoadingState, StorageWrapper};
@@ -22,6 +27,8 @@ pub enum Tab {
     Followers,
     Follows,
     Lists,
+    Relationships,
+    Tags,
     Search,
 }
 
@@ -34,6 +41,8 @@ impl std::fmt::Display for Tab {
             Tab::Followers => f.write_str("Followers"),
             Tab::Follows => f.write_str("Follows"),
             Tab::Lists => f.write_str("Lists"),
+            Tab::Relationships => f.write_str("Mutuals"),
+            Tab::Tags => f.write_str("Tags"),
             Tab::Search => f.write_str("Search"),
         }
     }
@@ -48,6 +57,8 @@ impl Tab {
             Tab::Followers => Shape::Users,
             Tab::Follows => Shape::UserGroup,
             Tab::Lists => Shape::ViewList,
+            Tab::Relationships => Shape::SwitchHorizontal,
+            Tab::Tags => Shape::Hashtag,
             Tab::Search => Shape::SearchCircle,
         }
     }
@@ -59,10 +70,14 @@ pub enum ColumnState {
     AnyTweet(TweetId),
     /// Responses to a tweet
     Responses(TweetId),
+    /// A tweet with all its nested responses
+    Thread(TweetId),
     /// A given profile
     Profile(UserId),
     /// A list
     List(List),
+    /// The state of the downloaded media
+    MediaReport,
     /// Nothing in the clumn
     None,
 }
@@ -95,6 +110,97 @@ pub fn MainComponent(
     let column2 = use_atom_state(&cx, COLUMN2);
     let is_column2 = column2.current().as_ref() != &ColumnState::None;
 
+    let dark_mode = use_atom_state(&cx, DARK_MODE);
+    let (theme_icon, theme_label) = if *dark_mode.current() {
+        (Shape::Sun, "Light")
+    } else {
+        (Shape::Moon, "Dark")
+    };
+
+    // Only the newest tweets are shown until the whole archive is loaded,
+    // so it can't be synced or exported yet
+    let is_preview = storage.is_preview();
+    let preview_note = is_preview.then(|| {
+        rsx!(div {
+            class: "m-2 p-2 flex-column d-inline-flex align-items-center",
+            title: "Only the newest tweets are shown until the whole archive is loaded",
+            Icon {
+                icon: Shape::Clock,
+                fill: "white",
+                size: 20
+            }
+            span {
+                class: "text-light",
+                style: "font-size: .55rem",
+                "Loading"
+            }
+        })
+    });
+
+    let export_button = (!is_preview).then(|| {
+        rsx!(ExportComponent {
+            storage: storage.clone(),
+            config: config.clone()
+        })
+    });
+
+    let api_status = (!config.is_offline()).then(|| {
+        rsx!(ApiStatusComponent {
+            config: config.clone()
+        })
+    });
+
+    // An archive that was opened without a login can't be synced
+    let sync_button = (!config.is_offline() && !is_preview).then(|| {
+        rsx!(div {
+            class: "m-2 p-2 flex-column d-inline-flex align-items-center",
+            style: "cursor: pointer",
+            onclick: move |_| state.set(LoadingState::Loading({
+                let mut cfg = config.clone();
+                cfg.is_sync = true;
+                cfg
+            })),
+            Icon {
+                icon: Shape::LightningBolt,
+                fill: "white",
+                size: 20
+            }
+            span {
+                class: "text-light",
+                style: "font-size: .55rem",
+                "Sync"
+            }
+        })
+    });
+
+    // An interrupted sync continues where it stopped, unless it starts over
+    let can_restart = !config.is_offline()
+        && !is_preview
+        && config.has_crawl_positions(config.archived_user_id());
+    let fresh_sync_button = can_restart.then(|| {
+        rsx!(div {
+            class: "m-2 p-2 flex-column d-inline-flex align-items-center",
+            style: "cursor: pointer",
+            title: "Sync from the beginning instead of continuing the interrupted sync. The archived data is kept",
+            onclick: move |_| state.set(LoadingState::Loading({
+                let mut cfg = config.clone();
+                cfg.reset_crawl_positions();
+                cfg.is_sync = true;
+                cfg
+            })),
+            Icon {
+                icon: Shape::Refresh,
+                fill: "white",
+                size: 20
+            }
+            span {
+                class: "text-light",
+                style: "font-size: .55rem",
+                "Restart"
+            }
+        })
+    });
+
     cx.render(rsx! {
         main {
             class: "d-flex flex-nowrap",
@@ -132,27 +238,73 @@ pub fn MainComponent(
                     label: Tab::Lists
                     selected: selected.clone()
                 }
+                NavElement {
+                    label: Tab::Relationships
+                    selected: selected.clone()
+                }
+                NavElement {
+                    label: Tab::Tags
+                    selected: selected.clone()
+                }
                 NavElement {
                     label: Tab::Search
                     selected: selected.clone()
                 }
+                sync_button
+                fresh_sync_button
+                preview_note
+                div {
+                    class: "m-2 p-2 flex-column d-inline-flex align-items-center",
+                    style: "cursor: pointer",
+                    onclick: move |_| column2.set(ColumnState::MediaReport),
+                    Icon {
+                        icon: Shape::ShieldCheck,
+                        fill: "white",
+                        size: 20
+                    }
+                    span {
+                        class: "text-light",
+                        style: "font-size: .55rem",
+                        "Verify"
+                    }
+                }
                 div {
                     class: "m-2 p-2 flex-column d-inline-flex align-items-center",
                     style: "cursor: pointer",
-                    onclick: move |_| state.set(LoadingState::Loading({
+                    onclick: move |_| state.set(LoadingState::PickAccount(Config::accounts())),
+                    Icon {
+                        icon: Shape::UserCircle,
+                        fill: "white",
+                        size: 20
+                    }
+                    span {
+                        class: "text-light",
+                        style: "font-size: .55rem",
+                        "Accounts"
+                    }
+                }
+                export_button
+                api_status
+                div {
+                    class: "m-2 p-2 flex-column d-inline-flex align-items-center",
+                    style: "cursor: pointer",
+                    onclick: move |_| {
+                        let enabled = !*dark_mode.current();
+                        dark_mode.set(enabled);
                         let mut cfg = config.clone();
-                        cfg.is_sync = true;
-                        cfg
-                    })),
+                        if let Err(e) = cfg.set_dark_mode(enabled) {
+                            warn!("Could not save theme: {e:?}");
+                        }
+                    },
                     Icon {
-                        icon: Shape::LightningBolt,
+                        icon: theme_icon,
                         fill: "white",
                         size: 20
                     }
                     span {
                         class: "text-light",
                         style: "font-size: .55rem",
-                        "Sync"
+                        "{theme_label}"
                     }
                 }
                 div {
@@ -222,3 +374,35 @@ fn NavElement(cx: Scope, label: Tab, selected: UseState<Tab>) -> Element {
         }
     })
 }
+
+/// Checks once whether Twitter is reachable, so that failing crawls can be
+/// told apart from an outage
+#[inline_props]
+fn ApiStatusComponent(cx: Scope, config: Config) -> Element {
+    let status = use_future(&cx, (), |_| {
+        let config = config.clone();
+        async move { api_status(&config).await }
+    });
+    let (fill, label, title) = match status.value() {
+        None => ("gray", "Checking", "Checking the Twitter API".to_string()),
+        Some(status @ ApiStatus::Ok) => ("limegreen", status.label(), status.to_string()),
+        Some(status @ ApiStatus::RateLimited(_)) => ("orange", status.label(), status.to_string()),
+        Some(status) => ("red", status.label(), status.to_string()),
+    };
+    rsx!(cx, div {
+        class: "m-2 p-2 flex-column d-inline-flex align-items-center",
+        style: "cursor: pointer",
+        title: "{title}",
+        onclick: move |_| status.restart(),
+        Icon {
+            icon: Shape::StatusOnline,
+            fill: fill,
+            size: 20
+        }
+        span {
+            class: "text-light text-center",
+            style: "font-size: .55rem",
+            "{label}"
+        }
+    })
+}
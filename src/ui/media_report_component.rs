@@ -0,0 +1,100 @@
+#![allow(non_snake_case)]
+
+use dioxus::fermi::use_atom_state;
+use dioxus::prelude::*;
+use tracing::warn;
+
+use crate::config::Config;
+
+use super::app::UPDATED_STORAGE;
+use super::helpers::{Box, NextButton, Spinner};
+use super::types::StorageWrapper;
+
+/// Shows which media files are missing or broken and
+/// allows downloading them again
+#[inline_props]
+pub fn MediaReportComponent(cx: Scope, storage: StorageWrapper, config: Config) -> Element {
+    // Checking every file is slow for large archives, so only on open
+    // and after downloading again
+    let report = use_state(&cx, || storage.verify_media());
+    let running = use_state(&cx, || false);
+    let error: &UseState<Option<String>> = use_state(&cx, || None);
+    let updated_storage = use_atom_state(&cx, UPDATED_STORAGE);
+
+    let missing = report.missing.len();
+    let corrupt = report.corrupt.len();
+    let total = report.total;
+    let queued = report.get().clone();
+
+    let broken_rendered = report.broken().map(|url| {
+        rsx!(li {
+            class: "list-group-item text-truncate",
+            small {
+                "{url}"
+            }
+        })
+    });
+
+    let action = if *running.get() {
+        rsx!(Spinner {
+            title: "Downloading".to_string()
+        })
+    } else if report.is_ok() {
+        rsx!(div {
+            class: "alert alert-success",
+            "All {total} media files are fine"
+        })
+    } else {
+        rsx!(NextButton {
+            title: "Download again",
+            kind: "button",
+            onclick: move |_| {
+                running.set(true);
+                let queued = queued.clone();
+                let config = config.clone();
+                let running = running.clone();
+                let report = report.clone();
+                let error = error.clone();
+                let updated_storage = updated_storage.clone();
+                let storage = storage.clone();
+                cx.spawn(async move {
+                    let result = storage
+                        .update(|storage| async move {
+                            crate::crawler::re_download_missing(storage, &queued, &config).await
+                        })
+                        .await;
+                    match result {
+                        Ok(storage) => {
+                            report.set(storage.verify_media());
+                            updated_storage.set(Some(StorageWrapper::new(storage)));
+                        }
+                        Err(e) => {
+                            warn!("Could not download media {e:?}");
+                            error.set(Some(e.to_string()));
+                        }
+                    }
+                    running.set(false);
+                });
+            }
+        })
+    };
+
+    cx.render(rsx!(div {
+        class: "p-3",
+        Box {
+            title: "Media",
+            p {
+                "{total} files, {missing} missing, {corrupt} empty"
+            }
+            error.get().as_ref().map(|e| rsx!(div {
+                class: "alert alert-danger",
+                "{e}"
+            }))
+            action
+            ul {
+                class: "list-group mt-3",
+                broken_rendered
+            }
+        }
+    }))
+}
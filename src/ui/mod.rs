@@ -1,13 +1,21 @@
+mod account_component;
 mod app;
+mod export_component;
+mod hashtag_component;
 mod helpers;
 mod list_list;
 mod loading_component;
 mod login_component;
 mod main_component;
+mod media_report_component;
 mod primary_column;
+mod profile_component;
+mod relationship_component;
 mod search_list;
 mod secondary_column;
 mod setup_component;
+mod stats_component;
+mod thread_component;
 mod tweet_component;
 mod tweet_list;
 mod types;
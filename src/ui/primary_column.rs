@@ -6,9 +6,12 @@ use dioxus::prelude::*;
 
 use crate::config::Config;
 
+use super::hashtag_component::HashtagComponent;
 use super::list_list::ListListComponent;
 use super::main_component::Tab;
+use super::relationship_component::RelationshipComponent;
 use super::search_list::SearchComponent;
+use super::stats_component::StatsComponent;
 use super::tweet_list::TweetListComponent;
 use super::types::StorageWrapper;
 use super::user_list::AuthorListComponent;
@@ -33,12 +36,18 @@ pub fn MainColumn(
                 div {
                     class: "{column_class}",
                     style: "{column_style}",
+                    StatsComponent {
+                        storage: storage.clone()
+                    }
                     TweetListComponent {
                         data: &storage.data().tweets,
                         media: storage.resolver(),
                         label: label,
                         user: &storage.data().profile,
+                        profiles: &storage.data().profiles,
                         responses: &storage.data().responses,
+                        edit_history: &storage.data().edit_history,
+                        hidden: &storage.data().inner_tweets,
                         config: config
                     }
                 }
@@ -55,7 +64,9 @@ pub fn MainColumn(
                         media: storage.resolver(),
                         label: label.clone(),
                         user: &storage.data().profile,
+                        profiles: &storage.data().profiles,
                         responses: &storage.data().responses,
+                        edit_history: &storage.data().edit_history,
                         config: config
                     }
                 }
@@ -72,7 +83,9 @@ pub fn MainColumn(
                         media: storage.resolver(),
                         label: label.clone(),
                         user: &storage.data().profile,
+                        profiles: &storage.data().profiles,
                         responses: &storage.data().responses,
+                        edit_history: &storage.data().edit_history,
                         config: config
                     }
                 }
@@ -116,7 +129,33 @@ pub fn MainColumn(
                     class: "{column_class}",
                     style: "{column_style}",
                     ListListComponent {
-                        lists: &storage.data().lists
+                        lists: &storage.data().lists,
+                        config: config,
+                        storage: storage
+                    }
+                }
+            }
+        } else {rsx!{ div {}}}}
+        {if current == Tab::Relationships {
+            rsx! {
+                div {
+                    class: "{column_class}",
+                    style: "{column_style}",
+                    RelationshipComponent {
+                        storage: storage.clone(),
+                        config: config.clone()
+                    }
+                }
+            }
+        } else {rsx!{ div {}}}}
+        {if current == Tab::Tags {
+            rsx! {
+                div {
+                    class: "{column_class}",
+                    style: "{column_style}",
+                    HashtagComponent {
+                        storage: storage.clone(),
+                        config: config.clone()
                     }
                 }
             }
@@ -0,0 +1,196 @@
+#![allow(non_snake_case)]
+
+use dioxus::{fermi::use_atom_state, prelude::*};
+use egg_mode::user::TwitterUser;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::storage::{Data, MediaResolver};
+
+use super::app::UPDATED_STORAGE;
+use super::helpers::{BottomSpacer, ShowMoreButton};
+use super::tweet_component::TweetComponent;
+use super::types::StorageWrapper;
+use super::user_component::AuthorComponent;
+
+#[derive(Props)]
+pub struct ProfileProps<'a> {
+    profile: &'a TwitterUser,
+    data: &'a Data,
+    media: MediaResolver<'a>,
+    config: &'a Config,
+    storage: &'a StorageWrapper,
+}
+
+/// A profile with its banner and all the archived tweets
+/// and retweets of that profile, or the tweets mentioning it
+pub fn ProfileComponent<'a>(cx: Scope<'a, ProfileProps>) -> Element<'a> {
+    let page_size = 50;
+    let page = use_state(&cx, || page_size);
+    let show_mentions = use_state(&cx, || false);
+    let profile = cx.props.profile;
+
+    let authored = cx.props.data.tweets_by(profile.id);
+    let mentioning = cx.props.data.tweets_mentioning(profile.id);
+    let (authored_count, mentioning_count) = (authored.len(), mentioning.len());
+    let tweets = if *show_mentions.get() {
+        mentioning
+    } else {
+        authored
+    };
+    let count = tweets.len();
+    let has_more = count > *page.get();
+    let (authored_class, mentioning_class) = if *show_mentions.get() {
+        ("nav-link", "nav-link active")
+    } else {
+        ("nav-link active", "nav-link")
+    };
+    let empty_message = if *show_mentions.get() {
+        format!("No archived tweets mention @{}.", profile.screen_name)
+    } else {
+        format!("There are no tweets from @{} in this archive.", profile.screen_name)
+    };
+
+    let banner = profile
+        .profile_banner_url
+        .as_ref()
+        .and_then(|url| cx.props.media.resolve(url))
+        .map(|url| {
+            rsx!(img {
+                class: "img-fluid rounded",
+                style: "margin: 12px; margin-bottom: 0;",
+                src: "{url}"
+            })
+        });
+
+    // Only the own profile can be refreshed, the others are updated by a sync
+    let is_owner = profile.id == cx.props.data.profile.id
+        && !cx.props.config.is_offline()
+        && !cx.props.storage.is_preview();
+    let refresh = is_owner.then(|| rsx!(RefreshProfileButton {
+        config: cx.props.config,
+        storage: cx.props.storage
+    }));
+
+    let tweets_rendered = tweets.into_iter().take(*page.get()).map(|tweet| {
+        let responses = cx.props.data.responses.get(&tweet.id).map(|e| e.len());
+        cx.render(rsx!(TweetComponent {
+            tweet: tweet,
+            media: cx.props.media.clone(),
+            user: &cx.props.data.profile,
+            profiles: &cx.props.data.profiles,
+            responses: responses,
+            edited: cx.props.data.is_edited(tweet.id),
+            config: cx.props.config
+        }))
+    });
+
+    cx.render(rsx!(div {
+        banner
+        AuthorComponent {
+            profile: profile,
+            media: cx.props.media.clone(),
+            profiles: &cx.props.data.profiles,
+            config: cx.props.config
+        }
+        refresh
+        ul {
+            class: "nav nav-pills m-2",
+            li {
+                class: "nav-item",
+                a {
+                    class: "{authored_class}",
+                    href: "#",
+                    onclick: move |_| {
+                        show_mentions.set(false);
+                        page.set(page_size);
+                    },
+                    "Archived Tweets ({authored_count})"
+                }
+            }
+            li {
+                class: "nav-item",
+                a {
+                    class: "{mentioning_class}",
+                    href: "#",
+                    onclick: move |_| {
+                        show_mentions.set(true);
+                        page.set(page_size);
+                    },
+                    "Mentioned In ({mentioning_count})"
+                }
+            }
+        }
+        (count == 0).then(|| rsx!(p {
+            class: "text-muted m-3",
+            "{empty_message}"
+        }))
+        tweets_rendered
+        ShowMoreButton {
+            visible: has_more,
+            onclick: move |_| page.set(page.get() + page_size)
+        }
+        BottomSpacer {}
+    }))
+}
+
+/// Downloads the profile of the owner again, without a full sync
+#[inline_props]
+fn RefreshProfileButton<'a>(
+    cx: Scope,
+    config: &'a Config,
+    storage: &'a StorageWrapper,
+) -> Element {
+    let updated_storage = use_atom_state(&cx, UPDATED_STORAGE);
+    let running = use_state(&cx, || false);
+    let error: &UseState<Option<String>> = use_state(&cx, || None);
+
+    let button = if *running.get() {
+        rsx!(span {
+            class: "text-muted",
+            "Refreshing"
+        })
+    } else {
+        rsx!(a {
+            href: "#",
+            onclick: move |_| {
+                running.set(true);
+                let config = (*config).clone();
+                let running = running.clone();
+                let error = error.clone();
+                let updated_storage = updated_storage.clone();
+                let storage = (*storage).clone();
+                cx.spawn(async move {
+                    let (sender, mut receiver) = tokio::sync::mpsc::channel(256);
+                    tokio::spawn(async move { while receiver.recv().await.is_some() {} });
+                    let result = storage
+                        .update(|storage| async move {
+                            crate::crawler::refresh_profile(&config, storage, sender).await
+                        })
+                        .await;
+                    match result {
+                        Ok(storage) => {
+                            error.set(None);
+                            updated_storage.set(Some(StorageWrapper::new(storage)));
+                        }
+                        Err(e) => {
+                            warn!("Could not refresh the profile {e:?}");
+                            error.set(Some(e.to_string()));
+                        }
+                    }
+                    running.set(false);
+                });
+            },
+            "Refresh Profile"
+        })
+    };
+
+    cx.render(rsx!(div {
+        class: "mx-3 mb-2",
+        button
+        error.get().as_ref().map(|e| rsx!(div {
+            class: "alert alert-danger mt-2",
+            "{e}"
+        }))
+    }))
+}
@@ -0,0 +1,77 @@
+#![allow(non_snake_case)]
+
+use std::borrow::Cow;
+
+use dioxus::prelude::*;
+
+use crate::config::Config;
+
+use super::types::StorageWrapper;
+use super::user_list::AuthorListComponent;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Relationship {
+    Mutuals,
+    NotFollowingBack,
+    NotFollowedBack,
+}
+
+impl std::fmt::Display for Relationship {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Relationship::Mutuals => f.write_str("Mutuals"),
+            Relationship::NotFollowingBack => f.write_str("Not following back"),
+            Relationship::NotFollowedBack => f.write_str("Not followed back"),
+        }
+    }
+}
+
+/// Mutuals and the accounts that don't follow back
+#[inline_props]
+pub fn RelationshipComponent(cx: Scope, storage: StorageWrapper, config: Config) -> Element {
+    let report = use_state(&cx, || storage.relationship_report());
+    let selected = use_state(&cx, || Relationship::Mutuals);
+
+    let entries = match *selected.get() {
+        Relationship::Mutuals => &report.mutuals,
+        Relationship::NotFollowingBack => &report.not_following_back,
+        Relationship::NotFollowedBack => &report.not_followed_back,
+    };
+    let ids: Vec<u64> = entries.iter().map(|(id, _)| *id).collect();
+    let label = format!("{} ({})", selected.get(), ids.len());
+
+    let buttons = [
+        Relationship::Mutuals,
+        Relationship::NotFollowingBack,
+        Relationship::NotFollowedBack,
+    ]
+    .into_iter()
+    .map(|relationship| {
+        let class = if *selected.get() == relationship {
+            "btn btn-sm btn-secondary"
+        } else {
+            "btn btn-sm btn-outline-secondary"
+        };
+        rsx!(button {
+            class: "{class}",
+            r#type: "button",
+            onclick: move |_| selected.set(relationship),
+            "{relationship}"
+        })
+    });
+
+    cx.render(rsx!(div {
+        div {
+            class: "btn-group m-2",
+            role: "group",
+            buttons
+        }
+        AuthorListComponent {
+            data: Cow::Owned(ids),
+            media: storage.resolver(),
+            profiles: &storage.data().profiles,
+            label: label,
+            config: config
+        }
+    }))
+}
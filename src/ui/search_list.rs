@@ -1,13 +1,12 @@
 #![allow(non_snake_case)]
 
-use dioxus::events::MouseEvent;
 use dioxus::fermi::use_atom_state;
 use dioxus::prelude::*;
 
 use crate::search::{search, Description, Kind, Options, SearchResult};
 use crate::ui::main_component::ColumnState;
 
-use super::helpers::Spinner;
+use super::helpers::{InlineCheckbox, Spinner};
 use super::main_component::COLUMN2;
 use super::types::StorageWrapper;
 
@@ -46,34 +45,34 @@ pub fn SearchComponent(cx: Scope, storage: StorageWrapper) -> Element<'a> {
                 }
                 div {
                     class: "m-2",
-                    Checkbox {
+                    InlineCheckbox {
                         label: "Tweets",
                         name: "tweets"
                         checked: filters.tweets,
                         onclick: move |_| filters.set(filters.get().change(|s| s.tweets = !s.tweets))
                     }
-                    Checkbox {
+                    InlineCheckbox {
                         label: "Mentions",
                         name: "mentions"
                         checked: filters.mentions,
                         onclick: move |_| filters.set(filters.get().change(|s| s.mentions = !s.mentions))
                     }
 
-                    Checkbox {
+                    InlineCheckbox {
                         label: "Likes",
                         name: "likes"
                         checked: filters.likes,
                         onclick: move |_| filters.set(filters.get().change(|s| s.likes = !s.likes))
                     }
 
-                    Checkbox {
+                    InlineCheckbox {
                         label: "Responses",
                         name: "responses"
                         checked: filters.profiles,
                         onclick: move |_| filters.set(filters.get().change(|s| s.responses = !s.responses))
                     }
 
-                    Checkbox {
+                    InlineCheckbox {
                         label: "Profiles",
                         name: "profiles"
                         checked: filters.profiles,
@@ -99,33 +98,6 @@ pub fn SearchComponent(cx: Scope, storage: StorageWrapper) -> Element<'a> {
     }))
 }
 
-#[inline_props]
-fn Checkbox<'a>(
-    cx: Scope,
-    label: &'static str,
-    name: &'static str,
-    checked: bool,
-    onclick: EventHandler<'a, MouseEvent>,
-) -> Element {
-    cx.render(rsx!(div {
-        class: "form-check form-check-inline",
-        input {
-            class: "form-check-input",
-            onclick: move |evt| onclick.call(evt),
-            r#type: "checkbox",
-            name: "{name}",
-            checked: "{checked}",
-            id: "{name}"
-        }
-        label {
-            style: "font-size: 13px;",
-            class: "form-check-label",
-            r#for: "{name}",
-            "{label}"
-        }
-    }))
-}
-
 #[derive(Props)]
 pub struct ResultListProps<'a> {
     data: &'a [SearchResult],
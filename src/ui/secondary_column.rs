@@ -10,10 +10,12 @@ use crate::ui::user_list::AuthorListComponent;
 
 use super::helpers::Box;
 use super::main_component::{ColumnState, COLUMN2};
+use super::media_report_component::MediaReportComponent;
+use super::profile_component::ProfileComponent;
+use super::thread_component::ThreadComponent;
 use super::tweet_component::TweetComponent;
 use super::tweet_list::TweetListComponent;
 use super::types::StorageWrapper;
-use super::user_component::AuthorComponent;
 
 #[inline_props]
 pub fn SecondaryColumn(
@@ -44,7 +46,9 @@ pub fn SecondaryColumn(
                         media: storage.resolver(),
                         label: label,
                         user: &storage.data().profile,
+                        profiles: &storage.data().profiles,
                         responses: &storage.data().responses,
+                        edit_history: &storage.data().edit_history,
                         config: config
                     }
                 }
@@ -74,10 +78,12 @@ pub fn SecondaryColumn(
                     div {
                         class: "{column_class}",
                         style: "{column_style}",
-                        AuthorComponent {
+                        ProfileComponent {
                             profile: profile,
+                            data: storage.data(),
                             media: storage.resolver(),
-                            config: config
+                            config: config,
+                            storage: storage
                         }
                     }
                 }
@@ -99,6 +105,51 @@ pub fn SecondaryColumn(
             }
         } else {rsx!{ div {} }}}
 
+        {if let ColumnState::MediaReport = column2.current().as_ref() {
+            rsx!{
+                div {
+                    class: "{column_class}",
+                    style: "{column_style}",
+                    MediaReportComponent {
+                        storage: storage.clone(),
+                        config: config.clone()
+                    }
+                }
+            }
+        } else {rsx!{ div {} }}}
+
+        {if let ColumnState::Thread(id) = column2.current().as_ref() {
+            if let Some(tweet) = storage.data().any_tweet(*id) {
+                rsx!{
+                    div {
+                        class: "{column_class}",
+                        style: "{column_style}",
+                        ThreadComponent {
+                            tweet: tweet,
+                            data: storage.data(),
+                            media: storage.resolver(),
+                            config: config
+                        }
+                    }
+                }
+            } else {
+                rsx! { div {
+                    class: "p-3",
+                    Box {
+                        title: "Unknown Tweet"
+                        p {
+                            class: "m-4",
+                            "Tweet for id "
+                            strong {
+                                "{id} "
+                            }
+                            "not found"
+                        }
+                    }
+                }}
+            }
+        } else {rsx!{ div {} }}}
+
         {if let ColumnState::AnyTweet(id) = column2.current().as_ref() {
             if let Some(tweet) = storage.data().any_tweet(*id) {
                 rsx!{
@@ -109,7 +160,9 @@ pub fn SecondaryColumn(
                             tweet: tweet,
                             media: storage.resolver(),
                             user: &storage.data().profile,
+                            profiles: &storage.data().profiles,
                             responses: None,
+                            edited: storage.data().is_edited(tweet.id),
                             config: config
                         }
                     }
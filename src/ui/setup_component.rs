@@ -11,6 +11,26 @@ use super::helpers::{Box, Checkbox, NextButton};
 #[inline_props]
 pub fn SetupComponent(cx: Scope, config: Config, loading_state: UseState<LoadingState>) -> Element {
     let params = use_state(&cx, move || config.crawl_options().clone());
+    // The outcome of the "Test Login" button
+    let verification: &UseState<Option<Result<String, String>>> = use_state(&cx, || None);
+    let verify = move |_| {
+        let config = config.clone();
+        let verification = verification.clone();
+        cx.spawn(async move {
+            let result = crate::crawler::test_login(&config).await;
+            verification.set(Some(result));
+        });
+    };
+    let verification_alert = verification.get().as_ref().map(|result| {
+        let (class, text) = match result {
+            Ok(text) => ("alert alert-success", text),
+            Err(text) => ("alert alert-danger", text),
+        };
+        rsx!(div {
+            class: "{class}",
+            "{text}"
+        })
+    });
     cx.render(rsx! { Box {
         title: "Setup Config"
         div {
@@ -45,6 +65,62 @@ pub fn SetupComponent(cx: Scope, config: Config, loading_state: UseState<Loading
                     checked: params.get().likes,
                     disabled: false
                 }
+                Checkbox {
+                    name: "Metrics",
+                    label: "Record the likes and retweets of already archived tweets",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.metrics = !o.metrics)),
+                    checked: params.get().metrics,
+                    disabled: false
+                }
+                Checkbox {
+                    name: "Deleted Tweets",
+                    label: "Check the whole feed on sync to find deleted tweets",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.deleted_tweets = !o.deleted_tweets)),
+                    checked: params.get().deleted_tweets,
+                    disabled: !params.get().tweets,
+                }
+                Checkbox {
+                    name: "Polls",
+                    label: "The options and votes of polls in archived tweets",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.polls = !o.polls)),
+                    checked: params.get().polls,
+                    disabled: false
+                }
+                Checkbox {
+                    name: "Link Previews",
+                    label: "The title, description and image of links in archived tweets",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.link_previews = !o.link_previews)),
+                    checked: params.get().link_previews,
+                    disabled: false
+                }
+                Checkbox {
+                    name: "Tweet Edits",
+                    label: "The earlier versions of edited tweets",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.tweet_edits = !o.tweet_edits)),
+                    checked: params.get().tweet_edits,
+                    disabled: false
+                }
+                Checkbox {
+                    name: "Threads",
+                    label: "Which thread every archived tweet belongs to",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.threads = !o.threads)),
+                    checked: params.get().threads,
+                    disabled: false
+                }
+                Checkbox {
+                    name: "Full Archive Search",
+                    label: "Search the replies to tweets older than 7 days. Needs full-archive search access",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.full_archive_search = !o.full_archive_search)),
+                    checked: params.get().full_archive_search,
+                    disabled: !params.get().tweet_responses,
+                }
+                Checkbox {
+                    name: "Raw JSON",
+                    label: "The unmodified API data of tweets and profiles. Needs about twice the disk space",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.raw_json = !o.raw_json)),
+                    checked: params.get().raw_json,
+                    disabled: false
+                }
                 Checkbox {
                     name: "User Profiles",
                     label: "From Responses and Mentions",
@@ -52,6 +128,13 @@ pub fn SetupComponent(cx: Scope, config: Config, loading_state: UseState<Loading
                     checked: params.get().tweet_profiles,
                     disabled: false
                 }
+                Checkbox {
+                    name: "Quoted Profiles",
+                    label: "Also the authors of quoted tweets and retweets",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.quoted_profiles = !o.quoted_profiles)),
+                    checked: params.get().quoted_profiles,
+                    disabled: !params.get().tweet_profiles,
+                }
                 Checkbox {
                     name: "Followers",
                     label: "All your followers with profiles",
@@ -80,6 +163,27 @@ pub fn SetupComponent(cx: Scope, config: Config, loading_state: UseState<Loading
                     checked: params.get().media,
                     disabled: false
                 }
+                Checkbox {
+                    name: "Images",
+                    label: "The images of tweets",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.download_images = !o.download_images)),
+                    checked: params.get().download_images,
+                    disabled: !params.get().media,
+                }
+                Checkbox {
+                    name: "Videos",
+                    label: "The videos and GIFs of tweets",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.download_videos = !o.download_videos)),
+                    checked: params.get().download_videos,
+                    disabled: !params.get().media,
+                }
+                Checkbox {
+                    name: "Profile Media",
+                    label: "Profile images and banners",
+                    onclick: move |_| params.modify(|e| e.changed(|o| o.download_profile_media = !o.download_profile_media)),
+                    checked: params.get().download_profile_media,
+                    disabled: !params.get().media,
+                }
             }
             params.tweet_responses.then(|| {
                 rsx!(div {
@@ -87,6 +191,13 @@ pub fn SetupComponent(cx: Scope, config: Config, loading_state: UseState<Loading
                     "Tweet responses take a long time to load. Up to 3 hours per 1000 tweets"
                 })
             })
+            verification_alert
+            button {
+                class: "btn btn-outline-secondary",
+                r#type: "button",
+                onclick: verify,
+                "Test Login"
+            }
             NextButton {
                 title: "Start the Import!",
                 kind: "button",
@@ -0,0 +1,85 @@
+#![allow(non_snake_case)]
+
+use dioxus::prelude::*;
+
+use super::types::StorageWrapper;
+
+/// A one-glance summary of the archive
+#[inline_props]
+pub fn StatsComponent(cx: Scope, storage: StorageWrapper) -> Element {
+    let stats = use_state(&cx, || storage.stats());
+    let diff = use_state(&cx, || storage.last_backup_diff());
+    let megabytes = format!("{:.1}", stats.media_bytes as f64 / 1_000_000.0);
+    let range = match (stats.oldest_tweet, stats.newest_tweet) {
+        (Some(oldest), Some(newest)) => format!(
+            "{} - {}",
+            oldest.format("%b %Y"),
+            newest.format("%b %Y")
+        ),
+        _ => String::new(),
+    };
+    // Twitter counts accounts that the API doesn't return, e.g. suspended ones
+    let graph_gaps: Vec<String> = [
+        ("Followers", stats.missing_followers(), stats.followers_reported),
+        ("Follows", stats.missing_follows(), stats.follows_reported),
+    ]
+    .into_iter()
+    .filter_map(|(label, missing, reported)| {
+        let missing = missing.filter(|missing| *missing > 0)?;
+        Some(format!("{missing} of {} {label}", reported?))
+    })
+    .collect();
+    let graph_gaps = graph_gaps.join(", ");
+
+    cx.render(rsx!(div {
+        class: "card",
+        style: "margin: 12px",
+        div {
+            class: "card-body",
+            style: "font-size: 13px;",
+            h6 {
+                class: "card-title",
+                "Archive"
+                span {
+                    class: "text-muted ms-2",
+                    "{range}"
+                }
+            }
+            span {
+                class: "text-success",
+                "{stats.tweets} Tweets, {stats.mentions} Mentions, {stats.likes} Likes, {stats.responses} Responses"
+            }
+            br {}
+            span {
+                class: "text-success",
+                "{stats.followers} Followers, {stats.follows} Follows, {stats.profiles} Profiles, {stats.lists} Lists"
+            }
+            br {}
+            span {
+                class: "text-muted",
+                "{stats.media_files} Media files ({megabytes} MB)"
+            }
+            (!graph_gaps.is_empty()).then(|| rsx!(
+                br {}
+                span {
+                    class: "text-warning",
+                    title: "Deleted, suspended or protected accounts are counted by Twitter but not returned",
+                    "Not archived: {graph_gaps}"
+                }
+            ))
+            diff.get().as_ref().map(|diff| {
+                let tweets = diff.new_tweets.len();
+                let gained = diff.followers_gained.len();
+                let lost = diff.followers_lost.len();
+                let list_members: usize = diff.new_list_members.iter().map(|(_, m)| m.len()).sum();
+                rsx!(
+                    br {}
+                    span {
+                        class: "text-primary",
+                        "Since the last backup: {tweets} new Tweets, +{gained} / -{lost} Followers, {list_members} new List members"
+                    }
+                )
+            })
+        }
+    }))
+}
@@ -0,0 +1,172 @@
+#![allow(non_snake_case)]
+
+use dioxus::prelude::*;
+use egg_mode::tweet::Tweet;
+
+use crate::config::Config;
+use crate::storage::{Data, MediaResolver, TweetId};
+
+use super::tweet_component::TweetComponent;
+
+/// Responses to responses are nested up to this depth
+const MAX_DEPTH: usize = 8;
+
+#[derive(Props)]
+pub struct ThreadProps<'a> {
+    tweet: &'a Tweet,
+    data: &'a Data,
+    media: MediaResolver<'a>,
+    config: &'a Config,
+}
+
+/// A tweet, the tweet it quotes or retweets, the archived tweets quoting it
+/// and all archived responses, nested
+pub fn ThreadComponent<'a>(cx: Scope<'a, ThreadProps>) -> Element<'a> {
+    let tweet = cx.props.tweet;
+    let data = cx.props.data;
+
+    let retweeted = data
+        .retweeted_tweet(tweet.id)
+        .or(tweet.retweeted_status.as_deref())
+        .map(|retweet| {
+            rsx!(div {
+                style: "margin-left: 2rem;",
+                small {
+                    class: "text-muted",
+                    "Retweeted"
+                }
+                TweetComponent {
+                    tweet: retweet,
+                    media: cx.props.media.clone(),
+                    user: &cx.props.data.profile,
+                    profiles: &cx.props.data.profiles,
+                    responses: None,
+                    config: cx.props.config
+                }
+            })
+        });
+
+    // The tweet component shows the embedded quoted tweet. Quoted tweets of
+    // quoted tweets aren't embedded, but might be archived
+    let quoted = data
+        .quoted_tweet(tweet.id)
+        .filter(|_| tweet.quoted_status.is_none())
+        .map(|quoted| {
+            rsx!(div {
+                style: "margin-left: 2rem;",
+                small {
+                    class: "text-muted",
+                    "Quoted"
+                }
+                TweetComponent {
+                    tweet: quoted,
+                    media: cx.props.media.clone(),
+                    user: &data.profile,
+                    profiles: &data.profiles,
+                    responses: None,
+                    config: cx.props.config
+                }
+            })
+        });
+
+    let quoted_by = data.quoted_by(tweet.id);
+    let quoted_by_title = (!quoted_by.is_empty()).then(|| {
+        rsx!(h6 {
+            style: "margin-left: 15px; color: slategray;",
+            "Quoted by"
+        })
+    });
+    let quoted_by_rendered = quoted_by.into_iter().map(|quote| {
+        rsx!(TweetComponent {
+            tweet: quote,
+            media: cx.props.media.clone(),
+            user: &data.profile,
+            profiles: &data.profiles,
+            responses: None,
+            config: cx.props.config
+        })
+    });
+
+    // The tweets this one replies to, directly or further up the thread
+    let earlier = cx.props.data.ancestors(tweet).into_iter().map(|earlier| {
+        rsx!(TweetComponent {
+            tweet: earlier,
+            media: cx.props.media.clone(),
+            user: &cx.props.data.profile,
+            profiles: &cx.props.data.profiles,
+            responses: None,
+            config: cx.props.config
+        })
+    });
+
+    cx.render(rsx!(div {
+        h5 {
+            style: "margin-top: 10px; margin-bottom: 5px; margin-left: 15px; font-weight: bold; color: slategray;",
+            "Thread"
+        }
+        earlier
+        TweetComponent {
+            tweet: tweet,
+            media: cx.props.media.clone(),
+            user: &cx.props.data.profile,
+            profiles: &cx.props.data.profiles,
+            responses: None,
+            edited: cx.props.data.is_edited(tweet.id),
+            config: cx.props.config
+        }
+        retweeted
+        quoted
+        quoted_by_title
+        quoted_by_rendered
+        ThreadRepliesComponent {
+            id: tweet.id,
+            depth: 1,
+            data: cx.props.data,
+            media: cx.props.media.clone(),
+            config: cx.props.config
+        }
+    }))
+}
+
+#[derive(Props)]
+struct ThreadRepliesProps<'a> {
+    id: TweetId,
+    depth: usize,
+    data: &'a Data,
+    media: MediaResolver<'a>,
+    config: &'a Config,
+}
+
+fn ThreadRepliesComponent<'a>(cx: Scope<'a, ThreadRepliesProps>) -> Element<'a> {
+    let Some(replies) = cx.props.data.responses.get(&cx.props.id) else {
+        return cx.render(rsx!(div {}));
+    };
+    if cx.props.depth > MAX_DEPTH {
+        return cx.render(rsx!(div {}));
+    }
+
+    let replies_rendered = replies.iter().map(|reply| {
+        cx.render(rsx!(div {
+            TweetComponent {
+                tweet: reply,
+                media: cx.props.media.clone(),
+                user: &cx.props.data.profile,
+                profiles: &cx.props.data.profiles,
+                responses: None,
+                config: cx.props.config
+            }
+            ThreadRepliesComponent {
+                id: reply.id,
+                depth: cx.props.depth + 1,
+                data: cx.props.data,
+                media: cx.props.media.clone(),
+                config: cx.props.config
+            }
+        }))
+    });
+
+    cx.render(rsx!(div {
+        style: "margin-left: 1.5rem; border-left: 2px solid rgba(0, 0, 0, .1);",
+        replies_rendered
+    }))
+}
@@ -1,4 +1,5 @@
 #![allow(non_snake_case)]
+use std::collections::HashMap;
 
 use dioxus::fermi::use_atom_state;
 use dioxus::prelude::*;
@@ -8,12 +9,17 @@ use egg_mode::user::TwitterUser;
 
 use crate::config::Config;
 use crate::crawler::DownloadInstruction;
-use crate::helpers::{delete_tweet, open_file};
+use crate::helpers::{
+    delete_tweet, open_file, remove_archived_tweet, render_tweet_html, tweet_permalink,
+    EntityLinks,
+};
 use crate::storage::MediaResolver;
 
 use egg_mode::tweet::Tweet;
 
+use super::app::UPDATED_STORAGE;
 use super::main_component::{ColumnState, COLUMN2};
+use super::types::StorageWrapper;
 use super::user_component::AuthorImageComponent;
 
 #[derive(Props)]
@@ -21,7 +27,11 @@ pub struct TweetProps<'a> {
     tweet: &'a Tweet,
     media: MediaResolver<'a>,
     user: &'a TwitterUser,
+    /// The archived profiles, for the handles of tweets without one
+    profiles: &'a HashMap<u64, TwitterUser>,
     responses: Option<Option<usize>>,
+    /// Whether there are earlier versions of the tweet
+    edited: Option<bool>,
     config: &'a Config,
 }
 
@@ -36,11 +46,14 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
 
     let pure_text = &tweet.text;
 
-    let text = formatted_tweet(tweet);
+    let text = render_tweet_html(tweet, EntityLinks::Twitter);
 
     let media = crate::helpers::media_in_tweet(tweet);
 
+    let permalink = tweet_permalink(tweet, cx.props.profiles);
+
     let modal_id = format!("modal-{}", tweet.id);
+    let archive_modal_id = format!("modal-archive-{}", tweet.id);
 
     // we can only delete our own tweets
     let can_delete = cx.props.user.id == user.id;
@@ -55,6 +68,24 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
         Some(delete_tweet(*id, &cloned_config).await)
     });
 
+    // Removing the tweet from the local archive
+    let updated_storage = use_atom_state(&cx, UPDATED_STORAGE);
+    let removal_config = cx.props.config.clone();
+    let removal_tweet: &UseState<Option<u64>> = use_state(&cx, || None);
+    let removal_future = use_future(&cx, removal_tweet, |oid| {
+        let updated_storage = updated_storage.clone();
+        async move {
+            let Some(id) = oid.get() else {
+                return None
+            };
+            let result = remove_archived_tweet(*id, &removal_config);
+            if let Ok(storage) = &result {
+                updated_storage.set(Some(StorageWrapper::new(storage.clone())));
+            }
+            Some(result.map(|_| true))
+        }
+    });
+
     let action_dropdown = rsx! {
         div {
             class: "dropdown",
@@ -75,7 +106,7 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
                 li {
                     a {
                         class: "dropdown-item fs-6",
-                        href: "https://twitter.com/{user.screen_name}/status/{tweet.id}",
+                        href: "{permalink}",
                         "Open on Twitter"
                     }
                 }
@@ -107,75 +138,75 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
                         "Delete on Twitter"
                     }
                 })) }
+                li {
+                    button {
+                        class: "dropdown-item btn btn-danger text-danger",
+                        r#type: "button",
+                        "data-bs-toggle": "modal",
+                        "data-bs-target": "#{archive_modal_id}",
+                        "Remove from Archive"
+                    }
+                }
             }
         }
     };
     let modal_id = format!("modal-{}", tweet.id);
+    let archive_modal_id = format!("modal-archive-{}", tweet.id);
 
-    let image = media
-        .as_ref()
-        .and_then(|media| {
-            media.iter().find_map(|item| match item {
-                DownloadInstruction::Image(url) => Some(url.clone()),
-                _ => None,
-            })
-        })
-        .map(|entry| {
-            cx.props
-                .media
-                .resolve(&entry)
-                .unwrap_or_else(|| entry.clone())
-        })
-        .map(|entry| {
-            let cloned = entry.clone();
-            rsx!(img {
-                src: "{cloned}",
-                style: "cursor: pointer",
-                class: "card-img-bottom img-thumbnail",
-                onclick: move |_| {
-                    open_file(&entry);
+    let media_rendered = media.unwrap_or_default().into_iter().map(|item| match item {
+        DownloadInstruction::Image(url) => {
+            let alt = alt_text(tweet, &url).unwrap_or_default();
+            match cx.props.media.resolve(&url) {
+                Some(entry) => {
+                    let cloned = entry.clone();
+                    rsx!(img {
+                        src: "{cloned}",
+                        alt: "{alt}",
+                        title: "{alt}",
+                        style: "cursor: pointer",
+                        class: "card-img-bottom img-thumbnail",
+                        onclick: move |_| {
+                            open_file(&entry);
+                        }
+                    })
                 }
-            })
-        })
-        .unwrap_or_else(|| rsx!(div {}));
-
-    let video = media
-        .and_then(|media| {
-            media.iter().find_map(|item| match item {
-                DownloadInstruction::Movie(_, url) => Some(url.clone()),
-                _ => None,
-            })
-        })
-        .map(|entry| {
-            cx.props
-                .media
-                .resolve(&entry)
-                .unwrap_or_else(|| entry.clone())
-        })
-        .map(|entry| {
-            let clone = entry.clone();
-            rsx!( div {
-                class: "vstack text-center",
-                div {
-                    class: "ratio ratio-16x9",
-                    video {
-                        controls: "true",
-                        source {
-                            src: "{entry}"
+                None => rsx!(MediaPlaceholder {
+                    kind: "Image",
+                    alt: alt
+                }),
+            }
+        }
+        DownloadInstruction::Movie(_, url, _) => match cx.props.media.resolve(&url) {
+            Some(entry) => {
+                let clone = entry.clone();
+                rsx!( div {
+                    class: "vstack text-center",
+                    div {
+                        class: "ratio ratio-16x9",
+                        video {
+                            controls: "true",
+                            source {
+                                src: "{entry}"
+                            }
                         }
                     }
-                }
-                small {
-                    a {
-                        class: "link-secondary",
-                        href: "#",
-                        onclick: move |_| open_file(&clone),
-                        "Locate Video on Disk"
+                    small {
+                        a {
+                            class: "link-secondary",
+                            href: "#",
+                            onclick: move |_| open_file(&clone),
+                            "Locate Video on Disk"
+                        }
                     }
-                }
-            })
-        })
-        .unwrap_or_else(|| rsx!(div {}));
+                })
+            }
+            None => rsx!(MediaPlaceholder {
+                kind: "Video",
+                alt: String::new()
+            }),
+        },
+        _ => rsx!(div {}),
+    });
 
     let user_image = tweet
         .user
@@ -188,6 +219,17 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
         })
         .unwrap_or_else(|| rsx!(div {}));
 
+    let source = tweet.source.as_ref().map(|source| {
+        rsx!(
+            " via "
+            a {
+                class: "text-muted",
+                href: "{source.url}",
+                "{source.name}"
+            }
+        )
+    });
+
     let tweet_info = rsx!(
         div {
             class: "card-title d-flex flex-row justify-content-between align-items-center",
@@ -205,6 +247,11 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
                 class: "text-muted me-auto",
                 style: "font-size: 12px",
                 "{date}"
+                source
+                cx.props.edited.unwrap_or_default().then(|| rsx!(span {
+                    class: "badge text-bg-secondary ms-1",
+                    "Edited"
+                }))
             }
             action_dropdown
         }
@@ -217,6 +264,13 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
                 onclick: move |_| column2.set(ColumnState::Responses(tweet.id)),
                 "{e} Responses"
             }
+            " "
+            span {
+                class: "text-primary",
+                style: "cursor: pointer",
+                onclick: move |_| column2.set(ColumnState::Thread(tweet.id)),
+                "View Thread"
+            }
         )
     });
 
@@ -246,7 +300,8 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
                 TweetComponent {
                     tweet: quoted,
                     media: cx.props.media.clone(),
-                    user: cx.props.user
+                    user: cx.props.user,
+                    profiles: cx.props.profiles,
                     responses: None
                     config: cx.props.config
                 }
@@ -274,8 +329,7 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
                     }
                     tweet_actions
                     quoted
-                    video
-                    image
+                    media_rendered
                 }
             }
             div {
@@ -329,60 +383,82 @@ pub fn TweetComponent<'a>(cx: Scope<'a, TweetProps>) -> Element<'a> {
                     }
                 }
             }
+            div {
+                class: "modal",
+                "data-bs-backdrop": "false",
+                id: "{archive_modal_id}",
+                div {
+                    class: "modal-dialog",
+                    div {
+                        class: "modal-content",
+                        div {
+                            class: "modal-body",
+                            "Do you really want to remove this tweet from the archive?"
+                            div {
+                                class: "hstack gap-2",
+                                div {
+                                    class: "vr"
+                                }
+                                p {
+                                    small {
+                                        em {
+                                            "{pure_text}"
+                                        }
+                                    }
+                                }
+                            }
+                            div {
+                                class: "alert alert-warning",
+                                "The tweet and its media will be removed from this archive. Media that is used by other tweets is kept. The tweet on Twitter is not affected."
+                            }
+                        }
+                        div {
+                            class: "modal-footer",
+                            button {
+                                class: "btn btn-secondary",
+                                "data-bs-dismiss": "modal",
+                                r#type: "button",
+                                "Keep it"
+                            }
+                            button {
+                                class: "btn btn-danger",
+                                "data-bs-dismiss": "modal",
+                                r#type: "button",
+                                onclick: move |_| {
+                                    removal_tweet.set(Some(tweet.id));
+                                    removal_future.restart();
+                                },
+                                "Yes, remove please"
+                            }
+                        }
+                    }
+                }
+            }
         }
     }))
 }
 
-fn formatted_tweet(tweet: &Tweet) -> String {
-    let mut output = String::new();
-    let mut additions = Vec::new();
-    additions.extend(
-        tweet
-            .entities
-            .hashtags
-            .iter()
-            .map(|tag| (tag.range, "http://test.com".to_string())),
-    );
-    additions.extend(tweet.entities.urls.iter().map(|url| {
-        (
-            url.range,
-            url.expanded_url
-                .as_ref()
-                .unwrap_or(&url.display_url)
-                .clone(),
-        )
-    }));
-
-    additions.extend(
-        tweet
-            .entities
-            .user_mentions
-            .iter()
-            .map(|mention| (mention.range, format!("{}", mention.id))),
-    );
-
-    if let Some(media) = tweet.entities.media.as_ref() {
-        additions.extend(
-            media
-                .iter()
-                .map(|media| (media.range, media.expanded_url.clone())),
-        );
-    }
-
-    additions.sort_by(|a, b| a.0 .0.cmp(&b.0 .0));
-
-    let t = &tweet.text;
-
-    let mut current = 0;
-    for (range, link) in additions {
-        // Get the part from last to beginning
-        output.push_str(&t[current..range.0]);
-        output.push_str(&format!("<a href='{link}'>"));
-        output.push_str(&t[range.0..range.1]);
-        output.push_str("</a>");
-        current = range.1;
-    }
-    output.push_str(&t[current..t.len()]);
+#[inline_props]
+fn MediaPlaceholder(cx: Scope, kind: &'static str, alt: String) -> Element {
+    cx.render(rsx!(div {
+        class: "alert alert-secondary text-center",
+        small {
+            "{kind} not in archive"
+        }
+        (!alt.is_empty()).then(|| rsx!(p {
+            class: "text-muted mb-0",
+            "{alt}"
+        }))
+    }))
+}
 
-    output
+/// The stored alt text for the media entity with the given url
+fn alt_text(tweet: &Tweet, url: &str) -> Option<String> {
+    tweet
+        .extended_entities
+        .as_ref()?
+        .media
+        .iter()
+        .find(|media| media.media_url_https == url)
+        .and_then(|media| media.ext_alt_text.clone())
 }
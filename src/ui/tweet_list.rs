@@ -1,11 +1,12 @@
 #![allow(non_snake_case)]
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use dioxus::prelude::*;
 use egg_mode::user::TwitterUser;
 
 use crate::config::Config;
-use crate::storage::MediaResolver;
+use crate::search::TextIndex;
+use crate::storage::{tweet_lang, MediaResolver};
 
 use egg_mode::tweet::Tweet;
 
@@ -17,7 +18,11 @@ pub struct TweetListProps<'a> {
     data: &'a [Tweet],
     media: MediaResolver<'a>,
     user: &'a TwitterUser,
+    profiles: &'a HashMap<u64, TwitterUser>,
     responses: &'a HashMap<u64, Vec<Tweet>>,
+    edit_history: &'a HashMap<u64, Vec<u64>>,
+    /// Tweets of `data` that aren't listed
+    hidden: Option<&'a HashSet<u64>>,
     label: String,
     config: &'a Config,
 }
@@ -26,19 +31,56 @@ pub fn TweetListComponent<'a>(cx: Scope<'a, TweetListProps>) -> Element<'a> {
     let page_size = 100;
     let page = use_state(&cx, || page_size);
     let inverse = use_state(&cx, || false);
-    let has_more = cx.props.data.len() > *page.get();
+    let filter = use_state(&cx, String::new);
+    // A language code, empty for all languages
+    let lang = use_state(&cx, String::new);
+    let mut languages: Vec<&str> = cx.props.data.iter().map(tweet_lang).collect();
+    languages.sort_unstable();
+    languages.dedup();
     let iter: Box<dyn Iterator<Item = &Tweet>> = if *inverse.get() {
         Box::new(cx.props.data.iter().rev())
     } else {
         Box::new(cx.props.data.iter())
     };
-    let tweets_rendered = iter.take(*page.get()).map(|tweet| {
+    // Built again when a sync changed the tweets
+    let index = cx.use_hook(|_| TextIndex::default());
+    if index.len() != cx.props.data.len() {
+        *index = TextIndex::new(cx.props.data);
+    }
+    // Only tweets containing the filter text (case insensitive)
+    let needle = filter.get().to_lowercase();
+    let candidates = index.candidates(&needle);
+    let filtered: Vec<&Tweet> = iter
+        .filter(|tweet| match &candidates {
+            Some(ids) => ids.contains(&tweet.id) && tweet.text.to_lowercase().contains(&needle),
+            None => true,
+        })
+        .filter(|tweet| lang.get().is_empty() || tweet_lang(tweet) == lang.get())
+        .filter(|tweet| {
+            !cx.props
+                .hidden
+                .is_some_and(|hidden| hidden.contains(&tweet.id))
+        })
+        .collect();
+    let has_more = filtered.len() > *page.get();
+    let all_languages = lang.get().is_empty();
+    let language_options = languages.into_iter().map(|code| {
+        let selected = lang.get().as_str() == code;
+        rsx!(option {
+            value: "{code}",
+            selected: "{selected}",
+            "{code}"
+        })
+    });
+    let tweets_rendered = filtered.into_iter().take(*page.get()).map(|tweet| {
         let responses = cx.props.responses.get(&tweet.id).as_ref().map(|e| e.len());
         cx.render(rsx!(TweetComponent {
             tweet: tweet,
             media: cx.props.media.clone(),
-            user: cx.props.user
+            user: cx.props.user,
+            profiles: cx.props.profiles,
             responses: responses,
+            edited: cx.props.edit_history.contains_key(&tweet.id),
             config: cx.props.config
         }))
     });
@@ -60,6 +102,35 @@ pub fn TweetListComponent<'a>(cx: Scope<'a, TweetListProps>) -> Element<'a> {
                 "\u{21F5}"
             }
         }
+        div {
+            class: "m-2 hstack gap-2",
+            input {
+                r#type: "text",
+                class: "form-control form-control-sm",
+                placeholder: "Filter",
+                autocomplete: "off",
+                spellcheck: "false",
+                value: "{filter}",
+                oninput: move |evt| {
+                    page.set(page_size);
+                    filter.set(evt.value.clone());
+                }
+            }
+            select {
+                class: "form-select form-select-sm w-auto",
+                title: "Language",
+                oninput: move |evt| {
+                    page.set(page_size);
+                    lang.set(evt.value.clone());
+                },
+                option {
+                    value: "",
+                    selected: "{all_languages}",
+                    "All languages"
+                }
+                language_options
+            }
+        }
         tweets_rendered
         ShowMoreButton {
             visible: has_more,
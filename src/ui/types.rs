@@ -1,17 +1,26 @@
 #![allow(non_snake_case)]
+use std::future::Future;
+use std::path::PathBuf;
 use std::rc::Rc;
 
-use crate::config::Config;
+use crate::config::{Account, Config};
 
-use crate::storage::{Data, MediaResolver, Storage};
+use crate::storage::{
+    ArchiveStats, BackupDiff, Data, MediaReport, MediaResolver, RelationshipReport, Storage,
+};
 
 use egg_mode::tweet::Tweet;
+use eyre::Result;
 
 #[derive(Clone)]
 pub enum LoadingState {
+    PickAccount(Vec<Account>),
     Login,
     Setup(Config),
     Loading(Config),
+    /// Open the existing archive at the path in the background. Without a
+    /// config, the one of the archive is used, or else an offline one
+    Opening(PathBuf, Option<Config>),
     Loaded(StorageWrapper, Config),
 }
 
@@ -46,9 +55,56 @@ impl StorageWrapper {
         self.data.data()
     }
 
+    /// Whether only the newest tweets are shown while the archive loads
+    pub fn is_preview(&self) -> bool {
+        self.data.is_preview()
+    }
+
     pub fn resolver(&self) -> MediaResolver {
         self.data.resolver()
     }
+
+    /// The folder the media files are downloaded into
+    pub fn media_folder(&self) -> PathBuf {
+        self.data.media_path("")
+    }
+
+    pub fn verify_media(&self) -> MediaReport {
+        self.data.verify_media()
+    }
+
+    pub fn stats(&self) -> ArchiveStats {
+        self.data.stats()
+    }
+
+    pub fn relationship_report(&self) -> RelationshipReport {
+        self.data.relationship_report()
+    }
+
+    pub fn top_hashtags(&self, n: usize) -> Vec<(String, usize)> {
+        self.data.top_hashtags(n)
+    }
+
+    /// The changes since the crawl before the most recent one
+    pub fn last_backup_diff(&self) -> Option<BackupDiff> {
+        self.data
+            .previous_backup()
+            .map(|since| self.data.diff_since(since))
+    }
+
+    /// Runs `update` with a copy of the storage and saves the result, both
+    /// off the UI thread. The shown storage stays untouched
+    pub async fn update<F, Fut>(&self, update: F) -> Result<Storage>
+    where
+        F: FnOnce(Storage) -> Fut,
+        Fut: Future<Output = Result<Storage>> + Send + 'static,
+    {
+        let storage = update(self.data.as_ref().clone());
+        let storage = tokio::spawn(storage)
+            .await
+            .map_err(|e| eyre::eyre!("The update failed: {e:?}"))??;
+        tokio::task::spawn_blocking(move || storage.save().map(|_| storage)).await?
+    }
 }
 
 impl PartialEq for StorageWrapper {
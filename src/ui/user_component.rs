@@ -1,4 +1,5 @@
 #![allow(non_snake_case)]
+use std::collections::HashMap;
 
 use dioxus::fermi::use_atom_state;
 use dioxus::prelude::*;
@@ -14,6 +15,7 @@ use super::tweet_component::TweetComponent;
 pub struct AuthorProps<'a> {
     profile: &'a TwitterUser,
     media: MediaResolver<'a>,
+    profiles: &'a HashMap<u64, TwitterUser>,
     config: &'a Config,
 }
 
@@ -83,7 +85,8 @@ pub fn AuthorComponent<'a>(cx: Scope<'a, AuthorProps>) -> Element<'a> {
                 TweetComponent {
                     tweet: quoted,
                     media: cx.props.media.clone(),
-                    user: cx.props.profile
+                    user: cx.props.profile,
+                    profiles: cx.props.profiles,
                     responses: None,
                     config: cx.props.config
                 }
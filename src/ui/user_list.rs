@@ -28,6 +28,7 @@ pub fn AuthorListComponent<'a>(cx: Scope<'a, AuthorListProps>) -> Element<'a> {
             cx.render(rsx!(AuthorComponent {
                 profile: user,
                 media: cx.props.media.clone(),
+                profiles: cx.props.profiles,
                 config: cx.props.config
             }))
         } else {
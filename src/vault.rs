@@ -0,0 +1,129 @@
+//! The entry point for using TwitVault as a library
+use std::sync::{Arc, Mutex};
+
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc::channel;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::crawler;
+use crate::storage::Storage;
+use crate::types::Message;
+
+/// Backs up the data of one account.
+///
+/// ```no_run
+/// # async fn run(config: twitvault::config::Config) {
+/// use futures::StreamExt;
+/// use twitvault::{types::Message, TwitVault};
+///
+/// let vault = TwitVault::new(config);
+/// let mut messages = Box::pin(vault.backup());
+/// while let Some(message) = messages.next().await {
+///     if let Message::Loading(progress) = message {
+///         println!("{progress}");
+///     }
+/// }
+/// let storage = vault.storage();
+/// # }
+/// ```
+pub struct TwitVault {
+    config: Config,
+    user_id: u64,
+    /// An already opened archive to back up into, see `with_storage`
+    archive: Mutex<Option<Storage>>,
+    storage: Arc<Mutex<Option<Storage>>>,
+}
+
+impl TwitVault {
+    /// Backs up the account the config belongs to
+    pub fn new(config: Config) -> Self {
+        let user_id = config.archived_user_id();
+        Self {
+            config,
+            user_id,
+            archive: Default::default(),
+            storage: Default::default(),
+        }
+    }
+
+    /// Back up a different user with the account of the config
+    pub fn with_user(mut self, user_id: u64) -> Self {
+        self.user_id = user_id;
+        self
+    }
+
+    /// Back up into an archive that is already open instead of opening
+    /// the one at the storage path of the config
+    pub fn with_storage(self, storage: Storage) -> Self {
+        if let Ok(mut archive) = self.archive.lock() {
+            *archive = Some(storage);
+        }
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Start the backup. If there already is an archive, only new data is
+    /// downloaded. The last message is either `Message::Finished`
+    /// or `Message::Error`
+    pub fn backup(&self) -> impl Stream<Item = Message> {
+        self.run(false)
+    }
+
+    /// Start a full crawl that pages through all data instead of stopping
+    /// at already archived data. An interrupted crawl continues where it
+    /// stopped, otherwise the archive is started anew. The messages are
+    /// the same as the ones of `backup`
+    pub fn crawl(&self) -> impl Stream<Item = Message> {
+        self.run(true)
+    }
+
+    fn run(&self, full: bool) -> impl Stream<Item = Message> {
+        let (sender, receiver) = channel(256);
+        let mut config = self.config.clone();
+        let user_id = self.user_id;
+        let archive = self
+            .archive
+            .lock()
+            .ok()
+            .and_then(|mut archive| archive.take());
+        tokio::spawn(async move {
+            let existing = match archive {
+                _ if full => None,
+                Some(storage) => Some(storage),
+                None => Storage::open(config.actual_storage_path()).ok(),
+            };
+            let result = match existing {
+                Some(storage) => {
+                    config.is_sync = true;
+                    crawler::crawl_into_storage(user_id, config, storage, sender.clone()).await
+                }
+                None => crawler::crawl_new_storage(config, sender.clone(), user_id).await,
+            };
+            if let Err(e) = result {
+                if let Err(e) = sender.send(Message::Error(e)).await {
+                    warn!("Could not send error {e:?}");
+                }
+            }
+        });
+
+        let storage = self.storage.clone();
+        futures::stream::unfold(receiver, |mut receiver| async move {
+            let message = receiver.recv().await?;
+            Some((message, receiver))
+        })
+        .inspect(move |message| {
+            if let (Message::Finished(finished), Ok(mut storage)) = (message, storage.lock()) {
+                *storage = Some(finished.clone());
+            }
+        })
+    }
+
+    /// The archive after a finished backup
+    pub fn storage(&self) -> Option<Storage> {
+        self.storage.lock().ok()?.clone()
+    }
+}